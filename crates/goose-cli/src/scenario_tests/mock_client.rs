@@ -105,6 +105,7 @@ impl McpClientTrait for MockClient {
                 Ok(content) => Ok(CallToolResult {
                     content,
                     is_error: None,
+                    meta: None,
                 }),
                 Err(e) => Err(Error::UnexpectedResponse(e.to_string())),
             }
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use tokio::process::Command;
+
+/// Variables inherited by scripts under the default policy: enough to find binaries,
+/// resolve the user's home and locale, and use a temp directory. Everything else,
+/// including API tokens such as `GRAPHITI_API_TOKEN`, is dropped.
+#[cfg(not(windows))]
+const DEFAULT_ALLOWED: &[&str] = &[
+    "PATH", "HOME", "USER", "LOGNAME", "SHELL", "LANG", "LC_ALL", "LC_CTYPE", "TERM", "TMPDIR",
+    "TZ",
+];
+
+#[cfg(windows)]
+const DEFAULT_ALLOWED: &[&str] = &[
+    "PATH",
+    "PATHEXT",
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "WINDIR",
+    "COMSPEC",
+    "USERNAME",
+    "USERPROFILE",
+    "HOMEDRIVE",
+    "HOMEPATH",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "PROGRAMDATA",
+    "PROGRAMFILES",
+    "TEMP",
+    "TMP",
+];
+
+/// Controls which environment variables subprocesses started by the computer controller see.
+///
+/// The child environment is cleared, then the allowed variables are copied from the
+/// current process (when set), then injected variables are added on top.
+#[derive(Debug, Clone)]
+pub struct EnvPolicy {
+    allowed: Vec<String>,
+    injected: BTreeMap<String, String>,
+}
+
+impl Default for EnvPolicy {
+    fn default() -> Self {
+        Self {
+            allowed: DEFAULT_ALLOWED.iter().map(|var| var.to_string()).collect(),
+            injected: BTreeMap::new(),
+        }
+    }
+}
+
+impl EnvPolicy {
+    /// A policy that passes nothing through; combine with `allow` and `inject`
+    pub fn empty() -> Self {
+        Self {
+            allowed: Vec::new(),
+            injected: BTreeMap::new(),
+        }
+    }
+
+    /// Also pass `var` through from the current environment
+    pub fn allow(mut self, var: impl Into<String>) -> Self {
+        self.allowed.push(var.into());
+        self
+    }
+
+    /// Set `var` to `value` in the child, regardless of the current environment
+    pub fn inject(mut self, var: impl Into<String>, value: impl Into<String>) -> Self {
+        self.injected.insert(var.into(), value.into());
+        self
+    }
+
+    pub fn apply(&self, command: &mut Command) {
+        self.apply_from(command, |var| std::env::var_os(var));
+    }
+
+    /// Like [`apply`](Self::apply), reading the allowed variables through `lookup` instead
+    /// of from the current process
+    fn apply_from(&self, command: &mut Command, lookup: impl Fn(&str) -> Option<OsString>) {
+        command.env_clear();
+        for var in &self.allowed {
+            if let Some(value) = lookup(var) {
+                command.env(var, value);
+            }
+        }
+        command.envs(&self.injected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The variables a child started under `policy` would see, given the `parent` environment
+    fn child_env(policy: &EnvPolicy, parent: &[(&str, &str)]) -> BTreeMap<String, String> {
+        let mut command = Command::new("env");
+        policy.apply_from(&mut command, |var| {
+            parent
+                .iter()
+                .find(|(name, _)| *name == var)
+                .map(|(_, value)| OsString::from(value))
+        });
+        command
+            .as_std()
+            .get_envs()
+            .filter_map(|(var, value)| {
+                let value = value?.to_string_lossy().into_owned();
+                Some((var.to_string_lossy().into_owned(), value))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_default_policy_drops_secrets() {
+        let env = child_env(
+            &EnvPolicy::default(),
+            &[("PATH", "/usr/bin"), ("GRAPHITI_API_TOKEN", "secret-token")],
+        );
+        assert_eq!(
+            env,
+            BTreeMap::from([("PATH".to_string(), "/usr/bin".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_allow_and_inject() {
+        let policy = EnvPolicy::empty()
+            .allow("GOOSE_TEST_ALLOWED_VAR")
+            .allow("GOOSE_TEST_UNSET_VAR")
+            .inject("GOOSE_TEST_INJECTED_VAR", "injected");
+        let env = child_env(
+            &policy,
+            &[
+                ("PATH", "/usr/bin"),
+                ("GOOSE_TEST_ALLOWED_VAR", "passed-through"),
+            ],
+        );
+        assert_eq!(
+            env,
+            BTreeMap::from([
+                (
+                    "GOOSE_TEST_ALLOWED_VAR".to_string(),
+                    "passed-through".to_string()
+                ),
+                (
+                    "GOOSE_TEST_INJECTED_VAR".to_string(),
+                    "injected".to_string()
+                ),
+            ])
+        );
+    }
+}
@@ -23,11 +23,21 @@ use rmcp::model::{
 use rmcp::object;
 
 mod docx_tool;
+mod env_policy;
 mod pdf_tool;
+mod stream;
 mod xlsx_tool;
 
 mod platform;
 use platform::{create_system_automation, SystemAutomation};
+use stream::{run_streaming, MAX_SCRIPT_OUTPUT_BYTES};
+
+pub use env_policy::EnvPolicy;
+
+use crate::GooseConfig;
+
+/// The shared settings read by the router, none of which has a default
+const SETTINGS: &[(&str, Option<&str>)] = &[("goose_working_dir", None)];
 
 /// An extension designed for non-developers to help them with common tasks like
 /// web scraping, data processing, and automation.
@@ -39,6 +49,8 @@ pub struct ComputerControllerRouter {
     http_client: Client,
     instructions: String,
     system_automation: Arc<Box<dyn SystemAutomation + Send + Sync>>,
+    working_dir: PathBuf,
+    env_policy: EnvPolicy,
 }
 
 impl Default for ComputerControllerRouter {
@@ -195,6 +207,10 @@ impl ComputerControllerRouter {
                         "type": "boolean",
                         "default": false,
                         "description": "Whether to save the script output to a file"
+                    },
+                    "cwd": {
+                        "type": "string",
+                        "description": "Directory to run the script in; must be inside the working directory"
                     }
                 }
             }),
@@ -552,7 +568,50 @@ impl ComputerControllerRouter {
             http_client: Client::builder().user_agent("Goose/1.0").build().unwrap(),
             instructions: instructions.clone(),
             system_automation,
+            working_dir: default_working_dir(&GooseConfig::new(SETTINGS).with_env()),
+            env_policy: EnvPolicy::default(),
+        }
+    }
+
+    /// Replace the environment policy applied to scripts (see [`EnvPolicy`] for the default)
+    pub fn with_env_policy(mut self, env_policy: EnvPolicy) -> Self {
+        self.env_policy = env_policy;
+        self
+    }
+
+    /// Run scripts in `working_dir` instead of the default (`goose_working_dir` or the CWD)
+    pub fn with_working_dir(mut self, working_dir: PathBuf) -> Self {
+        self.working_dir = working_dir;
+        self
+    }
+
+    /// Resolve the directory a script runs in: the working directory itself, or a requested
+    /// `cwd` that must resolve (after following `..` and symlinks) to somewhere inside it
+    fn resolve_cwd(&self, requested: Option<&str>) -> Result<PathBuf, ToolError> {
+        let base = self.working_dir.canonicalize().map_err(|e| {
+            ToolError::ExecutionError(format!(
+                "Working directory {} is not accessible: {}",
+                self.working_dir.display(),
+                e
+            ))
+        })?;
+
+        let Some(requested) = requested else {
+            return Ok(base);
+        };
+
+        let resolved = base.join(requested).canonicalize().map_err(|e| {
+            ToolError::InvalidParameters(format!("Invalid 'cwd' {}: {}", requested, e))
+        })?;
+        if !resolved.starts_with(&base) {
+            return Err(ToolError::InvalidParameters(format!(
+                "'cwd' {} is outside the working directory {}",
+                requested,
+                base.display()
+            )));
         }
+
+        Ok(resolved)
     }
 
     // Helper function to generate a cache file path
@@ -667,7 +726,11 @@ impl ComputerControllerRouter {
     }
 
     // Implement quick_script tool functionality
-    async fn quick_script(&self, params: Value) -> Result<Vec<Content>, ToolError> {
+    async fn quick_script(
+        &self,
+        params: Value,
+        notifier: mpsc::Sender<JsonRpcMessage>,
+    ) -> Result<Vec<Content>, ToolError> {
         let language = params
             .get("language")
             .and_then(|v| v.as_str())
@@ -683,6 +746,8 @@ impl ComputerControllerRouter {
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
 
+        let cwd = self.resolve_cwd(params.get("cwd").and_then(|v| v.as_str()))?;
+
         // Create a temporary directory for the script
         let script_dir = tempfile::tempdir().map_err(|e| {
             ToolError::ExecutionError(format!("Failed to create temporary directory: {}", e))
@@ -743,39 +808,47 @@ impl ComputerControllerRouter {
         };
 
         // Run the script
-        let output = match language {
+        let mut cmd = match language {
             "powershell" => {
                 // For PowerShell, we need to use -File instead of -Command
-                Command::new("powershell")
-                    .arg("-NoProfile")
+                let mut cmd = Command::new("powershell");
+                cmd.arg("-NoProfile")
                     .arg("-NonInteractive")
                     .arg("-File")
-                    .arg(&command)
-                    .output()
-                    .await
-                    .map_err(|e| {
-                        ToolError::ExecutionError(format!("Failed to run script: {}", e))
-                    })?
+                    .arg(&command);
+                cmd
+            }
+            _ => {
+                let mut cmd = Command::new(shell);
+                cmd.arg(shell_arg).arg(&command);
+                cmd
             }
-            _ => Command::new(shell)
-                .arg(shell_arg)
-                .arg(&command)
-                .output()
-                .await
-                .map_err(|e| ToolError::ExecutionError(format!("Failed to run script: {}", e)))?,
         };
+        cmd.current_dir(&cwd);
+        self.env_policy.apply(&mut cmd);
+
+        // Output is streamed to the notifier line by line while the script runs
+        let (status, output) = run_streaming(cmd, notifier)
+            .await
+            .map_err(|e| ToolError::ExecutionError(format!("Failed to run script: {}", e)))?;
 
-        let output_str = String::from_utf8_lossy(&output.stdout).into_owned();
-        let error_str = String::from_utf8_lossy(&output.stderr).into_owned();
+        let output_str = output.stdout;
+        let error_str = output.stderr;
 
-        let mut result = if output.status.success() {
+        let mut result = if status.success() {
             format!("Script completed successfully.\n\nOutput:\n{}", output_str)
         } else {
             format!(
                 "Script failed with error code {}.\n\nError:\n{}\nOutput:\n{}",
-                output.status, error_str, output_str
+                status, error_str, output_str
             )
         };
+        if output.truncated {
+            result.push_str(&format!(
+                "\n\n[Output truncated after {} bytes]",
+                MAX_SCRIPT_OUTPUT_BYTES
+            ));
+        }
 
         // Save output if requested
         if save_output && !output_str.is_empty() {
@@ -1121,14 +1194,14 @@ impl Router for ComputerControllerRouter {
         &self,
         tool_name: &str,
         arguments: Value,
-        _notifier: mpsc::Sender<JsonRpcMessage>,
+        notifier: mpsc::Sender<JsonRpcMessage>,
     ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>> {
         let this = self.clone();
         let tool_name = tool_name.to_string();
         Box::pin(async move {
             match tool_name.as_str() {
                 "web_scrape" => this.web_scrape(arguments).await,
-                "automation_script" => this.quick_script(arguments).await,
+                "automation_script" => this.quick_script(arguments, notifier).await,
                 "computer_control" => this.computer_control(arguments).await,
                 "cache" => this.cache(arguments).await,
                 "pdf_tool" => this.pdf_tool(arguments).await,
@@ -1208,3 +1281,250 @@ impl Router for ComputerControllerRouter {
         })
     }
 }
+
+/// The directory scripts run in unless a router is given another one: the
+/// `goose_working_dir` setting, or else the current directory
+fn default_working_dir(settings: &GooseConfig) -> PathBuf {
+    settings
+        .get_path("goose_working_dir")
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn test_notifier() -> mpsc::Sender<JsonRpcMessage> {
+        mpsc::channel(1).0
+    }
+
+    #[test]
+    fn test_working_dir_comes_from_the_shared_settings() {
+        let settings = GooseConfig::new(SETTINGS)
+            .with_vars([("GOOSE_WORKING_DIR".to_string(), "/srv/project".to_string())]);
+        assert_eq!(
+            default_working_dir(&settings),
+            PathBuf::from("/srv/project")
+        );
+
+        let unset = GooseConfig::new(SETTINGS).with_vars([]);
+        assert_eq!(
+            default_working_dir(&unset),
+            std::env::current_dir().unwrap()
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_script_runs_in_working_dir() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        let router = ComputerControllerRouter::new().with_working_dir(temp_dir.path().into());
+
+        let result = router
+            .quick_script(
+                json!({"language": "shell", "script": "pwd"}),
+                test_notifier(),
+            )
+            .await
+            .unwrap();
+        let text = &result[0].as_text().unwrap().text;
+        let expected = temp_dir.path().canonicalize().unwrap();
+        assert!(text.contains(&expected.display().to_string()));
+
+        let result = router
+            .quick_script(
+                json!({"language": "shell", "script": "pwd", "cwd": "sub"}),
+                test_notifier(),
+            )
+            .await
+            .unwrap();
+        let text = &result[0].as_text().unwrap().text;
+        assert!(text.contains(&expected.join("sub").display().to_string()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_default_env_policy_drops_secrets() {
+        // Cargo sets this for the test process; it stands in for a secret here, so the test
+        // needs no changes to the shared process environment
+        assert!(std::env::var_os("CARGO_PKG_NAME").is_some());
+        let temp_dir = tempdir().unwrap();
+        let router = ComputerControllerRouter::new().with_working_dir(temp_dir.path().into());
+
+        let result = router
+            .quick_script(
+                json!({"language": "shell", "script": "env"}),
+                test_notifier(),
+            )
+            .await
+            .unwrap();
+        let text = &result[0].as_text().unwrap().text;
+        assert!(!text.contains("CARGO_PKG_NAME"));
+        assert!(text.contains("PATH="));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_env_policy_allow_and_inject() {
+        let temp_dir = tempdir().unwrap();
+        let router = ComputerControllerRouter::new()
+            .with_working_dir(temp_dir.path().into())
+            .with_env_policy(
+                EnvPolicy::default()
+                    .allow("CARGO_PKG_NAME")
+                    .inject("GOOSE_TEST_INJECTED_VAR", "injected"),
+            );
+
+        let result = router
+            .quick_script(
+                json!({"language": "shell", "script": "env"}),
+                test_notifier(),
+            )
+            .await
+            .unwrap();
+        let text = &result[0].as_text().unwrap().text;
+        assert!(text.contains("CARGO_PKG_NAME=goose-mcp"));
+        assert!(text.contains("GOOSE_TEST_INJECTED_VAR=injected"));
+    }
+
+    #[tokio::test]
+    async fn test_cwd_escape_rejected() {
+        let temp_dir = tempdir().unwrap();
+        let base = temp_dir.path().join("base");
+        std::fs::create_dir(&base).unwrap();
+        let router = ComputerControllerRouter::new().with_working_dir(base);
+
+        for escape in ["..", "../", "sub/../.."] {
+            let err = router
+                .quick_script(
+                    json!({"language": "shell", "script": "pwd", "cwd": escape}),
+                    test_notifier(),
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ToolError::InvalidParameters(_)), "{}", escape);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_script_output_streams_before_completion() {
+        let temp_dir = tempdir().unwrap();
+        let router = ComputerControllerRouter::new().with_working_dir(temp_dir.path().into());
+        let (notifier, mut notifications) = mpsc::channel(16);
+
+        let handle = tokio::spawn(async move {
+            router
+                .quick_script(
+                    json!({
+                        "language": "shell",
+                        "script": "echo one; sleep 1; echo two; sleep 1; echo three"
+                    }),
+                    notifier,
+                )
+                .await
+        });
+
+        let first = tokio::time::timeout(std::time::Duration::from_secs(5), notifications.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!handle.is_finished());
+        let JsonRpcMessage::Notification(first) = first else {
+            panic!("expected a notification");
+        };
+        assert_eq!(first.notification.params["data"]["output"], "one\n");
+
+        let result = handle.await.unwrap().unwrap();
+        let text = &result[0].as_text().unwrap().text;
+        assert!(text.contains("one\ntwo\nthree"));
+
+        let mut remaining = 0;
+        while notifications.try_recv().is_ok() {
+            remaining += 1;
+        }
+        assert_eq!(remaining, 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_script_output_is_capped() {
+        let temp_dir = tempdir().unwrap();
+        let router = ComputerControllerRouter::new().with_working_dir(temp_dir.path().into());
+        let (notifier, mut notifications) = mpsc::channel(2048);
+
+        let result = router
+            .quick_script(
+                json!({
+                    "language": "shell",
+                    "script": "head -c 1000000 /dev/zero | tr '\\0' x | fold -w 999"
+                }),
+                notifier,
+            )
+            .await
+            .unwrap();
+        let text = &result[0].as_text().unwrap().text;
+        assert!(text.contains("[Output truncated after"));
+        assert!(text.len() < MAX_SCRIPT_OUTPUT_BYTES + 1000);
+
+        let mut streamed = 0;
+        while let Ok(JsonRpcMessage::Notification(n)) = notifications.try_recv() {
+            streamed += n.notification.params["data"]["output"]
+                .as_str()
+                .unwrap()
+                .len();
+        }
+        assert!(streamed <= MAX_SCRIPT_OUTPUT_BYTES);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_overlong_line_is_cut_at_the_cap() {
+        let temp_dir = tempdir().unwrap();
+        let router = ComputerControllerRouter::new().with_working_dir(temp_dir.path().into());
+
+        let result = router
+            .quick_script(
+                json!({
+                    "language": "shell",
+                    "script": "head -c 1000000 /dev/zero | tr '\\0' x"
+                }),
+                test_notifier(),
+            )
+            .await
+            .unwrap();
+        let text = &result[0].as_text().unwrap().text;
+        assert!(text.contains("[Output truncated after"));
+        assert!(text.len() < MAX_SCRIPT_OUTPUT_BYTES + 1000);
+        assert!(text.contains(&"x".repeat(1000)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_every_line_is_streamed_to_a_slow_receiver() {
+        let temp_dir = tempdir().unwrap();
+        let router = ComputerControllerRouter::new().with_working_dir(temp_dir.path().into());
+        let (notifier, mut notifications) = mpsc::channel(1);
+        let receiver = tokio::spawn(async move {
+            let mut received = 0;
+            while notifications.recv().await.is_some() {
+                received += 1;
+                tokio::task::yield_now().await;
+            }
+            received
+        });
+
+        router
+            .quick_script(
+                json!({"language": "shell", "script": "seq 1 200"}),
+                notifier,
+            )
+            .await
+            .unwrap();
+        assert_eq!(receiver.await.unwrap(), 200);
+    }
+}
@@ -0,0 +1,168 @@
+use rmcp::model::{JsonRpcMessage, JsonRpcNotification, JsonRpcVersion2_0, Notification};
+use rmcp::object;
+use std::io;
+use std::process::{ExitStatus, Stdio};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+/// Upper bound on the script output that is streamed and kept for the final result,
+/// shared between stdout and stderr
+pub const MAX_SCRIPT_OUTPUT_BYTES: usize = 400_000;
+
+/// Output of a finished script, capped at [`MAX_SCRIPT_OUTPUT_BYTES`]
+#[derive(Debug, Default)]
+pub struct ScriptOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether output was dropped because the cap was reached
+    pub truncated: bool,
+    captured_bytes: usize,
+}
+
+impl ScriptOutput {
+    /// Keep a line and send it to `notifier`, waiting for room in the channel. A send only
+    /// fails once the receiver is gone, when nobody is left to miss the line.
+    async fn push_line(
+        &mut self,
+        stream: &str,
+        line: Line,
+        notifier: &mpsc::Sender<JsonRpcMessage>,
+    ) {
+        self.truncated |= line.overflowed;
+        if line.bytes.is_empty() {
+            return;
+        }
+        if self.captured_bytes + line.bytes.len() > MAX_SCRIPT_OUTPUT_BYTES {
+            self.truncated = true;
+            return;
+        }
+        self.captured_bytes += line.bytes.len();
+
+        let line = String::from_utf8_lossy(&line.bytes);
+        let _ = notifier
+            .send(JsonRpcMessage::Notification(JsonRpcNotification {
+                jsonrpc: JsonRpcVersion2_0,
+                notification: Notification {
+                    method: "notifications/message".to_string(),
+                    params: object!({
+                        "level": "info",
+                        "data": {
+                            "type": "automation_script",
+                            "stream": stream,
+                            "output": line.to_string(),
+                        }
+                    }),
+                    extensions: Default::default(),
+                },
+            }))
+            .await;
+
+        if stream == "stderr" {
+            self.stderr.push_str(&line);
+        } else {
+            self.stdout.push_str(&line);
+        }
+    }
+}
+
+/// A line read by [`LineReader`], cut short when it went past the limit it was read with
+struct Line {
+    bytes: Vec<u8>,
+    overflowed: bool,
+}
+
+/// Reads a stream line by line without holding more of a line than the output cap still
+/// allows; the rest of a longer line is read and discarded.
+///
+/// The partial line lives in the reader, so a read cancelled by `select!` loses nothing.
+struct LineReader<R> {
+    reader: R,
+    line: Vec<u8>,
+    overflowed: bool,
+}
+
+impl<R: AsyncBufRead + Unpin> LineReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    /// Read the next line, keeping at most `limit` bytes of it, or `None` at the end of
+    /// the stream
+    async fn next_line(&mut self, limit: usize) -> io::Result<Option<Line>> {
+        loop {
+            let chunk = self.reader.fill_buf().await?;
+            if chunk.is_empty() {
+                if self.line.is_empty() && !self.overflowed {
+                    return Ok(None);
+                }
+                return Ok(Some(self.take_line()));
+            }
+
+            let (end, complete) = match chunk.iter().position(|&byte| byte == b'\n') {
+                Some(newline) => (newline + 1, true),
+                None => (chunk.len(), false),
+            };
+            let kept = end.min(limit.saturating_sub(self.line.len()));
+            self.line.extend_from_slice(&chunk[..kept]);
+            self.overflowed |= kept < end;
+            self.reader.consume(end);
+            if complete {
+                return Ok(Some(self.take_line()));
+            }
+        }
+    }
+
+    fn take_line(&mut self) -> Line {
+        Line {
+            bytes: std::mem::take(&mut self.line),
+            overflowed: std::mem::take(&mut self.overflowed),
+        }
+    }
+}
+
+/// Run `command`, sending each stdout/stderr line to `notifier` as it arrives.
+/// Once the cap is reached the remaining output is still drained, but neither sent nor kept.
+pub async fn run_streaming(
+    mut command: Command,
+    notifier: mpsc::Sender<JsonRpcMessage>,
+) -> std::io::Result<(ExitStatus, ScriptOutput)> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut stdout_reader = LineReader::new(BufReader::new(
+        child.stdout.take().expect("stdout is piped"),
+    ));
+    let mut stderr_reader = LineReader::new(BufReader::new(
+        child.stderr.take().expect("stderr is piped"),
+    ));
+
+    let mut output = ScriptOutput::default();
+    let mut stdout_done = false;
+    let mut stderr_done = false;
+
+    while !(stdout_done && stderr_done) {
+        let limit = MAX_SCRIPT_OUTPUT_BYTES - output.captured_bytes;
+        tokio::select! {
+            line = stdout_reader.next_line(limit), if !stdout_done => match line? {
+                Some(line) => output.push_line("stdout", line, &notifier).await,
+                None => stdout_done = true,
+            },
+            line = stderr_reader.next_line(limit), if !stderr_done => match line? {
+                Some(line) => output.push_line("stderr", line, &notifier).await,
+                None => stderr_done = true,
+            },
+        }
+    }
+
+    let status = child.wait().await?;
+    Ok((status, output))
+}
@@ -0,0 +1,226 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where the effective value of a setting came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    Default,
+    File,
+    Env,
+}
+
+impl ConfigSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            ConfigSource::Default => "default",
+            ConfigSource::File => "file",
+            ConfigSource::Env => "env",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Setting {
+    value: Option<String>,
+    source: ConfigSource,
+}
+
+/// Settings shared by the routers, resolved as defaults < config file < environment.
+///
+/// Only declared keys are resolved. A key is read from the top level of a TOML config
+/// file under its own name, and from the environment variable of the same name in upper
+/// case, e.g. `graphiti_endpoint` from `GRAPHITI_ENDPOINT`, as goose's own config does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GooseConfig {
+    settings: BTreeMap<String, Setting>,
+}
+
+impl GooseConfig {
+    /// Declare the keys to resolve with their defaults; `None` leaves a key unset until a
+    /// later layer sets it
+    pub fn new(defaults: &[(&str, Option<&str>)]) -> Self {
+        let settings = defaults
+            .iter()
+            .map(|(key, value)| {
+                let setting = Setting {
+                    value: value.map(str::to_string),
+                    source: ConfigSource::Default,
+                };
+                (key.to_string(), setting)
+            })
+            .collect();
+        Self { settings }
+    }
+
+    /// Apply the top-level values of a TOML file. Tables and arrays are left to the
+    /// router that owns them; a missing file changes nothing and an invalid one is
+    /// ignored with a warning.
+    pub fn with_file(mut self, path: &Path) -> Self {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return self;
+        };
+        let table = match content.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(e) => {
+                tracing::warn!("Ignoring invalid {}: {}", path.display(), e);
+                return self;
+            }
+        };
+
+        for (key, setting) in self.settings.iter_mut() {
+            let value = match table.get(key) {
+                Some(toml::Value::String(value)) => value.clone(),
+                Some(toml::Value::Integer(value)) => value.to_string(),
+                Some(toml::Value::Float(value)) => value.to_string(),
+                Some(toml::Value::Boolean(value)) => value.to_string(),
+                _ => continue,
+            };
+            *setting = Setting {
+                value: Some(value),
+                source: ConfigSource::File,
+            };
+        }
+        self
+    }
+
+    /// Apply the environment variables of the process
+    pub fn with_env(self) -> Self {
+        self.with_vars(std::env::vars())
+    }
+
+    /// Apply environment variables from `vars`, which take precedence over every other
+    /// layer
+    pub fn with_vars(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let vars: BTreeMap<String, String> = vars.into_iter().collect();
+        for (key, setting) in self.settings.iter_mut() {
+            if let Some(value) = vars.get(&key.to_uppercase()) {
+                *setting = Setting {
+                    value: Some(value.clone()),
+                    source: ConfigSource::Env,
+                };
+            }
+        }
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.settings.get(key)?.value.as_deref()
+    }
+
+    /// The value of `key` parsed as `T`; an invalid value is ignored with a warning
+    pub fn get_parsed<T>(&self, key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.get(key)?;
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                tracing::warn!("Ignoring invalid {} value {:?}: {}", key, value, e);
+                None
+            }
+        }
+    }
+
+    /// Interpret the value of `key` as a boolean flag (`1`, `true`, `yes` or `on`)
+    pub fn get_flag(&self, key: &str) -> bool {
+        self.get(key).is_some_and(|value| {
+            matches!(
+                value.trim().to_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        })
+    }
+
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        self.get(key).map(PathBuf::from)
+    }
+
+    /// Which layer set the effective value of `key`
+    pub fn source(&self, key: &str) -> Option<ConfigSource> {
+        self.settings.get(key).map(|setting| setting.source)
+    }
+
+    /// Every declared key with its effective value and source, one `key = value (source)`
+    /// line each, for debugging
+    pub fn dump(&self) -> String {
+        self.settings
+            .iter()
+            .map(|(key, setting)| match &setting.value {
+                Some(value) => format!("{} = {:?} ({})", key, value, setting.source.as_str()),
+                None => format!("{} is unset", key),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_env_overrides_file_overrides_defaults() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("goose.toml");
+        std::fs::write(
+            &path,
+            "endpoint = \"http://file\"\nretries = 5\n[tables]\nlimit = 2\n",
+        )
+        .unwrap();
+
+        let config = GooseConfig::new(&[
+            ("endpoint", Some("http://default")),
+            ("retries", Some("1")),
+            ("limit", Some("10")),
+            ("verbose", None),
+        ])
+        .with_file(&path)
+        .with_vars(vars(&[("ENDPOINT", "http://env"), ("endpoint", "ignored")]));
+
+        assert_eq!(config.get("endpoint"), Some("http://env"));
+        assert_eq!(config.source("endpoint"), Some(ConfigSource::Env));
+        assert_eq!(config.get_parsed::<u32>("retries"), Some(5));
+        assert_eq!(config.source("retries"), Some(ConfigSource::File));
+        // Nested tables are not top-level settings
+        assert_eq!(config.get_parsed::<u32>("limit"), Some(10));
+        assert_eq!(config.source("limit"), Some(ConfigSource::Default));
+        assert_eq!(config.get("verbose"), None);
+        assert!(!config.get_flag("verbose"));
+        assert_eq!(config.source("undeclared"), None);
+
+        assert_eq!(
+            config.dump(),
+            "endpoint = \"http://env\" (env)\nlimit = \"10\" (default)\n\
+             retries = \"5\" (file)\nverbose is unset"
+        );
+    }
+
+    #[test]
+    fn test_invalid_values_are_ignored() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("goose.toml");
+        std::fs::write(&path, "retries = [").unwrap();
+
+        let config = GooseConfig::new(&[("retries", Some("1")), ("verbose", None)])
+            .with_file(&path)
+            .with_file(&dir.path().join("missing.toml"))
+            .with_vars(vars(&[("RETRIES", "many"), ("VERBOSE", "yes")]));
+        assert_eq!(config.get_parsed::<u32>("retries"), None);
+        assert_eq!(config.source("retries"), Some(ConfigSource::Env));
+        assert!(config.get_flag("verbose"));
+    }
+}
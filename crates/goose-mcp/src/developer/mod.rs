@@ -1,6 +1,8 @@
 mod editor_models;
 mod lang;
 mod shell;
+mod syntax;
+mod transaction;
 
 use anyhow::Result;
 use base64::Engine;
@@ -38,6 +40,8 @@ use rmcp::object;
 
 use self::editor_models::{create_editor_model, EditorModel};
 use self::shell::{expand_path, get_shell_config, is_absolute_path, normalize_line_endings};
+use self::syntax::SearchFilter;
+use self::transaction::PlannedWrite;
 use indoc::indoc;
 use std::process::Stdio;
 use std::sync::{Arc, Mutex};
@@ -95,7 +99,8 @@ pub struct DeveloperRouter {
     tools: Vec<Tool>,
     prompts: Arc<HashMap<String, Prompt>>,
     instructions: String,
-    file_history: Arc<Mutex<HashMap<PathBuf, Vec<String>>>>,
+    /// Content of each file before its recent edits, `None` where an edit created the file
+    file_history: Arc<Mutex<HashMap<PathBuf, Vec<Option<String>>>>>,
     ignore_patterns: Arc<Gitignore>,
     editor_model: Option<EditorModel>,
 }
@@ -235,6 +240,10 @@ impl DeveloperRouter {
                 - `find . -name "*.py" -exec grep -l "pattern" {} \;`
                 - `find . -type f -newer file.txt -exec grep "pattern" {} \;`
                 
+                **Filtering by construct**: set `kind` to `symbol`, `string` or `comment` together with
+                the `pattern` your command searches for to keep only matches in code, string literals
+                or comments. This needs `path:line:` output, e.g. `rg -n` or `grep -Hn`.
+
                 **Important**: Use this tool instead of the shell tool for search commands, as it
                 properly filters results to respect ignored files.
             "#}
@@ -243,7 +252,14 @@ impl DeveloperRouter {
                 "type": "object",
                 "required": ["command"],
                 "properties": {
-                    "command": {"type": "string", "description": "The search command to execute (rg, grep, find, etc.)"}
+                    "command": {"type": "string", "description": "The search command to execute (rg, grep, find, etc.)"},
+                    "kind": {
+                        "type": "string",
+                        "enum": ["symbol", "string", "comment", "any"],
+                        "default": "any",
+                        "description": "Only keep matches inside code (`symbol`), string literals or comments"
+                    },
+                    "pattern": {"type": "string", "description": "The regex searched for by the command; required unless `kind` is `any`"}
                 }
             })
         ).annotate(ToolAnnotations {
@@ -334,6 +350,51 @@ impl DeveloperRouter {
             }),
         );
 
+        let multi_edit_tool = Tool::new(
+            "multi_edit",
+            indoc! {r#"
+                Apply several related file edits as a single transaction: either all of them are
+                applied or none are.
+
+                Each edit has a `path` and either `file_text` (create or overwrite the file) or
+                `old_str` and `new_str` (replace a string that must appear exactly once). Edits are
+                applied in order, so later edits to the same file see the result of earlier ones.
+                Every edit is validated before any file is written, and if a write fails the files
+                already written are restored. Each file can be reverted with `text_editor`'s
+                `undo_edit` afterwards.
+            "#},
+            object!({
+                "type": "object",
+                "required": ["edits"],
+                "properties": {
+                    "edits": {
+                        "type": "array",
+                        "minItems": 1,
+                        "items": {
+                            "type": "object",
+                            "required": ["path"],
+                            "properties": {
+                                "path": {
+                                    "description": "Absolute path to the file",
+                                    "type": "string"
+                                },
+                                "old_str": {"type": "string"},
+                                "new_str": {"type": "string"},
+                                "file_text": {"type": "string"}
+                            }
+                        }
+                    }
+                }
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("Edit multiple files atomically".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(false),
+            open_world_hint: Some(false),
+        });
+
         let list_windows_tool = Tool::new(
             "list_windows",
             indoc! {r#"
@@ -577,6 +638,7 @@ impl DeveloperRouter {
                 glob_tool,
                 grep_tool,
                 text_editor_tool,
+                multi_edit_tool,
                 list_windows_tool,
                 screen_capture_tool,
                 image_processor_tool,
@@ -666,6 +728,9 @@ impl DeveloperRouter {
                     "The command string is required".to_string(),
                 ))?;
 
+        // Only the grep tool passes `kind`, to restrict matches to code, strings or comments
+        let search_filter = SearchFilter::from_params(&params)?;
+
         // Check if command might access ignored files and return early if it does
         let cmd_parts: Vec<&str> = command.split_whitespace().collect();
         for arg in &cmd_parts[1..] {
@@ -792,6 +857,10 @@ impl DeveloperRouter {
             Ok(result) => result.map_err(|e| ToolError::ExecutionError(e.to_string()))?,
             Err(e) => return Err(ToolError::ExecutionError(e.to_string())),
         };
+        let output_str = match search_filter {
+            Some(filter) => filter.apply(&output_str),
+            None => output_str,
+        };
 
         // Check the character count of the output
         const MAX_CHAR_COUNT: usize = 400_000; // 409600 chars = 400KB
@@ -1376,10 +1445,18 @@ impl DeveloperRouter {
         let mut history = self.file_history.lock().unwrap();
         if let Some(contents) = history.get_mut(path) {
             if let Some(previous_content) = contents.pop() {
-                // Write previous content back to file
-                std::fs::write(path, previous_content).map_err(|e| {
-                    ToolError::ExecutionError(format!("Failed to write file: {}", e))
-                })?;
+                match previous_content {
+                    // Write previous content back to file
+                    Some(previous_content) => {
+                        std::fs::write(path, previous_content).map_err(|e| {
+                            ToolError::ExecutionError(format!("Failed to write file: {}", e))
+                        })?
+                    }
+                    // The edit created the file
+                    None => std::fs::remove_file(path).map_err(|e| {
+                        ToolError::ExecutionError(format!("Failed to remove file: {}", e))
+                    })?,
+                }
                 Ok(vec![Content::text("Undid the last edit")])
             } else {
                 Err(ToolError::InvalidParameters(
@@ -1393,13 +1470,142 @@ impl DeveloperRouter {
         }
     }
 
+    async fn multi_edit(&self, params: Value) -> Result<Vec<Content>, ToolError> {
+        let edits = params
+            .get("edits")
+            .and_then(|v| v.as_array())
+            .filter(|edits| !edits.is_empty())
+            .ok_or_else(|| {
+                ToolError::InvalidParameters("'edits' must be a non-empty array".into())
+            })?;
+
+        // Validate every edit and compute the final content of each file before writing anything
+        let mut writes: Vec<PlannedWrite> = Vec::new();
+        let mut applied = Vec::new();
+        for (index, edit) in edits.iter().enumerate() {
+            let invalid = |message: String| {
+                ToolError::InvalidParameters(format!("Edit {}: {}", index + 1, message))
+            };
+
+            let path_str = edit
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| invalid("missing 'path'".into()))?;
+            let path = self
+                .resolve_path(path_str)
+                .map_err(|e| invalid(e.to_string()))?;
+            if self.is_ignored(&path) {
+                return Err(ToolError::ExecutionError(format!(
+                    "Edit {}: access to '{}' is restricted by .gooseignore",
+                    index + 1,
+                    path.display()
+                )));
+            }
+
+            let slot = match writes.iter().position(|w| w.path == path) {
+                Some(slot) => slot,
+                None => {
+                    let original = if path.is_file() {
+                        Some(std::fs::read_to_string(&path).map_err(|e| {
+                            invalid(format!("failed to read '{}': {}", path.display(), e))
+                        })?)
+                    } else if path.exists() {
+                        return Err(invalid(format!("'{}' is not a file", path.display())));
+                    } else {
+                        None
+                    };
+                    writes.push(PlannedWrite {
+                        path: path.clone(),
+                        content: original.clone().unwrap_or_default(),
+                        original,
+                    });
+                    writes.len() - 1
+                }
+            };
+            let planned = &mut writes[slot];
+
+            if let Some(file_text) = edit.get("file_text").and_then(|v| v.as_str()) {
+                let mut content = normalize_line_endings(file_text);
+                if !content.ends_with('\n') {
+                    content.push('\n');
+                }
+                planned.content = content;
+            } else {
+                let (Some(old_str), Some(new_str)) = (
+                    edit.get("old_str").and_then(|v| v.as_str()),
+                    edit.get("new_str").and_then(|v| v.as_str()),
+                ) else {
+                    return Err(invalid(
+                        "either 'file_text' or both 'old_str' and 'new_str' are required".into(),
+                    ));
+                };
+                if planned.original.is_none() && planned.content.is_empty() {
+                    return Err(invalid(format!("'{}' does not exist", path.display())));
+                }
+                match planned.content.matches(old_str).count() {
+                    1 => {
+                        planned.content =
+                            normalize_line_endings(&planned.content.replace(old_str, new_str))
+                    }
+                    0 => {
+                        return Err(invalid(format!(
+                            "'old_str' does not appear in '{}'",
+                            path.display()
+                        )))
+                    }
+                    _ => {
+                        return Err(invalid(format!(
+                            "'old_str' appears multiple times in '{}'",
+                            path.display()
+                        )))
+                    }
+                }
+            }
+            applied.push(format!("{}. {}", index + 1, path.display()));
+        }
+
+        transaction::commit(&writes, transaction::write_atomic).map_err(|(index, e)| {
+            ToolError::ExecutionError(format!(
+                "Failed to write '{}': {}. {} file(s) already written were restored, no edits were applied",
+                writes[index].path.display(),
+                e,
+                index
+            ))
+        })?;
+
+        // Record the pre-transaction content so each file can be undone individually
+        {
+            let mut history = self.file_history.lock().unwrap();
+            for planned in &writes {
+                history
+                    .entry(planned.path.clone())
+                    .or_default()
+                    .push(planned.original.clone());
+            }
+        }
+
+        let summary = format!(
+            "Applied {} edit(s) to {} file(s):\n{}",
+            applied.len(),
+            writes.len(),
+            applied.join("\n")
+        );
+        Ok(vec![
+            Content::text(summary.clone()).with_audience(vec![Role::Assistant]),
+            Content::text(summary)
+                .with_audience(vec![Role::User])
+                .with_priority(0.2),
+        ])
+    }
+
     fn save_file_history(&self, path: &PathBuf) -> Result<(), ToolError> {
         let mut history = self.file_history.lock().unwrap();
         let content = if path.exists() {
-            std::fs::read_to_string(path)
-                .map_err(|e| ToolError::ExecutionError(format!("Failed to read file: {}", e)))?
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| ToolError::ExecutionError(format!("Failed to read file: {}", e)))?;
+            Some(content)
         } else {
-            String::new()
+            None
         };
         history.entry(path.clone()).or_default().push(content);
         Ok(())
@@ -1647,6 +1853,7 @@ impl Router for DeveloperRouter {
                 "glob" => this.glob(arguments).await,
                 "grep" => this.bash(arguments, notifier).await,
                 "text_editor" => this.text_editor(arguments).await,
+                "multi_edit" => this.multi_edit(arguments).await,
                 "list_windows" => this.list_windows(arguments).await,
                 "screen_capture" => this.screen_capture(arguments).await,
                 "image_processor" => this.image_processor(arguments).await,
@@ -3353,4 +3560,125 @@ mod tests {
         assert_eq!(result.0, "");
         assert_eq!(result.1, "");
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_multi_edit_applies_all_edits() {
+        let router = get_router().await;
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_current_dir(&temp_dir).unwrap();
+        let existing = temp_dir.path().join("existing.txt");
+        let created = temp_dir.path().join("created.txt");
+        std::fs::write(&existing, "fn old_name() {}\nold_name();\n").unwrap();
+
+        router
+            .call_tool(
+                "multi_edit",
+                json!({
+                    "edits": [
+                        {"path": existing.to_str().unwrap(), "old_str": "fn old_name", "new_str": "fn new_name"},
+                        {"path": existing.to_str().unwrap(), "old_str": "old_name();", "new_str": "new_name();"},
+                        {"path": created.to_str().unwrap(), "file_text": "new file"}
+                    ]
+                }),
+                dummy_sender(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&existing).unwrap(),
+            "fn new_name() {}\nnew_name();\n"
+        );
+        assert_eq!(std::fs::read_to_string(&created).unwrap(), "new file\n");
+
+        // Each file can still be undone on its own
+        router
+            .call_tool(
+                "text_editor",
+                json!({"command": "undo_edit", "path": existing.to_str().unwrap()}),
+                dummy_sender(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&existing).unwrap(),
+            "fn old_name() {}\nold_name();\n"
+        );
+
+        // Undoing the creation of a file removes it
+        router
+            .call_tool(
+                "text_editor",
+                json!({"command": "undo_edit", "path": created.to_str().unwrap()}),
+                dummy_sender(),
+            )
+            .await
+            .unwrap();
+        assert!(!created.exists());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_multi_edit_failing_edit_applies_nothing() {
+        let router = get_router().await;
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_current_dir(&temp_dir).unwrap();
+        let first = temp_dir.path().join("first.txt");
+        let second = temp_dir.path().join("second.txt");
+        let third = temp_dir.path().join("third.txt");
+        std::fs::write(&first, "alpha\n").unwrap();
+        std::fs::write(&third, "gamma\n").unwrap();
+
+        let err = router
+            .call_tool(
+                "multi_edit",
+                json!({
+                    "edits": [
+                        {"path": first.to_str().unwrap(), "old_str": "alpha", "new_str": "ALPHA"},
+                        {"path": second.to_str().unwrap(), "file_text": "beta"},
+                        {"path": third.to_str().unwrap(), "old_str": "missing", "new_str": "x"}
+                    ]
+                }),
+                dummy_sender(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, ToolError::InvalidParameters(ref msg) if msg.starts_with("Edit 3:")));
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "alpha\n");
+        assert!(!second.exists());
+        assert_eq!(std::fs::read_to_string(&third).unwrap(), "gamma\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    #[serial]
+    async fn test_grep_symbol_filter() {
+        let router = get_router().await;
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_current_dir(&temp_dir).unwrap();
+        std::fs::write(
+            temp_dir.path().join("main.rs"),
+            "// TODO: call handler\nfn main() { handler(); }\n",
+        )
+        .unwrap();
+
+        let result = router
+            .call_tool(
+                "grep",
+                json!({
+                    "command": "grep -Hn handler main.rs",
+                    "kind": "symbol",
+                    "pattern": "handler"
+                }),
+                dummy_sender(),
+            )
+            .await
+            .unwrap();
+
+        let text = result[0].as_text().unwrap();
+        assert!(text.text.contains("main.rs:2:fn main() { handler(); }"));
+        assert!(!text.text.contains("TODO"));
+    }
 }
@@ -0,0 +1,434 @@
+use lazy_static::lazy_static;
+use mcp_core::handler::ToolError;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+lazy_static! {
+    /// A `path:line:` prefix, as printed by `rg -n`, `grep -Hn` and friends
+    static ref OUTPUT_LINE: Regex = Regex::new(r"^(.+?):(\d+):").unwrap();
+}
+
+/// Which language construct a search match must fall in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Code outside of comments and string literals
+    Symbol,
+    String,
+    Comment,
+    Any,
+}
+
+impl MatchKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "symbol" => Some(Self::Symbol),
+            "string" => Some(Self::String),
+            "comment" => Some(Self::Comment),
+            "any" => Some(Self::Any),
+            _ => None,
+        }
+    }
+}
+
+/// Lexical rules needed to tell code, strings and comments apart
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comments: &'static [(&'static str, &'static str)],
+    /// String delimiters, longest first; `'` strings never span lines
+    strings: &'static [&'static str],
+    /// Whether `'` starts a char literal such as `'"'`, or a lifetime such as `'a` when no
+    /// quote closes it right away
+    char_literals: bool,
+}
+
+const RUST: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &["\""],
+    char_literals: true,
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &["\"", "'", "`"],
+    char_literals: false,
+};
+
+const GO: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &["\"", "`"],
+    char_literals: false,
+};
+
+const C_LIKE: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &["\"", "'"],
+    char_literals: false,
+};
+
+const PYTHON: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    strings: &["\"\"\"", "'''", "\"", "'"],
+    char_literals: false,
+};
+
+const HASH: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    strings: &["\"", "'"],
+    char_literals: false,
+};
+
+const DASH_DASH: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("/*", "*/"), ("{-", "-}")],
+    strings: &["'", "\""],
+    char_literals: false,
+};
+
+/// Used for unknown file types: the most common comment and string forms
+const GENERIC: Syntax = Syntax {
+    line_comments: &["//", "#"],
+    block_comments: &[("/*", "*/")],
+    strings: &["\"", "'"],
+    char_literals: false,
+};
+
+fn syntax_for(path: &Path) -> &'static Syntax {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("rs") => &RUST,
+        Some("js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs") => &JAVASCRIPT,
+        Some("go") => &GO,
+        Some(
+            "c" | "h" | "cpp" | "cc" | "cxx" | "hpp" | "java" | "cs" | "swift" | "kt" | "kts"
+            | "scala" | "php" | "css",
+        ) => &C_LIKE,
+        Some("py") => &PYTHON,
+        Some("rb" | "sh" | "bash" | "zsh" | "toml" | "yaml" | "yml" | "pl" | "r") => &HASH,
+        Some("sql" | "hs" | "lua") => &DASH_DASH,
+        _ => &GENERIC,
+    }
+}
+
+/// Byte ranges of the comments and string literals in `source`
+fn classify(source: &str, syntax: &Syntax) -> Vec<(Range<usize>, MatchKind)> {
+    let mut regions = Vec::new();
+    let mut i = 0;
+
+    while i < source.len() {
+        let rest = &source[i..];
+
+        if syntax.line_comments.iter().any(|p| rest.starts_with(p)) {
+            let end = rest.find('\n').map_or(source.len(), |n| i + n);
+            regions.push((i..end, MatchKind::Comment));
+            i = end;
+            continue;
+        }
+
+        if let Some((open, close)) = syntax
+            .block_comments
+            .iter()
+            .find(|(open, _)| rest.starts_with(open))
+        {
+            let end = rest[open.len()..]
+                .find(close)
+                .map_or(source.len(), |n| i + open.len() + n + close.len());
+            regions.push((i..end, MatchKind::Comment));
+            i = end;
+            continue;
+        }
+
+        if syntax.char_literals && rest.starts_with('\'') {
+            match char_literal_len(rest) {
+                Some(len) => {
+                    regions.push((i..i + len, MatchKind::String));
+                    i += len;
+                }
+                None => i += 1,
+            }
+            continue;
+        }
+
+        if let Some(delim) = syntax.strings.iter().find(|d| rest.starts_with(*d)) {
+            let end = string_end(source, i + delim.len(), delim);
+            regions.push((i..end, MatchKind::String));
+            i = end;
+            continue;
+        }
+
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+
+    regions
+}
+
+/// Length of the char literal `rest` starts with, or `None` when its `'` starts a lifetime
+/// or loop label instead
+fn char_literal_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    let (_, first) = chars.next()?;
+    if first == '\\' {
+        // Escapes such as '\n', '\'' and '\u{1F600}' end at the next quote on the line
+        chars.next()?;
+        return chars
+            .take_while(|(_, c)| *c != '\n')
+            .find(|(_, c)| *c == '\'')
+            .map(|(j, _)| j + 1);
+    }
+    let (j, second) = chars.next()?;
+    (second == '\'').then_some(j + 1)
+}
+
+/// Offset just past the closing `delim` of a string whose body starts at `start`
+fn string_end(source: &str, start: usize, delim: &str) -> usize {
+    let mut j = start;
+    while j < source.len() {
+        let rest = &source[j..];
+        if rest.starts_with('\\') {
+            j += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+        } else if rest.starts_with(delim) {
+            return j + delim.len();
+        } else if delim == "'" && rest.starts_with('\n') {
+            return j;
+        } else {
+            j += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    source.len()
+}
+
+/// A source file with its comment and string regions
+struct ClassifiedFile {
+    source: String,
+    line_starts: Vec<usize>,
+    regions: Vec<(Range<usize>, MatchKind)>,
+}
+
+impl ClassifiedFile {
+    fn load(path: &Path) -> Option<Self> {
+        let source = std::fs::read_to_string(path).ok()?;
+        let regions = classify(&source, syntax_for(path));
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Some(Self {
+            source,
+            line_starts,
+            regions,
+        })
+    }
+
+    /// Byte range of the 1-based `line`, without its newline
+    fn line(&self, line: usize) -> Option<Range<usize>> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source.len(), |next| next - 1);
+        Some(start..end)
+    }
+
+    fn kind_at(&self, offset: usize) -> MatchKind {
+        self.regions
+            .iter()
+            .find(|(range, _)| range.contains(&offset))
+            .map_or(MatchKind::Symbol, |(_, kind)| *kind)
+    }
+}
+
+/// Restricts `path:line:text` search output to lines where `pattern` matches inside a
+/// particular kind of construct
+pub struct SearchFilter {
+    kind: MatchKind,
+    pattern: Regex,
+}
+
+impl SearchFilter {
+    /// Build a filter from the `kind` and `pattern` tool arguments; `None` when no
+    /// filtering is needed (`kind` absent or `any`)
+    pub fn from_params(params: &Value) -> Result<Option<Self>, ToolError> {
+        let kind = match params.get("kind").and_then(|v| v.as_str()) {
+            None => return Ok(None),
+            Some(kind) => MatchKind::parse(kind).ok_or_else(|| {
+                ToolError::InvalidParameters(format!(
+                    "Invalid 'kind' {}, expected one of: symbol, string, comment, any",
+                    kind
+                ))
+            })?,
+        };
+        if kind == MatchKind::Any {
+            return Ok(None);
+        }
+
+        let pattern = params
+            .get("pattern")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                ToolError::InvalidParameters(
+                    "'pattern' is required when 'kind' is not 'any'".into(),
+                )
+            })?;
+        let pattern = Regex::new(pattern)
+            .map_err(|e| ToolError::InvalidParameters(format!("Invalid 'pattern': {}", e)))?;
+
+        Ok(Some(Self { kind, pattern }))
+    }
+
+    /// Keep the lines whose match falls in the requested construct. Lines that are not
+    /// `path:line:` results, or whose file cannot be read, are passed through unchanged.
+    pub fn apply(&self, output: &str) -> String {
+        let mut files: HashMap<String, Option<ClassifiedFile>> = HashMap::new();
+        output
+            .lines()
+            .filter(|line| self.keep(line, &mut files))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn keep(&self, line: &str, files: &mut HashMap<String, Option<ClassifiedFile>>) -> bool {
+        let Some(captures) = OUTPUT_LINE.captures(line) else {
+            return true;
+        };
+        let path = &captures[1];
+        let Ok(line_number) = captures[2].parse::<usize>() else {
+            return true;
+        };
+
+        let file = files
+            .entry(path.to_string())
+            .or_insert_with(|| ClassifiedFile::load(Path::new(path)));
+        let Some(file) = file else {
+            return true;
+        };
+        let Some(range) = file.line(line_number) else {
+            return true;
+        };
+
+        self.pattern
+            .find_iter(&file.source[range.clone()])
+            .any(|m| file.kind_at(range.start + m.start()) == self.kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn kinds(source: &str, syntax: &Syntax, needle: &str) -> Vec<MatchKind> {
+        let regions = classify(source, syntax);
+        source
+            .match_indices(needle)
+            .map(|(offset, _)| {
+                regions
+                    .iter()
+                    .find(|(range, _)| range.contains(&offset))
+                    .map_or(MatchKind::Symbol, |(_, kind)| *kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classify_rust() {
+        let source = "// needle\nlet needle = \"needle \\\" needle\";\n/* multi\nneedle */ needle";
+        assert_eq!(
+            kinds(source, &RUST, "needle"),
+            vec![
+                MatchKind::Comment,
+                MatchKind::Symbol,
+                MatchKind::String,
+                MatchKind::String,
+                MatchKind::Comment,
+                MatchKind::Symbol,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_classify_rust_char_literals() {
+        let source = "let quote = '\"'; needle('\\'', '\\u{1F600}'); // needle";
+        assert_eq!(
+            kinds(source, &RUST, "needle"),
+            vec![MatchKind::Symbol, MatchKind::Comment]
+        );
+        assert_eq!(kinds(source, &RUST, "1F600"), vec![MatchKind::String]);
+    }
+
+    #[test]
+    fn test_classify_rust_lifetimes() {
+        let source = "fn f<'a>(s: &'a str) -> &'a str { needle(\"needle\") } // needle\n'outer: loop { needle }";
+        assert_eq!(
+            kinds(source, &RUST, "needle"),
+            vec![
+                MatchKind::Symbol,
+                MatchKind::String,
+                MatchKind::Comment,
+                MatchKind::Symbol,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_classify_python() {
+        let source = "needle = '''\nneedle\n'''  # needle\nprint(\"needle\")";
+        assert_eq!(
+            kinds(source, &PYTHON, "needle"),
+            vec![
+                MatchKind::Symbol,
+                MatchKind::String,
+                MatchKind::Comment,
+                MatchKind::String,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_symbol_filter_excludes_comment_hit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lib.rs");
+        std::fs::write(&path, "// call handler here\nfn handler() {}\n").unwrap();
+        let output = format!(
+            "{path}:1:// call handler here\n{path}:2:fn handler() {{}}",
+            path = path.display()
+        );
+
+        let symbol = SearchFilter::from_params(&json!({"kind": "symbol", "pattern": "handler"}))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            symbol.apply(&output),
+            format!("{}:2:fn handler() {{}}", path.display())
+        );
+
+        let comment = SearchFilter::from_params(&json!({"kind": "comment", "pattern": "handler"}))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            comment.apply(&output),
+            format!("{}:1:// call handler here", path.display())
+        );
+    }
+
+    #[test]
+    fn test_from_params() {
+        assert!(SearchFilter::from_params(&json!({})).unwrap().is_none());
+        assert!(SearchFilter::from_params(&json!({"kind": "any"}))
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            SearchFilter::from_params(&json!({"kind": "symbol"})),
+            Err(ToolError::InvalidParameters(_))
+        ));
+        assert!(matches!(
+            SearchFilter::from_params(&json!({"kind": "keyword", "pattern": "x"})),
+            Err(ToolError::InvalidParameters(_))
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The final content of one file touched by a multi-file edit, with what to restore on rollback
+#[derive(Debug, Clone)]
+pub struct PlannedWrite {
+    pub path: PathBuf,
+    /// Content before the edit, or `None` if the edit creates the file
+    pub original: Option<String>,
+    pub content: String,
+}
+
+/// Write `content` through a temporary file in the same directory followed by a rename,
+/// so readers never see a partially written file. Existing permissions are kept.
+pub fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(content.as_bytes())?;
+    tmp.as_file().sync_all()?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(tmp.path(), metadata.permissions())?;
+    }
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Apply `writes` in order using `write`. If one fails, every file written before it is
+/// restored (or removed, if it was created) and the index of the failing write is returned.
+pub fn commit<F>(writes: &[PlannedWrite], mut write: F) -> Result<(), (usize, io::Error)>
+where
+    F: FnMut(&Path, &str) -> io::Result<()>,
+{
+    for (index, planned) in writes.iter().enumerate() {
+        if let Err(e) = write(&planned.path, &planned.content) {
+            rollback(&writes[..index]);
+            return Err((index, e));
+        }
+    }
+    Ok(())
+}
+
+fn rollback(applied: &[PlannedWrite]) {
+    for planned in applied.iter().rev() {
+        let restored = match &planned.original {
+            Some(original) => write_atomic(&planned.path, original),
+            None => fs::remove_file(&planned.path),
+        };
+        if let Err(e) = restored {
+            tracing::error!("Failed to roll back {}: {}", planned.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_atomic_replaces_content() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, "new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_failed_write_rolls_back_earlier_writes() {
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("first.txt");
+        let second = dir.path().join("second.txt");
+        let third = dir.path().join("third.txt");
+        fs::write(&first, "first original").unwrap();
+        fs::write(&third, "third original").unwrap();
+
+        let writes = vec![
+            PlannedWrite {
+                path: first.clone(),
+                original: Some("first original".to_string()),
+                content: "first edited".to_string(),
+            },
+            PlannedWrite {
+                path: second.clone(),
+                original: None,
+                content: "second created".to_string(),
+            },
+            PlannedWrite {
+                path: third.clone(),
+                original: Some("third original".to_string()),
+                content: "third edited".to_string(),
+            },
+        ];
+
+        let result = commit(&writes, |path, content| {
+            if path == third {
+                return Err(io::Error::other("disk full"));
+            }
+            write_atomic(path, content)
+        });
+
+        let (index, error) = result.unwrap_err();
+        assert_eq!(index, 2);
+        assert_eq!(error.to_string(), "disk full");
+        assert_eq!(fs::read_to_string(&first).unwrap(), "first original");
+        assert!(!second.exists());
+        assert_eq!(fs::read_to_string(&third).unwrap(), "third original");
+    }
+}
@@ -0,0 +1,375 @@
+use anyhow::{anyhow, bail, Context, Error};
+use async_trait::async_trait;
+use google_apis_common as common;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Default size of each range request, overridable with `GOOGLE_DRIVE_DOWNLOAD_CHUNK_SIZE`
+pub const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Something that can serve byte ranges of a single remote file
+#[async_trait]
+pub trait RangeSource: Send + Sync {
+    /// Fetch bytes `start..=end`, inclusive like an HTTP `Range` header
+    async fn fetch_range(&self, start: u64, end: u64) -> Result<Vec<u8>, Error>;
+}
+
+/// Serves ranges of a Drive file with `alt=media` requests
+pub struct DriveRangeSource<'a, C> {
+    pub client: &'a common::Client<C>,
+    pub auth: &'a dyn common::GetToken,
+    pub scope: &'a str,
+    pub file_id: &'a str,
+}
+
+#[async_trait]
+impl<C> RangeSource for DriveRangeSource<'_, C>
+where
+    C: common::Connector,
+{
+    async fn fetch_range(&self, start: u64, end: u64) -> Result<Vec<u8>, Error> {
+        use hyper::header::{AUTHORIZATION, RANGE};
+
+        let token = self
+            .auth
+            .get_token(&[self.scope])
+            .await
+            .map_err(|e| anyhow!("Failed to get token: {}", e))?;
+
+        let mut request = hyper::Request::builder()
+            .method(hyper::Method::GET)
+            .uri(format!(
+                "https://www.googleapis.com/drive/v3/files/{}?alt=media&supportsAllDrives=true",
+                self.file_id
+            ))
+            .header(RANGE, format!("bytes={}-{}", start, end));
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        let response = self
+            .client
+            .request(request.body(common::to_body::<String>(None))?)
+            .await?;
+        let (parts, body) = response.into_parts();
+        let bytes = common::to_bytes(common::Body::new(body))
+            .await
+            .ok_or_else(|| anyhow!("Failed to read the response body"))?;
+        if !parts.status.is_success() {
+            bail!(
+                "Range request failed with {}: {}",
+                parts.status,
+                String::from_utf8_lossy(&bytes)
+            );
+        }
+        Ok(bytes.to_vec())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub chunk_size: u64,
+    /// Attempts per chunk after the first failure
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each further retry
+    pub retry_delay: Duration,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: std::env::var("GOOGLE_DRIVE_DOWNLOAD_CHUNK_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|size| *size > 0)
+                .unwrap_or(DEFAULT_CHUNK_SIZE),
+            max_retries: 3,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Progress of an interrupted download, stored next to the partial file
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Progress {
+    file_id: String,
+    total_size: u64,
+    /// Bytes of the partial file known to be complete
+    offset: u64,
+}
+
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+fn progress_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part.json");
+    dest.with_file_name(name)
+}
+
+/// Offset to resume `file_id` from, or 0 if there is no matching progress
+fn load_progress(dest: &Path, file_id: &str, total_size: u64) -> u64 {
+    fs::read_to_string(progress_path(dest))
+        .ok()
+        .and_then(|json| serde_json::from_str::<Progress>(&json).ok())
+        .filter(|p| p.file_id == file_id && p.total_size == total_size && p.offset <= total_size)
+        .map_or(0, |p| p.offset)
+}
+
+/// Download `total_size` bytes of `file_id` to `dest` in chunks, retrying each chunk.
+///
+/// Data goes to `<dest>.part` and the completed offset to `<dest>.part.json` after every
+/// chunk, so calling this again after a failure resumes from the last completed chunk.
+/// Returns the offset the download resumed from.
+pub async fn download_resumable(
+    source: &dyn RangeSource,
+    file_id: &str,
+    total_size: u64,
+    dest: &Path,
+    options: &DownloadOptions,
+) -> Result<u64, Error> {
+    let partial = partial_path(dest);
+    let progress = progress_path(dest);
+    let resumed_from = load_progress(dest, file_id, total_size);
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&partial)
+        .with_context(|| format!("Failed to open {}", partial.display()))?;
+    // Drop anything written after the last recorded chunk
+    file.set_len(resumed_from)?;
+    file.seek(SeekFrom::Start(resumed_from))?;
+
+    let mut offset = resumed_from;
+    while offset < total_size {
+        let end = (offset + options.chunk_size).min(total_size) - 1;
+        let chunk = fetch_with_retry(source, offset, end, options).await?;
+        file.write_all(&chunk)?;
+        file.sync_data()?;
+        offset = end + 1;
+        fs::write(
+            &progress,
+            serde_json::to_string(&Progress {
+                file_id: file_id.to_string(),
+                total_size,
+                offset,
+            })?,
+        )?;
+    }
+
+    drop(file);
+    fs::rename(&partial, dest)
+        .with_context(|| format!("Failed to move download to {}", dest.display()))?;
+    let _ = fs::remove_file(&progress);
+    Ok(resumed_from)
+}
+
+/// Fetch bytes `start..=end`, retrying failed requests and responses that came back short
+async fn fetch_with_retry(
+    source: &dyn RangeSource,
+    start: u64,
+    end: u64,
+    options: &DownloadOptions,
+) -> Result<Vec<u8>, Error> {
+    let expected = end - start + 1;
+    let mut attempt = 0;
+    loop {
+        let fetched = source.fetch_range(start, end).await.and_then(|chunk| {
+            if chunk.len() as u64 != expected {
+                bail!(
+                    "Expected {} bytes at offset {}, got {}",
+                    expected,
+                    start,
+                    chunk.len()
+                );
+            }
+            Ok(chunk)
+        });
+        match fetched {
+            Ok(chunk) => return Ok(chunk),
+            Err(e) if attempt < options.max_retries => {
+                tracing::warn!(
+                    "Chunk {}-{} failed (attempt {}): {}",
+                    start,
+                    end,
+                    attempt + 1,
+                    e
+                );
+                tokio::time::sleep(options.retry_delay * 2u32.pow(attempt)).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.context(format!("Failed to download bytes {}-{}", start, end))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+
+    /// Serves `data`, failing every request at or after `fail_at` until `recover` is called
+    struct MockServer {
+        data: Vec<u8>,
+        fail_at: u64,
+        failing: AtomicBool,
+        requests: Mutex<Vec<(u64, u64)>>,
+    }
+
+    impl MockServer {
+        fn new(data: Vec<u8>, fail_at: u64) -> Self {
+            Self {
+                data,
+                fail_at,
+                failing: AtomicBool::new(true),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn recover(&self) {
+            self.failing.store(false, Ordering::SeqCst);
+            self.requests.lock().unwrap().clear();
+        }
+    }
+
+    #[async_trait]
+    impl RangeSource for MockServer {
+        async fn fetch_range(&self, start: u64, end: u64) -> Result<Vec<u8>, Error> {
+            self.requests.lock().unwrap().push((start, end));
+            if self.failing.load(Ordering::SeqCst) && start >= self.fail_at {
+                bail!("connection reset");
+            }
+            Ok(self.data[start as usize..=end as usize].to_vec())
+        }
+    }
+
+    /// Serves `data`, cutting the first response short
+    struct ShortOnceServer {
+        data: Vec<u8>,
+        cut: AtomicBool,
+    }
+
+    #[async_trait]
+    impl RangeSource for ShortOnceServer {
+        async fn fetch_range(&self, start: u64, end: u64) -> Result<Vec<u8>, Error> {
+            let mut chunk = self.data[start as usize..=end as usize].to_vec();
+            if !self.cut.swap(true, Ordering::SeqCst) {
+                chunk.truncate(chunk.len() / 2);
+            }
+            Ok(chunk)
+        }
+    }
+
+    fn options(chunk_size: u64) -> DownloadOptions {
+        DownloadOptions {
+            chunk_size,
+            max_retries: 1,
+            retry_delay: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_in_chunks() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("file.bin");
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let server = MockServer::new(data.clone(), u64::MAX);
+
+        let resumed_from = download_resumable(&server, "id", 1000, &dest, &options(300))
+            .await
+            .unwrap();
+
+        assert_eq!(resumed_from, 0);
+        assert_eq!(fs::read(&dest).unwrap(), data);
+        assert_eq!(
+            *server.requests.lock().unwrap(),
+            vec![(0, 299), (300, 599), (600, 899), (900, 999)]
+        );
+        assert!(!partial_path(&dest).exists());
+        assert!(!progress_path(&dest).exists());
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_failure() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("file.bin");
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let server = MockServer::new(data.clone(), 600);
+
+        let err = download_resumable(&server, "id", 1000, &dest, &options(300))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("600-899"));
+        // The failing chunk was retried once before giving up
+        assert_eq!(
+            *server.requests.lock().unwrap(),
+            vec![(0, 299), (300, 599), (600, 899), (600, 899)]
+        );
+        assert!(!dest.exists());
+        assert_eq!(load_progress(&dest, "id", 1000), 600);
+
+        server.recover();
+        let resumed_from = download_resumable(&server, "id", 1000, &dest, &options(300))
+            .await
+            .unwrap();
+
+        assert_eq!(resumed_from, 600);
+        assert_eq!(
+            *server.requests.lock().unwrap(),
+            vec![(600, 899), (900, 999)]
+        );
+        assert_eq!(fs::read(&dest).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_short_chunk_is_retried() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("file.bin");
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let server = ShortOnceServer {
+            data: data.clone(),
+            cut: AtomicBool::new(false),
+        };
+
+        download_resumable(&server, "id", 1000, &dest, &options(300))
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_progress_for_other_file_is_ignored() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("file.bin");
+        let data = vec![7u8; 100];
+        fs::write(partial_path(&dest), [1u8; 50]).unwrap();
+        fs::write(
+            progress_path(&dest),
+            serde_json::to_string(&Progress {
+                file_id: "other".to_string(),
+                total_size: 100,
+                offset: 50,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let server = MockServer::new(data.clone(), u64::MAX);
+        let resumed_from = download_resumable(&server, "id", 100, &dest, &options(64))
+            .await
+            .unwrap();
+
+        assert_eq!(resumed_from, 0);
+        assert_eq!(fs::read(&dest).unwrap(), data);
+    }
+}
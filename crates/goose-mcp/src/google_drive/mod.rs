@@ -1,3 +1,4 @@
+mod download;
 mod google_labels;
 mod oauth_pkce;
 pub mod storage;
@@ -25,6 +26,7 @@ use std::{env, fs, future::Future, path::Path, pin::Pin, sync::Arc};
 use storage::CredentialsManager;
 use tokio::sync::mpsc;
 
+use download::{download_resumable, DownloadOptions, DriveRangeSource};
 use google_docs1::{self, Docs};
 use google_drive3::common::ReadSeek;
 use google_drive3::{
@@ -75,6 +77,54 @@ lazy_static! {
             .unwrap();
 }
 
+/// Pick the corpus for a file search. Without an explicit `corpora`, search a single shared
+/// drive when `driveId` is given and otherwise My Drive plus every shared drive.
+fn resolve_corpus(params: &Value) -> Result<(&str, Option<&str>), ToolError> {
+    let drive_id = params.get("driveId").and_then(|q| q.as_str());
+    let corpus = match params.get("corpora").and_then(|c| c.as_str()) {
+        Some(c) if ["user", "drive", "allDrives"].contains(&c) => c,
+        Some(c) => {
+            return Err(ToolError::InvalidParameters(format!(
+                "corpora must be either 'user', 'drive', or 'allDrives', got {}",
+                c
+            )))
+        }
+        None if drive_id.is_some() => "drive",
+        None => "allDrives",
+    };
+
+    // The drive_id param is only valid, and is required, for the "drive" corpus
+    match (corpus, drive_id) {
+        ("drive", None) => Err(ToolError::InvalidParameters(
+            "corpora 'drive' requires a driveId".to_string(),
+        )),
+        ("drive", Some(d)) => Ok((corpus, Some(d))),
+        (_, Some(_)) => Err(ToolError::InvalidParameters(format!(
+            "driveId can only be used with corpora 'drive', got {}",
+            corpus
+        ))),
+        (_, None) => Ok((corpus, None)),
+    }
+}
+
+/// One line of search output for a file, noting the shared drive it lives on
+fn format_file(f: File, include_labels: bool) -> String {
+    format!(
+        "{} ({}) (uri: {}){}{}",
+        f.name.unwrap_or_default(),
+        f.mime_type.unwrap_or_default(),
+        f.id.unwrap_or_default(),
+        f.drive_id
+            .map(|d| format!(" (shared drive: {})", d))
+            .unwrap_or_default(),
+        if include_labels {
+            format!(" (labels: {:?})", f.label_info.unwrap_or_default())
+        } else {
+            "".to_string()
+        }
+    )
+}
+
 fn extract_google_drive_id(url: &str) -> Option<&str> {
     GOOGLE_DRIVE_ID_REGEX
         .captures(url)
@@ -242,11 +292,11 @@ impl GoogleDriveRouter {
                 },
                 "driveId": {
                     "type": "string",
-                    "description": "ID of a shared drive to constrain the search to when using the corpus 'drive'.",
+                    "description": "ID of a shared drive to constrain the search to, implies the corpus 'drive'.",
                 },
                 "corpora": {
                     "type": "string",
-                    "description": "Which corpus to search, either 'user' (default), 'drive' (requires a driveID) or 'allDrives'",
+                    "description": "Which corpus to search, either 'user', 'drive' (requires a driveId) or 'allDrives'. Defaults to 'drive' when a driveId is given, otherwise 'allDrives' (My Drive and all shared drives).",
                 },
                 "pageSize": {
                     "type": "number",
@@ -306,6 +356,40 @@ impl GoogleDriveRouter {
             open_world_hint: Some(false)
         });
 
+        let download_file_tool = Tool::new(
+            "download_file".to_string(),
+            indoc! {r#"
+                Download a binary file stored in Google Drive (not a Google Doc, Sheet or Slides file) to a
+                local path. Large files are fetched in chunks with retries, and if a download is interrupted,
+                calling this again with the same path resumes from the last completed chunk.
+            "#}
+                .to_string(),
+            object!({
+              "type": "object",
+              "properties": {
+                  "uri": {
+                      "type": "string",
+                      "description": "google drive uri of the file to download",
+                  },
+                  "path": {
+                      "type": "string",
+                      "description": "Absolute local path to save the file to",
+                  },
+                  "chunkSize": {
+                      "type": "number",
+                      "description": "Bytes to fetch per request, defaults to GOOGLE_DRIVE_DOWNLOAD_CHUNK_SIZE or 8 MiB",
+                  }
+              },
+              "required": ["uri", "path"],
+            })
+        ).annotate(ToolAnnotations {
+            title: Some("Download GDrive file".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
         let create_file_tool = Tool::new(
             "create_file".to_string(),
             indoc! {r#"
@@ -796,23 +880,26 @@ impl GoogleDriveRouter {
             The Google Drive MCP server provides tools for interacting with Google Drive files, Google Sheets, and Google Docs:
             1. search - List or search for files or labels in your Google Drive
             2. read - Read file contents directly using a uri in the `gdrive:///uri` format
-            3. move_file - Move a file to a new location in Google Drive
-            4. list_drives - List the shared drives to which you have access
-            5. get_permissions - List the permissions of a file or folder
-            6. sharing - Share a file or folder with others
-            7. get_comments - List a file or folder's comments
-            8. manage_comment - Manage comment for a Google Drive file.
-            9. create_file - Create a new file
-            10. update_file - Update an existing file's contents or labels
-            11. sheets_tool - Work with Google Sheets data using various operations
-            12. docs_tool - Work with Google Docs data using various operations
+            3. download_file - Save a binary file to a local path, resuming interrupted downloads
+            4. move_file - Move a file to a new location in Google Drive
+            5. list_drives - List the shared drives to which you have access
+            6. get_permissions - List the permissions of a file or folder
+            7. sharing - Share a file or folder with others
+            8. get_comments - List a file or folder's comments
+            9. manage_comment - Manage comment for a Google Drive file.
+            10. create_file - Create a new file
+            11. update_file - Update an existing file's contents or labels
+            12. sheets_tool - Work with Google Sheets data using various operations
+            13. docs_tool - Work with Google Docs data using various operations
 
             ## Available Tools
 
             ### 1. Search Tool
             Search for or list files or labels in Google Drive. Files are
             searched by name and ordered by most recently viewedByMeTime.
-            A corpora parameter controls which corpus is searched.
+            A corpora parameter controls which corpus is searched; by default files
+            in My Drive and all shared drives are included, or a single shared drive when
+            a driveId is given.
             Returns: List of files with their names, MIME types, and IDs or a
             list of labels and their fields.
 
@@ -966,6 +1053,7 @@ impl GoogleDriveRouter {
             tools: vec![
                 search_tool,
                 read_tool,
+                download_file_tool,
                 create_file_tool,
                 move_file_tool,
                 update_file_tool,
@@ -1006,24 +1094,8 @@ impl GoogleDriveRouter {
     async fn search_files(&self, params: Value) -> Result<Vec<Content>, ToolError> {
         let name = params.get("name").and_then(|q| q.as_str());
         let mime_type = params.get("mimeType").and_then(|q| q.as_str());
-        let drive_id = params.get("driveId").and_then(|q| q.as_str());
         let parent = params.get("parent").and_then(|q| q.as_str());
-
-        // extract corpora query parameter, validate options, or default to "user"
-        let corpus = params
-            .get("corpora")
-            .and_then(|c| c.as_str())
-            .map(|s| {
-                if ["user", "drive", "allDrives"].contains(&s) {
-                    Ok(s)
-                } else {
-                    Err(ToolError::InvalidParameters(format!(
-                        "corpora must be either 'user', 'drive', or 'allDrives', got {}",
-                        s
-                    )))
-                }
-            })
-            .unwrap_or(Ok("user"))?;
+        let (corpus, drive_id) = resolve_corpus(&params)?;
 
         // extract pageSize, and convert it to an i32, default to 10
         let page_size: i32 = params
@@ -1083,7 +1155,7 @@ impl GoogleDriveRouter {
             .param(
                 "fields",
                 &format!(
-                    "files(id, name, mimeType, modifiedTime, size{})",
+                    "files(id, name, mimeType, modifiedTime, size, driveId{})",
                     if include_labels { ", labelInfo" } else { "" }
                 ),
             )
@@ -1092,8 +1164,7 @@ impl GoogleDriveRouter {
             .include_items_from_all_drives(true)
             .clear_scopes() // Scope::MeetReadonly is the default, remove it
             .add_scope(GOOGLE_DRIVE_SCOPES);
-        // You can only use the drive_id param when the corpus is "drive".
-        if let (Some(d), "drive") = (drive_id, corpus) {
+        if let Some(d) = drive_id {
             builder = builder.drive_id(d);
         }
         // If we want labels, we have to go look up the IDs first.
@@ -1133,23 +1204,9 @@ impl GoogleDriveRouter {
             Ok(r) => {
                 let content =
                     r.1.files
-                        .map(|files| {
-                            files.into_iter().map(|f| {
-                                format!(
-                                    "{} ({}) (uri: {}){}",
-                                    f.name.unwrap_or_default(),
-                                    f.mime_type.unwrap_or_default(),
-                                    f.id.unwrap_or_default(),
-                                    if include_labels {
-                                        format!(" (labels: {:?})", f.label_info.unwrap_or_default())
-                                    } else {
-                                        "".to_string()
-                                    }
-                                )
-                            })
-                        })
+                        .unwrap_or_default()
                         .into_iter()
-                        .flatten()
+                        .map(|f| format_file(f, include_labels))
                         .collect::<Vec<_>>()
                         .join("\n");
 
@@ -1320,6 +1377,7 @@ impl GoogleDriveRouter {
             .files()
             .get(uri)
             .param("alt", "media")
+            .supports_all_drives(true)
             .clear_scopes()
             .add_scope(GOOGLE_DRIVE_SCOPES)
             .doit()
@@ -1438,6 +1496,88 @@ impl GoogleDriveRouter {
         }
     }
 
+    async fn download_file(&self, params: Value) -> Result<Vec<Content>, ToolError> {
+        let uri =
+            params
+                .get("uri")
+                .and_then(|q| q.as_str())
+                .ok_or(ToolError::InvalidParameters(
+                    "The uri is required".to_string(),
+                ))?;
+        let file_id = uri.replace("gdrive:///", "");
+        let path = params
+            .get("path")
+            .and_then(|q| q.as_str())
+            .map(Path::new)
+            .filter(|p| p.is_absolute())
+            .ok_or(ToolError::InvalidParameters(
+                "An absolute local path is required".to_string(),
+            ))?;
+
+        let mut options = DownloadOptions::default();
+        if let Some(chunk_size) = params.get("chunkSize").and_then(|q| q.as_u64()) {
+            if chunk_size == 0 {
+                return Err(ToolError::InvalidParameters(
+                    "chunkSize must be greater than 0".to_string(),
+                ));
+            }
+            options.chunk_size = chunk_size;
+        }
+
+        let metadata = self
+            .drive
+            .files()
+            .get(&file_id)
+            .param("fields", "size,mimeType")
+            .supports_all_drives(true)
+            .clear_scopes()
+            .add_scope(GOOGLE_DRIVE_SCOPES)
+            .doit()
+            .await
+            .map_err(|e| {
+                ToolError::ExecutionError(format!(
+                    "Failed to execute Google Drive get query, {}.",
+                    e
+                ))
+            })?
+            .1;
+        let Some(size) = metadata.size else {
+            return Err(ToolError::InvalidParameters(format!(
+                "{} has no binary content ({}), use the read tool instead.",
+                uri,
+                metadata.mime_type.unwrap_or_default()
+            )));
+        };
+
+        let source = DriveRangeSource {
+            client: &self.drive.client,
+            auth: &*self.drive.auth,
+            scope: GOOGLE_DRIVE_SCOPES.as_ref(),
+            file_id: &file_id,
+        };
+        let resumed_from = download_resumable(&source, &file_id, size as u64, path, &options)
+            .await
+            .map_err(|e| {
+                ToolError::ExecutionError(format!(
+                    "Failed to download {}, {:#}. Call download_file again to resume.",
+                    uri, e
+                ))
+            })?;
+
+        let message = if resumed_from > 0 {
+            format!(
+                "Downloaded {} ({} bytes, resumed at byte {}) to {}",
+                uri,
+                size,
+                resumed_from,
+                path.display()
+            )
+        } else {
+            format!("Downloaded {} ({} bytes) to {}", uri, size, path.display())
+        };
+        Ok(vec![Content::text(message).with_priority(0.3)])
+    }
+
     // Implement sheets_tool functionality
     async fn sheets_tool(&self, params: Value) -> Result<Vec<Content>, ToolError> {
         let spreadsheet_id = params.get("spreadsheetId").and_then(|q| q.as_str()).ok_or(
@@ -1854,6 +1994,7 @@ impl GoogleDriveRouter {
             .order_by("viewedByMeTime desc")
             .page_size(10)
             .param("fields", "nextPageToken, files(id, name, mimeType)")
+            .corpora("allDrives")
             .supports_all_drives(true)
             .include_items_from_all_drives(true)
             .clear_scopes() // Scope::MeetReadonly is the default, remove it
@@ -3287,6 +3428,7 @@ impl Router for GoogleDriveRouter {
             match tool_name.as_str() {
                 "search" => this.search(arguments).await,
                 "read" => this.read(arguments).await,
+                "download_file" => this.download_file(arguments).await,
                 "create_file" => this.create_file(arguments).await,
                 "move_file" => this.move_file(arguments).await,
                 "update_file" => this.update_file(arguments).await,
@@ -3408,4 +3550,148 @@ mod tests {
             "https://docs.google.com/document/1QG8d8wtWe7ZfmG93sW-1h2WXDJDUkOi-9hDnvJLmWrc/edit";
         assert_eq!(extract_google_drive_id(url), None);
     }
+
+    #[test]
+    fn test_resolve_corpus_defaults_to_all_drives() {
+        assert_eq!(resolve_corpus(&json!({})).unwrap(), ("allDrives", None));
+        assert_eq!(
+            resolve_corpus(&json!({"driveId": "0AShared"})).unwrap(),
+            ("drive", Some("0AShared"))
+        );
+        assert_eq!(
+            resolve_corpus(&json!({"corpora": "user"})).unwrap(),
+            ("user", None)
+        );
+    }
+
+    #[test]
+    fn test_resolve_corpus_invalid() {
+        for params in [
+            json!({"corpora": "everything"}),
+            json!({"corpora": "drive"}),
+            json!({"corpora": "user", "driveId": "0AShared"}),
+        ] {
+            assert!(matches!(
+                resolve_corpus(&params),
+                Err(ToolError::InvalidParameters(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_shared_drive_items_are_listed() {
+        // A files.list response as returned for corpora=allDrives
+        let response: google_drive3::api::FileList = serde_json::from_value(json!({
+            "files": [
+                {"id": "mine", "name": "notes.txt", "mimeType": "text/plain"},
+                {"id": "team", "name": "roadmap.txt", "mimeType": "text/plain", "driveId": "0AShared"}
+            ]
+        }))
+        .unwrap();
+
+        let lines: Vec<String> = response
+            .files
+            .unwrap()
+            .into_iter()
+            .map(|f| format_file(f, false))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "notes.txt (text/plain) (uri: mine)",
+                "roadmap.txt (text/plain) (uri: team) (shared drive: 0AShared)",
+            ]
+        );
+    }
+
+    /// Answer one request on a local port with `body`, handing back the request line
+    fn serve_once(body: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            let request = String::from_utf8_lossy(&request).into_owned();
+            request.lines().next().unwrap_or_default().to_string()
+        });
+        (format!("http://{}/", address), handle)
+    }
+
+    /// A router whose Drive calls go to `base_url` with a fixed token
+    fn router_at(base_url: &str) -> GoogleDriveRouter {
+        let client =
+            hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+                .build(
+                    hyper_rustls::HttpsConnectorBuilder::new()
+                        .with_native_roots()
+                        .unwrap()
+                        .https_or_http()
+                        .enable_http1()
+                        .build(),
+                );
+        let auth = "test-token".to_string();
+        let mut drive = DriveHub::new(client.clone(), auth.clone());
+        drive.base_url(format!("{}drive/v3/", base_url));
+        let mut drive_labels = DriveLabelsHub::new(client.clone(), auth.clone());
+        drive_labels.base_url(base_url.to_string());
+        GoogleDriveRouter {
+            tools: Vec::new(),
+            instructions: String::new(),
+            drive,
+            drive_labels,
+            sheets: Sheets::new(client.clone(), auth.clone()),
+            docs: Docs::new(client, auth),
+            credentials_manager: Arc::new(CredentialsManager::new(
+                String::new(),
+                false,
+                KEYCHAIN_SERVICE.to_string(),
+                KEYCHAIN_USERNAME.to_string(),
+            )),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shared_drive_search_request() {
+        let (base_url, server) = serve_once(
+            r#"{"files": [{"id": "team", "name": "roadmap.txt", "mimeType": "text/plain", "driveId": "0AShared"}]}"#,
+        );
+        let router = router_at(&base_url);
+
+        let result = router
+            .search_files(json!({"name": "roadmap", "corpora": "drive", "driveId": "0AShared"}))
+            .await
+            .unwrap();
+        assert_eq!(
+            result[0].as_text().unwrap().text,
+            "roadmap.txt (text/plain) (uri: team) (shared drive: 0AShared)"
+        );
+
+        let request_line = server.join().unwrap();
+        assert!(request_line.starts_with("GET /drive/v3/files?"));
+        for param in [
+            "corpora=drive",
+            "supportsAllDrives=true",
+            "includeItemsFromAllDrives=true",
+            "driveId=0AShared",
+        ] {
+            assert!(request_line.contains(param), "{}", request_line);
+        }
+    }
 }
@@ -8,6 +8,7 @@ pub static APP_STRATEGY: Lazy<AppStrategyArgs> = Lazy::new(|| AppStrategyArgs {
 });
 
 pub mod computercontroller;
+mod config;
 mod developer;
 pub mod google_drive;
 mod memory;
@@ -15,8 +16,14 @@ mod tutorial;
 mod ultrathink;
 
 pub use computercontroller::ComputerControllerRouter;
+pub use config::{ConfigSource, GooseConfig};
 pub use developer::DeveloperRouter;
 pub use google_drive::GoogleDriveRouter;
 pub use memory::MemoryRouter;
 pub use tutorial::TutorialRouter;
-pub use ultrathink::UltraThinkRouter;
+pub use ultrathink::{
+    CategoryCase, CategoryStats, Clock, EmojiFormatter, IntegrityReport, JsonFormatter,
+    MemoryEvent, MemoryEventKind, MemoryScope, MockClock, MultilineStyle, ResponseFormatter,
+    ScopePrecedence, StorageFormat, SyncOnWrite, SyncReport, SystemClock, TimestampDisplay,
+    ToolCallRecord, UltraThinkConfig, UltraThinkRouter,
+};
@@ -325,8 +325,16 @@ impl MemoryRouter {
         if base_dir.exists() {
             for entry in fs::read_dir(base_dir)? {
                 let entry = entry?;
-                if entry.file_type()?.is_file() {
-                    let category = entry.file_name().to_string_lossy().replace(".txt", "");
+                let path = entry.path();
+                // Only `.txt` files are categories; the dir may be shared with tools that
+                // keep indexes, locks and checksums next to them
+                if entry.file_type()?.is_file() && path.extension().is_some_and(|ext| ext == "txt")
+                {
+                    let category = path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned();
                     let category_memories = self.retrieve(&category, is_global)?;
                     memories.insert(
                         category,
@@ -740,6 +748,37 @@ mod tests {
         assert!(router.local_memory_dir.join("category.txt").exists());
     }
 
+    #[test]
+    fn test_retrieve_all_skips_bookkeeping_files() {
+        let temp_dir = tempdir().unwrap();
+        let memory_base = temp_dir.path().join("shared_test");
+
+        let router = MemoryRouter {
+            tools: vec![],
+            instructions: String::new(),
+            global_memory_dir: memory_base.join("global"),
+            local_memory_dir: memory_base.join("local"),
+        };
+
+        router
+            .remember("context", "category", "data", &[], false)
+            .unwrap();
+        for bookkeeping in [
+            "category.lock",
+            "category.meta.json",
+            "category.txt.sha256",
+            "category.txt.tmp",
+            "index.json",
+            "graphiti_sync.json",
+        ] {
+            fs::write(router.local_memory_dir.join(bookkeeping), "{}").unwrap();
+        }
+        fs::create_dir(router.local_memory_dir.join("snapshots")).unwrap();
+
+        let memories = router.retrieve_all(false).unwrap();
+        assert_eq!(memories.keys().collect::<Vec<_>>(), vec!["category"]);
+    }
+
     #[test]
     fn test_remove_specific_memory() {
         let temp_dir = tempdir().unwrap();
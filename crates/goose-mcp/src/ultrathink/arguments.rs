@@ -0,0 +1,98 @@
+use serde_json::Value;
+use std::io;
+
+/// A string argument; missing and `null` arguments are `None`, as for every reader here
+pub fn optional_str<'a>(arguments: &'a Value, field: &str) -> io::Result<Option<&'a str>> {
+    typed(arguments, field, "a string", Value::as_str)
+}
+
+pub fn optional_bool(arguments: &Value, field: &str) -> io::Result<Option<bool>> {
+    typed(arguments, field, "a boolean", Value::as_bool)
+}
+
+pub fn optional_usize(arguments: &Value, field: &str) -> io::Result<Option<usize>> {
+    typed(arguments, field, "a non-negative integer", |value| {
+        value.as_u64().map(|value| value as usize)
+    })
+}
+
+pub fn required_str<'a>(arguments: &'a Value, field: &str) -> io::Result<&'a str> {
+    optional_str(arguments, field)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is required and must be a string", field),
+        )
+    })
+}
+
+/// A list of strings, where a single string is a list of one
+pub fn optional_strings<'a>(arguments: &'a Value, field: &str) -> io::Result<Option<Vec<&'a str>>> {
+    typed(
+        arguments,
+        field,
+        "an array of strings",
+        |value| match value {
+            Value::Array(values) => values.iter().map(Value::as_str).collect(),
+            Value::String(value) => Some(vec![value.as_str()]),
+            _ => None,
+        },
+    )
+}
+
+/// Read `field` with `read`. An argument of the wrong type is an `InvalidInput` error naming
+/// the field and the expected type rather than reading as the default, so that e.g.
+/// `is_global: "yes"` does not quietly mean the local scope.
+fn typed<'a, T>(
+    arguments: &'a Value,
+    field: &str,
+    expected: &str,
+    read: impl Fn(&'a Value) -> Option<T>,
+) -> io::Result<Option<T>> {
+    match arguments.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => read(value).map(Some).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} must be {}, got {}", field, expected, value),
+            )
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mistyped_arguments_are_errors() {
+        let arguments = json!({
+            "is_global": "yes",
+            "limit": -1,
+            "tags": ["api", 3],
+            "query": null,
+            "flag": true,
+        });
+
+        let err = optional_bool(&arguments, "is_global").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "is_global must be a boolean, got \"yes\"");
+        assert!(optional_usize(&arguments, "limit")
+            .unwrap_err()
+            .to_string()
+            .contains("limit must be a non-negative integer"));
+        assert!(optional_strings(&arguments, "tags").is_err());
+
+        assert_eq!(optional_str(&arguments, "query").unwrap(), None);
+        assert_eq!(optional_bool(&arguments, "missing").unwrap(), None);
+        assert_eq!(optional_bool(&arguments, "flag").unwrap(), Some(true));
+        assert_eq!(
+            required_str(&arguments, "query").unwrap_err().to_string(),
+            "query is required and must be a string"
+        );
+        assert_eq!(
+            optional_strings(&json!({"tags": "api"}), "tags").unwrap(),
+            Some(vec!["api"])
+        );
+    }
+}
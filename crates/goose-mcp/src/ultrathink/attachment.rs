@@ -0,0 +1,221 @@
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::entry::MemoryEntry;
+use super::file_name::{decode_category, encode_category};
+
+/// Scheme of the resource URIs that serve attachments through `read_resource`
+pub const ATTACHMENT_SCHEME: &str = "memory-attachment";
+
+/// Header field holding the path of an attached file
+const PATH_FIELD: &str = "attachment";
+/// Header field holding the MIME type of an attached file
+const MIME_FIELD: &str = "mime";
+/// Header field holding the size of an attached file in bytes, when it was remembered
+const SIZE_FIELD: &str = "size";
+
+/// MIME type of files whose extension is not recognised
+const DEFAULT_MIME: &str = "application/octet-stream";
+
+/// A file a memory points at instead of holding its content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Attachment {
+    pub path: PathBuf,
+    pub mime: String,
+    pub size: u64,
+}
+
+impl Attachment {
+    /// Reference the file at `path`, which must exist and, once symlinks are resolved,
+    /// lie under one of `allowed_dirs`
+    pub fn resolve(path: &Path, allowed_dirs: &[PathBuf]) -> io::Result<Self> {
+        let resolved = fs::canonicalize(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Attachment not found: {}", path.display()),
+            ),
+            _ => e,
+        })?;
+        let allowed = allowed_dirs
+            .iter()
+            .filter_map(|dir| fs::canonicalize(dir).ok())
+            .any(|dir| resolved.starts_with(dir));
+        if !allowed {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "Attachment {} is outside the allowed directories",
+                    path.display()
+                ),
+            ));
+        }
+
+        let metadata = fs::metadata(&resolved)?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Attachment is not a file: {}", path.display()),
+            ));
+        }
+        Ok(Self {
+            mime: mime_type(&resolved).to_string(),
+            size: metadata.len(),
+            path: resolved,
+        })
+    }
+
+    /// The reference recorded in the header fields of `entry`, if it has one
+    pub fn from_entry(entry: &MemoryEntry) -> Option<Self> {
+        Some(Self {
+            path: PathBuf::from(entry.fields.get(PATH_FIELD)?),
+            mime: entry
+                .fields
+                .get(MIME_FIELD)
+                .cloned()
+                .unwrap_or_else(|| DEFAULT_MIME.to_string()),
+            size: entry
+                .fields
+                .get(SIZE_FIELD)
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+        })
+    }
+
+    /// Record the reference in the header fields of `entry`
+    pub fn store_in(&self, entry: &mut MemoryEntry) {
+        for (field, value) in [
+            (PATH_FIELD, self.path.display().to_string()),
+            (MIME_FIELD, self.mime.clone()),
+            (SIZE_FIELD, self.size.to_string()),
+        ] {
+            entry.fields.insert(field.to_string(), value);
+        }
+    }
+
+    /// The data of an entry referencing the file: its description, or else the file name,
+    /// followed by a line describing the reference so every retrieve mode shows it
+    pub fn entry_data(&self, description: Option<&str>) -> String {
+        let description = description.map(str::to_string).unwrap_or_else(|| {
+            self.path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        format!(
+            "{}\n[attachment: {} ({}, {} bytes)]",
+            description,
+            self.path.display(),
+            self.mime,
+            self.size
+        )
+    }
+
+    /// Whether the file is served as text rather than base64
+    pub fn is_text(&self) -> bool {
+        self.mime.starts_with("text/") || self.mime == "application/json"
+    }
+}
+
+/// The URI serving the attachment of an entry, e.g.
+/// `memory-attachment://local/dev/1a2b3c4d5e6f`
+pub fn attachment_uri(category: &str, id: &str, is_global: bool) -> String {
+    let scope = if is_global { "global" } else { "local" };
+    format!(
+        "{}://{}/{}/{}",
+        ATTACHMENT_SCHEME,
+        scope,
+        encode_category(category),
+        id
+    )
+}
+
+/// The scope (`is_global`), category and entry id of an attachment URI
+pub fn parse_attachment_uri(uri: &str) -> Option<(bool, String, String)> {
+    let rest = uri.strip_prefix(ATTACHMENT_SCHEME)?.strip_prefix("://")?;
+    let mut parts = rest.split('/');
+    let is_global = match parts.next()? {
+        "global" => true,
+        "local" => false,
+        _ => return None,
+    };
+    let category = decode_category(parts.next()?);
+    let id = parts.next()?.to_string();
+    if parts.next().is_some() || category.is_empty() || id.is_empty() {
+        return None;
+    }
+    Some((is_global, category, id))
+}
+
+/// MIME type guessed from the file extension
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "json" => "application/json",
+        "zip" => "application/zip",
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        _ => DEFAULT_MIME,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_checks_allowed_dirs() {
+        let allowed = tempdir().unwrap();
+        let other = tempdir().unwrap();
+        let path = allowed.path().join("Shot.PNG");
+        fs::write(&path, [0u8; 16]).unwrap();
+        let outside = other.path().join("notes.txt");
+        fs::write(&outside, "notes").unwrap();
+        let allowed_dirs = [allowed.path().to_path_buf()];
+
+        let attachment = Attachment::resolve(&path, &allowed_dirs).unwrap();
+        assert_eq!(attachment.mime, "image/png");
+        assert_eq!(attachment.size, 16);
+        assert!(!attachment.is_text());
+
+        let err = Attachment::resolve(&outside, &allowed_dirs).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        // Escaping through `..` is caught once the path is resolved
+        let escaped = allowed
+            .path()
+            .join("..")
+            .join(other.path().file_name().unwrap())
+            .join("notes.txt");
+        assert!(Attachment::resolve(&escaped, &allowed_dirs).is_err());
+        let err = Attachment::resolve(allowed.path(), &allowed_dirs).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_uri_round_trips() {
+        let uri = attachment_uri("team: docs", "1a2b3c4d5e6f", true);
+        assert_eq!(uri, "memory-attachment://global/team%3A docs/1a2b3c4d5e6f");
+        assert_eq!(
+            parse_attachment_uri(&uri),
+            Some((true, "team: docs".to_string(), "1a2b3c4d5e6f".to_string()))
+        );
+        assert_eq!(
+            parse_attachment_uri("memory-attachment://shared/dev/1"),
+            None
+        );
+        assert_eq!(parse_attachment_uri("file:///tmp/shot.png"), None);
+    }
+}
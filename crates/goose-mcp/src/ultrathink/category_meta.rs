@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Per-category settings, stored in the `<category>.meta.json` next to the category file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoryMeta {
+    /// The category holds a single evolving value: storing replaces the current entry
+    /// instead of appending, and retrieval returns only the latest one
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub singleton: bool,
+    /// Keys written by other tools or by hand, kept when the file is saved again
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl CategoryMeta {
+    /// Load the settings at `path`; a missing or unreadable file gives the defaults
+    pub fn load(path: &Path) -> Self {
+        let Ok(content) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring invalid {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_round_trip_keeps_unknown_keys() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("dev.meta.json");
+        assert_eq!(CategoryMeta::load(&path), CategoryMeta::default());
+
+        fs::write(&path, r#"{"owner": "ops", "singleton": false}"#).unwrap();
+        let mut meta = CategoryMeta::load(&path);
+        assert!(!meta.singleton);
+        meta.singleton = true;
+        meta.save(&path).unwrap();
+
+        let meta = CategoryMeta::load(&path);
+        assert!(meta.singleton);
+        assert_eq!(meta.other["owner"], "ops");
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(CategoryMeta::load(&path), CategoryMeta::default());
+    }
+}
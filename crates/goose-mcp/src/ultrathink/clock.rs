@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// The source of the current time for timestamps, expiry and the other time reads of a
+/// router, so tests can control it
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    fn utc_now(&self) -> DateTime<Utc> {
+        self.now().into()
+    }
+}
+
+/// The default clock, reading the system time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that stands still until it is set or advanced
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<SystemTime>,
+}
+
+impl MockClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// A clock stopped at `now`
+    pub fn at(now: DateTime<Utc>) -> Self {
+        Self::new(now.into())
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_moves_only_when_told() {
+        let start = DateTime::parse_from_rfc3339("2025-01-06T09:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let clock = MockClock::at(start);
+        assert_eq!(clock.utc_now(), start);
+        assert_eq!(clock.utc_now(), start);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.utc_now(), start + chrono::Duration::seconds(90));
+        clock.set(SystemTime::UNIX_EPOCH);
+        assert_eq!(clock.utc_now().timestamp(), 0);
+    }
+}
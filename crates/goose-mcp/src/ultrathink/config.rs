@@ -0,0 +1,599 @@
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, Utc};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::lock::DEFAULT_LOCK_TIMEOUT;
+use super::mmap_reader::DEFAULT_MMAP_THRESHOLD_BYTES;
+use super::storage::StorageFormat;
+use super::trace::{DEFAULT_REDACTED_FIELDS, DEFAULT_TRACE_SIZE};
+
+/// Name of the config file kept next to the global memory dir
+pub const CONFIG_FILE: &str = "ultrathink.toml";
+
+/// Where the config file of a global memory dir lives
+pub fn config_file_path(global_memory_dir: &Path) -> PathBuf {
+    global_memory_dir
+        .parent()
+        .unwrap_or(global_memory_dir)
+        .join(CONFIG_FILE)
+}
+
+/// How entries are combined when a category exists in both the local and global scope
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScopePrecedence {
+    /// Local entries first, then global entries; duplicates are kept
+    LocalFirst,
+    /// Global entries first, then local entries; duplicates are kept
+    GlobalFirst,
+    /// Local entries first, then any global entries that are not equal to a local one
+    /// (see [`MemoryEntry`](super::entry::MemoryEntry) equality)
+    #[default]
+    Union,
+}
+
+impl ScopePrecedence {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "local_first" => Some(Self::LocalFirst),
+            "global_first" => Some(Self::GlobalFirst),
+            "union" => Some(Self::Union),
+            _ => None,
+        }
+    }
+}
+
+/// Which remembered memories are uploaded to Graphiti as soon as they are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncOnWrite {
+    /// None; memories reach Graphiti with the next sync
+    #[default]
+    Off,
+    /// Memories stored with `priority: "high"`. The upload is awaited, so its outcome is
+    /// part of the response.
+    HighPriorityOnly,
+    /// Every memory: high-priority ones as above, the others in the background with the
+    /// outcome broadcast as a `Synced` or `SyncFailed` event
+    All,
+}
+
+impl SyncOnWrite {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "high_priority_only" => Some(Self::HighPriorityOnly),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+}
+
+/// How a memory spanning several lines is listed in the instructions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MultilineStyle {
+    /// Continuation lines are indented under the bullet
+    #[default]
+    Indent,
+    /// The whole memory is a fenced block nested under an empty bullet
+    Block,
+    /// The lines are joined into one with ` / `
+    Join,
+}
+
+impl MultilineStyle {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "indent" => Some(Self::Indent),
+            "block" => Some(Self::Block),
+            "join" => Some(Self::Join),
+            _ => None,
+        }
+    }
+
+    /// The bullet listing `data`, ending with a newline
+    pub fn bullet(self, data: &str) -> String {
+        if !data.contains('\n') {
+            return format!("- {}\n", data);
+        }
+        match self {
+            Self::Indent => format!("- {}\n", data.lines().collect::<Vec<_>>().join("\n  ")),
+            Self::Block => {
+                let mut bullet = String::from("-\n  ```\n");
+                for line in data.lines() {
+                    bullet.push_str(&format!("  {}\n", line));
+                }
+                bullet.push_str("  ```\n");
+                bullet
+            }
+            Self::Join => format!("- {}\n", data.lines().collect::<Vec<_>>().join(" / ")),
+        }
+    }
+}
+
+/// How category names map to category files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CategoryCase {
+    /// Names are used as given, so `Development` and `development` are two categories
+    #[default]
+    Preserve,
+    /// Names are lowercased, so their case variants share one category
+    Lower,
+}
+
+impl CategoryCase {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "preserve" => Some(Self::Preserve),
+            "lower" | "lowercase" => Some(Self::Lower),
+            _ => None,
+        }
+    }
+
+    /// The name `category` is stored under
+    pub fn apply(self, category: &str) -> Cow<'_, str> {
+        match self {
+            Self::Preserve => Cow::Borrowed(category),
+            Self::Lower => Cow::Owned(category.to_lowercase()),
+        }
+    }
+}
+
+/// The time zone retrieve results show timestamps in. Timestamps are always stored in UTC,
+/// so date ranges compare the same instants on every machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampDisplay {
+    #[default]
+    Utc,
+    /// The time zone of the machine running the router
+    Local,
+    /// A fixed offset from UTC, e.g. `+02:00`
+    Offset(FixedOffset),
+}
+
+impl TimestampDisplay {
+    /// Parse `utc`, `local` or an offset such as `+02:00`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "utc" | "z" => Some(Self::Utc),
+            "local" => Some(Self::Local),
+            offset => offset.parse().ok().map(Self::Offset),
+        }
+    }
+
+    /// `time` as RFC 3339 with milliseconds, in UTC with a `Z` suffix or in the display
+    /// time zone with its offset
+    pub fn render(self, time: DateTime<Utc>) -> String {
+        match self {
+            Self::Utc => time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            Self::Local => time
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Millis, false),
+            Self::Offset(offset) => time
+                .with_timezone(&offset)
+                .to_rfc3339_opts(SecondsFormat::Millis, false),
+        }
+    }
+}
+
+/// Number of memories injected into the instructions when no limit is configured
+pub const DEFAULT_MAX_INSTRUCTION_MEMORIES: usize = 50;
+
+/// Newest entries of each category read for the instructions when no limit is configured
+pub const DEFAULT_INSTRUCTION_ENTRIES_PER_CATEGORY: usize = 20;
+
+/// Largest entry data, in bytes, stored when no limit is configured
+pub const DEFAULT_MAX_ENTRY_BYTES: usize = 32 * 1024;
+
+/// Largest assembled instructions, in bytes, when no limit is configured
+pub const DEFAULT_MAX_INSTRUCTION_BYTES: usize = 64 * 1024;
+
+/// Marker wrapped around query matches when no marker is configured
+pub const DEFAULT_HIGHLIGHT_MARKER: &str = "**";
+
+/// Behaviour settings for the UltraThink router
+#[derive(Debug, Clone)]
+pub struct UltraThinkConfig {
+    /// `ULTRATHINK_SCOPE_PRECEDENCE`: `local_first`, `global_first` or `union`
+    pub scope_precedence: ScopePrecedence,
+    /// `ULTRATHINK_STORAGE_FORMAT`: `text` for `.txt` category files or `markdown` for
+    /// `.md` files with YAML frontmatter. Files in the other format are not read.
+    pub storage_format: StorageFormat,
+    /// `ULTRATHINK_NORMALIZE_CATEGORY_CASE`: `preserve` or `lower`, whether category names
+    /// are lowercased wherever they name a file. Stores written with `preserve` may hold
+    /// case variants that `lower` no longer reaches.
+    pub normalize_category_case: CategoryCase,
+    /// `ULTRATHINK_TIMESTAMP_DISPLAY`: `utc`, `local` or an offset such as `+02:00`, the
+    /// time zone retrieve results show timestamps in
+    pub timestamp_display: TimestampDisplay,
+    /// `ULTRATHINK_INDEX`: keep an `index.json` of category statistics and a `tags.json`
+    /// of the entries of each tag in each memory dir
+    pub use_index: bool,
+    /// `ULTRATHINK_CHECKSUMS`: keep a `.sha256` sidecar next to each category file, updated
+    /// on every write and checked on every read. A file that no longer matches is skipped
+    /// with a warning, and moved aside before the next write to its category.
+    pub use_checksums: bool,
+    /// `ULTRATHINK_PRELOAD_MEMORIES`: list stored memories in the instructions. Turning it
+    /// off keeps the system prompt to the base instructions, and the model retrieves
+    /// memories with `ultrathink_retrieve` when it needs them.
+    pub preload_memories: bool,
+    /// `ULTRATHINK_MAX_INSTRUCTION_MEMORIES`: how many memories are listed in the
+    /// instructions, pinned first and then most recent first
+    pub max_instruction_memories: usize,
+    /// `ULTRATHINK_INSTRUCTION_ENTRIES_PER_CATEGORY`: how many of the newest entries of
+    /// each category are read when listing memories in the instructions, so building a
+    /// router does not read every entry of a large store; 0 reads them all
+    pub instruction_entries_per_category: usize,
+    /// `ULTRATHINK_MULTILINE_MEMORIES`: `indent`, `block` or `join`, how memories with
+    /// several lines are listed in the instructions
+    pub multiline_style: MultilineStyle,
+    /// `ULTRATHINK_MAX_ENTRY_BYTES`: larger data is truncated when remembered; 0 disables
+    /// the limit
+    pub max_entry_bytes: usize,
+    /// `ULTRATHINK_MAX_CATEGORIES`: how many categories each scope may hold; storing to a
+    /// new category beyond it fails while existing categories stay writable. 0 disables
+    /// the limit.
+    pub max_categories: usize,
+    /// `ULTRATHINK_MAX_INSTRUCTION_BYTES`: the memory appendix is trimmed so the
+    /// instructions stay within this size; 0 disables the limit
+    pub max_instruction_bytes: usize,
+    /// `ULTRATHINK_MMAP_THRESHOLD_BYTES`: text category files of at least this size are
+    /// memory-mapped when a retrieve only needs their newest entries; 0 always reads
+    /// them whole
+    pub mmap_threshold_bytes: usize,
+    /// `ULTRATHINK_LOCK_TIMEOUT_MS`: how long a write waits for another process holding
+    /// the same category before failing
+    pub lock_timeout: Duration,
+    /// `ULTRATHINK_STRICT_GRAPHITI_UPLOAD`: report a failed upload of a high-priority
+    /// memory as a tool error instead of a warning. The memory is stored locally either way.
+    pub strict_graphiti_upload: bool,
+    /// `ULTRATHINK_COMPRESS_GRAPHITI_REQUESTS`: gzip the bodies of requests sent to a
+    /// Graphiti endpoint, falling back to uncompressed ones if the server rejects them
+    pub compress_graphiti_requests: bool,
+    /// `ULTRATHINK_SYNC_ON_WRITE`: `off`, `high_priority_only` or `all`, which remembered
+    /// memories are uploaded to Graphiti right away
+    pub sync_on_write: SyncOnWrite,
+    /// `ULTRATHINK_DEFAULT_PRIORITY`: `low`, `medium` or `high`, the priority of memories
+    /// remembered without one; unset leaves them without a priority
+    pub default_priority: Option<String>,
+    /// `ULTRATHINK_HIGHLIGHT_MARKER`: written before and after each query match when a
+    /// retrieve asks for `highlight`
+    pub highlight_marker: String,
+    /// `ULTRATHINK_READ_ONLY_GLOBAL`: reject every write to the global scope, for global
+    /// memories curated centrally. Global memories are still read and merged.
+    pub read_only_global: bool,
+    /// `ULTRATHINK_ATTACHMENT_DIRS`: directories, separated like `PATH`, that files
+    /// remembered with `attachment_path` must lie under; when empty, the project directory
+    /// holding the local memory dir
+    pub attachment_dirs: Vec<PathBuf>,
+    /// `ULTRATHINK_TRACE_SIZE`: how many recent tool calls `ultrathink_trace` reports;
+    /// 0 disables the trace
+    pub trace_size: usize,
+    /// `ULTRATHINK_TRACE_REDACT`: comma separated argument fields whose values are
+    /// replaced in the trace, at any depth and ignoring case
+    pub trace_redacted_fields: Vec<String>,
+    /// `[entity_types]` in `ultrathink.toml`: the Graphiti entity type each category is
+    /// stored as; unmapped categories use their uppercased name
+    pub entity_types: BTreeMap<String, String>,
+}
+
+/// The settings read from `ultrathink.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ConfigFile {
+    entity_types: BTreeMap<String, String>,
+    default_priority: Option<String>,
+    /// `true` uploads every remembered memory and `false` none, as `all` and `off` of
+    /// `ULTRATHINK_SYNC_ON_WRITE`
+    auto_sync: Option<bool>,
+}
+
+impl Default for UltraThinkConfig {
+    fn default() -> Self {
+        Self {
+            scope_precedence: ScopePrecedence::default(),
+            storage_format: StorageFormat::default(),
+            normalize_category_case: CategoryCase::default(),
+            timestamp_display: TimestampDisplay::default(),
+            use_index: false,
+            use_checksums: false,
+            preload_memories: true,
+            max_instruction_memories: DEFAULT_MAX_INSTRUCTION_MEMORIES,
+            instruction_entries_per_category: DEFAULT_INSTRUCTION_ENTRIES_PER_CATEGORY,
+            multiline_style: MultilineStyle::default(),
+            max_entry_bytes: DEFAULT_MAX_ENTRY_BYTES,
+            max_categories: 0,
+            max_instruction_bytes: DEFAULT_MAX_INSTRUCTION_BYTES,
+            mmap_threshold_bytes: DEFAULT_MMAP_THRESHOLD_BYTES,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            strict_graphiti_upload: false,
+            compress_graphiti_requests: false,
+            sync_on_write: SyncOnWrite::default(),
+            default_priority: None,
+            read_only_global: false,
+            attachment_dirs: Vec::new(),
+            trace_size: DEFAULT_TRACE_SIZE,
+            trace_redacted_fields: DEFAULT_REDACTED_FIELDS
+                .iter()
+                .map(|field| field.to_string())
+                .collect(),
+            highlight_marker: DEFAULT_HIGHLIGHT_MARKER.to_string(),
+            entity_types: BTreeMap::new(),
+        }
+    }
+}
+
+impl UltraThinkConfig {
+    /// Read the configuration from environment variables, falling back to defaults
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(value) = std::env::var("ULTRATHINK_SCOPE_PRECEDENCE") {
+            match ScopePrecedence::parse(&value) {
+                Some(precedence) => config.scope_precedence = precedence,
+                None => tracing::warn!(
+                    "Ignoring invalid ULTRATHINK_SCOPE_PRECEDENCE value: {}",
+                    value
+                ),
+            }
+        }
+
+        if let Ok(value) = std::env::var("ULTRATHINK_STORAGE_FORMAT") {
+            match StorageFormat::parse(&value) {
+                Some(format) => config.storage_format = format,
+                None => tracing::warn!(
+                    "Ignoring invalid ULTRATHINK_STORAGE_FORMAT value: {}",
+                    value
+                ),
+            }
+        }
+
+        if let Ok(value) = std::env::var("ULTRATHINK_NORMALIZE_CATEGORY_CASE") {
+            match CategoryCase::parse(&value) {
+                Some(case) => config.normalize_category_case = case,
+                None => tracing::warn!(
+                    "Ignoring invalid ULTRATHINK_NORMALIZE_CATEGORY_CASE value: {}",
+                    value
+                ),
+            }
+        }
+
+        if let Ok(value) = std::env::var("ULTRATHINK_TIMESTAMP_DISPLAY") {
+            match TimestampDisplay::parse(&value) {
+                Some(display) => config.timestamp_display = display,
+                None => tracing::warn!(
+                    "Ignoring invalid ULTRATHINK_TIMESTAMP_DISPLAY value: {}",
+                    value
+                ),
+            }
+        }
+
+        if let Ok(value) = std::env::var("ULTRATHINK_SYNC_ON_WRITE") {
+            match SyncOnWrite::parse(&value) {
+                Some(mode) => config.sync_on_write = mode,
+                None => {
+                    tracing::warn!("Ignoring invalid ULTRATHINK_SYNC_ON_WRITE value: {}", value)
+                }
+            }
+        }
+
+        if let Ok(value) = std::env::var("ULTRATHINK_DEFAULT_PRIORITY") {
+            match parse_priority(&value) {
+                Some(priority) => config.default_priority = Some(priority),
+                None => tracing::warn!(
+                    "Ignoring invalid ULTRATHINK_DEFAULT_PRIORITY value: {}",
+                    value
+                ),
+            }
+        }
+
+        if let Ok(value) = std::env::var("ULTRATHINK_MULTILINE_MEMORIES") {
+            match MultilineStyle::parse(&value) {
+                Some(style) => config.multiline_style = style,
+                None => tracing::warn!(
+                    "Ignoring invalid ULTRATHINK_MULTILINE_MEMORIES value: {}",
+                    value
+                ),
+            }
+        }
+
+        config.use_index = env_flag("ULTRATHINK_INDEX");
+        config.use_checksums = env_flag("ULTRATHINK_CHECKSUMS");
+        config.strict_graphiti_upload = env_flag("ULTRATHINK_STRICT_GRAPHITI_UPLOAD");
+        config.compress_graphiti_requests = env_flag("ULTRATHINK_COMPRESS_GRAPHITI_REQUESTS");
+        config.read_only_global = env_flag("ULTRATHINK_READ_ONLY_GLOBAL");
+        // Unlike the other flags this one defaults to on, so it only changes when set
+        if std::env::var("ULTRATHINK_PRELOAD_MEMORIES").is_ok() {
+            config.preload_memories = env_flag("ULTRATHINK_PRELOAD_MEMORIES");
+        }
+
+        for (var, setting) in [
+            (
+                "ULTRATHINK_MAX_INSTRUCTION_MEMORIES",
+                &mut config.max_instruction_memories,
+            ),
+            (
+                "ULTRATHINK_INSTRUCTION_ENTRIES_PER_CATEGORY",
+                &mut config.instruction_entries_per_category,
+            ),
+            ("ULTRATHINK_MAX_ENTRY_BYTES", &mut config.max_entry_bytes),
+            ("ULTRATHINK_MAX_CATEGORIES", &mut config.max_categories),
+            (
+                "ULTRATHINK_MAX_INSTRUCTION_BYTES",
+                &mut config.max_instruction_bytes,
+            ),
+            (
+                "ULTRATHINK_MMAP_THRESHOLD_BYTES",
+                &mut config.mmap_threshold_bytes,
+            ),
+            ("ULTRATHINK_TRACE_SIZE", &mut config.trace_size),
+        ] {
+            if let Ok(value) = std::env::var(var) {
+                match value.trim().parse() {
+                    Ok(max) => *setting = max,
+                    Err(_) => tracing::warn!("Ignoring invalid {} value: {}", var, value),
+                }
+            }
+        }
+
+        if let Ok(value) = std::env::var("ULTRATHINK_LOCK_TIMEOUT_MS") {
+            match value.trim().parse() {
+                Ok(millis) => config.lock_timeout = Duration::from_millis(millis),
+                Err(_) => tracing::warn!(
+                    "Ignoring invalid ULTRATHINK_LOCK_TIMEOUT_MS value: {}",
+                    value
+                ),
+            }
+        }
+
+        if let Some(value) = std::env::var_os("ULTRATHINK_ATTACHMENT_DIRS") {
+            config.attachment_dirs = std::env::split_paths(&value)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .collect();
+        }
+
+        if let Ok(value) = std::env::var("ULTRATHINK_TRACE_REDACT") {
+            config.trace_redacted_fields = value
+                .split(',')
+                .map(|field| field.trim().to_string())
+                .filter(|field| !field.is_empty())
+                .collect();
+        }
+
+        if let Ok(value) = std::env::var("ULTRATHINK_HIGHLIGHT_MARKER") {
+            if value.is_empty() {
+                tracing::warn!("Ignoring empty ULTRATHINK_HIGHLIGHT_MARKER");
+            } else {
+                config.highlight_marker = value;
+            }
+        }
+
+        config
+    }
+
+    /// Apply the settings of a config file; a missing file changes nothing and an
+    /// invalid one is ignored with a warning. Settings also read from an environment
+    /// variable only apply when that variable is unset.
+    pub fn with_file(mut self, path: &Path) -> Self {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return self;
+        };
+        let file = match toml::from_str::<ConfigFile>(&content) {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::warn!("Ignoring invalid {}: {}", path.display(), e);
+                return self;
+            }
+        };
+
+        self.entity_types.extend(file.entity_types);
+        if std::env::var_os("ULTRATHINK_DEFAULT_PRIORITY").is_none() {
+            if let Some(value) = file.default_priority {
+                match parse_priority(&value) {
+                    Some(priority) => self.default_priority = Some(priority),
+                    None => tracing::warn!(
+                        "Ignoring invalid default_priority in {}: {}",
+                        path.display(),
+                        value
+                    ),
+                }
+            }
+        }
+        if std::env::var_os("ULTRATHINK_SYNC_ON_WRITE").is_none() {
+            match file.auto_sync {
+                Some(true) => self.sync_on_write = SyncOnWrite::All,
+                Some(false) => self.sync_on_write = SyncOnWrite::Off,
+                None => {}
+            }
+        }
+        self
+    }
+}
+
+/// A memory priority, ignoring case and surrounding whitespace
+fn parse_priority(value: &str) -> Option<String> {
+    let value = value.trim().to_ascii_lowercase();
+    matches!(value.as_str(), "low" | "medium" | "high").then_some(value)
+}
+
+/// Interpret an environment variable as a boolean flag (`1`, `true`, `yes` or `on`)
+pub(super) fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|value| {
+            matches!(
+                value.trim().to_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_entity_types_from_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE);
+        std::fs::write(
+            &path,
+            "[entity_types]\ndevelopment = \"Project\"\nbugs = \"Project\"\n",
+        )
+        .unwrap();
+
+        let config = UltraThinkConfig::default().with_file(&path);
+        assert_eq!(config.entity_types["development"], "Project");
+        assert_eq!(config.entity_types.len(), 2);
+
+        std::fs::write(&path, "entity_types = 3").unwrap();
+        let config = UltraThinkConfig::default().with_file(&path);
+        assert!(config.entity_types.is_empty());
+    }
+
+    #[test]
+    fn test_settings_from_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE);
+        // The shared settings, read by `GooseConfig`, sit beside these
+        std::fs::write(
+            &path,
+            "graphiti_endpoint = \"http://graphiti.internal:8000\"\ndefault_priority = \"High\"\nauto_sync = true\n",
+        )
+        .unwrap();
+
+        let config = UltraThinkConfig::default().with_file(&path);
+        assert_eq!(config.default_priority.as_deref(), Some("high"));
+        assert_eq!(config.sync_on_write, SyncOnWrite::All);
+
+        std::fs::write(&path, "default_priority = \"urgent\"\nauto_sync = false\n").unwrap();
+        let config = UltraThinkConfig::default().with_file(&path);
+        assert_eq!(config.default_priority, None);
+        assert_eq!(config.sync_on_write, SyncOnWrite::Off);
+    }
+
+    #[test]
+    fn test_timestamp_display() {
+        let time = DateTime::parse_from_rfc3339("2024-05-01T23:30:00.250+02:00")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            TimestampDisplay::default().render(time),
+            "2024-05-01T21:30:00.250Z"
+        );
+
+        let offset = TimestampDisplay::parse("-05:00").unwrap();
+        assert_eq!(offset.render(time), "2024-05-01T16:30:00.250-05:00");
+        assert_eq!(
+            TimestampDisplay::parse(" UTC "),
+            Some(TimestampDisplay::Utc)
+        );
+        assert_eq!(
+            TimestampDisplay::parse("local"),
+            Some(TimestampDisplay::Local)
+        );
+        assert_eq!(TimestampDisplay::parse("tomorrow"), None);
+    }
+}
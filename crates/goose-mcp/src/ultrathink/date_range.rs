@@ -0,0 +1,122 @@
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, Utc};
+use std::io;
+
+use super::entry::MemoryEntry;
+
+/// Inclusive bounds on the stored timestamp of an entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+    /// Whether entries stored before timestamps were recorded are kept
+    pub include_undated: bool,
+}
+
+impl DateRange {
+    /// Parse `after` and `before` bounds. Each is either an RFC 3339 time or a plain
+    /// `YYYY-MM-DD` date, which covers that whole day (UTC) so both ends stay inclusive.
+    pub fn parse(
+        after: Option<&str>,
+        before: Option<&str>,
+        include_undated: bool,
+    ) -> io::Result<Self> {
+        let range = Self {
+            after: after.map(|value| parse_bound(value, false)).transpose()?,
+            before: before.map(|value| parse_bound(value, true)).transpose()?,
+            include_undated,
+        };
+        if let (Some(after), Some(before)) = (range.after, range.before) {
+            if after > before {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "after must not be later than before",
+                ));
+            }
+        }
+        Ok(range)
+    }
+
+    pub fn contains(&self, entry: &MemoryEntry) -> bool {
+        match entry.timestamp {
+            Some(timestamp) => {
+                self.after.map_or(true, |after| timestamp >= after)
+                    && self.before.map_or(true, |before| timestamp <= before)
+            }
+            None => self.include_undated,
+        }
+    }
+}
+
+fn parse_bound(value: &str, end_of_day: bool) -> io::Result<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid date '{}', expected YYYY-MM-DD or RFC 3339", value),
+        )
+    })?;
+    let start = date.and_time(NaiveTime::MIN).and_utc();
+    if !end_of_day {
+        return Ok(start);
+    }
+    // The last representable instant of the day
+    Ok(start
+        .checked_add_days(Days::new(1))
+        .map_or(DateTime::<Utc>::MAX_UTC, |next| {
+            next - chrono::Duration::nanoseconds(1)
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_at(timestamp: &str) -> MemoryEntry {
+        let mut entry = MemoryEntry::new("note", &[]);
+        entry.timestamp = Some(DateTime::parse_from_rfc3339(timestamp).unwrap().into());
+        entry
+    }
+
+    #[test]
+    fn test_date_bounds_cover_whole_days() {
+        let range = DateRange::parse(Some("2025-01-06"), Some("2025-01-08"), false).unwrap();
+        assert!(range.contains(&entry_at("2025-01-06T00:00:00Z")));
+        assert!(range.contains(&entry_at("2025-01-08T23:59:59Z")));
+        assert!(!range.contains(&entry_at("2025-01-05T23:59:59Z")));
+        assert!(!range.contains(&entry_at("2025-01-09T00:00:00Z")));
+    }
+
+    #[test]
+    fn test_time_bounds_are_inclusive() {
+        let range = DateRange::parse(
+            Some("2025-01-06T12:00:00+02:00"),
+            Some("2025-01-06T18:00:00Z"),
+            false,
+        )
+        .unwrap();
+        assert!(range.contains(&entry_at("2025-01-06T10:00:00Z")));
+        assert!(range.contains(&entry_at("2025-01-06T18:00:00Z")));
+        assert!(!range.contains(&entry_at("2025-01-06T09:59:59Z")));
+    }
+
+    #[test]
+    fn test_undated_entries() {
+        let undated = MemoryEntry::new("legacy", &[]);
+        let range = DateRange::parse(Some("2025-01-06"), None, false).unwrap();
+        assert!(!range.contains(&undated));
+        let range = DateRange::parse(Some("2025-01-06"), None, true).unwrap();
+        assert!(range.contains(&undated));
+    }
+
+    #[test]
+    fn test_invalid_bounds() {
+        let err = DateRange::parse(Some("monday"), None, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = DateRange::parse(Some("2025-01-08"), Some("2025-01-06"), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,379 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use super::header::EntryHeader;
+
+/// Header field holding the time an entry was stored (RFC 3339, UTC)
+const TIMESTAMP_FIELD: &str = "ts";
+/// Header field marking an entry as protected from compaction and expiry
+const PINNED_FIELD: &str = "pinned";
+/// Header field holding the time after which an unpinned entry is dropped (RFC 3339, UTC)
+const EXPIRES_FIELD: &str = "expires";
+/// Header field holding the priority given when the entry was remembered
+pub const PRIORITY_FIELD: &str = "priority";
+/// Header field holding the context given when the entry was remembered
+pub const CONTEXT_FIELD: &str = "ctx";
+/// Header field marking an entry whose data was cut to the size limit when stored
+const TRUNCATED_FIELD: &str = "truncated";
+
+/// Appended to data that was cut to the size limit
+pub const TRUNCATION_MARKER: &str = "\n…[truncated]";
+
+/// A single memory entry, stored as a `\n\n`-separated block in a category file.
+///
+/// Two entries are equal when their [`normalize_data`] and their sorted tags are, so an
+/// entry stored again at another time, or with other spacing, is recognised as the same
+/// one; the timestamp, pin state, expiry and header fields are not compared.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryEntry {
+    pub tags: Vec<String>,
+    pub data: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub pinned: bool,
+    pub expires: Option<DateTime<Utc>>,
+    pub truncated: bool,
+    /// Header fields without a dedicated meaning, preserved when the entry is rewritten
+    pub fields: BTreeMap<String, String>,
+}
+
+impl MemoryEntry {
+    pub fn new(data: &str, tags: &[&str]) -> Self {
+        Self {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            data: data.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// SHA-256 of what entry equality compares, the same for every entry equal to this one
+    pub fn content_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(normalize_data(&self.data).as_bytes());
+        for tag in self.canonical_tags() {
+            hasher.update(b"\0");
+            hasher.update(tag.as_bytes());
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Trimmed, sorted and deduplicated tags without blank ones, as
+    /// [`Self::normalize_tags`] leaves them
+    fn canonical_tags(&self) -> Vec<&str> {
+        let mut tags: Vec<&str> = self
+            .tags
+            .iter()
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+
+    /// Stable identifier derived from the data and tags; metadata such as the timestamp
+    /// or pin state does not change it. The data is not normalized, so ids of stored
+    /// entries, which links and sync states refer to, never change.
+    pub fn id(&self) -> String {
+        let mut tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+        tags.sort_unstable();
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.data.as_bytes());
+        for tag in tags {
+            hasher.update(b"\0");
+            hasher.update(tag.as_bytes());
+        }
+        hasher.finalize()[..6]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Whether the entry has passed its expiry at `now`; pinned entries never expire
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        !self.pinned && self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Cut the data to at most `max_bytes` (on a character boundary) followed by
+    /// [`TRUNCATION_MARKER`], and mark the entry as truncated. Returns whether anything
+    /// was cut; data within the limit is left alone.
+    pub fn truncate_data(&mut self, max_bytes: usize) -> bool {
+        if self.data.len() <= max_bytes {
+            return false;
+        }
+
+        let mut cut = max_bytes;
+        while !self.data.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        self.data.truncate(cut);
+        self.data.push_str(TRUNCATION_MARKER);
+        self.truncated = true;
+        true
+    }
+
+    /// Trim the tags, drop blank ones and duplicates and sort the rest, so a tag set always
+    /// gets the same [`Self::tag_key`] however it was spelled. Only blank tags leave the
+    /// entry untagged.
+    pub fn normalize_tags(&mut self) {
+        for tag in &mut self.tags {
+            *tag = tag.trim().to_string();
+        }
+        self.tags.retain(|tag| !tag.is_empty());
+        self.tags.sort();
+        self.tags.dedup();
+    }
+
+    /// Key used to group entries by their tags
+    pub fn tag_key(&self) -> String {
+        if self.tags.is_empty() {
+            "untagged".to_string()
+        } else {
+            self.tags.join(" ")
+        }
+    }
+
+    /// Parse a single block; blocks without a header are legacy untagged entries
+    pub fn parse(block: &str) -> Option<Self> {
+        if block.trim().is_empty() {
+            return None;
+        }
+
+        let mut lines = block.lines();
+        let first_line = lines.next()?;
+
+        let Some(mut header) = EntryHeader::parse(first_line) else {
+            return Some(Self {
+                data: block.to_string(),
+                ..Default::default()
+            });
+        };
+
+        let timestamp = take_time(&mut header, TIMESTAMP_FIELD);
+        let expires = take_time(&mut header, EXPIRES_FIELD);
+        let pinned = header.fields.remove(PINNED_FIELD).as_deref() == Some("true");
+        let truncated = header.fields.remove(TRUNCATED_FIELD).as_deref() == Some("true");
+
+        Some(Self {
+            tags: header.tags,
+            data: lines.collect::<Vec<_>>().join("\n"),
+            timestamp,
+            pinned,
+            expires,
+            truncated,
+            fields: header.fields,
+        })
+    }
+
+    /// Render the entry as a block, without the trailing blank-line separator
+    pub fn to_block(&self) -> String {
+        let mut header = EntryHeader {
+            tags: self.tags.clone(),
+            fields: self.fields.clone(),
+        };
+        for (field, time) in [
+            (TIMESTAMP_FIELD, self.timestamp),
+            (EXPIRES_FIELD, self.expires),
+        ] {
+            if let Some(time) = time {
+                header.fields.insert(
+                    field.to_string(),
+                    time.to_rfc3339_opts(SecondsFormat::Millis, true),
+                );
+            }
+        }
+        for (field, set) in [
+            (PINNED_FIELD, self.pinned),
+            (TRUNCATED_FIELD, self.truncated),
+        ] {
+            if set {
+                header.fields.insert(field.to_string(), "true".to_string());
+            }
+        }
+
+        if header.is_empty() {
+            self.data.clone()
+        } else {
+            format!("{}\n{}", header.to_line(), self.data)
+        }
+    }
+}
+
+impl PartialEq for MemoryEntry {
+    fn eq(&self, other: &Self) -> bool {
+        normalize_data(&self.data) == normalize_data(&other.data)
+            && self.canonical_tags() == other.canonical_tags()
+    }
+}
+
+impl Eq for MemoryEntry {}
+
+impl Hash for MemoryEntry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        normalize_data(&self.data).hash(state);
+        self.canonical_tags().hash(state);
+    }
+}
+
+/// Data with surrounding whitespace trimmed and every inner run of whitespace collapsed to
+/// a single space, as entry equality compares it
+pub fn normalize_data(data: &str) -> String {
+    data.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Remove an RFC 3339 time field from a header; unparseable values are dropped
+fn take_time(header: &mut EntryHeader, field: &str) -> Option<DateTime<Utc>> {
+    header
+        .fields
+        .remove(field)
+        .and_then(|time| DateTime::parse_from_rfc3339(&time).ok())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// Parse the contents of a category file into its entries, in file order
+pub fn parse_entries(content: &str) -> Vec<MemoryEntry> {
+    content
+        .split("\n\n")
+        .filter_map(MemoryEntry::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_legacy_entries() {
+        let entries = parse_entries("# api auth\nfirst line\nsecond line\n\nno header\n\n");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].tags, vec!["api", "auth"]);
+        assert_eq!(entries[0].data, "first line\nsecond line");
+        assert!(entries[0].timestamp.is_none());
+        assert_eq!(entries[1].tag_key(), "untagged");
+        assert_eq!(entries[1].data, "no header");
+    }
+
+    #[test]
+    fn test_block_round_trip() {
+        let mut entry = MemoryEntry::new("some data", &["a", "b"]);
+        entry.timestamp = Some(
+            DateTime::parse_from_rfc3339("2024-05-01T10:00:00.250Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        );
+        entry
+            .fields
+            .insert("priority".to_string(), "high".to_string());
+        entry.pinned = true;
+        entry.expires = Some(
+            DateTime::parse_from_rfc3339("2024-05-02T10:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        );
+
+        let parsed = MemoryEntry::parse(&entry.to_block()).unwrap();
+        assert_eq!(parsed.tags, entry.tags);
+        assert_eq!(parsed.data, entry.data);
+        assert_eq!(parsed.timestamp, entry.timestamp);
+        assert!(parsed.pinned);
+        assert_eq!(parsed.expires, entry.expires);
+        assert_eq!(parsed.fields, entry.fields);
+    }
+
+    #[test]
+    fn test_offset_timestamps_are_stored_in_utc() {
+        // Entries written with an offset instead of `Z` still parse, and are rewritten in UTC
+        let entry = MemoryEntry::parse("# tags: api; ts: 2024-05-01T12:00:00+02:00\nnote").unwrap();
+        let block = entry.to_block();
+        assert!(block.contains("2024-05-01T10:00:00.000Z"));
+        assert_eq!(
+            MemoryEntry::parse(&block).unwrap().timestamp,
+            entry.timestamp
+        );
+    }
+
+    #[test]
+    fn test_entry_without_metadata_has_no_header() {
+        let entry = MemoryEntry::new("bare", &[]);
+        assert_eq!(entry.to_block(), "bare");
+    }
+
+    #[test]
+    fn test_id_ignores_metadata_and_tag_order() {
+        let mut entry = MemoryEntry::new("same data", &["b", "a"]);
+        let id = entry.id();
+        assert_eq!(id.len(), 12);
+
+        entry.timestamp = Some(Utc::now());
+        entry.pinned = true;
+        assert_eq!(entry.id(), id);
+        assert_eq!(MemoryEntry::new("same data", &["a", "b"]).id(), id);
+        assert_ne!(MemoryEntry::new("other data", &["a", "b"]).id(), id);
+    }
+
+    #[test]
+    fn test_equality_ignores_timestamp_and_whitespace() {
+        let mut stored = MemoryEntry::new("use tokio\nfor async", &["rust", "api"]);
+        stored.timestamp = Some(Utc::now());
+        stored.pinned = true;
+        let again = MemoryEntry::new("  use tokio for   async\n", &["api", " rust", "api"]);
+
+        assert_eq!(stored, again);
+        assert_eq!(stored.content_hash(), again.content_hash());
+        let hashes: std::collections::HashSet<MemoryEntry> = [stored.clone(), again].into();
+        assert_eq!(hashes.len(), 1);
+
+        assert_ne!(stored, MemoryEntry::new("use tokio for async", &["rust"]));
+        assert_ne!(
+            stored,
+            MemoryEntry::new("use tokio for sync", &["api", "rust"])
+        );
+        assert_ne!(
+            stored.content_hash(),
+            MemoryEntry::new("use tokio for sync", &["api", "rust"]).content_hash()
+        );
+    }
+
+    #[test]
+    fn test_truncate_data_boundary() {
+        let mut entry = MemoryEntry::new("abcdef", &[]);
+        assert!(!entry.truncate_data(6));
+        assert_eq!(entry.data, "abcdef");
+        assert!(!entry.truncated);
+
+        assert!(entry.truncate_data(5));
+        assert_eq!(entry.data, format!("abcde{}", TRUNCATION_MARKER));
+        assert!(entry.truncated);
+
+        let parsed = MemoryEntry::parse(&entry.to_block()).unwrap();
+        assert!(parsed.truncated);
+        assert_eq!(parsed.data, entry.data);
+    }
+
+    #[test]
+    fn test_truncate_data_keeps_whole_characters() {
+        // "é" is two bytes, so cutting after 2 bytes would split it
+        let mut entry = MemoryEntry::new("aéb", &[]);
+        assert!(entry.truncate_data(2));
+        assert_eq!(entry.data, format!("a{}", TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let now = Utc::now();
+        let mut entry = MemoryEntry::new("sprint goal", &[]);
+        assert!(!entry.is_expired(now));
+
+        entry.expires = Some(now - chrono::Duration::seconds(1));
+        assert!(entry.is_expired(now));
+
+        entry.pinned = true;
+        assert!(!entry.is_expired(now));
+    }
+}
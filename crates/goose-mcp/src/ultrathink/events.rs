@@ -0,0 +1,45 @@
+/// Events buffered per subscriber before the oldest are dropped
+pub const EVENT_CAPACITY: usize = 256;
+
+/// Which memory dir a mutation happened in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryScope {
+    Local,
+    Global,
+}
+
+impl MemoryScope {
+    pub fn from_global(is_global: bool) -> Self {
+        if is_global {
+            Self::Global
+        } else {
+            Self::Local
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryEventKind {
+    /// A new entry was stored
+    Created,
+    /// An existing entry changed without its content changing, e.g. it was pinned
+    Updated,
+    /// An entry was removed by compaction or expiry cleanup
+    Deleted,
+    /// The whole category was renamed or merged from `from`
+    CategoryRenamed { from: String },
+    /// A new entry was uploaded to Graphiti in the background after it was stored
+    Synced,
+    /// The background upload of a new entry failed; the next sync retries it
+    SyncFailed { error: String },
+}
+
+/// A change to the stored memories, broadcast to everyone subscribed to the router
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryEvent {
+    pub kind: MemoryEventKind,
+    pub scope: MemoryScope,
+    pub category: String,
+    /// The affected entry; `None` for events about a whole category
+    pub entry_id: Option<String>,
+}
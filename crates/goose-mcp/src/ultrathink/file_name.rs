@@ -0,0 +1,42 @@
+use percent_encoding::percent_decode_str;
+
+/// Characters that cannot appear in a file name on some supported OS, plus `%` itself so
+/// that encoded names decode unambiguously
+const UNSAFE_CHARS: &[char] = &['%', '/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// The file name stem a category is stored under. Unsafe characters and control characters
+/// are percent-encoded; everything else, non-ASCII included, is kept so that the files of
+/// ordinary categories keep their readable names.
+pub fn encode_category(category: &str) -> String {
+    let mut encoded = String::with_capacity(category.len());
+    for c in category.chars() {
+        if c.is_ascii_control() || UNSAFE_CHARS.contains(&c) {
+            encoded.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// The category stored under a file name stem. Stems that were not written by
+/// [`encode_category`] decode to themselves unless they happen to contain `%XX`.
+pub fn decode_category(stem: &str) -> String {
+    percent_decode_str(stem).decode_utf8_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_round_trips() {
+        for category in ["dev", "team: backend", "50% done?", "naïve café", "a*b|c"] {
+            let encoded = encode_category(category);
+            assert!(!encoded.contains(|c| matches!(c, ':' | '?' | '*' | '|')));
+            assert_eq!(decode_category(&encoded), category);
+        }
+        assert_eq!(encode_category("team: backend"), "team%3A backend");
+        assert_eq!(encode_category("naïve café"), "naïve café");
+    }
+}
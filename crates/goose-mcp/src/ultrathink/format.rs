@@ -0,0 +1,138 @@
+use serde_json::{json, Value};
+
+use super::sync_state::SyncReport;
+
+/// Presentation of UltraThink tool responses, so the embedding application can choose
+/// between terse text for CLIs and structured output for web UIs
+pub trait ResponseFormatter: Send + Sync {
+    /// A memory was stored in `category` as the entry `id`, which the id-based tools
+    /// (`ultrathink_get_entry`, `ultrathink_link`, ...) take to refer to it
+    fn stored(&self, category: &str, id: &str) -> String;
+    /// Memories were retrieved; `memories` is the JSON form of whatever shape was requested
+    fn retrieved(&self, memories: &Value) -> String;
+    /// A single category was retrieved that was never created
+    fn missing_category(&self, category: &str) -> String;
+    /// A single category was retrieved that exists but holds no live entries
+    fn empty_category(&self, category: &str) -> String;
+    /// A Graphiti sync finished, possibly with some entries failing
+    fn synced(&self, report: &SyncReport) -> String;
+    /// An operation failed in a way that is reported as content rather than as a tool error
+    fn error(&self, message: &str) -> String;
+}
+
+/// The default, emoji-prefixed style
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EmojiFormatter;
+
+impl ResponseFormatter for EmojiFormatter {
+    fn stored(&self, category: &str, id: &str) -> String {
+        format!(
+            "📝 UltraThink memory stored in category: {} (id: {})",
+            category, id
+        )
+    }
+
+    fn retrieved(&self, memories: &Value) -> String {
+        format!("🧠 UltraThink memories retrieved: {}", memories)
+    }
+
+    fn missing_category(&self, category: &str) -> String {
+        format!("🔍 No such UltraThink category: {}", category)
+    }
+
+    fn empty_category(&self, category: &str) -> String {
+        format!("🫙 UltraThink category is empty: {}", category)
+    }
+
+    fn synced(&self, report: &SyncReport) -> String {
+        format!("{}\n{}", report.summary(), json!(report))
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("❌ {}", message)
+    }
+}
+
+/// One JSON object per response, tagged with a `status` field
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonFormatter;
+
+impl ResponseFormatter for JsonFormatter {
+    fn stored(&self, category: &str, id: &str) -> String {
+        json!({"status": "stored", "category": category, "id": id}).to_string()
+    }
+
+    fn retrieved(&self, memories: &Value) -> String {
+        json!({"status": "retrieved", "memories": memories}).to_string()
+    }
+
+    fn missing_category(&self, category: &str) -> String {
+        json!({"status": "missing", "category": category}).to_string()
+    }
+
+    fn empty_category(&self, category: &str) -> String {
+        json!({"status": "empty", "category": category}).to_string()
+    }
+
+    fn synced(&self, report: &SyncReport) -> String {
+        json!({"status": "synced", "summary": report.summary(), "report": report}).to_string()
+    }
+
+    fn error(&self, message: &str) -> String {
+        json!({"status": "error", "message": message}).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emoji_formatter() {
+        let formatter = EmojiFormatter;
+        assert_eq!(
+            formatter.stored("dev", "1a2b3c4d5e6f"),
+            "📝 UltraThink memory stored in category: dev (id: 1a2b3c4d5e6f)"
+        );
+        assert_eq!(
+            formatter.retrieved(&json!({"untagged": ["a"]})),
+            r#"🧠 UltraThink memories retrieved: {"untagged":["a"]}"#
+        );
+        assert_eq!(
+            formatter.missing_category("dev"),
+            "🔍 No such UltraThink category: dev"
+        );
+        let synced = formatter.synced(&SyncReport::new("to_graphiti", None));
+        let (summary, report) = synced.split_once('\n').unwrap();
+        assert_eq!(summary, SyncReport::new("to_graphiti", None).summary());
+        let report: Value = serde_json::from_str(report).unwrap();
+        assert_eq!(report["direction"], "to_graphiti");
+        assert_eq!(formatter.error("boom"), "❌ boom");
+    }
+
+    #[test]
+    fn test_json_formatter() {
+        let formatter = JsonFormatter;
+
+        let stored: Value = serde_json::from_str(&formatter.stored("dev", "1a2b3c4d5e6f")).unwrap();
+        assert_eq!(
+            stored,
+            json!({"status": "stored", "category": "dev", "id": "1a2b3c4d5e6f"})
+        );
+
+        let retrieved: Value =
+            serde_json::from_str(&formatter.retrieved(&json!({"untagged": ["a"]}))).unwrap();
+        assert_eq!(retrieved["memories"]["untagged"][0], "a");
+        let empty: Value = serde_json::from_str(&formatter.empty_category("dev")).unwrap();
+        assert_eq!(empty, json!({"status": "empty", "category": "dev"}));
+
+        let mut report = SyncReport::new("to_graphiti", None);
+        report.uploaded = 2;
+        let synced: Value = serde_json::from_str(&formatter.synced(&report)).unwrap();
+        assert_eq!(synced["summary"], report.summary());
+        assert_eq!(synced["report"]["uploaded"], 2);
+
+        let error: Value = serde_json::from_str(&formatter.error("boom")).unwrap();
+        assert_eq!(error, json!({"status": "error", "message": "boom"}));
+    }
+}
@@ -0,0 +1,655 @@
+use async_trait::async_trait;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use mcp_core::handler::{ToolError, ToolResult};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Shared message returned by every call made without a configured endpoint
+pub const NOT_CONFIGURED: &str = "Graphiti MCP endpoint not configured";
+
+/// Endpoint whose graph is kept in process by [`InMemoryGraphiti`], for offline demos;
+/// every client built for it starts with an empty graph of its own
+pub const IN_MEMORY_ENDPOINT: &str = "memory://";
+
+/// Shared message returned by every call while offline mode is active
+pub const OFFLINE: &str = "Offline mode active (GOOSE_OFFLINE), Graphiti calls are disabled";
+
+#[derive(Debug, thiserror::Error)]
+pub enum GraphitiError {
+    #[error("{}", NOT_CONFIGURED)]
+    NotConfigured,
+    #[error("{}", OFFLINE)]
+    Offline,
+    /// The client could not be built, e.g. because its endpoint is malformed
+    #[error("Graphiti unavailable: {0}")]
+    Unavailable(String),
+    #[error("Invalid Graphiti endpoint '{0}': {1}")]
+    InvalidEndpoint(String, String),
+    #[error("Graphiti request failed: {0}")]
+    Transport(String),
+    #[error("Unexpected Graphiti response: {0}")]
+    InvalidResponse(String),
+}
+
+impl From<GraphitiError> for io::Error {
+    fn from(err: GraphitiError) -> Self {
+        io::Error::other(err)
+    }
+}
+
+/// A malformed endpoint is a configuration the caller has to fix; every other failure is
+/// reported as an execution failure
+impl From<GraphitiError> for ToolError {
+    fn from(err: GraphitiError) -> Self {
+        match err {
+            GraphitiError::InvalidEndpoint(..) => ToolError::InvalidParameters(err.to_string()),
+            _ => ToolError::ExecutionError(err.to_string()),
+        }
+    }
+}
+
+/// An entity in the Graphiti graph, as returned by the memory server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphitiNode {
+    pub name: String,
+    #[serde(rename = "entityType")]
+    pub entity_type: String,
+    #[serde(default)]
+    pub observations: Vec<String>,
+}
+
+impl GraphitiNode {
+    /// Read the `entities` of a memory server graph response
+    pub fn from_graph(graph: Value) -> Result<Vec<Self>, GraphitiError> {
+        let entities = match graph {
+            Value::Object(mut graph) => graph.remove("entities").unwrap_or(Value::Array(vec![])),
+            other => return Err(GraphitiError::InvalidResponse(other.to_string())),
+        };
+        serde_json::from_value(entities).map_err(|e| GraphitiError::InvalidResponse(e.to_string()))
+    }
+}
+
+/// Carries a single MCP tool call to a Graphiti memory server
+#[async_trait]
+pub trait GraphitiBackend: Send + Sync {
+    async fn call(&self, server: &str, method: &str, params: Value)
+        -> Result<Value, GraphitiError>;
+}
+
+/// Describes the call instead of making it, for clients built for an explicit endpoint
+/// rather than from the environment, e.g. in tests
+pub struct SimulatedBackend;
+
+#[async_trait]
+impl GraphitiBackend for SimulatedBackend {
+    async fn call(
+        &self,
+        server: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, GraphitiError> {
+        // Check if we can access the memory server by trying to run it
+        let response = if std::env::var("ULTRATHINK_GRAPHITI_TEST").is_ok() {
+            // This would be replaced with actual MCP client code
+            format!(
+                "✅ MCP call to {}: {}({}) - Simulated success",
+                server, method, params
+            )
+        } else {
+            format!(
+                "🔗 Would call MCP {}.{}({}) when GRAPHITI_MCP_ENDPOINT is configured",
+                server, method, params
+            )
+        };
+        Ok(Value::String(response))
+    }
+}
+
+/// Sends each call as a JSON-RPC 2.0 request, POSTed to the endpoint, and answers with the
+/// `result` of the response
+pub struct JsonRpcBackend {
+    url: String,
+    http: reqwest::Client,
+    next_id: AtomicU64,
+    /// Whether request bodies are gzipped; cleared once the server turns a gzipped body
+    /// down but accepts it uncompressed
+    compress: AtomicBool,
+}
+
+impl JsonRpcBackend {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            url: endpoint_url(endpoint.trim()),
+            http: reqwest::Client::new(),
+            next_id: AtomicU64::new(1),
+            compress: AtomicBool::new(false),
+        }
+    }
+
+    /// Send request bodies gzipped with `Content-Encoding: gzip`. A request the server
+    /// answers with an error status is sent once more uncompressed, and when that one
+    /// succeeds, later requests are no longer compressed.
+    pub fn with_compression(self, compress: bool) -> Self {
+        Self {
+            compress: AtomicBool::new(compress),
+            ..self
+        }
+    }
+
+    async fn post(&self, body: &[u8], gzip: bool) -> Result<reqwest::Response, GraphitiError> {
+        let mut request = self
+            .http
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        request = if gzip {
+            let compressed =
+                gzip_encode(body).map_err(|e| GraphitiError::Transport(e.to_string()))?;
+            request
+                .header(reqwest::header::CONTENT_ENCODING, "gzip")
+                .body(compressed)
+        } else {
+            request.body(body.to_vec())
+        };
+        request
+            .send()
+            .await
+            .map_err(|e| GraphitiError::Transport(e.to_string()))
+    }
+}
+
+fn gzip_encode(body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+#[async_trait]
+impl GraphitiBackend for JsonRpcBackend {
+    async fn call(
+        &self,
+        _server: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, GraphitiError> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+            "method": method,
+            "params": params,
+        });
+        let body = request.to_string().into_bytes();
+        let compress = self.compress.load(Ordering::Relaxed);
+        let mut response = self.post(&body, compress).await?;
+        if compress && response.status() != reqwest::StatusCode::OK {
+            // The server may not take gzipped bodies, so retry once without compression
+            response = self.post(&body, false).await?;
+            if response.status() == reqwest::StatusCode::OK {
+                tracing::warn!(
+                    "{} rejected a gzipped request body, sending requests uncompressed",
+                    self.url
+                );
+                self.compress.store(false, Ordering::Relaxed);
+            }
+        }
+        let status = response.status();
+        if status != reqwest::StatusCode::OK {
+            return Err(GraphitiError::Transport(format!(
+                "{} answered with HTTP status {}",
+                self.url,
+                status.as_u16()
+            )));
+        }
+
+        let mut response: Value = response
+            .json()
+            .await
+            .map_err(|e| GraphitiError::InvalidResponse(e.to_string()))?;
+        if let Some(error) = response.get("error") {
+            return Err(GraphitiError::Transport(format!(
+                "JSON-RPC error: {}",
+                error
+            )));
+        }
+        match response.get_mut("result") {
+            Some(result) => Ok(result.take()),
+            None => Err(GraphitiError::InvalidResponse(response.to_string())),
+        }
+    }
+}
+
+/// The URL of an endpoint, which may be a `host:port` pair meaning plain HTTP
+pub fn endpoint_url(endpoint: &str) -> String {
+    if endpoint.contains("://") {
+        endpoint.to_string()
+    } else {
+        format!("http://{}", endpoint)
+    }
+}
+
+/// A relation between two entities in the Graphiti graph
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphitiRelation {
+    pub from: String,
+    pub to: String,
+    #[serde(rename = "relationType")]
+    pub relation_type: String,
+}
+
+impl GraphitiRelation {
+    /// Read the `relations` of a memory server graph response
+    pub fn from_graph(graph: &Value) -> Result<Vec<Self>, GraphitiError> {
+        match graph.get("relations") {
+            None | Some(Value::Null) => Ok(Vec::new()),
+            Some(relations) => serde_json::from_value(relations.clone())
+                .map_err(|e| GraphitiError::InvalidResponse(e.to_string())),
+        }
+    }
+}
+
+/// An entity one relation away from another, as listed by `GraphitiClient::neighbors`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphitiNeighbor {
+    pub name: String,
+    pub relation: String,
+    /// Empty when the graph holds the relation but not the entity itself
+    pub observations: Vec<String>,
+}
+
+/// An entity as sent to `create_entities`
+#[derive(Deserialize)]
+struct NewEntity {
+    #[serde(flatten)]
+    node: GraphitiNode,
+    #[serde(rename = "idempotencyKey")]
+    idempotency_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NewObservations {
+    #[serde(rename = "entityName")]
+    entity_name: String,
+    contents: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ObservationDeletion {
+    #[serde(rename = "entityName")]
+    entity_name: String,
+    observations: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct Graph {
+    entities: BTreeMap<String, GraphitiNode>,
+    relations: Vec<GraphitiRelation>,
+    /// Entity name per idempotency key, so a store repeated with the same key creates one
+    /// entity
+    keys: BTreeMap<String, String>,
+}
+
+impl Graph {
+    /// The graph response for `entities` and the relations between them
+    fn subgraph<'a>(&self, entities: impl IntoIterator<Item = &'a GraphitiNode>) -> Value {
+        let entities: Vec<&GraphitiNode> = entities.into_iter().collect();
+        let relations: Vec<&GraphitiRelation> = self
+            .relations
+            .iter()
+            .filter(|relation| {
+                entities.iter().any(|node| node.name == relation.from)
+                    && entities.iter().any(|node| node.name == relation.to)
+            })
+            .collect();
+        json!({"entities": entities, "relations": relations})
+    }
+
+    fn entity_mut(&mut self, name: &str) -> Result<&mut GraphitiNode, GraphitiError> {
+        self.entities
+            .get_mut(name)
+            .ok_or_else(|| GraphitiError::Transport(format!("Entity with name {} not found", name)))
+    }
+}
+
+/// Keeps the graph in memory and answers the memory server tools the client calls, so
+/// stored entries can be read back in tests and offline demos without a server
+#[derive(Debug, Default)]
+pub struct InMemoryGraphiti {
+    graph: Mutex<Graph>,
+}
+
+#[async_trait]
+impl GraphitiBackend for InMemoryGraphiti {
+    async fn call(
+        &self,
+        _server: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, GraphitiError> {
+        let mut graph = self.graph.lock().unwrap();
+        match method {
+            "create_entities" => {
+                let mut created = Vec::new();
+                for NewEntity {
+                    node,
+                    idempotency_key,
+                } in param::<Vec<NewEntity>>(&params, "entities")?
+                {
+                    let known = idempotency_key
+                        .as_ref()
+                        .is_some_and(|key| graph.keys.contains_key(key));
+                    if known || graph.entities.contains_key(&node.name) {
+                        continue;
+                    }
+                    if let Some(key) = idempotency_key {
+                        graph.keys.insert(key, node.name.clone());
+                    }
+                    created.push(node.clone());
+                    graph.entities.insert(node.name.clone(), node);
+                }
+                Ok(json!(created))
+            }
+            "add_observations" => {
+                let mut added = Vec::new();
+                for NewObservations {
+                    entity_name,
+                    contents,
+                } in param::<Vec<NewObservations>>(&params, "observations")?
+                {
+                    let node = graph.entity_mut(&entity_name)?;
+                    let new: Vec<String> = contents
+                        .into_iter()
+                        .filter(|content| !node.observations.contains(content))
+                        .collect();
+                    node.observations.extend(new.iter().cloned());
+                    added.push(json!({"entityName": entity_name, "addedObservations": new}));
+                }
+                Ok(json!(added))
+            }
+            "delete_observations" => {
+                for ObservationDeletion {
+                    entity_name,
+                    observations,
+                } in param::<Vec<ObservationDeletion>>(&params, "deletions")?
+                {
+                    if let Some(node) = graph.entities.get_mut(&entity_name) {
+                        node.observations
+                            .retain(|observation| !observations.contains(observation));
+                    }
+                }
+                Ok(json!("Observations deleted successfully"))
+            }
+            "delete_entities" => {
+                let names = param::<Vec<String>>(&params, "entityNames")?;
+                let graph = &mut *graph;
+                graph.entities.retain(|name, _| !names.contains(name));
+                graph.keys.retain(|_, name| !names.contains(name));
+                graph.relations.retain(|relation| {
+                    !names.contains(&relation.from) && !names.contains(&relation.to)
+                });
+                Ok(json!("Entities deleted successfully"))
+            }
+            "create_relations" => {
+                let mut created = Vec::new();
+                for relation in param::<Vec<GraphitiRelation>>(&params, "relations")? {
+                    if !graph.relations.contains(&relation) {
+                        created.push(relation.clone());
+                        graph.relations.push(relation);
+                    }
+                }
+                Ok(json!(created))
+            }
+            "read_graph" => Ok(graph.subgraph(graph.entities.values())),
+            "search_nodes" => {
+                let query = param::<String>(&params, "query")?.to_lowercase();
+                let matches_query = |text: &str| text.to_lowercase().contains(&query);
+                Ok(graph.subgraph(graph.entities.values().filter(|node| {
+                    matches_query(&node.name)
+                        || matches_query(&node.entity_type)
+                        || node.observations.iter().any(|o| matches_query(o))
+                })))
+            }
+            "open_nodes" => {
+                let names = param::<Vec<String>>(&params, "names")?;
+                Ok(graph.subgraph(names.iter().filter_map(|name| graph.entities.get(name))))
+            }
+            _ => Err(GraphitiError::Transport(format!(
+                "Unknown tool: {}",
+                method
+            ))),
+        }
+    }
+}
+
+/// Read the `field` parameter of a call, rejecting it as the memory server would when it
+/// is missing or malformed
+fn param<T: DeserializeOwned>(params: &Value, field: &str) -> Result<T, GraphitiError> {
+    let value = params.get(field).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value)
+        .map_err(|e| GraphitiError::Transport(format!("Invalid {} parameter: {}", field, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(err: GraphitiError) -> ToolResult<()> {
+        Err(err)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_graphiti_error_conversion() {
+        let invalid = run(GraphitiError::InvalidEndpoint(
+            "not a url".to_string(),
+            "relative URL without a base".to_string(),
+        ));
+        assert_eq!(
+            invalid,
+            Err(ToolError::InvalidParameters(
+                "Invalid Graphiti endpoint 'not a url': relative URL without a base".to_string()
+            ))
+        );
+
+        let offline = run(GraphitiError::Offline);
+        assert_eq!(offline, Err(ToolError::ExecutionError(OFFLINE.to_string())));
+
+        let transport = run(GraphitiError::Transport("connection reset".to_string()));
+        assert!(
+            matches!(transport, Err(ToolError::ExecutionError(m)) if m.contains("connection reset"))
+        );
+    }
+
+    /// Answer one HTTP request on a local port with `status` and `body`, handing back the
+    /// request it received
+    async fn serve_once(
+        status: &'static str,
+        body: &'static str,
+    ) -> (String, tokio::task::JoinHandle<Vec<u8>>) {
+        let (endpoint, server) = serve(vec![(status, body)]).await;
+        let server = tokio::spawn(async move { server.await.unwrap().remove(0) });
+        (endpoint, server)
+    }
+
+    /// Answer one HTTP request per entry of `responses`, each on its own connection, handing
+    /// back the requests received
+    async fn serve(
+        responses: Vec<(&'static str, &'static str)>,
+    ) -> (String, tokio::task::JoinHandle<Vec<Vec<u8>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for (status, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                while !is_complete(&request) {
+                    let read = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..read]);
+                }
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (endpoint, server)
+    }
+
+    /// The headers of `request`, lowercased, and its body
+    fn split_request(request: &[u8]) -> Option<(String, &[u8])> {
+        let end = request
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&request[..end]).to_ascii_lowercase();
+        Some((headers, &request[end + 4..]))
+    }
+
+    /// Whether `request` holds its headers and as much body as they announce
+    fn is_complete(request: &[u8]) -> bool {
+        let Some((headers, body)) = split_request(request) else {
+            return false;
+        };
+        let length = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:")?.trim().parse().ok())
+            .unwrap_or(0);
+        body.len() >= length
+    }
+
+    #[tokio::test]
+    async fn test_json_rpc_round_trip() {
+        let (endpoint, server) = serve_once(
+            "200 OK",
+            r#"{"jsonrpc": "2.0", "id": 1, "result": {"entities": []}}"#,
+        )
+        .await;
+        let backend = JsonRpcBackend::new(&endpoint);
+        let result = backend
+            .call("memory", "search_nodes", json!({"query": "DEV"}))
+            .await
+            .unwrap();
+        assert_eq!(result, json!({"entities": []}));
+
+        let request = server.await.unwrap();
+        let (headers, body) = split_request(&request).unwrap();
+        assert!(headers.starts_with("post / http/1.1"));
+        assert!(!headers.contains("content-encoding"));
+        assert_eq!(
+            serde_json::from_slice::<Value>(body).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "search_nodes",
+                "params": {"query": "DEV"}
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_rpc_failures() {
+        let (endpoint, _server) = serve_once("503 Service Unavailable", "{}").await;
+        let err = JsonRpcBackend::new(&endpoint)
+            .call("memory", "read_graph", json!({}))
+            .await
+            .unwrap_err();
+        let err = io::Error::from(err);
+        assert!(err.to_string().contains("HTTP status 503"), "{}", err);
+
+        let (endpoint, _server) = serve_once(
+            "200 OK",
+            r#"{"jsonrpc": "2.0", "id": 1, "error": {"code": -32601, "message": "Method not found"}}"#,
+        )
+        .await;
+        let err = JsonRpcBackend::new(&endpoint)
+            .call("memory", "forget", json!({}))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Method not found"));
+    }
+
+    /// The JSON body of a gzipped request
+    fn gunzip(body: &[u8]) -> Value {
+        use std::io::Read;
+
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(body)
+            .read_to_string(&mut json)
+            .unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_json_rpc_compressed_requests() {
+        let (endpoint, server) = serve_once(
+            "200 OK",
+            r#"{"jsonrpc": "2.0", "id": 1, "result": {"entities": []}}"#,
+        )
+        .await;
+        let backend = JsonRpcBackend::new(&endpoint).with_compression(true);
+        let result = backend
+            .call("memory", "search_nodes", json!({"query": "DEV"}))
+            .await
+            .unwrap();
+        assert_eq!(result, json!({"entities": []}));
+
+        let request = server.await.unwrap();
+        let (headers, body) = split_request(&request).unwrap();
+        assert!(headers.contains("content-encoding: gzip"));
+        assert_eq!(
+            gunzip(body),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "search_nodes",
+                "params": {"query": "DEV"}
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_rpc_compression_fallback() {
+        let result = r#"{"jsonrpc": "2.0", "id": 1, "result": {}}"#;
+        let (endpoint, server) = serve(vec![
+            ("415 Unsupported Media Type", "{}"),
+            ("200 OK", result),
+            ("200 OK", result),
+        ])
+        .await;
+        let backend = JsonRpcBackend::new(&endpoint).with_compression(true);
+        backend
+            .call("memory", "read_graph", json!({}))
+            .await
+            .unwrap();
+        backend
+            .call("memory", "read_graph", json!({}))
+            .await
+            .unwrap();
+
+        // The rejected body is sent again uncompressed, and so is every later one
+        let requests = server.await.unwrap();
+        let (headers, body) = split_request(&requests[0]).unwrap();
+        assert!(headers.contains("content-encoding: gzip"));
+        assert_eq!(gunzip(body)["method"], "read_graph");
+        for request in &requests[1..] {
+            let (headers, body) = split_request(request).unwrap();
+            assert!(!headers.contains("content-encoding"));
+            assert_eq!(
+                serde_json::from_slice::<Value>(body).unwrap()["method"],
+                "read_graph"
+            );
+        }
+    }
+}
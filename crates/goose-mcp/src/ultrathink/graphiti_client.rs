@@ -1,20 +1,237 @@
 use serde_json::{json, Value};
+use std::collections::BTreeMap;
 use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::config::env_flag;
+use super::graphiti_backend::{
+    endpoint_url, GraphitiBackend, GraphitiError, GraphitiNeighbor, GraphitiNode, GraphitiRelation,
+    InMemoryGraphiti, JsonRpcBackend, SimulatedBackend, IN_MEMORY_ENDPOINT, NOT_CONFIGURED,
+    OFFLINE,
+};
+use super::query_cache::{QueryCache, QueryKey, DEFAULT_CACHE_SIZE, DEFAULT_CACHE_TTL};
+use super::sync_state::{idempotency_key, SyncReport};
+
+/// How [`GraphitiClient::update_entity`] treats the observations an entity already has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateMode {
+    /// Drop them, so the entity holds only the new observations
+    Replace,
+    /// Keep them and add the new observations after them
+    Append,
+}
 
 /// Simple Graphiti client that uses MCP memory server
 #[derive(Clone)]
 pub struct GraphitiClient {
     memory_server_endpoint: Option<String>,
+    offline: bool,
+    /// Shared between clones, so every handle of a router sees the same cached results
+    cache: Arc<Mutex<QueryCache>>,
+    backend: Arc<dyn GraphitiBackend>,
+    /// Entity type per category, for graphs with a fixed schema
+    entity_types: BTreeMap<String, String>,
+    /// Why the client could not be built; every call reports it instead of reaching Graphiti
+    failure: Option<String>,
 }
 
 impl GraphitiClient {
+    /// Client for `GRAPHITI_MCP_ENDPOINT`. Never fails: a client that cannot be built is
+    /// replaced by one reporting why, see [`Self::failed`].
     pub fn new() -> Self {
+        Self::try_new().unwrap_or_else(Self::failed)
+    }
+
+    pub fn try_new() -> Result<Self, GraphitiError> {
+        Self::try_from_env(std::env::var("GRAPHITI_MCP_ENDPOINT").ok())
+    }
+
+    /// Client for the global or local memory scope, falling back like [`Self::new`]
+    pub fn for_scope(is_global: bool, configured: Option<String>) -> Self {
+        Self::try_for_scope(is_global, configured).unwrap_or_else(Self::failed)
+    }
+
+    /// Client for the global or local memory scope. `GRAPHITI_GLOBAL_MCP_ENDPOINT` and
+    /// `GRAPHITI_LOCAL_MCP_ENDPOINT` select a graph per scope, see [`scope_endpoints`];
+    /// `configured` is used when none of the `GRAPHITI_*MCP_ENDPOINT` variables is set.
+    pub fn try_for_scope(
+        is_global: bool,
+        configured: Option<String>,
+    ) -> Result<Self, GraphitiError> {
+        let (global, local) = scope_endpoints(
+            std::env::var("GRAPHITI_MCP_ENDPOINT").ok(),
+            std::env::var("GRAPHITI_GLOBAL_MCP_ENDPOINT").ok(),
+            std::env::var("GRAPHITI_LOCAL_MCP_ENDPOINT").ok(),
+        );
+        let endpoint = if is_global { global } else { local };
+        Self::try_from_env(endpoint.or(configured))
+    }
+
+    /// A client for a configured endpoint talks JSON-RPC over HTTP to it, see
+    /// [`JsonRpcBackend`]
+    fn try_from_env(endpoint: Option<String>) -> Result<Self, GraphitiError> {
+        let mut client = Self::try_with_endpoint(endpoint)?.with_offline(env_flag("GOOSE_OFFLINE"));
+        client.cache = Arc::new(Mutex::new(QueryCache::from_env()));
+        if let Some(endpoint) = client.json_rpc_endpoint() {
+            client.backend = Arc::new(JsonRpcBackend::new(endpoint));
+        }
+        Ok(client)
+    }
+
+    /// The endpoint calls are sent to over JSON-RPC, if any
+    fn json_rpc_endpoint(&self) -> Option<&str> {
+        self.endpoint().filter(|e| e.trim() != IN_MEMORY_ENDPOINT)
+    }
+
+    /// Gzip the request bodies sent to the endpoint, see [`JsonRpcBackend::with_compression`].
+    /// Compressing replaces the backend, so call this before [`Self::with_backend`].
+    pub fn with_compressed_requests(mut self, compress: bool) -> Self {
+        if compress {
+            if let Some(endpoint) = self.json_rpc_endpoint() {
+                self.backend = Arc::new(JsonRpcBackend::new(endpoint).with_compression(true));
+            }
+        }
+        self
+    }
+
+    /// A client standing in for one that could not be built. Local memory keeps working
+    /// while every Graphiti call reports "Graphiti unavailable: <reason>".
+    pub fn failed(err: GraphitiError) -> Self {
+        tracing::warn!("Graphiti client could not be created: {}", err);
+        let mut client = Self::with_endpoint(None);
+        client.failure = Some(err.to_string());
+        client
+    }
+
+    /// Like [`Self::with_endpoint`], but rejects an endpoint that is not a valid URL or
+    /// `host:port` pair
+    pub fn try_with_endpoint(endpoint: Option<String>) -> Result<Self, GraphitiError> {
+        if let Some(endpoint) = endpoint.as_deref().map(str::trim) {
+            if !endpoint.is_empty() {
+                validate_endpoint(endpoint)?;
+            }
+        }
+        Ok(Self::with_endpoint(endpoint))
+    }
+
+    /// Create a client for an explicit endpoint; blank endpoints count as unconfigured, and
+    /// [`IN_MEMORY_ENDPOINT`] keeps the graph in memory. Calls to any other endpoint are
+    /// only described, see [`SimulatedBackend`], until [`Self::with_backend`] sets a
+    /// transport.
+    pub fn with_endpoint(endpoint: Option<String>) -> Self {
+        let endpoint = endpoint.filter(|e| !e.trim().is_empty());
+        let backend: Arc<dyn GraphitiBackend> = match endpoint.as_deref().map(str::trim) {
+            Some(IN_MEMORY_ENDPOINT) => Arc::new(InMemoryGraphiti::default()),
+            _ => Arc::new(SimulatedBackend),
+        };
         Self {
-            memory_server_endpoint: std::env::var("GRAPHITI_MCP_ENDPOINT").ok(),
+            memory_server_endpoint: endpoint,
+            offline: false,
+            cache: Arc::new(Mutex::new(QueryCache::new(
+                DEFAULT_CACHE_SIZE,
+                DEFAULT_CACHE_TTL,
+            ))),
+            backend,
+            entity_types: BTreeMap::new(),
+            failure: None,
+        }
+    }
+
+    /// Store categories as the mapped entity types instead of their uppercased names
+    pub fn with_entity_types(mut self, entity_types: BTreeMap<String, String>) -> Self {
+        self.entity_types = entity_types;
+        self
+    }
+
+    /// The entity type entries of `category` are stored as
+    pub fn entity_type(&self, category: &str) -> String {
+        self.entity_types
+            .get(category)
+            .cloned()
+            .unwrap_or_else(|| category.to_uppercase())
+    }
+
+    /// The categories whose entries are stored as `entity_type`; several categories can
+    /// share a mapped type, and an unmapped type is the lowercased category name
+    pub fn categories_for(&self, entity_type: &str) -> Vec<String> {
+        let mapped: Vec<String> = self
+            .entity_types
+            .iter()
+            .filter(|(_, mapped)| *mapped == entity_type)
+            .map(|(category, _)| category.clone())
+            .collect();
+        if !mapped.is_empty() {
+            return mapped;
+        }
+        let category = entity_type.to_lowercase();
+        if self.entity_types.contains_key(&category) {
+            // That category is stored under a different type
+            return Vec::new();
+        }
+        vec![category]
+    }
+
+    /// Send calls through `backend` instead of the one chosen for the endpoint
+    pub fn with_backend(mut self, backend: Arc<dyn GraphitiBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Cache up to `capacity` retrieval results for `ttl`; a capacity of zero disables caching
+    pub fn with_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.cache = Arc::new(Mutex::new(QueryCache::new(capacity, ttl)));
+        self
+    }
+
+    /// Offline mode guarantees no network I/O, even when an endpoint is configured
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Whether a Graphiti MCP endpoint has been configured
+    pub fn is_configured(&self) -> bool {
+        self.memory_server_endpoint.is_some()
+    }
+
+    /// The configured Graphiti MCP endpoint, if any
+    pub fn endpoint(&self) -> Option<&str> {
+        self.memory_server_endpoint.as_deref()
+    }
+
+    /// Why Graphiti can't be called right now, as an error
+    fn check_available(&self) -> Result<(), GraphitiError> {
+        if let Some(reason) = &self.failure {
+            Err(GraphitiError::Unavailable(reason.clone()))
+        } else if self.is_offline() {
+            Err(GraphitiError::Offline)
+        } else if !self.is_configured() {
+            Err(GraphitiError::NotConfigured)
+        } else {
+            Ok(())
         }
     }
 
-    /// Store memory in Graphiti through MCP memory server
+    /// Why Graphiti can't be called right now, or `None` when calls may proceed
+    pub fn unavailable(&self) -> Option<String> {
+        if let Some(reason) = &self.failure {
+            Some(format!("⚠️ Graphiti unavailable: {}", reason))
+        } else if self.is_offline() {
+            Some(format!("📴 {}", OFFLINE))
+        } else if !self.is_configured() {
+            Some(format!("⚠️ {}", NOT_CONFIGURED))
+        } else {
+            None
+        }
+    }
+
+    /// Store memory in Graphiti through MCP memory server. The entity carries the
+    /// [`idempotency_key`] of its content, so storing it again is recognised server-side.
     pub async fn store_memory(
         &self,
         category: &str,
@@ -22,8 +239,8 @@ impl GraphitiClient {
         _tags: &[String],
         context: Option<&str>,
     ) -> Result<String, io::Error> {
-        if self.memory_server_endpoint.is_none() {
-            return Ok("⚠️ Graphiti MCP endpoint not configured".to_string());
+        if let Some(message) = self.unavailable() {
+            return Ok(message);
         }
 
         // Prepare enhanced data with context
@@ -33,37 +250,225 @@ impl GraphitiClient {
             data.to_string()
         };
 
-        // In a real implementation, this would make MCP calls to memory server
-        // For now, we'll simulate the call
-        self.simulate_mcp_call("memory", "create_entities", json!({
-            "entities": [{
-                "name": format!("{}_{}", category, uuid::Uuid::new_v4()),
-                "entityType": category.to_uppercase(),
-                "observations": [enhanced_data]
-            }]
-        })).await
+        let name = entity_name(category);
+        let entity_type = self.entity_type(category);
+        let idempotency_key = idempotency_key(category, &enhanced_data);
+
+        let result = self
+            .mcp_call(
+                "memory",
+                "create_entities",
+                json!({
+                    "entities": [{
+                        "name": name,
+                        "entityType": entity_type,
+                        "observations": [enhanced_data],
+                        "idempotencyKey": idempotency_key
+                    }]
+                }),
+            )
+            .await?;
+
+        self.cache.lock().unwrap().invalidate_matching(&[
+            &name,
+            &entity_type,
+            enhanced_data.as_str(),
+        ]);
+        Ok(result)
+    }
+
+    /// Change the observations of an existing entity through MCP memory server, so a
+    /// corrected fact replaces the stale one instead of being stored next to it
+    pub async fn update_entity(
+        &self,
+        name: &str,
+        observations: Vec<String>,
+        mode: UpdateMode,
+    ) -> Result<String, io::Error> {
+        if let Some(message) = self.unavailable() {
+            return Ok(message);
+        }
+
+        let mut stale = Vec::new();
+        if mode == UpdateMode::Replace {
+            let graph = self
+                .backend
+                .call("memory", "open_nodes", json!({"names": [name]}))
+                .await?;
+            stale = GraphitiNode::from_graph(graph)?
+                .into_iter()
+                .filter(|node| node.name == name)
+                .flat_map(|node| node.observations)
+                .collect();
+            if !stale.is_empty() {
+                self.backend
+                    .call(
+                        "memory",
+                        "delete_observations",
+                        json!({"deletions": [{"entityName": name, "observations": stale}]}),
+                    )
+                    .await?;
+            }
+        }
+
+        let result = self
+            .mcp_call(
+                "memory",
+                "add_observations",
+                json!({
+                    "observations": [{
+                        "entityName": name,
+                        "contents": observations
+                    }]
+                }),
+            )
+            .await?;
+
+        let mut texts = vec![name];
+        texts.extend(stale.iter().chain(&observations).map(String::as_str));
+        self.cache.lock().unwrap().invalidate_matching(&texts);
+        Ok(result)
+    }
+
+    /// Delete entities by name through MCP memory server
+    pub async fn delete_entities(&self, names: &[String]) -> Result<String, io::Error> {
+        if let Some(message) = self.unavailable() {
+            return Ok(message);
+        }
+
+        let result = self
+            .mcp_call(
+                "memory",
+                "delete_entities",
+                json!({
+                    "entityNames": names
+                }),
+            )
+            .await?;
+
+        // Which queries matched the deleted observations is unknown, so start over
+        self.cache.lock().unwrap().clear();
+        Ok(result)
     }
 
-    /// Retrieve memories from Graphiti through MCP memory server
+    /// Retrieve memories from Graphiti through MCP memory server, at most `limit` of the
+    /// matching entities when given.
+    ///
+    /// Results are cached per normalized query, category and limit; `fresh` skips the
+    /// cache and refreshes it.
     pub async fn retrieve_memories(
         &self,
         category: &str,
         query: Option<&str>,
-        _limit: Option<usize>,
+        limit: Option<usize>,
+        fresh: bool,
     ) -> Result<Vec<String>, io::Error> {
-        if self.memory_server_endpoint.is_none() {
-            return Ok(vec!["⚠️ Graphiti MCP endpoint not configured".to_string()]);
+        let (results, _) = self.search(category, query, limit, fresh).await?;
+        Ok(results)
+    }
+
+    /// Like [`Self::retrieve_memories`], also telling whether the results were served
+    /// from the cache
+    pub async fn search(
+        &self,
+        category: &str,
+        query: Option<&str>,
+        limit: Option<usize>,
+        fresh: bool,
+    ) -> Result<(Vec<String>, bool), io::Error> {
+        if let Some(message) = self.unavailable() {
+            return Ok((vec![message], false));
         }
 
-        // In a real implementation, this would search Graphiti through MCP
-        let search_query = query.unwrap_or(category);
-        
-        let result = self.simulate_mcp_call("memory", "search_nodes", json!({
-            "query": search_query
-        })).await?;
+        let search_query = query
+            .map(str::to_string)
+            .unwrap_or_else(|| self.entity_type(category));
+        let key = QueryKey::new(&search_query, category, limit);
+        if !fresh {
+            if let Some(results) = self.cache.lock().unwrap().get(&key) {
+                return Ok((results, true));
+            }
+        }
+
+        let mut result = self
+            .backend
+            .call("memory", "search_nodes", json!({"query": &search_query}))
+            .await?;
+        if let Some(limit) = limit {
+            if let Some(entities) = result.get_mut("entities").and_then(Value::as_array_mut) {
+                entities.truncate(limit);
+            }
+        }
+        let result = match result {
+            Value::String(response) => response,
+            response => response.to_string(),
+        };
 
         // Parse and return results
-        Ok(vec![format!("🧠 Graphiti results for '{}': {}", search_query, result)])
+        let results = vec![format!(
+            "🧠 Graphiti results for '{}': {}",
+            search_query, result
+        )];
+        self.cache.lock().unwrap().insert(key, results.clone());
+        Ok((results, false))
+    }
+
+    /// Entities stored for a local category, i.e. those of its [`Self::entity_type`], as
+    /// `store_memory` creates them
+    pub async fn list_entities(&self, category: &str) -> Result<Vec<GraphitiNode>, GraphitiError> {
+        self.check_available()?;
+
+        let entity_type = self.entity_type(category);
+        let graph = self
+            .backend
+            .call("memory", "search_nodes", json!({"query": entity_type}))
+            .await?;
+
+        // The search also matches names and observations, so keep only the exact type
+        Ok(GraphitiNode::from_graph(graph)?
+            .into_iter()
+            .filter(|node| node.entity_type == entity_type)
+            .collect())
+    }
+
+    /// The entities one relation away from `name`, in either direction, at most `limit`
+    /// of them. Relations to entities the graph does not hold are listed without
+    /// observations.
+    pub async fn neighbors(
+        &self,
+        name: &str,
+        limit: usize,
+    ) -> Result<Vec<GraphitiNeighbor>, GraphitiError> {
+        self.check_available()?;
+
+        // The memory server only returns relations whose both ends match a search, so
+        // the neighbors of an entity are found in the whole graph
+        let graph = self.backend.call("memory", "read_graph", json!({})).await?;
+        let relations = GraphitiRelation::from_graph(&graph)?;
+        let nodes = GraphitiNode::from_graph(graph)?;
+        Ok(relations
+            .into_iter()
+            .filter_map(|relation| {
+                let other = if relation.from == name {
+                    relation.to
+                } else if relation.to == name {
+                    relation.from
+                } else {
+                    return None;
+                };
+                let observations = nodes
+                    .iter()
+                    .find(|node| node.name == other)
+                    .map(|node| node.observations.clone())
+                    .unwrap_or_default();
+                Some(GraphitiNeighbor {
+                    name: other,
+                    relation: relation.relation_type,
+                    observations,
+                })
+            })
+            .take(limit)
+            .collect())
     }
 
     /// Create relationships between memories in Graphiti
@@ -73,98 +478,134 @@ impl GraphitiClient {
         to_entity: &str,
         relationship_type: &str,
     ) -> Result<String, io::Error> {
-        if self.memory_server_endpoint.is_none() {
-            return Ok("⚠️ Graphiti MCP endpoint not configured".to_string());
+        if let Some(message) = self.unavailable() {
+            return Ok(message);
         }
 
-        self.simulate_mcp_call("memory", "create_relations", json!({
-            "relations": [{
-                "from": from_entity,
-                "to": to_entity,
-                "relationType": relationship_type
-            }]
-        })).await
+        self.mcp_call(
+            "memory",
+            "create_relations",
+            json!({
+                "relations": [{
+                    "from": from_entity,
+                    "to": to_entity,
+                    "relationType": relationship_type
+                }]
+            }),
+        )
+        .await
     }
 
-    /// Sync local memories with Graphiti
-    pub async fn sync_memories(&self, direction: &str) -> Result<String, io::Error> {
-        match direction {
-            "to_graphiti" => {
-                // In real implementation: read local files and upload to Graphiti
-                Ok("📤 Local memories synced to Graphiti".to_string())
-            }
-            "from_graphiti" => {
-                // In real implementation: download from Graphiti and save locally
-                Ok("📥 Memories downloaded from Graphiti".to_string())
+    /// Fetch what a sync in `direction` would download, limited to one category when
+    /// `category` is given, and count it as `downloaded`.
+    ///
+    /// Nothing is written here; uploads and saving downloads need the local files, see
+    /// `UltraThinkRouter::sync_to_graphiti` and `UltraThinkRouter::sync_from_graphiti`.
+    /// Failures are listed in the report.
+    pub async fn sync_memories(
+        &self,
+        direction: &str,
+        category: Option<&str>,
+    ) -> Result<SyncReport, io::Error> {
+        if !matches!(direction, "to_graphiti" | "from_graphiti" | "bidirectional") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid sync direction. Use: to_graphiti, from_graphiti, or bidirectional",
+            ));
+        }
+
+        let started = Instant::now();
+        let mut report = SyncReport::new(direction, category);
+        if let Some(message) = self.unavailable() {
+            report.errors.push(format!("{}, skipping sync", message));
+            return Ok(report);
+        }
+
+        if direction != "to_graphiti" {
+            match self.download(category).await {
+                Ok(nodes) => report.downloaded = nodes.len(),
+                Err(e) => report.errors.push(e.to_string()),
             }
-            "bidirectional" => {
-                // Avoid recursion by implementing the logic directly
-                let to_result = "📤 Local memories synced to Graphiti";
-                let from_result = "📥 Memories downloaded from Graphiti";
-                Ok(format!("🔄 Bidirectional sync completed:\n{}\n{}", to_result, from_result))
+        }
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        Ok(report)
+    }
+
+    /// Fetch the entities of one category, or the whole graph
+    pub async fn download(
+        &self,
+        category: Option<&str>,
+    ) -> Result<Vec<GraphitiNode>, GraphitiError> {
+        match category {
+            Some(category) => self.list_entities(category).await,
+            None => {
+                self.check_available()?;
+                let graph = self.backend.call("memory", "read_graph", json!({})).await?;
+                GraphitiNode::from_graph(graph)
             }
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Invalid sync direction. Use: to_graphiti, from_graphiti, or bidirectional"
-            ))
         }
     }
 
-    /// Simulate MCP call to memory server
-    /// In a real implementation, this would use actual MCP protocol
-    async fn simulate_mcp_call(
+    /// Make an MCP call to the memory server through the backend, answering with its
+    /// result as text
+    async fn mcp_call(
         &self,
         server: &str,
         method: &str,
         params: Value,
     ) -> Result<String, io::Error> {
-        // For testing purposes, we'll check if we have access to the memory MCP server
-        // In a real implementation, this would:
-        // 1. Connect to MCP memory server
-        // 2. Send JSON-RPC request
-        // 3. Parse response
-        
-        // Check if we can access the memory server by trying to run it
-        if std::env::var("ULTRATHINK_GRAPHITI_TEST").is_ok() {
-            // This would be replaced with actual MCP client code
-            let response = format!(
-                "✅ MCP call to {}: {}({}) - Simulated success",
-                server, method, params
-            );
-            Ok(response)
-        } else {
-            Ok(format!(
-                "🔗 Would call MCP {}.{}({}) when GRAPHITI_MCP_ENDPOINT is configured",
-                server, method, params
-            ))
+        // Every public method checks this first; this is the last line of defence
+        if let Some(message) = self.unavailable() {
+            return Ok(message);
+        }
+
+        match self.backend.call(server, method, params).await? {
+            Value::String(response) => Ok(response),
+            response => Ok(response.to_string()),
         }
     }
 
     /// Test Graphiti connection
     pub async fn test_connection(&self) -> Result<String, io::Error> {
-        if let Some(endpoint) = &self.memory_server_endpoint {
+        if self.is_offline() {
+            return Ok(format!("📴 {}", OFFLINE));
+        }
+
+        if let Some(endpoint) = self.endpoint() {
             Ok(format!("🟢 Graphiti MCP endpoint configured: {}", endpoint))
         } else {
-            Ok("🟡 Graphiti MCP endpoint not configured. Set GRAPHITI_MCP_ENDPOINT environment variable.".to_string())
+            Ok(format!(
+                "🟡 {}. Set GRAPHITI_MCP_ENDPOINT environment variable.",
+                NOT_CONFIGURED
+            ))
         }
     }
 }
 
-/// Helper to generate UUID for entities (simplified)
-mod uuid {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    
-    pub struct Uuid;
-    
-    impl Uuid {
-        pub fn new_v4() -> String {
-            let timestamp = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_nanos();
-            format!("uuid-{:x}", timestamp)
-        }
-    }
+/// A new, globally unique name for an entity of `category`: `{category}_{uuid}` with a
+/// random hyphenated v4 UUID
+fn entity_name(category: &str) -> String {
+    format!("{}_{}", category, uuid::Uuid::new_v4())
+}
+
+/// Endpoints are URLs, or `host:port` pairs that get an `http://` scheme
+fn validate_endpoint(endpoint: &str) -> Result<(), GraphitiError> {
+    url::Url::parse(&endpoint_url(endpoint))
+        .map(|_| ())
+        .map_err(|e| GraphitiError::InvalidEndpoint(endpoint.to_string(), e.to_string()))
+}
+
+/// The endpoints of the global and local scope. Each scope uses its own endpoint if set,
+/// otherwise the shared one; when only one endpoint is set at all, both scopes use it.
+pub fn scope_endpoints(
+    shared: Option<String>,
+    global: Option<String>,
+    local: Option<String>,
+) -> (Option<String>, Option<String>) {
+    let [shared, global, local] =
+        [shared, global, local].map(|endpoint| endpoint.filter(|e| !e.trim().is_empty()));
+    let fallback = shared.or_else(|| global.clone()).or_else(|| local.clone());
+    (global.or_else(|| fallback.clone()), local.or(fallback))
 }
 
 #[cfg(test)]
@@ -181,20 +622,569 @@ mod tests {
     #[tokio::test]
     async fn test_store_memory() {
         let client = GraphitiClient::new();
-        let result = client.store_memory(
-            "test_category",
-            "test data",
-            &["tag1".to_string(), "tag2".to_string()],
-            Some("test context")
-        ).await.unwrap();
-        
+        let result = client
+            .store_memory(
+                "test_category",
+                "test data",
+                &["tag1".to_string(), "tag2".to_string()],
+                Some("test context"),
+            )
+            .await
+            .unwrap();
+
         assert!(result.contains("MCP call") || result.contains("not configured"));
     }
 
     #[tokio::test]
     async fn test_sync_memories() {
-        let client = GraphitiClient::new();
-        let result = client.sync_memories("bidirectional").await.unwrap();
-        assert!(result.contains("sync"));
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()));
+        let report = client.sync_memories("bidirectional", None).await.unwrap();
+        assert_eq!(report.direction, "bidirectional");
+        assert_eq!(report.uploaded, 0);
+
+        let err = client.sync_memories("sideways", None).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_entity_names_are_unique() {
+        let names: std::collections::HashSet<String> =
+            (0..1000).map(|_| entity_name("dev")).collect();
+        assert_eq!(names.len(), 1000);
+
+        let name = names.iter().next().unwrap();
+        let uuid = name.strip_prefix("dev_").unwrap();
+        assert_eq!(uuid::Uuid::parse_str(uuid).unwrap().get_version_num(), 4);
+        assert_eq!(uuid.len(), 36);
+    }
+
+    /// Stores through an in-memory graph, but reports the first store as timed out after
+    /// it went through
+    #[derive(Default)]
+    struct LostReplyBackend {
+        graph: InMemoryGraphiti,
+        timed_out: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl GraphitiBackend for LostReplyBackend {
+        async fn call(
+            &self,
+            server: &str,
+            method: &str,
+            params: Value,
+        ) -> Result<Value, GraphitiError> {
+            let result = self.graph.call(server, method, params).await?;
+            if method == "create_entities"
+                && !self
+                    .timed_out
+                    .swap(true, std::sync::atomic::Ordering::SeqCst)
+            {
+                return Err(GraphitiError::Transport("request timed out".to_string()));
+            }
+            Ok(result)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retried_store_creates_one_entity() {
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()))
+            .with_backend(Arc::new(LostReplyBackend::default()))
+            .with_cache(0, Duration::ZERO);
+
+        client
+            .store_memory("dev", "uses tokio", &[], None)
+            .await
+            .unwrap_err();
+        client
+            .store_memory("dev", "uses tokio", &[], None)
+            .await
+            .unwrap();
+
+        // The retry gets a new name but the same idempotency key, so the graph keeps one
+        let nodes = client.list_entities("dev").await.unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].observations, vec!["uses tokio"]);
+    }
+
+    #[test]
+    fn test_configured_client() {
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()));
+        assert!(client.is_configured());
+        assert_eq!(client.endpoint(), Some("http://localhost:8000"));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_unconfigured_client() {
+        let client = GraphitiClient::with_endpoint(None);
+        assert!(!client.is_configured());
+        assert_eq!(client.endpoint(), None);
+
+        let stored = client
+            .store_memory("test_category", "test data", &[], None)
+            .await
+            .unwrap();
+        assert!(stored.contains(NOT_CONFIGURED));
+
+        let retrieved = client
+            .retrieve_memories("test_category", None, None, false)
+            .await
+            .unwrap();
+        assert_eq!(retrieved, vec![format!("⚠️ {}", NOT_CONFIGURED)]);
+
+        let synced = client.sync_memories("bidirectional", None).await.unwrap();
+        assert!(synced.errors[0].contains(NOT_CONFIGURED));
+
+        // Blank endpoints are treated the same as a missing one
+        assert!(!GraphitiClient::with_endpoint(Some("  ".to_string())).is_configured());
+    }
+
+    #[tokio::test]
+    async fn test_offline_mode_skips_all_calls() {
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()))
+            .with_offline(true);
+        assert!(client.is_offline());
+
+        let results = vec![
+            client
+                .store_memory("test_category", "test data", &[], None)
+                .await
+                .unwrap(),
+            client
+                .retrieve_memories("test_category", None, None, false)
+                .await
+                .unwrap()
+                .join("\n"),
+            client
+                .create_relationship("a", "b", "relates_to")
+                .await
+                .unwrap(),
+            client
+                .sync_memories("bidirectional", None)
+                .await
+                .unwrap()
+                .errors
+                .join("\n"),
+            client.test_connection().await.unwrap(),
+            client
+                .mcp_call("memory", "search_nodes", json!({}))
+                .await
+                .unwrap(),
+        ];
+
+        for result in results {
+            assert!(result.contains(OFFLINE), "unexpected result: {}", result);
+            assert!(!result.contains("MCP call"));
+        }
+    }
+
+    fn seed(client: &GraphitiClient, query: &str) {
+        client.cache.lock().unwrap().insert(
+            QueryKey::new(query, "dev", None),
+            vec!["cached".to_string()],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_served_from_cache() {
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()));
+        seed(&client, "auth flow");
+
+        let cached = client
+            .retrieve_memories("dev", Some("  Auth   Flow"), None, false)
+            .await
+            .unwrap();
+        assert_eq!(cached, vec!["cached"]);
+
+        // `fresh` bypasses the cache and replaces the cached entry
+        let fresh = client
+            .retrieve_memories("dev", Some("auth flow"), None, true)
+            .await
+            .unwrap();
+        assert_ne!(fresh, vec!["cached"]);
+        let again = client
+            .retrieve_memories("dev", Some("auth flow"), None, false)
+            .await
+            .unwrap();
+        assert_eq!(again, fresh);
+    }
+
+    #[tokio::test]
+    async fn test_cached_results_expire() {
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()))
+            .with_cache(8, Duration::ZERO);
+        seed(&client, "auth");
+
+        let results = client
+            .retrieve_memories("dev", Some("auth"), None, false)
+            .await
+            .unwrap();
+        assert_ne!(results, vec!["cached"]);
+    }
+
+    #[tokio::test]
+    async fn test_store_invalidates_matching_queries() {
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()));
+        seed(&client, "auth");
+        seed(&client, "billing");
+
+        client
+            .store_memory("dev", "New AUTH flow uses tokens", &[], None)
+            .await
+            .unwrap();
+
+        let auth = client
+            .retrieve_memories("dev", Some("auth"), None, false)
+            .await
+            .unwrap();
+        assert_ne!(auth, vec!["cached"]);
+        let billing = client
+            .retrieve_memories("dev", Some("billing"), None, false)
+            .await
+            .unwrap();
+        assert_eq!(billing, vec!["cached"]);
+
+        client
+            .delete_entities(&["dev_1".to_string()])
+            .await
+            .unwrap();
+        let billing = client
+            .retrieve_memories("dev", Some("billing"), None, false)
+            .await
+            .unwrap();
+        assert_ne!(billing, vec!["cached"]);
+    }
+
+    /// Answers every call with a fixed graph and records the calls it received
+    struct MockBackend {
+        graph: Value,
+        calls: Mutex<Vec<(String, Value)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl GraphitiBackend for MockBackend {
+        async fn call(
+            &self,
+            _server: &str,
+            method: &str,
+            params: Value,
+        ) -> Result<Value, GraphitiError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push((method.to_string(), params));
+            Ok(self.graph.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_entities_for_category() {
+        let backend = Arc::new(MockBackend {
+            graph: json!({
+                "entities": [
+                    {"name": "dev_1", "entityType": "DEV", "observations": ["uses tokio"]},
+                    {"name": "dev_2", "entityType": "DEV", "observations": ["a", "b"]},
+                    {"name": "ops_1", "entityType": "OPS", "observations": ["mentions dev"]},
+                    {"name": "dev_3", "entityType": "DEV"}
+                ],
+                "relations": []
+            }),
+            calls: Mutex::new(Vec::new()),
+        });
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()))
+            .with_backend(backend.clone());
+
+        let nodes = client.list_entities("dev").await.unwrap();
+        let names: Vec<&str> = nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, vec!["dev_1", "dev_2", "dev_3"]);
+        assert_eq!(nodes[1].observations, vec!["a", "b"]);
+        assert!(nodes[2].observations.is_empty());
+        assert_eq!(
+            *backend.calls.lock().unwrap(),
+            vec![("search_nodes".to_string(), json!({"query": "DEV"}))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_applies_the_limit() {
+        let backend = Arc::new(MockBackend {
+            graph: json!({
+                "entities": [
+                    {"name": "dev_1", "entityType": "DEV", "observations": ["uses tokio"]},
+                    {"name": "dev_2", "entityType": "DEV", "observations": ["uses serde"]},
+                    {"name": "dev_3", "entityType": "DEV", "observations": ["uses tracing"]}
+                ],
+                "relations": []
+            }),
+            calls: Mutex::new(Vec::new()),
+        });
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()))
+            .with_backend(backend.clone());
+
+        let (limited, cached) = client
+            .search("dev", Some("uses"), Some(2), false)
+            .await
+            .unwrap();
+        assert!(!cached);
+        assert!(limited[0].contains("dev_2") && !limited[0].contains("dev_3"));
+
+        // Another limit or category is a different query, not served from the cache
+        let (all, cached) = client
+            .search("dev", Some("uses"), None, false)
+            .await
+            .unwrap();
+        assert!(!cached);
+        assert!(all[0].contains("dev_3"));
+        let (_, cached) = client
+            .search("ops", Some("uses"), Some(2), false)
+            .await
+            .unwrap();
+        assert!(!cached);
+        let (again, cached) = client
+            .search("dev", Some("uses"), Some(2), false)
+            .await
+            .unwrap();
+        assert!(cached);
+        assert_eq!(again, limited);
+        assert_eq!(backend.calls.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_update_entity() {
+        let backend = Arc::new(MockBackend {
+            graph: json!({
+                "entities": [{"name": "dev_1", "entityType": "DEV", "observations": ["old"]}]
+            }),
+            calls: Mutex::new(Vec::new()),
+        });
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()))
+            .with_backend(backend.clone());
+
+        client
+            .update_entity("dev_1", vec!["new".to_string()], UpdateMode::Replace)
+            .await
+            .unwrap();
+        let added = json!({"observations": [{"entityName": "dev_1", "contents": ["new"]}]});
+        assert_eq!(
+            *backend.calls.lock().unwrap(),
+            vec![
+                ("open_nodes".to_string(), json!({"names": ["dev_1"]})),
+                (
+                    "delete_observations".to_string(),
+                    json!({"deletions": [{"entityName": "dev_1", "observations": ["old"]}]})
+                ),
+                ("add_observations".to_string(), added.clone()),
+            ]
+        );
+
+        backend.calls.lock().unwrap().clear();
+        client
+            .update_entity("dev_1", vec!["new".to_string()], UpdateMode::Append)
+            .await
+            .unwrap();
+        assert_eq!(
+            *backend.calls.lock().unwrap(),
+            vec![("add_observations".to_string(), added)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mapped_entity_types() {
+        let backend = Arc::new(MockBackend {
+            graph: json!({
+                "entities": [
+                    {"name": "development_1", "entityType": "Project"},
+                    {"name": "ops_1", "entityType": "OPS"}
+                ]
+            }),
+            calls: Mutex::new(Vec::new()),
+        });
+        let entity_types = BTreeMap::from([
+            ("development".to_string(), "Project".to_string()),
+            ("bugs".to_string(), "Project".to_string()),
+        ]);
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()))
+            .with_backend(backend.clone())
+            .with_entity_types(entity_types);
+
+        client
+            .store_memory("development", "uses tokio", &[], None)
+            .await
+            .unwrap();
+        client
+            .store_memory("ops", "deploy", &[], None)
+            .await
+            .unwrap();
+        let types: Vec<Value> = backend
+            .calls
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, params)| params["entities"][0]["entityType"].clone())
+            .collect();
+        assert_eq!(types, vec![json!("Project"), json!("OPS")]);
+
+        let nodes = client.list_entities("development").await.unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].name, "development_1");
+        assert_eq!(
+            backend.calls.lock().unwrap().last().unwrap().1,
+            json!({"query": "Project"})
+        );
+
+        assert_eq!(
+            client.categories_for("Project"),
+            vec!["bugs", "development"]
+        );
+        assert_eq!(client.categories_for("OPS"), vec!["ops"]);
+        assert!(client.categories_for("DEVELOPMENT").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_entities_errors() {
+        let unconfigured = GraphitiClient::with_endpoint(None);
+        assert!(matches!(
+            unconfigured.list_entities("dev").await,
+            Err(GraphitiError::NotConfigured)
+        ));
+
+        let offline = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()))
+            .with_offline(true);
+        assert!(matches!(
+            offline.list_entities("dev").await,
+            Err(GraphitiError::Offline)
+        ));
+
+        // The simulated transport has no graph to return
+        let simulated = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()));
+        assert!(matches!(
+            simulated.list_entities("dev").await,
+            Err(GraphitiError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_download_scoped_to_category() {
+        let backend = Arc::new(MockBackend {
+            graph: json!({
+                "entities": [
+                    {"name": "dev_1", "entityType": "DEV"},
+                    {"name": "ops_1", "entityType": "OPS", "observations": ["dev"]}
+                ]
+            }),
+            calls: Mutex::new(Vec::new()),
+        });
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()))
+            .with_backend(backend.clone());
+
+        let report = client
+            .sync_memories("from_graphiti", Some("dev"))
+            .await
+            .unwrap();
+        assert_eq!(report.category.as_deref(), Some("dev"));
+        assert_eq!((report.downloaded, report.uploaded), (1, 0));
+        assert!(report.is_success());
+        let report = client.sync_memories("from_graphiti", None).await.unwrap();
+        assert_eq!(report.downloaded, 2);
+
+        assert_eq!(
+            *backend.calls.lock().unwrap(),
+            vec![
+                ("search_nodes".to_string(), json!({"query": "DEV"})),
+                ("read_graph".to_string(), json!({})),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_graph_round_trip() {
+        let client = GraphitiClient::try_with_endpoint(Some(IN_MEMORY_ENDPOINT.to_string()))
+            .unwrap()
+            .with_cache(0, Duration::ZERO);
+        for data in ["uses tokio", "tests with tempdir", "uses tokio"] {
+            client.store_memory("dev", data, &[], None).await.unwrap();
+        }
+        client
+            .store_memory("ops", "deploys on fridays", &[], Some("release"))
+            .await
+            .unwrap();
+
+        // The repeated store carries the same idempotency key, so it creates nothing
+        let nodes = client.list_entities("dev").await.unwrap();
+        let mut observations: Vec<&str> = nodes
+            .iter()
+            .flat_map(|node| node.observations.iter().map(String::as_str))
+            .collect();
+        observations.sort();
+        assert_eq!(observations, vec!["tests with tempdir", "uses tokio"]);
+        let report = client.sync_memories("from_graphiti", None).await.unwrap();
+        assert_eq!(report.downloaded, 3);
+
+        let ops = client.list_entities("ops").await.unwrap();
+        assert_eq!(
+            ops[0].observations,
+            vec!["[Context: release] deploys on fridays"]
+        );
+        client
+            .update_entity(
+                &ops[0].name,
+                vec!["deploys on mondays".to_string()],
+                UpdateMode::Replace,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            client.list_entities("ops").await.unwrap()[0].observations,
+            vec!["deploys on mondays"]
+        );
+
+        client
+            .delete_entities(&[ops[0].name.clone()])
+            .await
+            .unwrap();
+        assert!(client.list_entities("ops").await.unwrap().is_empty());
+        let report = client.sync_memories("from_graphiti", None).await.unwrap();
+        assert_eq!(report.downloaded, 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_construction_reports_reason() {
+        for endpoint in ["localhost:8000", "http://graphiti.internal:8000/mcp"] {
+            assert!(GraphitiClient::try_with_endpoint(Some(endpoint.to_string())).is_ok());
+        }
+        let err = GraphitiClient::try_with_endpoint(Some("bad host:8000".to_string()))
+            .err()
+            .unwrap();
+        assert!(matches!(err, GraphitiError::InvalidEndpoint(..)));
+
+        let client = GraphitiClient::failed(err);
+        assert!(!client.is_configured());
+        let message = client.unavailable().unwrap();
+        assert!(message.contains("Graphiti unavailable: Invalid Graphiti endpoint 'bad host:8000'"));
+        assert!(matches!(
+            client.download(None).await,
+            Err(GraphitiError::Unavailable(_))
+        ));
+        let report = client.sync_memories("bidirectional", None).await.unwrap();
+        assert!(report.errors[0].contains("Graphiti unavailable"));
+    }
+
+    #[test]
+    fn test_scope_endpoints() {
+        let endpoint = |url: &str| Some(url.to_string());
+        assert_eq!(
+            scope_endpoints(endpoint("shared"), endpoint("personal"), None),
+            (endpoint("personal"), endpoint("shared"))
+        );
+        assert_eq!(
+            scope_endpoints(None, endpoint("personal"), endpoint("project")),
+            (endpoint("personal"), endpoint("project"))
+        );
+        // A single endpoint serves both scopes, whichever variable it came from
+        assert_eq!(
+            scope_endpoints(None, None, endpoint("project")),
+            (endpoint("project"), endpoint("project"))
+        );
+        assert_eq!(scope_endpoints(endpoint(" "), None, None), (None, None));
+    }
+}
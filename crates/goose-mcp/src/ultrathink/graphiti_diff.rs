@@ -0,0 +1,124 @@
+use std::collections::BTreeSet;
+
+use super::entry::{normalize_data, MemoryEntry};
+use super::graphiti_backend::GraphitiNode;
+
+/// How the memories of a scope differ between the local files and Graphiti
+#[derive(Debug, Default)]
+pub struct GraphitiDiff {
+    /// Local entries that no entity of their category holds
+    pub local_only: Vec<(String, MemoryEntry)>,
+    /// Entities holding no local entry of their category
+    pub remote_only: Vec<(String, GraphitiNode)>,
+    /// Singleton categories whose current entry and entity hold different values
+    pub conflicting: Vec<(String, MemoryEntry, GraphitiNode)>,
+}
+
+impl GraphitiDiff {
+    /// Match local entries and entities of the same category by their normalized data.
+    ///
+    /// Entities get random names, so their content is all that ties them to an entry.
+    /// The one entity of a category in `singletons` is updated in place instead, so there
+    /// an unmatched entry and an unmatched entity are the same memory holding two values.
+    pub fn new(
+        local: Vec<(String, MemoryEntry)>,
+        remote: Vec<(String, GraphitiNode)>,
+        singletons: &BTreeSet<String>,
+    ) -> Self {
+        let local_data: BTreeSet<(String, String)> = local
+            .iter()
+            .map(|(category, entry)| (category.clone(), normalize_data(&entry.data)))
+            .collect();
+        let remote_data: BTreeSet<(String, String)> = remote
+            .iter()
+            .flat_map(|(category, node)| {
+                node.observations
+                    .iter()
+                    .map(|observation| (category.clone(), normalize_data(observation)))
+            })
+            .collect();
+
+        let mut diff = Self::default();
+        for (category, entry) in local {
+            if !remote_data.contains(&(category.clone(), normalize_data(&entry.data))) {
+                diff.local_only.push((category, entry));
+            }
+        }
+        for (category, node) in remote {
+            let matched = node.observations.iter().any(|observation| {
+                local_data.contains(&(category.clone(), normalize_data(observation)))
+            });
+            if !matched {
+                diff.remote_only.push((category, node));
+            }
+        }
+
+        for category in singletons {
+            let local = diff
+                .local_only
+                .iter()
+                .position(|(name, _)| name == category);
+            let remote = diff
+                .remote_only
+                .iter()
+                .position(|(name, _)| name == category);
+            if let (Some(local), Some(remote)) = (local, remote) {
+                let (category, entry) = diff.local_only.remove(local);
+                let (_, node) = diff.remote_only.remove(remote);
+                diff.conflicting.push((category, entry, node));
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.local_only.is_empty() && self.remote_only.is_empty() && self.conflicting.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, observations: &[&str]) -> GraphitiNode {
+        GraphitiNode {
+            name: name.to_string(),
+            entity_type: "DEV".to_string(),
+            observations: observations.iter().map(|o| o.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_matches_by_normalized_data() {
+        let local = vec![
+            ("dev".to_string(), MemoryEntry::new("uses  tokio", &["api"])),
+            ("dev".to_string(), MemoryEntry::new("local fact", &[])),
+            ("ops".to_string(), MemoryEntry::new("uses tokio", &[])),
+            ("status".to_string(), MemoryEntry::new("green", &[])),
+        ];
+        let remote = vec![
+            ("dev".to_string(), node("dev_1", &["uses tokio\n"])),
+            ("dev".to_string(), node("dev_2", &["remote fact"])),
+            ("status".to_string(), node("status_1", &["red"])),
+        ];
+        let singletons = BTreeSet::from(["status".to_string()]);
+
+        let diff = GraphitiDiff::new(local, remote, &singletons);
+        let local_only: Vec<(&str, &str)> = diff
+            .local_only
+            .iter()
+            .map(|(category, entry)| (category.as_str(), entry.data.as_str()))
+            .collect();
+        // The same data in another category is a different memory
+        assert_eq!(
+            local_only,
+            vec![("dev", "local fact"), ("ops", "uses tokio")]
+        );
+        assert_eq!(diff.remote_only.len(), 1);
+        assert_eq!(diff.remote_only[0].1.name, "dev_2");
+        assert_eq!(diff.conflicting.len(), 1);
+        assert_eq!(diff.conflicting[0].1.data, "green");
+        assert_eq!(diff.conflicting[0].2.name, "status_1");
+        assert!(!diff.is_empty());
+    }
+}
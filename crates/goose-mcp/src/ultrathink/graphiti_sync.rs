@@ -0,0 +1,1178 @@
+use mcp_core::tool::ToolCall;
+use std::collections::BTreeSet;
+use std::io;
+
+use super::entry::MemoryEntry;
+use super::graphiti_backend::GraphitiNode;
+use super::graphiti_client::UpdateMode;
+use super::graphiti_diff::GraphitiDiff;
+use super::lock::CategoryLock;
+use super::sync_state::{idempotency_key, stored_hash, SyncState, SyncedEntry, SYNC_STATE_LOCK};
+use super::{
+    validate_category, MemoryEventKind, MemoryScope, SyncReport, UltraThinkArgs, UltraThinkRouter,
+};
+
+impl UltraThinkRouter {
+    /// Upload the entries of both scopes that are new or changed since the last sync.
+    ///
+    /// Each memory dir keeps a sync state with the content hash of every uploaded entry;
+    /// entries whose hash still matches are skipped, and so are entries whose metadata
+    /// changed but whose idempotency key Graphiti already confirmed, since re-sending them
+    /// would store the same content again. The state is rewritten after every
+    /// sync, so deleted entries drop out of it, and progress made before a failed upload
+    /// is kept.
+    ///
+    /// With a `category` only that category is uploaded; the recorded state of every
+    /// other category is carried over untouched.
+    pub async fn sync_to_graphiti(&self, category: Option<&str>) -> io::Result<SyncReport> {
+        if let Some(category) = category {
+            validate_category(category)?;
+        }
+        let started = std::time::Instant::now();
+        let mut report = SyncReport::new("to_graphiti", category);
+        for is_global in [true, false] {
+            let this = self.clone();
+            let scope = category.map(str::to_string);
+            let (mut previous, entries) = tokio::task::spawn_blocking(move || {
+                this.load_for_sync(is_global, scope.as_deref())
+            })
+            .await
+            .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))??;
+            let client = self.graphiti_for(is_global);
+            if let Some(message) = client.unavailable() {
+                // Leave the state alone so everything is uploaded once Graphiti is reachable
+                report.errors.push(format!(
+                    "{:?} memories not synced: {}",
+                    MemoryScope::from_global(is_global),
+                    message
+                ));
+                continue;
+            }
+
+            let mut next = SyncState::default();
+            if let Some(category) = category {
+                let prefix = format!("{}/", category);
+                let (scoped, others) = std::mem::take(&mut previous.entries)
+                    .into_iter()
+                    .partition(|(key, _)| key.starts_with(&prefix));
+                previous.entries = scoped;
+                next.entries = others;
+            }
+            for (category, entry) in entries {
+                let key = SyncState::key(&category, &entry);
+                let hash = stored_hash(&entry);
+                let idempotency_key = idempotency_key(&category, &entry.data);
+                if let Some(synced) = previous.entries.remove(&key) {
+                    if synced.hash == hash {
+                        next.entries.insert(key, synced);
+                        report.skipped += 1;
+                        continue;
+                    }
+                    if synced.idempotency_key.as_ref() == Some(&idempotency_key) {
+                        next.entries.insert(key, SyncedEntry { hash, ..synced });
+                        report.skipped += 1;
+                        continue;
+                    }
+                }
+
+                // A failed entry is left out of the state, so the next sync retries it
+                match self.store_in_graphiti(&category, &entry, is_global).await {
+                    Ok(_) => {
+                        next.entries.insert(
+                            key,
+                            SyncedEntry {
+                                hash,
+                                synced_at: self.clock.utc_now(),
+                                idempotency_key: Some(idempotency_key),
+                            },
+                        );
+                        report.uploaded += 1;
+                    }
+                    Err(e) => report.errors.push(format!("{}: {}", key, e)),
+                }
+            }
+
+            let this = self.clone();
+            tokio::task::spawn_blocking(move || this.save_sync_state(&next, is_global))
+                .await
+                .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))??;
+        }
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        Ok(report)
+    }
+
+    /// Compare the live entries of a scope, or of one category, with the entities Graphiti
+    /// holds for them, changing neither side. Across all categories, every entity is
+    /// attributed to a category its type maps to, preferring one stored locally.
+    pub async fn graphiti_diff(
+        &self,
+        category: Option<&str>,
+        is_global: bool,
+    ) -> io::Result<GraphitiDiff> {
+        let this = self.clone();
+        let scoped = category.map(str::to_string);
+        let (local, singletons) = tokio::task::spawn_blocking(move || {
+            let (_, entries) = this.load_for_sync(is_global, scoped.as_deref())?;
+            let singletons: BTreeSet<String> = entries
+                .iter()
+                .map(|(category, _)| category)
+                .filter(|category| this.category_meta(category, is_global).singleton)
+                .cloned()
+                .collect();
+            Ok::<_, io::Error>((entries, singletons))
+        })
+        .await
+        .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))??;
+
+        let client = self.graphiti_for(is_global);
+        let remote = match category {
+            Some(category) => client
+                .list_entities(category)
+                .await?
+                .into_iter()
+                .map(|node| (category.to_string(), node))
+                .collect(),
+            None => {
+                let stored: BTreeSet<&str> = local.iter().map(|(name, _)| name.as_str()).collect();
+                client
+                    .download(None)
+                    .await?
+                    .into_iter()
+                    .map(|node| {
+                        let categories = client.categories_for(&node.entity_type);
+                        let category = categories
+                            .iter()
+                            .find(|category| stored.contains(category.as_str()))
+                            .or(categories.first())
+                            .cloned()
+                            .unwrap_or_else(|| node.entity_type.to_lowercase());
+                        (category, node)
+                    })
+                    .collect()
+            }
+        };
+        Ok(GraphitiDiff::new(local, remote, &singletons))
+    }
+
+    /// The sync state of a scope and every live entry in it, or only in `category`
+    fn load_for_sync(
+        &self,
+        is_global: bool,
+        category: Option<&str>,
+    ) -> io::Result<(SyncState, Vec<(String, MemoryEntry)>)> {
+        let mut entries = Vec::new();
+        let categories = self.list_categories(is_global)?;
+        for category in categories
+            .into_iter()
+            .filter(|name| category.map_or(true, |category| name == category))
+        {
+            for entry in self.read_entries(&category, is_global)? {
+                entries.push((category.clone(), entry));
+            }
+        }
+        Ok((SyncState::load(self.memory_dir(is_global)), entries))
+    }
+
+    /// Save the entities Graphiti holds that match no local entry, one entry per
+    /// observation, into the category files of their scope, or only those of `category`.
+    ///
+    /// Entities are attributed to categories as [`Self::graphiti_diff`] does, and a
+    /// singleton whose entity holds another value keeps its local one. With one graph for
+    /// both scopes, entities matching no entry of either scope are saved in the local
+    /// scope. Saved entries are recorded in the sync state, so the next upload does not
+    /// send them back. Failures are listed in the report.
+    pub async fn sync_from_graphiti(&self, category: Option<&str>) -> io::Result<SyncReport> {
+        if let Some(category) = category {
+            validate_category(category)?;
+        }
+        let started = std::time::Instant::now();
+        let mut report = SyncReport::new("from_graphiti", category);
+        let shared = self.graphiti_clients().len() == 1;
+        let scopes: &[bool] = if shared { &[false] } else { &[true, false] };
+        for &is_global in scopes {
+            let scope = MemoryScope::from_global(is_global);
+            if let Some(message) = self.graphiti_for(is_global).unavailable() {
+                report
+                    .errors
+                    .push(format!("{:?} memories not downloaded: {}", scope, message));
+                continue;
+            }
+            let remote_only = match self.remote_only(category, is_global, shared).await {
+                Ok(remote_only) => remote_only,
+                Err(e) => {
+                    report
+                        .errors
+                        .push(format!("{:?} memories not downloaded: {}", scope, e));
+                    continue;
+                }
+            };
+
+            let this = self.clone();
+            let (saved, errors) =
+                tokio::task::spawn_blocking(move || this.save_downloaded(remote_only, is_global))
+                    .await
+                    .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))?;
+            report.downloaded += saved;
+            report.errors.extend(errors);
+        }
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        Ok(report)
+    }
+
+    /// The entities of a scope's graph holding no entry of the scope, and with a `shared`
+    /// graph no global entry either
+    async fn remote_only(
+        &self,
+        category: Option<&str>,
+        is_global: bool,
+        shared: bool,
+    ) -> io::Result<Vec<(String, GraphitiNode)>> {
+        let mut remote_only = self.graphiti_diff(category, is_global).await?.remote_only;
+        if shared && !is_global {
+            let global: BTreeSet<String> = self
+                .graphiti_diff(category, true)
+                .await?
+                .remote_only
+                .into_iter()
+                .map(|(_, node)| node.name)
+                .collect();
+            remote_only.retain(|(_, node)| global.contains(&node.name));
+        }
+        Ok(remote_only)
+    }
+
+    /// Append the observations of downloaded entities to their categories and record them
+    /// as synced, answering how many entries were saved and what failed
+    fn save_downloaded(
+        &self,
+        nodes: Vec<(String, GraphitiNode)>,
+        is_global: bool,
+    ) -> (usize, Vec<String>) {
+        let (mut saved, mut errors) = (0, Vec::new());
+        for (category, node) in nodes {
+            for observation in &node.observations {
+                let entry = self.new_entry(observation, &[]);
+                let result = validate_category(&category)
+                    .and_then(|()| self.append_entry(&category, &entry, is_global))
+                    .and_then(|()| self.record_synced(&category, &entry, is_global));
+                match result {
+                    Ok(()) => saved += 1,
+                    Err(e) => errors.push(format!("{}/{}: {}", category, node.name, e)),
+                }
+            }
+        }
+        (saved, errors)
+    }
+
+    /// Run an incremental upload, downloading afterwards for `bidirectional`
+    pub(super) async fn sync_to_graphiti_report(
+        &self,
+        direction: &str,
+        category: Option<&str>,
+    ) -> io::Result<SyncReport> {
+        let mut report = self.sync_to_graphiti(category).await?;
+        report.direction = direction.to_string();
+        if direction != "bidirectional" {
+            return Ok(report);
+        }
+
+        let download = self.sync_from_graphiti(category).await?;
+        report.downloaded = download.downloaded;
+        report.errors.extend(download.errors);
+        report.duration_ms += download.duration_ms;
+        Ok(report)
+    }
+
+    /// Store a high-priority memory locally, then upload it to Graphiti straight away.
+    ///
+    /// The local write always comes first and is kept whatever happens to the upload. A
+    /// failed upload is flagged in the response (or returned as an error with
+    /// [`UltraThinkConfig::strict_graphiti_upload`](super::UltraThinkConfig::strict_graphiti_upload)); the entry is missing from the sync
+    /// state, so the next sync picks it up.
+    pub(super) async fn remember_high_priority(
+        &self,
+        tool_call: ToolCall,
+    ) -> Result<String, io::Error> {
+        let this = self.clone();
+        let (category, is_global, entry) = tokio::task::spawn_blocking(move || {
+            let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+            let entry = this.store_remembered(&args, &tool_call.arguments)?;
+            Ok::<_, io::Error>((args.category.to_string(), args.is_global, entry))
+        })
+        .await
+        .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))??;
+
+        let stored = self.formatter.stored(&category, &entry.id());
+        if self.graphiti_for(is_global).unavailable().is_some() {
+            return Ok(stored);
+        }
+        let Err(e) = self.upload_entry(&category, &entry, is_global).await else {
+            return Ok(stored);
+        };
+        tracing::warn!(
+            "Graphiti upload of a high-priority memory in '{}' failed: {}",
+            category,
+            e
+        );
+        let message = format!(
+            "Stored locally, but the Graphiti upload failed and is queued for the next sync: {}",
+            e
+        );
+        if self.config.strict_graphiti_upload {
+            return Err(io::Error::other(message));
+        }
+        Ok(format!("{}\n{}", stored, self.formatter.error(&message)))
+    }
+
+    /// Store a memory locally and start uploading it to Graphiti without waiting for the
+    /// upload. Its outcome is broadcast as a [`MemoryEventKind::Synced`] or
+    /// [`MemoryEventKind::SyncFailed`] event; a failed upload is retried by the next sync.
+    pub(super) async fn remember_in_background(
+        &self,
+        tool_call: ToolCall,
+    ) -> Result<String, io::Error> {
+        let this = self.clone();
+        let (category, is_global, entry) = tokio::task::spawn_blocking(move || {
+            let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+            let entry = this.store_remembered(&args, &tool_call.arguments)?;
+            Ok::<_, io::Error>((args.category.to_string(), args.is_global, entry))
+        })
+        .await
+        .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))??;
+
+        let stored = self.formatter.stored(&category, &entry.id());
+        if self.graphiti_for(is_global).unavailable().is_some() {
+            return Ok(stored);
+        }
+        let this = self.clone();
+        tokio::spawn(async move {
+            let kind = match this.upload_entry(&category, &entry, is_global).await {
+                Ok(()) => MemoryEventKind::Synced,
+                Err(e) => {
+                    tracing::warn!(
+                        "Graphiti upload of a memory in '{}' failed: {}",
+                        category,
+                        e
+                    );
+                    MemoryEventKind::SyncFailed {
+                        error: e.to_string(),
+                    }
+                }
+            };
+            this.emit(kind, &category, Some(entry.id()), is_global);
+        });
+        Ok(stored)
+    }
+
+    /// Upload a single entry and record it in the sync state, so the next sync skips it
+    async fn upload_entry(
+        &self,
+        category: &str,
+        entry: &MemoryEntry,
+        is_global: bool,
+    ) -> io::Result<()> {
+        self.store_in_graphiti(category, entry, is_global).await?;
+
+        let this = self.clone();
+        let (category, entry) = (category.to_string(), entry.clone());
+        tokio::task::spawn_blocking(move || this.record_synced(&category, &entry, is_global))
+            .await
+            .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))?
+    }
+
+    /// Add an uploaded entry to the sync state of its scope. The state is read and
+    /// rewritten under its lock, so uploads finishing together keep each other's records;
+    /// this blocks while another holder has the lock.
+    fn record_synced(
+        &self,
+        category: &str,
+        entry: &MemoryEntry,
+        is_global: bool,
+    ) -> io::Result<()> {
+        let _lock = self.lock_sync_state(is_global)?;
+        let dir = self.memory_dir(is_global);
+        let mut state = SyncState::load(dir);
+        state.entries.insert(
+            SyncState::key(category, entry),
+            SyncedEntry {
+                hash: stored_hash(entry),
+                synced_at: self.clock.utc_now(),
+                idempotency_key: Some(idempotency_key(category, &entry.data)),
+            },
+        );
+        state.save(dir)
+    }
+
+    /// Replace the sync state of a scope whose memory dir exists, under its lock; this
+    /// blocks while another holder has the lock
+    fn save_sync_state(&self, state: &SyncState, is_global: bool) -> io::Result<()> {
+        let dir = self.memory_dir(is_global);
+        if !dir.exists() {
+            return Ok(());
+        }
+        let _lock = self.lock_sync_state(is_global)?;
+        state.save(dir)
+    }
+
+    fn lock_sync_state(&self, is_global: bool) -> io::Result<CategoryLock> {
+        CategoryLock::acquire(
+            self.memory_dir(is_global),
+            SYNC_STATE_LOCK,
+            self.config.lock_timeout,
+        )
+    }
+
+    /// Store an entry in Graphiti. Once a singleton category has an entity, its
+    /// observations are replaced instead, so Graphiti keeps one current value like the
+    /// category file does.
+    async fn store_in_graphiti(
+        &self,
+        category: &str,
+        entry: &MemoryEntry,
+        is_global: bool,
+    ) -> io::Result<()> {
+        let client = self.graphiti_for(is_global);
+        if self.category_meta(category, is_global).singleton {
+            // Entities are named after their category by `store_memory`
+            let prefix = format!("{}_", category);
+            let existing = client
+                .list_entities(category)
+                .await?
+                .into_iter()
+                .find(|node| node.name.starts_with(&prefix));
+            if let Some(node) = existing {
+                client
+                    .update_entity(&node.name, vec![entry.data.clone()], UpdateMode::Replace)
+                    .await?;
+                return Ok(());
+            }
+        }
+        client
+            .store_memory(category, &entry.data, &entry.tags, None)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ultrathink::graphiti_backend::{GraphitiBackend, GraphitiError, InMemoryGraphiti};
+    use crate::ultrathink::graphiti_client::GraphitiClient;
+    use crate::ultrathink::tests::{backed_router, syncing_router, test_router};
+    use crate::ultrathink::{sync_state, MemoryEvent, SyncOnWrite};
+    use async_trait::async_trait;
+    use mcp_core::handler::ToolError;
+    use mcp_server::Router;
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use tokio::sync::{broadcast, mpsc};
+
+    #[tokio::test]
+    async fn test_incremental_graphiti_sync() {
+        let temp_dir = tempdir().unwrap();
+        let router = syncing_router(temp_dir.path());
+        router.remember("dev", "first", &["api"], false).unwrap();
+        router.remember("dev", "second", &[], false).unwrap();
+        router.remember("ops", "deploy", &[], true).unwrap();
+
+        // First sync uploads everything and records it
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (3, 0));
+        let state = SyncState::load(&temp_dir.path().join("local"));
+        assert_eq!(state.entries.len(), 2);
+
+        // Nothing changed, nothing uploaded
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (0, 3));
+
+        // A new entry is uploaded; pinning changes nothing Graphiti stores, so the
+        // pinned entry is not sent again
+        router.remember("dev", "third", &[], false).unwrap();
+        let id = MemoryEntry::new("second", &[]).id();
+        router.set_pinned("dev", &id, true, false).unwrap();
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (1, 3));
+
+        // States written before idempotency keys were recorded still re-send changes
+        let local_dir = temp_dir.path().join("local");
+        let mut state = SyncState::load(&local_dir);
+        for synced in state.entries.values_mut() {
+            synced.idempotency_key = None;
+        }
+        state.save(&local_dir).unwrap();
+        router.set_pinned("dev", &id, false, false).unwrap();
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (1, 3));
+    }
+
+    #[tokio::test]
+    async fn test_sync_round_trips_through_in_memory_graph() {
+        let temp_dir = tempdir().unwrap();
+        let router = backed_router(temp_dir.path(), Arc::new(InMemoryGraphiti::default()));
+        router
+            .remember("dev", "uses tokio", &["api"], false)
+            .unwrap();
+        router
+            .remember("dev", "tests with tempdir", &[], false)
+            .unwrap();
+        router
+            .remember("ops", "deploys on fridays", &[], false)
+            .unwrap();
+
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!(report.uploaded, 3);
+
+        let client = &router.local_graphiti_client;
+        let download = client.sync_memories("from_graphiti", None).await.unwrap();
+        assert_eq!(download.downloaded, 3);
+        assert!(download.errors.is_empty());
+        let mut dev: Vec<String> = client
+            .list_entities("dev")
+            .await
+            .unwrap()
+            .into_iter()
+            .flat_map(|node| node.observations)
+            .collect();
+        dev.sort();
+        assert_eq!(dev, vec!["tests with tempdir", "uses tokio"]);
+        let ops = client
+            .sync_memories("from_graphiti", Some("ops"))
+            .await
+            .unwrap();
+        assert_eq!(ops.downloaded, 1);
+    }
+
+    #[tokio::test]
+    async fn test_download_saves_remote_entities() {
+        let temp_dir = tempdir().unwrap();
+        let router = backed_router(temp_dir.path(), Arc::new(InMemoryGraphiti::default()));
+        router.remember("dev", "uses tokio", &[], false).unwrap();
+        router.remember("ops", "on call", &[], true).unwrap();
+        router.sync_to_graphiti(None).await.unwrap();
+        let client = &router.local_graphiti_client;
+        client
+            .store_memory("dev", "remote fact", &[], None)
+            .await
+            .unwrap();
+        client
+            .store_memory("ops", "deploys on fridays", &[], None)
+            .await
+            .unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let result = router
+            .call_tool(
+                "ultrathink_graphiti_sync",
+                json!({"direction": "from_graphiti", "category": "dev"}),
+                tx,
+            )
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        let report: Value = serde_json::from_str(text.lines().nth(1).unwrap()).unwrap();
+        assert_eq!(report["downloaded"], 1);
+        let data: Vec<String> = router
+            .read_entries("dev", false)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.data)
+            .collect();
+        assert_eq!(data, vec!["uses tokio", "remote fact"]);
+        assert!(!router.get_memory_file("ops", false).exists());
+
+        // Both scopes share the graph, so the global entry is not downloaded again
+        let report = router.sync_from_graphiti(None).await.unwrap();
+        assert_eq!(report.downloaded, 1);
+        assert!(report.errors.is_empty());
+        assert_eq!(
+            router.read_entries("ops", false).unwrap()[0].data,
+            "deploys on fridays"
+        );
+        assert_eq!(router.read_entries("ops", true).unwrap().len(), 1);
+
+        // Saved entries are neither downloaded nor uploaded again
+        assert_eq!(router.sync_from_graphiti(None).await.unwrap().downloaded, 0);
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (0, 4));
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_sync_state_triggers_full_sync() {
+        let temp_dir = tempdir().unwrap();
+        let router = syncing_router(temp_dir.path());
+        router.remember("dev", "first", &[], false).unwrap();
+        router.remember("dev", "second", &[], false).unwrap();
+        router.sync_to_graphiti(None).await.unwrap();
+
+        let local_dir = temp_dir.path().join("local");
+        fs::write(local_dir.join(sync_state::SYNC_STATE_FILE), "not json").unwrap();
+
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (2, 0));
+        assert_eq!(SyncState::load(&local_dir).entries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_category_scoped_graphiti_sync() {
+        let temp_dir = tempdir().unwrap();
+        let router = syncing_router(temp_dir.path());
+        router.remember("dev", "first", &[], false).unwrap();
+        router.remember("ops", "deploy", &[], false).unwrap();
+        router.sync_to_graphiti(None).await.unwrap();
+        let local_dir = temp_dir.path().join("local");
+        let before = SyncState::load(&local_dir);
+
+        router.remember("dev", "second", &[], false).unwrap();
+        router.remember("ops", "rollback", &[], false).unwrap();
+        let report = router.sync_to_graphiti(Some("dev")).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (1, 1));
+
+        // The ops records are untouched and its new entry is still pending
+        let after = SyncState::load(&local_dir);
+        assert_eq!(after.entries.len(), 3);
+        let ops_key = SyncState::key("ops", &MemoryEntry::new("deploy", &[]));
+        assert_eq!(after.entries[&ops_key], before.entries[&ops_key]);
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (1, 3));
+    }
+
+    #[tokio::test]
+    async fn test_sync_tool_honours_category() {
+        let temp_dir = tempdir().unwrap();
+        let router = syncing_router(temp_dir.path());
+        router.remember("dev", "first", &[], false).unwrap();
+        router.remember("ops", "deploy", &[], false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let result = router
+            .call_tool(
+                "ultrathink_graphiti_sync",
+                json!({"direction": "to_graphiti", "category": "dev"}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.contains("(category: dev): 1 uploaded, 0 unchanged skipped"));
+
+        let result = router
+            .call_tool(
+                "ultrathink_graphiti_sync",
+                json!({"direction": "to_graphiti", "category": "*"}),
+                tx,
+            )
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.contains("1 uploaded, 1 unchanged skipped"));
+    }
+
+    #[tokio::test]
+    async fn test_sync_tool_reports_counts() {
+        let temp_dir = tempdir().unwrap();
+        let router = syncing_router(temp_dir.path());
+        router.remember("dev", "first", &[], false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let args = json!({"direction": "to_graphiti"});
+        let result = router
+            .call_tool("ultrathink_graphiti_sync", args.clone(), tx.clone())
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.contains("1 uploaded, 0 unchanged skipped"));
+        let report: Value = serde_json::from_str(text.lines().nth(1).unwrap()).unwrap();
+        assert_eq!(report["direction"], "to_graphiti");
+        assert_eq!(report["uploaded"], 1);
+        assert_eq!(report["errors"], json!([]));
+
+        let result = router
+            .call_tool("ultrathink_graphiti_sync", args, tx)
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.contains("0 uploaded, 1 unchanged skipped"));
+    }
+
+    #[tokio::test]
+    async fn test_graphiti_diff_reports_divergence() {
+        let temp_dir = tempdir().unwrap();
+        let router = backed_router(temp_dir.path(), Arc::new(InMemoryGraphiti::default()));
+        router.remember("dev", "uses tokio", &[], false).unwrap();
+        router.set_singleton("status", true, false).unwrap();
+        router.remember("status", "green", &[], false).unwrap();
+        router.sync_to_graphiti(None).await.unwrap();
+        assert!(router.graphiti_diff(None, false).await.unwrap().is_empty());
+
+        router.remember("dev", "local only", &[], false).unwrap();
+        router.remember("status", "red", &[], false).unwrap();
+        let client = &router.local_graphiti_client;
+        client
+            .store_memory("dev", "remote  only", &[], None)
+            .await
+            .unwrap();
+
+        let (tx, _rx) = mpsc::channel(1);
+        let (content, meta) = router
+            .call_tool_with_meta(
+                "ultrathink_graphiti_diff",
+                json!({"category": "*"}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        assert!(content[0].as_text().unwrap().text.contains("Graphiti diff"));
+        let diff = &meta.unwrap()["diff"];
+        let local_only = diff["local_only"].as_array().unwrap();
+        assert_eq!(local_only.len(), 1);
+        assert_eq!(local_only[0]["data"], "local only");
+        assert_eq!(diff["remote_only"][0]["category"], "dev");
+        assert_eq!(
+            diff["remote_only"][0]["entity"]["observations"],
+            json!(["remote  only"])
+        );
+        let conflicting = diff["conflicting"].as_array().unwrap();
+        assert_eq!(conflicting.len(), 1);
+        assert_eq!(conflicting[0]["local"]["data"], "red");
+        assert_eq!(conflicting[0]["remote"]["observations"], json!(["green"]));
+
+        // Comparing one category leaves the others out, and nothing was changed
+        let dev = router.graphiti_diff(Some("dev"), false).await.unwrap();
+        assert_eq!((dev.local_only.len(), dev.remote_only.len()), (1, 1));
+        assert!(dev.conflicting.is_empty());
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 2);
+
+        let unconfigured = test_router(temp_dir.path());
+        let result = unconfigured
+            .call_tool("ultrathink_graphiti_diff", json!({}), tx)
+            .await
+            .unwrap();
+        let text = &result[0].as_text().unwrap().text;
+        assert!(text.contains("nothing to compare"));
+    }
+
+    struct FailingBackend;
+
+    #[async_trait]
+    impl GraphitiBackend for FailingBackend {
+        async fn call(
+            &self,
+            _server: &str,
+            _method: &str,
+            _params: Value,
+        ) -> Result<Value, GraphitiError> {
+            Err(GraphitiError::Transport("connection refused".to_string()))
+        }
+    }
+
+    /// Fails every call whose parameters mention the given text
+    struct FailOnBackend(&'static str);
+
+    #[async_trait]
+    impl GraphitiBackend for FailOnBackend {
+        async fn call(
+            &self,
+            _server: &str,
+            _method: &str,
+            params: Value,
+        ) -> Result<Value, GraphitiError> {
+            if params.to_string().contains(self.0) {
+                return Err(GraphitiError::Transport("connection refused".to_string()));
+            }
+            Ok(json!({
+                "entities": [{"name": "dev_1", "entityType": "DEV", "observations": ["remote"]}]
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partially_failed_sync_report() {
+        let temp_dir = tempdir().unwrap();
+        let router = backed_router(temp_dir.path(), Arc::new(FailOnBackend("broken")));
+        router.remember("dev", "fine", &[], false).unwrap();
+        router.remember("dev", "broken", &[], false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let sync = |tx| {
+            let router = router.clone();
+            async move {
+                let result = router
+                    .call_tool(
+                        "ultrathink_graphiti_sync",
+                        json!({"direction": "bidirectional"}),
+                        tx,
+                    )
+                    .await
+                    .unwrap();
+                let text = result[0].as_text().unwrap().text.clone();
+                serde_json::from_str::<Value>(text.lines().nth(1).unwrap()).unwrap()
+            }
+        };
+
+        let report = sync(tx.clone()).await;
+        assert_eq!(report["uploaded"], 1);
+        assert_eq!(report["downloaded"], 1);
+        let errors = report["errors"].as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].as_str().unwrap().contains("connection refused"));
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 3);
+
+        // The failed entry is retried, neither the uploaded nor the downloaded one is
+        let report = sync(tx).await;
+        assert_eq!(
+            (report["uploaded"].clone(), report["skipped"].clone()),
+            (json!(0), json!(2))
+        );
+        assert_eq!(report["downloaded"], 0);
+        assert_eq!(report["errors"].as_array().unwrap().len(), 1);
+    }
+
+    /// Stores entities once per idempotency key, like a server honouring the key, and
+    /// times out on the first store after it went through
+    #[derive(Default)]
+    struct TimeoutOnceBackend {
+        entities: std::sync::Mutex<BTreeMap<String, Value>>,
+        timed_out: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl GraphitiBackend for TimeoutOnceBackend {
+        async fn call(
+            &self,
+            _server: &str,
+            method: &str,
+            params: Value,
+        ) -> Result<Value, GraphitiError> {
+            if method != "create_entities" {
+                return Ok(json!({"entities": []}));
+            }
+            let mut entities = self.entities.lock().unwrap();
+            for entity in params["entities"].as_array().unwrap() {
+                let key = entity["idempotencyKey"].as_str().unwrap().to_string();
+                entities.entry(key).or_insert_with(|| entity.clone());
+            }
+            if !self
+                .timed_out
+                .swap(true, std::sync::atomic::Ordering::SeqCst)
+            {
+                return Err(GraphitiError::Transport("request timed out".to_string()));
+            }
+            Ok(json!({}))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retried_store_after_timeout_creates_one_entity() {
+        let temp_dir = tempdir().unwrap();
+        let backend = Arc::new(TimeoutOnceBackend::default());
+        let router = backed_router(temp_dir.path(), backend.clone());
+        router
+            .remember("dev", "deploy on fridays", &[], false)
+            .unwrap();
+
+        // The store went through but timed out, so it is not recorded as synced
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!(report.uploaded, 0);
+        assert_eq!(report.errors.len(), 1);
+
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!(report.uploaded, 1);
+        assert!(report.errors.is_empty());
+        assert_eq!(backend.entities.lock().unwrap().len(), 1);
+
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_failed_high_priority_upload_keeps_local_write() {
+        let temp_dir = tempdir().unwrap();
+        let mut router = backed_router(temp_dir.path(), Arc::new(FailingBackend));
+        let (tx, _rx) = mpsc::channel(1);
+        let args = json!({
+            "category": "dev", "data": "critical", "is_global": false, "priority": "high"
+        });
+
+        let result = router
+            .call_tool("ultrathink_remember", args.clone(), tx.clone())
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.contains("queued for the next sync: "));
+        assert!(text.contains("connection refused"));
+        assert_eq!(
+            router.read_entries("dev", false).unwrap()[0].data,
+            "critical"
+        );
+        // Not recorded as synced, so the next sync retries it
+        assert!(SyncState::load(&temp_dir.path().join("local"))
+            .entries
+            .is_empty());
+
+        router.config.strict_graphiti_upload = true;
+        let err = router
+            .call_tool("ultrathink_remember", args, tx)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ToolError::ExecutionError(_)));
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 2);
+    }
+
+    /// Has one entity for the `status` category and records the methods called
+    #[derive(Default)]
+    struct ExistingEntityBackend {
+        calls: std::sync::Mutex<Vec<(String, Value)>>,
+    }
+
+    #[async_trait]
+    impl GraphitiBackend for ExistingEntityBackend {
+        async fn call(
+            &self,
+            _server: &str,
+            method: &str,
+            params: Value,
+        ) -> Result<Value, GraphitiError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push((method.to_string(), params));
+            Ok(json!({
+                "entities": [{"name": "status_1", "entityType": "STATUS", "observations": ["green"]}]
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_singleton_remember_updates_existing_entity() {
+        let temp_dir = tempdir().unwrap();
+        let backend = Arc::new(ExistingEntityBackend::default());
+        let router = backed_router(temp_dir.path(), backend.clone());
+        router.set_singleton("status", true, false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+        router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "status", "data": "red", "is_global": false, "priority": "high"}),
+                tx,
+            )
+            .await
+            .unwrap();
+
+        let calls = backend.calls.lock().unwrap().clone();
+        let methods: Vec<&str> = calls.iter().map(|(method, _)| method.as_str()).collect();
+        assert_eq!(
+            methods,
+            vec![
+                "search_nodes",
+                "open_nodes",
+                "delete_observations",
+                "add_observations"
+            ]
+        );
+        assert_eq!(
+            calls[3].1,
+            json!({"observations": [{"entityName": "status_1", "contents": ["red"]}]})
+        );
+    }
+
+    /// The next `Synced` or `SyncFailed` event, skipping the others
+    async fn next_sync_event(events: &mut broadcast::Receiver<MemoryEvent>) -> MemoryEvent {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if matches!(
+                    event.kind,
+                    MemoryEventKind::Synced | MemoryEventKind::SyncFailed { .. }
+                ) {
+                    return event;
+                }
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sync_on_write_modes() {
+        for (mode, expected_uploads) in [
+            (SyncOnWrite::Off, 0),
+            (SyncOnWrite::HighPriorityOnly, 1),
+            (SyncOnWrite::All, 2),
+        ] {
+            let temp_dir = tempdir().unwrap();
+            let backend = Arc::new(RecordingBackend::default());
+            let mut router = backed_router(temp_dir.path(), backend.clone());
+            router.config.sync_on_write = mode;
+            let mut events = router.subscribe();
+            let (tx, _rx) = mpsc::channel(1);
+
+            for priority in ["high", "low"] {
+                router
+                    .call_tool(
+                        "ultrathink_remember",
+                        json!({"category": "dev", "data": priority, "is_global": false, "priority": priority}),
+                        tx.clone(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            if mode == SyncOnWrite::All {
+                let event = next_sync_event(&mut events).await;
+                assert_eq!(event.kind, MemoryEventKind::Synced);
+                assert_eq!(event.entry_id, Some(MemoryEntry::new("low", &[]).id()));
+            }
+            assert_eq!(
+                backend.calls.lock().unwrap().len(),
+                expected_uploads,
+                "{:?}",
+                mode
+            );
+            assert_eq!(router.read_entries("dev", false).unwrap().len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_background_upload_failure_is_reported() {
+        let temp_dir = tempdir().unwrap();
+        let mut router = backed_router(temp_dir.path(), Arc::new(FailingBackend));
+        router.config.sync_on_write = SyncOnWrite::All;
+        let mut events = router.subscribe();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let result = router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "note", "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap();
+        assert!(!result[0].as_text().unwrap().text.contains("failed"));
+        match next_sync_event(&mut events).await.kind {
+            MemoryEventKind::SyncFailed { error } => assert!(error.contains("connection refused")),
+            other => panic!("expected a failed upload, got {:?}", other),
+        }
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 1);
+        assert!(SyncState::load(&temp_dir.path().join("local"))
+            .entries
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_remember_is_uploaded() {
+        let temp_dir = tempdir().unwrap();
+        let router = syncing_router(temp_dir.path());
+        let (tx, _rx) = mpsc::channel(1);
+        router
+            .call_tool(
+                "ultrathink_remember",
+                json!({
+                    "category": "dev", "data": "critical", "is_global": false, "priority": "high"
+                }),
+                tx,
+            )
+            .await
+            .unwrap();
+
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!((report.uploaded, report.skipped), (0, 1));
+    }
+
+    /// Records the parameters of every call
+    #[derive(Default)]
+    struct RecordingBackend {
+        calls: std::sync::Mutex<Vec<Value>>,
+    }
+
+    #[async_trait]
+    impl GraphitiBackend for RecordingBackend {
+        async fn call(
+            &self,
+            _server: &str,
+            _method: &str,
+            params: Value,
+        ) -> Result<Value, GraphitiError> {
+            self.calls.lock().unwrap().push(params);
+            Ok(json!({"entities": []}))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_link_is_mirrored_to_graphiti() {
+        let temp_dir = tempdir().unwrap();
+        let backend = Arc::new(RecordingBackend::default());
+        let router = backed_router(temp_dir.path(), backend.clone());
+        router.remember("dev", "decision", &[], false).unwrap();
+        router.remember("dev", "reason", &[], false).unwrap();
+        let ids: Vec<String> = router
+            .read_entries("dev", false)
+            .unwrap()
+            .iter()
+            .map(MemoryEntry::id)
+            .collect();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let result = router
+            .call_tool(
+                "ultrathink_link",
+                json!({"from": ids[0], "to": ids[1], "relation": "because", "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap();
+        assert!(result[0]
+            .as_text()
+            .unwrap()
+            .text
+            .contains("mirrored to Graphiti"));
+        let calls = backend.calls.lock().unwrap();
+        assert_eq!(
+            calls.last().unwrap()["relations"][0],
+            json!({"from": ids[0], "to": ids[1], "relationType": "because"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scopes_sync_to_their_own_graph() {
+        let temp_dir = tempdir().unwrap();
+        let mut router = test_router(temp_dir.path());
+        let personal = Arc::new(RecordingBackend::default());
+        let project = Arc::new(RecordingBackend::default());
+        router.global_graphiti_client =
+            GraphitiClient::with_endpoint(Some("http://personal:8000".to_string()))
+                .with_backend(personal.clone());
+        router.local_graphiti_client =
+            GraphitiClient::with_endpoint(Some("http://project:8000".to_string()))
+                .with_backend(project.clone());
+        router.remember("dev", "project note", &[], false).unwrap();
+        router.remember("dev", "personal note", &[], true).unwrap();
+
+        let report = router.sync_to_graphiti(None).await.unwrap();
+        assert_eq!(report.uploaded, 2);
+        let observations = |backend: &RecordingBackend| -> Vec<Value> {
+            backend
+                .calls
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|params| params["entities"][0]["observations"][0].clone())
+                .collect()
+        };
+        assert_eq!(observations(&personal), vec![json!("personal note")]);
+        assert_eq!(observations(&project), vec![json!("project note")]);
+
+        // Queries spanning both scopes ask both graphs
+        let (tx, _rx) = mpsc::channel(1);
+        router
+            .call_tool("ultrathink_graphiti_query", json!({"query": "note"}), tx)
+            .await
+            .unwrap();
+        assert_eq!(personal.calls.lock().unwrap().len(), 2);
+        assert_eq!(project.calls.lock().unwrap().len(), 2);
+    }
+}
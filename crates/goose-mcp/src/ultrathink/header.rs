@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+
+/// Header line written above an UltraThink memory entry.
+///
+/// Two forms are understood when reading:
+/// - legacy: `# tag1 tag2`, space separated tags as written by the memory extension
+/// - structured: `# tags: a, b; priority: high; ctx: "some context"`
+///
+/// Structured headers are always used when writing. Values containing separators,
+/// quotes or newlines are double-quoted with backslash escapes so the header stays
+/// on a single line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryHeader {
+    pub tags: Vec<String>,
+    pub fields: BTreeMap<String, String>,
+}
+
+impl EntryHeader {
+    /// Parse a header line, returning `None` if the line is not a header
+    pub fn parse(line: &str) -> Option<Self> {
+        let body = line.strip_prefix('#')?;
+
+        if !is_structured(body) {
+            return Some(Self {
+                tags: body.split_whitespace().map(String::from).collect(),
+                fields: BTreeMap::new(),
+            });
+        }
+
+        let mut header = Self::default();
+        for segment in split_unquoted(body, ';') {
+            // Segments without a `key:` prefix carry no meaning in a structured header
+            let Some((key, value)) = segment.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            if key == "tags" {
+                header.tags = split_unquoted(value, ',')
+                    .iter()
+                    .map(|tag| unquote(tag))
+                    .filter(|tag| !tag.is_empty())
+                    .collect();
+            } else if !key.is_empty() {
+                header.fields.insert(key, unquote(value));
+            }
+        }
+        Some(header)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.fields.is_empty()
+    }
+
+    /// Render the header in the structured form, including the leading `#`
+    pub fn to_line(&self) -> String {
+        let mut segments = Vec::new();
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self.tags.iter().map(|tag| quote(tag)).collect();
+            segments.push(format!("tags: {}", tags.join(", ")));
+        }
+        for (key, value) in &self.fields {
+            segments.push(format!("{}: {}", key, quote(value)));
+        }
+        format!("# {}", segments.join("; "))
+    }
+}
+
+/// A header is structured when its first segment looks like `key: value`.
+/// The colon must be followed by whitespace so legacy tags such as URLs stay legacy.
+fn is_structured(body: &str) -> bool {
+    let first = split_unquoted(body, ';')
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    let Some((key, rest)) = first.split_once(':') else {
+        return false;
+    };
+    if !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+        return false;
+    }
+    let key = key.trim();
+    let mut chars = key.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Split on `separator` wherever it appears outside of a double-quoted section
+fn split_unquoted(input: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for c in input.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+        } else if in_quotes && c == '\\' {
+            current.push(c);
+            escaped = true;
+        } else if c == '"' {
+            current.push(c);
+            in_quotes = !in_quotes;
+        } else if c == separator && !in_quotes {
+            parts.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    let Some(inner) = value
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    else {
+        return value.to_string();
+    };
+
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+fn quote(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value.trim() != value
+        || value
+            .chars()
+            .any(|c| matches!(c, ';' | ',' | '"' | '\\' | '\n'));
+    if !needs_quotes {
+        return value.to_string();
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            other => quoted.push(other),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_header_line() {
+        assert_eq!(EntryHeader::parse("plain data"), None);
+    }
+
+    #[test]
+    fn test_legacy_header() {
+        let header = EntryHeader::parse("# rust  async tokio").unwrap();
+        assert_eq!(header.tags, vec!["rust", "async", "tokio"]);
+        assert!(header.fields.is_empty());
+    }
+
+    #[test]
+    fn test_legacy_header_with_colon_in_tag() {
+        let header = EntryHeader::parse("# http://example.com docs").unwrap();
+        assert_eq!(header.tags, vec!["http://example.com", "docs"]);
+        assert!(header.fields.is_empty());
+    }
+
+    #[test]
+    fn test_structured_header() {
+        let header = EntryHeader::parse("# tags: a, b; priority: high").unwrap();
+        assert_eq!(header.tags, vec!["a", "b"]);
+        assert_eq!(header.fields.get("priority").unwrap(), "high");
+    }
+
+    #[test]
+    fn test_quoted_values() {
+        let header =
+            EntryHeader::parse(r#"# tags: "two words", "a,b"; ctx: "uses ; and \"quotes\"""#)
+                .unwrap();
+        assert_eq!(header.tags, vec!["two words", "a,b"]);
+        assert_eq!(header.fields.get("ctx").unwrap(), r#"uses ; and "quotes""#);
+    }
+
+    #[test]
+    fn test_multiple_metadata_keys() {
+        let header =
+            EntryHeader::parse(r#"# tags: api; priority: low; ctx: "some context"; Owner: me"#)
+                .unwrap();
+        assert_eq!(header.tags, vec!["api"]);
+        assert_eq!(header.fields.len(), 3);
+        assert_eq!(header.fields.get("priority").unwrap(), "low");
+        assert_eq!(header.fields.get("ctx").unwrap(), "some context");
+        assert_eq!(header.fields.get("owner").unwrap(), "me");
+    }
+
+    #[test]
+    fn test_structured_header_without_tags() {
+        let header = EntryHeader::parse("# priority: medium").unwrap();
+        assert!(header.tags.is_empty());
+        assert_eq!(header.fields.get("priority").unwrap(), "medium");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut header = EntryHeader {
+            tags: vec![
+                "plain".to_string(),
+                "with space".to_string(),
+                "a;b".to_string(),
+            ],
+            fields: BTreeMap::new(),
+        };
+        header
+            .fields
+            .insert("ctx".to_string(), "line one\nline \"two\"".to_string());
+        header
+            .fields
+            .insert("priority".to_string(), "high".to_string());
+
+        let line = header.to_line();
+        assert!(!line.contains('\n'));
+        assert_eq!(EntryHeader::parse(&line).unwrap(), header);
+    }
+}
@@ -0,0 +1,136 @@
+use serde_json::Value;
+
+/// Marks the terms of a query wherever they occur in retrieved text
+#[derive(Debug, Clone)]
+pub struct Highlighter {
+    terms: Vec<String>,
+    marker: String,
+}
+
+impl Highlighter {
+    /// A highlighter for the whitespace separated terms of `query`; `None` if it has none
+    pub fn new(query: &str, marker: &str) -> Option<Self> {
+        let mut terms: Vec<String> = Vec::new();
+        for term in query.split_whitespace() {
+            if !terms.iter().any(|existing| existing == term) {
+                terms.push(term.to_string());
+            }
+        }
+        (!terms.is_empty()).then(|| Self {
+            terms,
+            marker: marker.to_string(),
+        })
+    }
+
+    /// Wrap every case-insensitive match of a term in the marker. Matches that overlap or
+    /// touch are wrapped together, so markers never nest or split a character.
+    pub fn highlight(&self, text: &str) -> String {
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        for (start, _) in text.char_indices() {
+            for term in &self.terms {
+                let Some(len) = match_len(&text[start..], term) else {
+                    continue;
+                };
+                match spans.last_mut() {
+                    Some(last) if start <= last.1 => last.1 = last.1.max(start + len),
+                    _ => spans.push((start, start + len)),
+                }
+            }
+        }
+
+        let mut highlighted =
+            String::with_capacity(text.len() + spans.len() * 2 * self.marker.len());
+        let mut copied = 0;
+        for (start, end) in spans {
+            highlighted.push_str(&text[copied..start]);
+            highlighted.push_str(&self.marker);
+            highlighted.push_str(&text[start..end]);
+            highlighted.push_str(&self.marker);
+            copied = end;
+        }
+        highlighted.push_str(&text[copied..]);
+        highlighted
+    }
+
+    /// Highlight the entry text in a retrieve result: plain strings, and the `data` of
+    /// entry objects. Ids, timestamps and other entry fields are left alone.
+    pub fn highlight_value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.highlight(text),
+            Value::Object(map) if map.contains_key("data") => {
+                if let Some(data) = map.get_mut("data") {
+                    self.highlight_value(data);
+                }
+            }
+            Value::Object(map) => map.values_mut().for_each(|v| self.highlight_value(v)),
+            Value::Array(values) => values.iter_mut().for_each(|v| self.highlight_value(v)),
+            _ => {}
+        }
+    }
+}
+
+/// Byte length of the prefix of `text` that matches `term` ignoring case
+fn match_len(text: &str, term: &str) -> Option<usize> {
+    let mut chars = text.char_indices();
+    for expected in term.chars() {
+        let (_, c) = chars.next()?;
+        if c != expected && !c.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(chars.next().map_or(text.len(), |(end, _)| end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn highlight(query: &str, text: &str) -> String {
+        Highlighter::new(query, "**").unwrap().highlight(text)
+    }
+
+    #[test]
+    fn test_single_match_ignores_case() {
+        assert_eq!(
+            highlight("token", "Refresh the Token daily"),
+            "Refresh the **Token** daily"
+        );
+        assert_eq!(highlight("missing", "nothing here"), "nothing here");
+        assert!(Highlighter::new("  ", "**").is_none());
+    }
+
+    #[test]
+    fn test_multiple_matches() {
+        assert_eq!(
+            highlight("api auth", "API keys and auth: the api"),
+            "**API** keys and **auth**: the **api**"
+        );
+        assert_eq!(highlight("ü", "Über über"), "**Ü**ber **ü**ber");
+    }
+
+    #[test]
+    fn test_overlapping_matches_are_merged() {
+        assert_eq!(
+            highlight("auth authentication", "authentication"),
+            "**authentication**"
+        );
+        assert_eq!(highlight("abc cde", "abcdef"), "**abcde**f");
+        assert_eq!(highlight("aa", "aaa"), "**aaa**");
+        assert_eq!(highlight("ab cd", "abcd"), "**abcd**");
+    }
+
+    #[test]
+    fn test_highlight_value_skips_entry_metadata() {
+        let highlighter = Highlighter::new("dev", "__").unwrap();
+        let mut value = json!({
+            "untagged": ["dev note"],
+            "entries": [{"id": "dev1", "category": "dev", "data": "a dev entry"}]
+        });
+        highlighter.highlight_value(&mut value);
+        assert_eq!(value["untagged"][0], "__dev__ note");
+        assert_eq!(value["entries"][0]["data"], "a __dev__ entry");
+        assert_eq!(value["entries"][0]["id"], "dev1");
+        assert_eq!(value["entries"][0]["category"], "dev");
+    }
+}
@@ -0,0 +1,182 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::file_name::{decode_category, encode_category};
+use super::storage::StorageFormat;
+
+/// Name of the index file kept next to the category files of a memory dir
+pub const INDEX_FILE: &str = "index.json";
+
+/// What the index records about one category file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CategoryStats {
+    /// Number of stored entries, including expired ones not cleaned up yet
+    pub count: usize,
+    /// Modification time of the category file
+    pub last_modified: DateTime<Utc>,
+    pub byte_size: u64,
+}
+
+impl CategoryStats {
+    fn read(path: &Path, format: StorageFormat) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            count: format.parse_entries(&content).len(),
+            last_modified: metadata.modified()?.into(),
+            byte_size: metadata.len(),
+        })
+    }
+
+    /// Whether the file described by `metadata` is still the one these stats were taken from
+    fn matches(&self, metadata: &fs::Metadata) -> bool {
+        metadata.len() == self.byte_size
+            && metadata
+                .modified()
+                .is_ok_and(|modified| DateTime::<Utc>::from(modified) == self.last_modified)
+    }
+}
+
+/// Category statistics for one memory dir, stored in its `index.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryIndex {
+    pub categories: BTreeMap<String, CategoryStats>,
+}
+
+impl MemoryIndex {
+    /// Load the index of `dir`; `None` if it is missing or unreadable
+    pub fn load(dir: &Path) -> Option<Self> {
+        let content = fs::read_to_string(dir.join(INDEX_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Build the index of `dir` from scratch by reading every category file
+    pub fn rebuild(dir: &Path, format: StorageFormat) -> io::Result<Self> {
+        let mut categories = BTreeMap::new();
+        for (category, path) in scan_categories(dir, format)? {
+            categories.insert(category, CategoryStats::read(&path, format)?);
+        }
+        Ok(Self { categories })
+    }
+
+    /// Save the index through a temporary file so readers never see a partial index
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let path = dir.join(INDEX_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)
+    }
+
+    /// Re-read the stats of one category, dropping it if its file no longer exists
+    pub fn refresh(&mut self, dir: &Path, category: &str, format: StorageFormat) -> io::Result<()> {
+        let path = dir.join(format!(
+            "{}.{}",
+            encode_category(category),
+            format.extension()
+        ));
+        if path.exists() {
+            self.categories
+                .insert(category.to_string(), CategoryStats::read(&path, format)?);
+        } else {
+            self.categories.remove(category);
+        }
+        Ok(())
+    }
+
+    /// Whether the index describes exactly the category files in `dir`. Only file
+    /// metadata is compared, so this is much cheaper than a rebuild.
+    pub fn matches_dir(&self, dir: &Path, format: StorageFormat) -> io::Result<bool> {
+        let files = scan_categories(dir, format)?;
+        if files.len() != self.categories.len() {
+            return Ok(false);
+        }
+        for (category, path) in files {
+            let Some(stats) = self.categories.get(&category) else {
+                return Ok(false);
+            };
+            if !stats.matches(&fs::metadata(&path)?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Category names and file paths in `dir`, sorted by name. Only files with the extension
+/// of `format` hold entries; sidecars such as `.meta.json` and the index itself are skipped.
+pub fn scan_categories(dir: &Path, format: StorageFormat) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut categories = Vec::new();
+    if dir.exists() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_file()
+                && path.extension().and_then(|ext| ext.to_str()) == Some(format.extension())
+            {
+                if let Some(stem) = path.file_stem() {
+                    categories.push((decode_category(&stem.to_string_lossy()), path));
+                }
+            }
+        }
+    }
+    categories.sort();
+    Ok(categories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rebuild_and_round_trip() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("dev.txt"), "first\n\nsecond\n\n").unwrap();
+        fs::write(dir.path().join("dev.meta.json"), "{}").unwrap();
+
+        let index = MemoryIndex::rebuild(dir.path(), StorageFormat::Text).unwrap();
+        assert_eq!(index.categories.keys().collect::<Vec<_>>(), vec!["dev"]);
+        assert_eq!(index.categories["dev"].count, 2);
+        assert_eq!(index.categories["dev"].byte_size, 15);
+
+        index.save(dir.path()).unwrap();
+        assert_eq!(MemoryIndex::load(dir.path()), Some(index));
+    }
+
+    #[test]
+    fn test_matches_dir_detects_changes() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("dev.txt"), "first\n\n").unwrap();
+        let index = MemoryIndex::rebuild(dir.path(), StorageFormat::Text).unwrap();
+        assert!(index.matches_dir(dir.path(), StorageFormat::Text).unwrap());
+
+        fs::write(dir.path().join("dev.txt"), "first\n\nsecond\n\n").unwrap();
+        assert!(!index.matches_dir(dir.path(), StorageFormat::Text).unwrap());
+
+        let index = MemoryIndex::rebuild(dir.path(), StorageFormat::Text).unwrap();
+        fs::write(dir.path().join("ops.txt"), "deploy\n\n").unwrap();
+        assert!(!index.matches_dir(dir.path(), StorageFormat::Text).unwrap());
+    }
+
+    #[test]
+    fn test_scan_uses_format_extension() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("dev.txt"), "first\n\n").unwrap();
+        fs::write(dir.path().join("ops.md"), "---\n---\ndeploy\n\n").unwrap();
+
+        let index = MemoryIndex::rebuild(dir.path(), StorageFormat::Markdown).unwrap();
+        assert_eq!(index.categories.keys().collect::<Vec<_>>(), vec!["ops"]);
+        assert_eq!(index.categories["ops"].count, 1);
+    }
+
+    #[test]
+    fn test_corrupt_index_is_ignored() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(INDEX_FILE), "not json").unwrap();
+        assert_eq!(MemoryIndex::load(dir.path()), None);
+    }
+}
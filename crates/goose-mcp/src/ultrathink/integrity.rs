@@ -0,0 +1,160 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Appended to the name of a category file to name its checksum sidecar
+pub const CHECKSUM_EXTENSION: &str = "sha256";
+
+/// Appended to the name of a corrupted category file when it is moved aside
+pub const QUARANTINE_EXTENSION: &str = "corrupt";
+
+/// What a category file was found to be when checked against its sidecar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integrity {
+    Intact,
+    /// There is no sidecar yet, e.g. because the file was written before checksums were
+    /// enabled; it gets one on its next write
+    Unchecked,
+    Corrupted {
+        expected: String,
+        actual: String,
+    },
+}
+
+/// The categories of a scope by integrity, returned as JSON by `ultrathink_verify`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IntegrityReport {
+    pub intact: Vec<String>,
+    pub unchecked: Vec<String>,
+    pub corrupted: Vec<String>,
+}
+
+impl IntegrityReport {
+    pub fn add(&mut self, category: String, integrity: &Integrity) {
+        match integrity {
+            Integrity::Intact => self.intact.push(category),
+            Integrity::Unchecked => self.unchecked.push(category),
+            Integrity::Corrupted { .. } => self.corrupted.push(category),
+        }
+    }
+}
+
+/// The sidecar holding the checksum of `memory_file`, e.g. `dev.txt.sha256`
+pub fn checksum_path(memory_file: &Path) -> PathBuf {
+    with_suffix(memory_file, CHECKSUM_EXTENSION)
+}
+
+/// Record the current checksum of `memory_file`, or remove its sidecar when the file is
+/// gone. The sidecar is swapped in atomically like the category files themselves.
+pub fn update(memory_file: &Path) -> io::Result<()> {
+    let sidecar = checksum_path(memory_file);
+    if !memory_file.exists() {
+        if sidecar.exists() {
+            fs::remove_file(&sidecar)?;
+        }
+        return Ok(());
+    }
+
+    let tmp_path = sidecar.with_extension(format!("{}.tmp", CHECKSUM_EXTENSION));
+    fs::write(&tmp_path, format!("{}\n", file_digest(memory_file)?))?;
+    fs::rename(&tmp_path, &sidecar)
+}
+
+/// Check `memory_file` against its sidecar. A missing file has nothing to check.
+pub fn verify(memory_file: &Path) -> io::Result<Integrity> {
+    let expected = match fs::read_to_string(checksum_path(memory_file)) {
+        Ok(content) => content.trim().to_string(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Integrity::Unchecked),
+        Err(e) => return Err(e),
+    };
+    if !memory_file.exists() {
+        return Ok(Integrity::Unchecked);
+    }
+
+    let actual = file_digest(memory_file)?;
+    Ok(if actual == expected {
+        Integrity::Intact
+    } else {
+        Integrity::Corrupted { expected, actual }
+    })
+}
+
+/// Move a corrupted category file out of its category, keeping it next to the other files
+/// for inspection, and drop its sidecar. Returns where the file was moved.
+pub fn quarantine(memory_file: &Path, now: DateTime<Utc>) -> io::Result<PathBuf> {
+    let stamp = now.format("%Y%m%dT%H%M%S%.3fZ");
+    let destination = with_suffix(memory_file, &format!("{}.{}", stamp, QUARANTINE_EXTENSION));
+    fs::rename(memory_file, &destination)?;
+    let sidecar = checksum_path(memory_file);
+    if sidecar.exists() {
+        fs::remove_file(sidecar)?;
+    }
+    Ok(destination)
+}
+
+/// SHA-256 of a file's content, streamed so large category files are not read whole
+fn file_digest(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_detects_changed_content() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("dev.txt");
+        fs::write(&path, "first\n\n").unwrap();
+        assert_eq!(verify(&path).unwrap(), Integrity::Unchecked);
+
+        update(&path).unwrap();
+        assert!(dir.path().join("dev.txt.sha256").exists());
+        assert_eq!(verify(&path).unwrap(), Integrity::Intact);
+
+        fs::write(&path, "first\n\nsec").unwrap();
+        assert!(matches!(
+            verify(&path).unwrap(),
+            Integrity::Corrupted { .. }
+        ));
+
+        fs::remove_file(&path).unwrap();
+        update(&path).unwrap();
+        assert!(!checksum_path(&path).exists());
+    }
+
+    #[test]
+    fn test_quarantine_moves_file_aside() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("dev.txt");
+        fs::write(&path, "first\n\n").unwrap();
+        update(&path).unwrap();
+
+        let moved = quarantine(&path, Utc::now()).unwrap();
+        assert!(!path.exists());
+        assert!(!checksum_path(&path).exists());
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "first\n\n");
+        assert_eq!(
+            moved.extension().and_then(|ext| ext.to_str()),
+            Some(QUARANTINE_EXTENSION)
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Name of the file holding the links between the entries of a memory dir
+pub const LINKS_FILE: &str = "links.json";
+
+/// Name the links file is locked under; categories cannot start with a dot, so it never
+/// shares a lock with one
+pub const LINKS_LOCK: &str = ".links";
+
+/// A labelled, directed relation between two entries of the same memory dir
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryLink {
+    pub from: String,
+    pub to: String,
+    pub relation: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl MemoryLink {
+    /// The entry at the other end of the link, if it touches `id`
+    pub fn other_end(&self, id: &str) -> Option<&str> {
+        if self.from == id {
+            Some(&self.to)
+        } else if self.to == id {
+            Some(&self.from)
+        } else {
+            None
+        }
+    }
+}
+
+/// The links of one memory dir, stored in its `links.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryLinks {
+    pub links: Vec<MemoryLink>,
+}
+
+impl MemoryLinks {
+    /// Load the links of `dir`; a missing file has none and a corrupt one is ignored
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(LINKS_FILE);
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring corrupt memory links {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let path = dir.join(LINKS_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)
+    }
+
+    /// Add a link, returning false if the same relation between the same entries exists
+    pub fn add(&mut self, link: MemoryLink) -> bool {
+        let exists = self.links.iter().any(|existing| {
+            existing.from == link.from
+                && existing.to == link.to
+                && existing.relation == link.relation
+        });
+        if !exists {
+            self.links.push(link);
+        }
+        !exists
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn link(from: &str, to: &str, relation: &str) -> MemoryLink {
+        MemoryLink {
+            from: from.to_string(),
+            to: to.to_string(),
+            relation: relation.to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_add_skips_duplicates_and_round_trips() {
+        let dir = tempdir().unwrap();
+        let mut links = MemoryLinks::load(dir.path());
+        assert!(links.links.is_empty());
+
+        assert!(links.add(link("a", "b", "explains")));
+        assert!(!links.add(link("a", "b", "explains")));
+        assert!(links.add(link("a", "b", "supersedes")));
+        links.save(dir.path()).unwrap();
+
+        let loaded = MemoryLinks::load(dir.path());
+        assert_eq!(loaded, links);
+        assert_eq!(loaded.links[0].other_end("b"), Some("a"));
+        assert_eq!(loaded.links[0].other_end("c"), None);
+
+        fs::write(dir.path().join(LINKS_FILE), "[").unwrap();
+        assert!(MemoryLinks::load(dir.path()).links.is_empty());
+    }
+}
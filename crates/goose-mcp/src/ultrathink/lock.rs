@@ -0,0 +1,115 @@
+use fs2::FileExt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::file_name::encode_category;
+
+/// How long a category lock is waited for when no timeout is configured
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest pause between two attempts to take a contended lock
+const MAX_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// An OS-level advisory lock on one category of a memory dir, held until dropped.
+///
+/// The lock is taken on a `<category>.lock` file next to the category rather than on
+/// the category file itself, because rewrites replace the category file and a lock on
+/// the replaced file would no longer exclude anyone. Since the lock belongs to an open
+/// file, it also excludes other routers in the same process.
+#[derive(Debug)]
+pub struct CategoryLock {
+    file: File,
+}
+
+impl CategoryLock {
+    /// Take the lock of `category` in `dir`, retrying with backoff until `timeout` has
+    /// passed. Gives up with `TimedOut` so a stuck holder cannot block a writer forever.
+    pub fn acquire(dir: &Path, category: &str, timeout: Duration) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = lock_path(dir, category);
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut delay = Duration::from_millis(1);
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(Self { file }),
+                Err(e) if e.kind() != fs2::lock_contended_error().kind() => return Err(e),
+                Err(_) => {}
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "Timed out after {:?} waiting for the lock on {}",
+                        timeout,
+                        path.display()
+                    ),
+                ));
+            }
+            thread::sleep(delay.min(deadline - now));
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+}
+
+impl Drop for CategoryLock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock as well; unlocking first just makes it prompt
+        let _ = self.file.unlock();
+    }
+}
+
+fn lock_path(dir: &Path, category: &str) -> PathBuf {
+    dir.join(format!("{}.lock", encode_category(category)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_contended_lock_times_out() {
+        let dir = tempdir().unwrap();
+        let held = CategoryLock::acquire(dir.path(), "dev", DEFAULT_LOCK_TIMEOUT).unwrap();
+
+        let err = CategoryLock::acquire(dir.path(), "dev", Duration::from_millis(20)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // Other categories are independent
+        CategoryLock::acquire(dir.path(), "ops", Duration::from_millis(20)).unwrap();
+
+        drop(held);
+        CategoryLock::acquire(dir.path(), "dev", Duration::from_millis(20)).unwrap();
+    }
+
+    #[test]
+    fn test_waiter_gets_lock_once_released() {
+        let dir = tempdir().unwrap();
+        let held = CategoryLock::acquire(dir.path(), "dev", DEFAULT_LOCK_TIMEOUT).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let path = dir.path().to_path_buf();
+        let waiter = thread::spawn(move || {
+            tx.send(()).unwrap();
+            CategoryLock::acquire(&path, "dev", DEFAULT_LOCK_TIMEOUT).map(|_| ())
+        });
+
+        rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(30));
+        drop(held);
+        waiter.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use super::entry::MemoryEntry;
+
+/// Size from which text category files are memory-mapped when no threshold is configured
+pub const DEFAULT_MMAP_THRESHOLD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Written at the start of files by some Windows editors; skipped like
+/// [`super::storage::StorageFormat::parse_entries`] skips it
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The last `limit` entries of a text category file that are live at `now`, in file order.
+///
+/// The file is mapped instead of read and scanned backwards from its end for the blank
+/// lines between blocks, so only the blocks up to the oldest returned entry are parsed and
+/// memory use depends on `limit` rather than on the size of the file. Blocks are split
+/// exactly as [`super::entry::parse_entries`] splits them.
+pub fn read_last_entries(
+    path: &Path,
+    limit: usize,
+    now: DateTime<Utc>,
+) -> io::Result<Vec<MemoryEntry>> {
+    let file = File::open(path)?;
+    if limit == 0 || file.metadata()?.len() == 0 {
+        return Ok(Vec::new());
+    }
+    // SAFETY: category files are only ever appended to or replaced by renaming a new file
+    // over them, so the mapped bytes are never truncated or rewritten while mapped.
+    let map = unsafe { Mmap::map(&file)? };
+    let content = map.strip_prefix(UTF8_BOM).unwrap_or(&map);
+
+    let mut entries = Vec::new();
+    let mut end = content.len();
+    while end > 0 && entries.len() < limit {
+        let (start, next_end) = last_block(&content[..end]);
+        if let Some(entry) = MemoryEntry::parse(&String::from_utf8_lossy(&content[start..end])) {
+            if !entry.is_expired(now) {
+                entries.push(entry);
+            }
+        }
+        end = next_end;
+    }
+    entries.reverse();
+    Ok(entries)
+}
+
+/// Where the last block of `bytes` starts, and where the content before its separator
+/// ends. A forward split on `\n\n` takes newline pairs from the left of a run of newlines,
+/// so an odd newline left over belongs to the start of the following block.
+fn last_block(bytes: &[u8]) -> (usize, usize) {
+    let Some(separator) = bytes.windows(2).rposition(|pair| pair == b"\n\n") else {
+        return (0, 0);
+    };
+    let mut run_start = separator;
+    while run_start > 0 && bytes[run_start - 1] == b'\n' {
+        run_start -= 1;
+    }
+    let run = separator + 2 - run_start;
+    (run_start + run / 2 * 2, run_start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ultrathink::entry::parse_entries;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_matches_forward_parse() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("dev.txt");
+        let content = "# api\nfirst\n\nsecond\n\n\nthird\n\n\n\n\nfourth\nline\n\n";
+        fs::write(&path, content).unwrap();
+
+        let all = read_last_entries(&path, usize::MAX, Utc::now()).unwrap();
+        let expected = parse_entries(content);
+        assert_eq!(all.len(), expected.len());
+        for (read, parsed) in all.iter().zip(&expected) {
+            assert_eq!(read.data, parsed.data);
+            assert_eq!(read.tags, parsed.tags);
+        }
+
+        let last = read_last_entries(&path, 2, Utc::now()).unwrap();
+        assert_eq!(last.len(), 2);
+        assert_eq!(last[1].data, "fourth\nline");
+    }
+
+    #[test]
+    fn test_skips_expired_and_empty_files() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("dev.txt");
+        fs::write(&path, "").unwrap();
+        assert!(read_last_entries(&path, 5, Utc::now()).unwrap().is_empty());
+
+        let now = Utc::now();
+        let mut expired = MemoryEntry::new("expired", &[]);
+        expired.expires = Some(now - chrono::Duration::seconds(1));
+        let content = format!("kept\n\n{}\n\n", expired.to_block());
+        fs::write(&path, content).unwrap();
+        let entries = read_last_entries(&path, 1, now).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].data, "kept");
+    }
+}
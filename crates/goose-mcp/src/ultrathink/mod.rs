@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use base64::Engine;
 use etcetera::{choose_app_strategy, AppStrategy};
 use indoc::formatdoc;
 use mcp_core::{
@@ -6,23 +7,109 @@ use mcp_core::{
     protocol::ServerCapabilities,
     tool::ToolCall,
 };
-use mcp_server::router::CapabilitiesBuilder;
+use mcp_server::router::{CapabilitiesBuilder, ToolResultMeta};
 use mcp_server::Router;
 use rmcp::model::{Content, JsonRpcMessage, Prompt, Resource, Tool, ToolAnnotations};
 use rmcp::object;
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
     fs,
     future::Future,
-    io::{self, Read, Write},
-    path::PathBuf,
+    io::{self, Read},
+    path::{Path, PathBuf},
     pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::{JoinError, JoinSet};
 
+mod arguments;
+mod attachment;
+mod category_meta;
+mod clock;
+mod config;
+mod date_range;
+mod entry;
+mod events;
+mod file_name;
+mod format;
+mod graphiti_backend;
 mod graphiti_client;
+mod graphiti_diff;
+mod graphiti_sync;
+mod header;
+mod highlight;
+mod index;
+mod integrity;
+mod links;
+mod lock;
+mod mmap_reader;
+mod mutations;
+mod query_cache;
+mod stages;
+mod storage;
+mod suggest;
+mod sync_state;
+mod tag_index;
+mod trace;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use config::{
+    CategoryCase, MultilineStyle, ScopePrecedence, SyncOnWrite, TimestampDisplay, UltraThinkConfig,
+};
+pub use events::{MemoryEvent, MemoryEventKind, MemoryScope};
+pub use format::{EmojiFormatter, JsonFormatter, ResponseFormatter};
+pub use index::CategoryStats;
+pub use integrity::IntegrityReport;
+pub use storage::StorageFormat;
+pub use sync_state::SyncReport;
+pub use trace::ToolCallRecord;
+
+use crate::GooseConfig;
+use arguments::{optional_bool, optional_str, optional_strings, optional_usize, required_str};
+use attachment::{attachment_uri, parse_attachment_uri, Attachment};
+use category_meta::CategoryMeta;
+use chrono::{DateTime, Utc};
+use date_range::DateRange;
+use entry::{normalize_data, MemoryEntry, CONTEXT_FIELD, PRIORITY_FIELD, TRUNCATION_MARKER};
+use file_name::encode_category;
 use graphiti_client::GraphitiClient;
+use highlight::Highlighter;
+use index::MemoryIndex;
+use integrity::Integrity;
+use links::MemoryLinks;
+use stages::{StageTracker, ThinkingStage};
+use tag_index::TagIndex;
+use trace::ToolTrace;
+
+/// Number of entries returned by a `recent` retrieve when no `limit` is given
+const DEFAULT_RECENT_LIMIT: usize = 10;
+
+/// Most retrieved entries an `expand_graph` retrieve looks up in Graphiti
+const MAX_EXPANDED_ENTRIES: usize = 10;
+
+/// Most Graphiti neighbors an `expand_graph` retrieve lists per entry
+const MAX_GRAPH_NEIGHBORS: usize = 5;
+
+/// Most category files `retrieve_all_parallel` reads at the same time
+const MAX_PARALLEL_READS: usize = 8;
+
+/// Fewest categories `retrieve_all_parallel` reads in parallel; smaller stores are read in
+/// a single task, where spawning one per file would cost more than it saves
+const PARALLEL_READ_THRESHOLD: usize = 16;
+
+/// Size from which text category files are read from their end through a memory map when
+/// listing memories in the instructions, unless memory maps are disabled
+const INSTRUCTION_TAIL_BYTES: u64 = 256 * 1024;
+
+/// Ends the instructions when the memory appendix was cut to fit the size limit
+const APPENDIX_TRIMMED_NOTE: &str = "\n[memory appendix trimmed]\n";
+
+/// The shared settings read by the router, none of which has a default
+const SETTINGS: &[(&str, Option<&str>)] =
+    &[("goose_working_dir", None), ("graphiti_endpoint", None)];
 
 /// UltraThink Router - Advanced Memory & Sequential Thinking System
 /// Combines local file storage with Graphiti integration for persistent memory
@@ -33,7 +120,19 @@ pub struct UltraThinkRouter {
     global_memory_dir: PathBuf,
     local_memory_dir: PathBuf,
     graphiti_endpoint: Option<String>,
-    graphiti_client: GraphitiClient,
+    /// The shared settings resolved from `ultrathink.toml` and the environment
+    settings: GooseConfig,
+    /// Each scope can sync to its own graph; both clients share an endpoint by default
+    global_graphiti_client: GraphitiClient,
+    local_graphiti_client: GraphitiClient,
+    config: UltraThinkConfig,
+    formatter: Arc<dyn ResponseFormatter>,
+    clock: Arc<dyn Clock>,
+    events: broadcast::Sender<MemoryEvent>,
+    /// Shared between clones, so calls through every handle of a router are traced
+    trace: Arc<Mutex<ToolTrace>>,
+    /// How far `ultrathink_sequence` has progressed, shared between clones like the trace
+    stages: Arc<Mutex<StageTracker>>,
 }
 
 impl Default for UltraThinkRouter {
@@ -44,6 +143,33 @@ impl Default for UltraThinkRouter {
 
 impl UltraThinkRouter {
     pub fn new() -> Self {
+        // Directory setup (same as MemoryRouter)
+        let global_memory_dir = choose_app_strategy(crate::APP_STRATEGY.clone())
+            .map(|strategy| strategy.in_config_dir("memory"))
+            .unwrap_or_else(|_| PathBuf::from(".config/goose/memory"));
+
+        let local_memory_dir = resolve_settings(&global_memory_dir)
+            .get_path("goose_working_dir")
+            .unwrap_or_else(|| std::env::current_dir().unwrap())
+            .join(".goose")
+            .join("memory");
+
+        Self::with_memory_dirs(global_memory_dir, local_memory_dir)
+    }
+
+    /// Build a router that stores memories under the given global and local directories
+    pub fn with_memory_dirs(global_memory_dir: PathBuf, local_memory_dir: PathBuf) -> Self {
+        let config =
+            UltraThinkConfig::from_env().with_file(&config::config_file_path(&global_memory_dir));
+        Self::with_config(config, global_memory_dir, local_memory_dir)
+    }
+
+    /// Build a router with an explicit configuration
+    pub fn with_config(
+        config: UltraThinkConfig,
+        global_memory_dir: PathBuf,
+        local_memory_dir: PathBuf,
+    ) -> Self {
         // Enhanced memory tools for UltraThink
         let remember_memory = Tool::new(
             "ultrathink_remember",
@@ -55,8 +181,13 @@ impl UltraThinkRouter {
                     "data": {"type": "string"},
                     "tags": {"type": "array", "items": {"type": "string"}},
                     "is_global": {"type": "boolean"},
+                    "debug": {"type": "boolean", "description": "Append the resolved file path, scope and whether the memory dir was created"},
                     "priority": {"type": "string", "enum": ["low", "medium", "high"]},
-                    "context": {"type": "string"}
+                    "context": {"type": "string"},
+                    "attachment_path": {"type": "string", "description": "Store a reference to this file (path, MIME type and size) instead of its content; `data` describes it"},
+                    "pinned": {"type": "boolean", "description": "Protect the entry from compaction and expiry"},
+                    "ttl_secs": {"type": "integer", "minimum": 0, "description": "Expire the entry after this many seconds"},
+                    "singleton": {"type": "boolean", "description": "Mark the category as holding one current value that each store replaces (false to accumulate again)"}
                 },
                 "required": ["category", "data", "is_global"]
             }),
@@ -77,8 +208,20 @@ impl UltraThinkRouter {
                 "properties": {
                     "category": {"type": "string"},
                     "is_global": {"type": "boolean"},
+                    "debug": {"type": "boolean", "description": "Append the resolved file path, scope and whether the memory dir was created"},
+                    "scope": {"type": "string", "enum": ["local", "global", "both"]},
                     "query": {"type": "string"},
-                    "limit": {"type": "number"}
+                    "limit": {"type": "number", "description": "With recent, how many entries to return; in one scope, return only the newest entries of the category, or of each category with \"*\""},
+                    "flatten": {"type": "boolean"},
+                    "recent": {"type": "boolean"},
+                    "after": {"type": "string", "description": "Only entries stored at or after this date or RFC 3339 time"},
+                    "before": {"type": "string", "description": "Only entries stored at or before this date or RFC 3339 time"},
+                    "include_undated": {"type": "boolean", "description": "Keep entries without a timestamp when after or before is given"},
+                    "tag": {"type": "string", "description": "Only entries with this tag; use category \"*\" to search every category"},
+                    "join": {"type": "string", "description": "Return each category as one string, its entries joined with this separator"},
+                    "include_links": {"type": "boolean", "description": "Also return the links of the retrieved entries, with both linked entries expanded"},
+                    "expand_graph": {"type": "boolean", "description": "Also return the Graphiti entities one relation away from the retrieved entries that have links"},
+                    "highlight": {"type": "boolean", "description": "Wrap the terms of query in a marker wherever they occur in the returned entries"}
                 },
                 "required": ["category", "is_global"]
             }),
@@ -100,7 +243,8 @@ impl UltraThinkRouter {
                     "thought": {"type": "string"},
                     "stage": {"type": "string", "enum": ["Problem Definition", "Research", "Analysis", "Synthesis", "Conclusion"]},
                     "save_to_memory": {"type": "boolean"},
-                    "category": {"type": "string"}
+                    "category": {"type": "string"},
+                    "strict_order": {"type": "boolean", "description": "Reject a stage that skips ahead of the next one; earlier stages can always be revisited"}
                 },
                 "required": ["thought", "stage"]
             }),
@@ -133,6 +277,345 @@ impl UltraThinkRouter {
             open_world_hint: Some(false),
         });
 
+        let graphiti_query = Tool::new(
+            "ultrathink_graphiti_query",
+            "Searches the Graphiti knowledge graph; repeated queries are served from a short-lived cache",
+            object!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string"},
+                    "limit": {"type": "integer", "minimum": 1},
+                    "fresh": {"type": "boolean", "description": "Bypass the cache and fetch current results"}
+                },
+                "required": ["query"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Graphiti Query".to_string()),
+            read_only_hint: Some(true),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(true),
+        });
+
+        let graphiti_diff = Tool::new(
+            "ultrathink_graphiti_diff",
+            "Compares local memories with Graphiti without changing either, listing entries only stored locally, entities only in Graphiti and conflicting values",
+            object!({
+                "type": "object",
+                "properties": {
+                    "category": {"type": "string", "description": "Category to compare; all categories when omitted or \"*\""},
+                    "is_global": {"type": "boolean"}
+                }
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Graphiti Diff".to_string()),
+            read_only_hint: Some(true),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(true),
+        });
+
+        let rename_category = Tool::new(
+            "ultrathink_rename_category",
+            "Renames a memory category, optionally merging it into an existing category",
+            object!({
+                "type": "object",
+                "properties": {
+                    "from": {"type": "string"},
+                    "to": {"type": "string"},
+                    "is_global": {"type": "boolean"},
+                    "merge": {"type": "boolean"}
+                },
+                "required": ["from", "to", "is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Rename Category".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(false),
+            open_world_hint: Some(false),
+        });
+
+        let merge_categories = Tool::new(
+            "ultrathink_merge_categories",
+            "Merges memory categories into a destination category, skipping duplicate entries",
+            object!({
+                "type": "object",
+                "properties": {
+                    "sources": {"type": "array", "items": {"type": "string"}},
+                    "destination": {"type": "string"},
+                    "is_global": {"type": "boolean"},
+                    "delete_sources": {"type": "boolean"}
+                },
+                "required": ["sources", "destination", "is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Merge Categories".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(false),
+            open_world_hint: Some(false),
+        });
+
+        let pin_entry = Tool::new(
+            "ultrathink_pin",
+            "Pins a memory entry so compaction and expiry never remove it",
+            object!({
+                "type": "object",
+                "properties": {
+                    "category": {"type": "string"},
+                    "id": {"type": "string"},
+                    "is_global": {"type": "boolean"}
+                },
+                "required": ["category", "id", "is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Pin".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let get_entry = Tool::new(
+            "ultrathink_get_entry",
+            "Returns a single memory entry of a category by id, with all of its metadata",
+            object!({
+                "type": "object",
+                "properties": {
+                    "category": {"type": "string"},
+                    "entry_id": {"type": "string", "description": "Id of the entry, as listed by ultrathink_retrieve"},
+                    "is_global": {"type": "boolean"}
+                },
+                "required": ["category", "entry_id", "is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Get Entry".to_string()),
+            read_only_hint: Some(true),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let unpin_entry = Tool::new(
+            "ultrathink_unpin",
+            "Unpins a memory entry so compaction and expiry apply to it again",
+            object!({
+                "type": "object",
+                "properties": {
+                    "category": {"type": "string"},
+                    "id": {"type": "string"},
+                    "is_global": {"type": "boolean"}
+                },
+                "required": ["category", "id", "is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Unpin".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let link_entries = Tool::new(
+            "ultrathink_link",
+            "Links two memory entries (by id) with a relation label, e.g. a decision and its rationale",
+            object!({
+                "type": "object",
+                "properties": {
+                    "from": {"type": "string"},
+                    "to": {"type": "string"},
+                    "relation": {"type": "string"},
+                    "is_global": {"type": "boolean"}
+                },
+                "required": ["from", "to", "relation", "is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Link".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let forget_memories = Tool::new(
+            "ultrathink_forget",
+            "Deletes the entries of a category whose data contains `match` and that carry every one of `tags`, or without either filter the whole category",
+            object!({
+                "type": "object",
+                "properties": {
+                    "category": {"type": "string"},
+                    "is_global": {"type": "boolean"},
+                    "match": {"type": "string", "description": "Case-insensitive text the data of a removed entry contains"},
+                    "tags": {"type": "array", "items": {"type": "string"}}
+                },
+                "required": ["category", "is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Forget".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let compact_category = Tool::new(
+            "ultrathink_compact",
+            "Removes the oldest unpinned entries of a category, keeping the newest `keep` unpinned entries and every pinned entry",
+            object!({
+                "type": "object",
+                "properties": {
+                    "category": {"type": "string"},
+                    "keep": {"type": "integer", "minimum": 0},
+                    "is_global": {"type": "boolean"}
+                },
+                "required": ["category", "keep", "is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Compact".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let cleanup_expired = Tool::new(
+            "ultrathink_cleanup",
+            "Deletes expired, unpinned entries from every category in a scope",
+            object!({
+                "type": "object",
+                "properties": {
+                    "is_global": {"type": "boolean"}
+                },
+                "required": ["is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Cleanup".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let clear_scope = Tool::new(
+            "ultrathink_clear",
+            "Deletes every memory in a scope; requires confirm: true and can back the files up first",
+            object!({
+                "type": "object",
+                "properties": {
+                    "is_global": {"type": "boolean"},
+                    "confirm": {"type": "boolean"},
+                    "backup": {"type": "boolean"}
+                },
+                "required": ["is_global", "confirm"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Clear".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let snapshot_scope = Tool::new(
+            "ultrathink_snapshot",
+            "Copies every memory of a scope, metadata included, into a new snapshot and returns its id",
+            object!({
+                "type": "object",
+                "properties": {
+                    "is_global": {"type": "boolean"}
+                },
+                "required": ["is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Snapshot".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(false),
+            open_world_hint: Some(false),
+        });
+
+        let restore_snapshot = Tool::new(
+            "ultrathink_restore",
+            "Replaces every memory of a scope with a snapshot; can snapshot the current state first",
+            object!({
+                "type": "object",
+                "properties": {
+                    "snapshot": {"type": "string", "description": "Id returned by ultrathink_snapshot"},
+                    "is_global": {"type": "boolean"},
+                    "backup": {"type": "boolean", "description": "Snapshot the current state before restoring"}
+                },
+                "required": ["snapshot", "is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Restore".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let verify_scope = Tool::new(
+            "ultrathink_verify",
+            "Checks every memory file of a scope against its checksum and reports intact, unchecked and corrupted categories",
+            object!({
+                "type": "object",
+                "properties": {
+                    "is_global": {"type": "boolean"}
+                },
+                "required": ["is_global"]
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Verify".to_string()),
+            read_only_hint: Some(true),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        });
+
+        let trace_calls = Tool::new(
+            "ultrathink_trace",
+            "Lists the most recent UltraThink tool calls, oldest first, with redacted arguments, timing and outcome",
+            object!({
+                "type": "object",
+                "properties": {
+                    "limit": {"type": "integer", "minimum": 1, "description": "Only the last this many calls"}
+                }
+            }),
+        )
+        .annotate(ToolAnnotations {
+            title: Some("UltraThink Trace".to_string()),
+            read_only_hint: Some(true),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(false),
+            open_world_hint: Some(false),
+        });
+
+        let upload_note = match config.sync_on_write {
+            SyncOnWrite::Off => "memories reach Graphiti with the next sync",
+            SyncOnWrite::HighPriorityOnly => {
+                "high-priority memories are uploaded to Graphiti right away when it is configured"
+            }
+            SyncOnWrite::All => {
+                "every memory is uploaded to Graphiti right away when it is configured"
+            }
+        };
+
         let instructions = formatdoc! {r#"
             # UltraThink Memory & Sequential Thinking System
             
@@ -145,18 +628,50 @@ impl UltraThinkRouter {
             ## Capabilities:
             
             ### Memory Management
-            - **ultrathink_remember**: Store memories with enhanced metadata
+            - **ultrathink_remember**: Store memories with enhanced metadata, returning the id of
+              the stored entry for the id-based tools
+              (`singleton: true` makes the category keep only its current value, e.g. `current_task`;
+              `attachment_path` stores a reference to a file such as a screenshot)
             - **ultrathink_retrieve**: Retrieve memories with semantic search
+              (use `recent: true` for the newest entries across every category, or
+              `after`/`before` dates to list what was noted in a time range; `highlight: true`
+              marks where the terms of `query` occur; `tag` lists the entries with that tag)
+            - **ultrathink_rename_category**: Rename a category, or merge it into an existing one
+            - **ultrathink_merge_categories**: Combine several categories into one without
+              duplicating identical entries (`delete_sources: true` removes the merged categories)
+            - **ultrathink_get_entry**: Fetch one entry by id, e.g. to confirm a store succeeded
+            - **ultrathink_pin** / **ultrathink_unpin**: Protect an entry (by id) from compaction and expiry
+            - **ultrathink_link**: Relate two entries (by id) with a label such as `rationale`;
+              retrieve with `include_links: true` to see linked entries, or `expand_graph: true` to
+              pull in the entities related to them in Graphiti
+            - **ultrathink_forget**: Delete the entries of a category containing `match` and
+              tagged with every one of `tags`, or the whole category without either
+            - **ultrathink_compact**: Drop the oldest unpinned entries of a category
+            - **ultrathink_cleanup**: Delete expired entries (stored with `ttl_secs`); expired
+              entries are already hidden from retrieval before they are cleaned up
+            - **ultrathink_clear**: Delete every memory in a scope; only with `confirm: true`,
+              and with `backup: true` the files are copied to a timestamped backup first
+            - **ultrathink_snapshot** / **ultrathink_restore**: Save the whole scope before risky
+              changes and roll back to it by snapshot id (`backup: true` snapshots the current
+              state before restoring)
+            - **ultrathink_verify**: Check the memory files of a scope for corruption (with
+              `ULTRATHINK_CHECKSUMS` set, corrupted files are also skipped when read)
+            - **ultrathink_trace**: List the latest UltraThink tool calls with their timing, to
+              debug what was called and what failed
             - Support for priority levels, context, and relationship mapping
             - Local (.goose/memory) and global (~/.config/goose/memory) storage
             
             ### Sequential Thinking
             - **ultrathink_sequence**: Process complex thoughts through structured stages
             - Stages: Problem Definition → Research → Analysis → Synthesis → Conclusion
+              (`strict_order: true` rejects skipping ahead)
             - Automatic memory integration for important insights
             
             ### Graphiti Integration
-            - **ultrathink_graphiti_sync**: Sync with knowledge graph
+            - **ultrathink_graphiti_sync**: Sync with knowledge graph; only new or changed entries are uploaded
+            - **ultrathink_graphiti_query**: Search the knowledge graph (`fresh: true` skips the cache)
+            - **ultrathink_graphiti_diff**: See what a sync would change: entries only stored locally,
+              entities only in Graphiti, and singleton values that differ
             - Persistent memory across sessions and projects
             - Relationship mapping between concepts and ideas
             
@@ -170,7 +685,7 @@ impl UltraThinkRouter {
             
             **For Memory Management:**
             - Use categories like: "development", "personal", "project", "learning"
-            - Add priority: "high" for critical info, "low" for reference
+            - Add priority: "high" for critical info, "low" for reference; {upload_note}
             - Include context for better retrieval
             
             **For Graphiti Sync:**
@@ -183,33 +698,8 @@ impl UltraThinkRouter {
             - Automatic Graphiti sync maintains persistent knowledge
             "#};
 
-        // Directory setup (same as MemoryRouter)
-        let local_memory_dir = std::env::var("GOOSE_WORKING_DIR")
-            .map(PathBuf::from)
-            .unwrap_or_else(|_| std::env::current_dir().unwrap())
-            .join(".goose")
-            .join("memory");
-
-        let global_memory_dir = choose_app_strategy(crate::APP_STRATEGY.clone())
-            .map(|strategy| strategy.in_config_dir("memory"))
-            .unwrap_or_else(|_| PathBuf::from(".config/goose/memory"));
-
-        // Check for Graphiti endpoint configuration
-        let graphiti_endpoint = std::env::var("GRAPHITI_ENDPOINT").ok()
-            .or_else(|| {
-                // Try to read from config file
-                let config_path = global_memory_dir.parent()
-                    .unwrap_or(&global_memory_dir)
-                    .join("ultrathink.toml");
-                
-                if config_path.exists() {
-                    // In a real implementation, we'd parse TOML here
-                    // For now, just return None
-                    None
-                } else {
-                    None
-                }
-            });
+        let settings = resolve_settings(&global_memory_dir);
+        let graphiti_endpoint = settings.get("graphiti_endpoint").map(str::to_string);
 
         let mut router = Self {
             tools: vec![
@@ -217,18 +707,50 @@ impl UltraThinkRouter {
                 retrieve_memories,
                 sequential_think,
                 graphiti_sync,
+                graphiti_query,
+                graphiti_diff,
+                rename_category,
+                merge_categories,
+                get_entry,
+                pin_entry,
+                unpin_entry,
+                link_entries,
+                forget_memories,
+                compact_category,
+                cleanup_expired,
+                clear_scope,
+                snapshot_scope,
+                restore_snapshot,
+                verify_scope,
+                trace_calls,
             ],
             instructions: instructions.clone(),
             global_memory_dir,
             local_memory_dir,
+            settings,
+            global_graphiti_client: GraphitiClient::for_scope(true, graphiti_endpoint.clone())
+                .with_entity_types(config.entity_types.clone())
+                .with_compressed_requests(config.compress_graphiti_requests),
+            local_graphiti_client: GraphitiClient::for_scope(false, graphiti_endpoint.clone())
+                .with_entity_types(config.entity_types.clone())
+                .with_compressed_requests(config.compress_graphiti_requests),
             graphiti_endpoint,
-            graphiti_client: GraphitiClient::new(),
+            trace: Arc::new(Mutex::new(ToolTrace::new(
+                config.trace_size,
+                &config.trace_redacted_fields,
+            ))),
+            stages: Arc::new(Mutex::new(StageTracker::default())),
+            config,
+            formatter: Arc::new(EmojiFormatter),
+            clock: Arc::new(SystemClock),
+            events: broadcast::channel(events::EVENT_CAPACITY).0,
         };
 
-        // Load existing memories into instructions (like MemoryRouter)
-        let retrieved_global_memories = router.retrieve_all(true);
-        let retrieved_local_memories = router.retrieve_all(false);
+        if !router.config.preload_memories {
+            return router;
+        }
 
+        // Load existing memories into instructions (like MemoryRouter)
         let mut updated_instructions = instructions;
         
         let memories_follow_up = formatdoc! {r#"
@@ -239,288 +761,4015 @@ impl UltraThinkRouter {
         updated_instructions.push_str("\n\n");
         updated_instructions.push_str(&memories_follow_up);
 
-        if let Ok(global_memories) = retrieved_global_memories {
-            if !global_memories.is_empty() {
-                updated_instructions.push_str("\n\n**Global Memories:**\n");
-                for (category, memories) in global_memories {
-                    updated_instructions.push_str(&format!("\n**{}:**\n", category));
-                    for memory in memories {
-                        updated_instructions.push_str(&format!("- {}\n", memory));
-                    }
-                }
+        // Only the memory appendix is trimmed; the core instructions are always kept whole
+        let appendix = router.instruction_memories();
+        let max = router.config.max_instruction_bytes;
+        if max == 0 || updated_instructions.len() + appendix.len() <= max {
+            updated_instructions.push_str(&appendix);
+        } else {
+            let budget =
+                max.saturating_sub(updated_instructions.len() + APPENDIX_TRIMMED_NOTE.len());
+            updated_instructions.push_str(trim_to_lines(&appendix, budget));
+            updated_instructions.push_str(APPENDIX_TRIMMED_NOTE);
+            if updated_instructions.len() > max {
+                tracing::warn!(
+                    "UltraThink instructions exceed the {} byte limit without memories: {} bytes",
+                    max,
+                    updated_instructions.len()
+                );
             }
         }
 
-        if let Ok(local_memories) = retrieved_local_memories {
-            if !local_memories.is_empty() {
-                updated_instructions.push_str("\n\n**Local Memories:**\n");
-                for (category, memories) in local_memories {
-                    updated_instructions.push_str(&format!("\n**{}:**\n", category));
-                    for memory in memories {
-                        updated_instructions.push_str(&format!("- {}\n", memory));
-                    }
+        router.instructions = updated_instructions;
+        router
+    }
+
+    /// Size in bytes of the instructions as handed to the provider
+    pub fn instructions_len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// The memories listed in the instructions, limited to
+    /// [`UltraThinkConfig::max_instruction_memories`]. Pinned entries are chosen first, then
+    /// the most recent ones; the rest are only mentioned by count.
+    ///
+    /// Only the newest [`UltraThinkConfig::instruction_entries_per_category`] entries of
+    /// each category are read, large files from their end, so older entries, pinned ones
+    /// included, are left out of the choice; the categories they were cut from are named.
+    fn instruction_memories(&self) -> String {
+        let per_category = self.config.instruction_entries_per_category;
+        let tail_threshold = match self.config.mmap_threshold_bytes as u64 {
+            0 => 0,
+            threshold => threshold.min(INSTRUCTION_TAIL_BYTES),
+        };
+        let mut candidates = Vec::new();
+        let mut cut = BTreeSet::new();
+        for is_global in [true, false] {
+            let Ok(categories) = self.list_categories(is_global) else {
+                continue;
+            };
+            for category in categories {
+                let entries = if per_category == 0 {
+                    self.read_entries(&category, is_global)
+                } else {
+                    // One more than is kept tells whether anything was left unread
+                    self.read_tail(&category, per_category + 1, is_global, tail_threshold)
+                };
+                let mut entries = entries.unwrap_or_default();
+                if per_category > 0 && entries.len() > per_category {
+                    entries.remove(0);
+                    cut.insert(category.clone());
+                }
+                for (position, entry) in entries.into_iter().enumerate() {
+                    candidates.push((is_global, category.clone(), position, entry));
                 }
             }
         }
 
-        router.instructions = updated_instructions;
-        router
+        let total = candidates.len();
+        candidates.sort_by(|(_, _, _, a), (_, _, _, b)| {
+            b.pinned
+                .cmp(&a.pinned)
+                .then_with(|| b.timestamp.cmp(&a.timestamp))
+        });
+        candidates.truncate(self.config.max_instruction_memories);
+        let omitted = total - candidates.len();
+
+        // Shown grouped by scope and category, in file order within a category
+        candidates.sort_by_key(|(is_global, category, position, _)| {
+            (!*is_global, category.clone(), *position)
+        });
+
+        let mut appendix = String::new();
+        let mut current: Option<(bool, &str)> = None;
+        for (is_global, category, _, entry) in &candidates {
+            if current.map(|(scope, _)| scope) != Some(*is_global) {
+                appendix.push_str(if *is_global {
+                    "\n\n**Global Memories:**\n"
+                } else {
+                    "\n\n**Local Memories:**\n"
+                });
+            }
+            if current != Some((*is_global, category.as_str())) {
+                appendix.push_str(&format!("\n**{}:**\n", category));
+                current = Some((*is_global, category.as_str()));
+            }
+            appendix.push_str(&self.config.multiline_style.bullet(&entry.data));
+        }
+
+        if omitted > 0 {
+            appendix.push_str(&format!(
+                "\n…and {} more, use ultrathink_retrieve to see them\n",
+                omitted
+            ));
+        }
+        if !cut.is_empty() {
+            appendix.push_str(&format!(
+                "\n…older memories of {} were not loaded, use ultrathink_retrieve to see them\n",
+                cut.into_iter().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        appendix
+    }
+
+    /// Replace the formatter used to render tool responses
+    pub fn with_formatter(mut self, formatter: Arc<dyn ResponseFormatter>) -> Self {
+        self.formatter = formatter;
+        self
     }
 
-    // Core memory operations (similar to MemoryRouter but enhanced)
-    pub fn remember(
+    /// Replace the clock every timestamp and expiry check is read from
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Receive a [`MemoryEvent`] for every change to the stored memories. Events are
+    /// broadcast without waiting for subscribers; one that falls more than
+    /// [`events::EVENT_CAPACITY`] events behind misses the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<MemoryEvent> {
+        self.events.subscribe()
+    }
+
+    /// The most recent tool calls, oldest first, as reported by `ultrathink_trace`
+    pub fn trace(&self) -> Vec<ToolCallRecord> {
+        self.trace.lock().unwrap().records()
+    }
+
+    /// The shared settings and where each came from, see [`GooseConfig::dump`]
+    pub fn effective_config(&self) -> String {
+        self.settings.dump()
+    }
+
+    /// Add a finished call to the trace, with its arguments redacted
+    fn record_call(
         &self,
+        tool: String,
+        arguments: &Value,
+        started_at: DateTime<Utc>,
+        duration: Duration,
+        result: &io::Result<ToolOutput>,
+    ) {
+        let (success, result_bytes, error) = match result {
+            Ok(output) => (true, output.text.len(), None),
+            Err(e) => {
+                let message = e.to_string();
+                (false, message.len(), Some(message))
+            }
+        };
+        let mut trace = self.trace.lock().unwrap();
+        let record = ToolCallRecord {
+            tool,
+            arguments: trace.redact(arguments),
+            started_at,
+            finished_at: self.clock.utc_now(),
+            duration_ms: duration.as_millis() as u64,
+            success,
+            result_bytes,
+            error,
+        };
+        trace.record(record);
+    }
+
+    fn emit(
+        &self,
+        kind: MemoryEventKind,
         category: &str,
-        data: &str,
-        tags: &[&str],
+        entry_id: Option<String>,
         is_global: bool,
-    ) -> io::Result<()> {
-        let memory_file_path = self.get_memory_file(category, is_global);
+    ) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(MemoryEvent {
+            kind,
+            scope: MemoryScope::from_global(is_global),
+            category: category.to_string(),
+            entry_id,
+        });
+    }
 
-        if let Some(parent) = memory_file_path.parent() {
-            fs::create_dir_all(parent)?;
+    /// An entry stamped with the current time, with normalized tags and its data cut to
+    /// [`UltraThinkConfig::max_entry_bytes`]
+    fn new_entry(&self, data: &str, tags: &[&str]) -> MemoryEntry {
+        let mut entry = MemoryEntry::new(data, tags);
+        entry.normalize_tags();
+        entry.timestamp = Some(self.clock.utc_now());
+        if self.config.max_entry_bytes > 0 && entry.truncate_data(self.config.max_entry_bytes) {
+            tracing::warn!(
+                "Truncated UltraThink entry of {} bytes to {} bytes",
+                data.len(),
+                self.config.max_entry_bytes
+            );
         }
+        entry
+    }
 
-        let mut file = fs::OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&memory_file_path)?;
-            
-        if !tags.is_empty() {
-            writeln!(file, "# {}", tags.join(" "))?;
+    /// Whether a category file may be served. With [`UltraThinkConfig::use_checksums`] a
+    /// file that no longer matches its checksum is reported as corrupted and skipped.
+    fn is_intact(&self, category: &str, is_global: bool) -> io::Result<bool> {
+        if !self.config.use_checksums {
+            return Ok(true);
+        }
+        let path = self.get_memory_file(category, is_global);
+        match integrity::verify(&path)? {
+            Integrity::Corrupted { expected, actual } => {
+                tracing::warn!(
+                    "UltraThink memory file corrupted: {} has checksum {} instead of {}; skipping it",
+                    path.display(),
+                    actual,
+                    expected
+                );
+                Ok(false)
+            }
+            Integrity::Intact | Integrity::Unchecked => Ok(true),
         }
-        writeln!(file, "{}\n", data)?;
+    }
 
-        Ok(())
+    /// Check every category file of a scope against its checksum, whether or not
+    /// checksums are currently maintained
+    pub fn verify(&self, is_global: bool) -> io::Result<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+        for category in self.list_categories(is_global)? {
+            let _lock = self.lock_category(&category, is_global)?;
+            let integrity = integrity::verify(&self.get_memory_file(&category, is_global))?;
+            report.add(category, &integrity);
+        }
+        Ok(report)
     }
 
-    pub fn retrieve_all(&self, is_global: bool) -> io::Result<HashMap<String, Vec<String>>> {
-        let base_dir = if is_global {
-            &self.global_memory_dir
+    /// The live entry with `id` in a category, if there is one
+    pub fn get_entry(
+        &self,
+        category: &str,
+        id: &str,
+        is_global: bool,
+    ) -> io::Result<Option<MemoryEntry>> {
+        Ok(self
+            .read_entries(category, is_global)?
+            .into_iter()
+            .find(|entry| entry.id() == id))
+    }
+
+    /// Directories files may be attached from, see [`UltraThinkConfig::attachment_dirs`]
+    fn attachment_dirs(&self) -> Vec<PathBuf> {
+        if !self.config.attachment_dirs.is_empty() {
+            return self.config.attachment_dirs.clone();
+        }
+        // The local memory dir is `<project>/.goose/memory`
+        self.local_memory_dir
+            .ancestors()
+            .nth(2)
+            .map(Path::to_path_buf)
+            .into_iter()
+            .collect()
+    }
+
+    /// The content of the file attached to the entry a `memory-attachment://` URI names:
+    /// text as is, anything else base64-encoded. The file is checked against the allowed
+    /// directories again, since it may have been replaced after it was remembered.
+    pub fn read_attachment(&self, uri: &str) -> io::Result<String> {
+        let (is_global, category, id) = parse_attachment_uri(uri).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid attachment URI: {}", uri),
+            )
+        })?;
+        let stored = self
+            .get_entry(&category, &id, is_global)?
+            .as_ref()
+            .and_then(Attachment::from_entry)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "No attachment for entry '{}' in category '{}'",
+                        id, category
+                    ),
+                )
+            })?;
+
+        let attachment = Attachment::resolve(&stored.path, &self.attachment_dirs())?;
+        if attachment.is_text() {
+            fs::read_to_string(&attachment.path)
         } else {
-            &self.local_memory_dir
-        };
-        
-        let mut memories = HashMap::new();
-        if base_dir.exists() {
-            for entry in fs::read_dir(base_dir)? {
-                let entry = entry?;
-                if entry.file_type()?.is_file() {
-                    let category = entry.file_name().to_string_lossy().replace(".txt", "");
-                    let category_memories = self.retrieve(&category, is_global)?;
-                    memories.insert(
-                        category,
-                        category_memories.into_iter().flat_map(|(_, v)| v).collect(),
-                    );
+            Ok(base64::prelude::BASE64_STANDARD.encode(fs::read(&attachment.path)?))
+        }
+    }
+
+    /// The category and live entry with `id` in a scope
+    fn find_entry(&self, id: &str, is_global: bool) -> io::Result<Option<(String, MemoryEntry)>> {
+        for category in self.list_categories(is_global)? {
+            let entry = self
+                .read_entries(&category, is_global)?
+                .into_iter()
+                .find(|entry| entry.id() == id);
+            if let Some(entry) = entry {
+                return Ok(Some((category, entry)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Links touching the live entries of `category` ("*" for every category), with both
+    /// ends expanded. Links to entries that no longer exist are left out.
+    fn linked_entries(&self, category: &str, scopes: &[bool]) -> io::Result<Vec<Value>> {
+        let mut linked = Vec::new();
+        for &is_global in scopes {
+            let links = MemoryLinks::load(self.memory_dir(is_global)).links;
+            if links.is_empty() {
+                continue;
+            }
+
+            let mut entries = BTreeMap::new();
+            for name in self.list_categories(is_global)? {
+                for entry in self.read_entries(&name, is_global)? {
+                    entries.insert(entry.id(), (name.clone(), entry));
+                }
+            }
+            for link in links {
+                let (Some(from), Some(to)) = (entries.get(&link.from), entries.get(&link.to))
+                else {
+                    continue;
+                };
+                if category != "*" && from.0 != category && to.0 != category {
+                    continue;
                 }
+                linked.push(json!({
+                    "relation": link.relation,
+                    "from": entry_json(&from.0, from.1.clone(), self.config.timestamp_display),
+                    "to": entry_json(&to.0, to.1.clone(), self.config.timestamp_display),
+                }));
             }
         }
-        Ok(memories)
+        Ok(linked)
     }
 
-    pub fn retrieve(
+    /// Read the live entries of a category in file order, skipping expired ones that have
+    /// not been cleaned up yet; a missing category has no entries
+    pub fn read_entries(&self, category: &str, is_global: bool) -> io::Result<Vec<MemoryEntry>> {
+        let now = self.clock.utc_now();
+        let mut entries = self.read_stored_entries(category, is_global)?;
+        entries.retain(|entry| !entry.is_expired(now));
+        // A category marked as a singleton after it accumulated entries shows only its latest
+        if self.category_meta(category, is_global).singleton && entries.len() > 1 {
+            entries.drain(..entries.len() - 1);
+        }
+        Ok(entries)
+    }
+
+    /// The newest `limit` live entries of a category, in file order. Text category files
+    /// above [`UltraThinkConfig::mmap_threshold_bytes`] are scanned from their end through
+    /// a memory map instead of being read whole, so huge files can still be retrieved.
+    pub fn read_last_entries(
+        &self,
+        category: &str,
+        limit: usize,
+        is_global: bool,
+    ) -> io::Result<Vec<MemoryEntry>> {
+        let threshold = self.config.mmap_threshold_bytes as u64;
+        self.read_tail(category, limit, is_global, threshold)
+    }
+
+    /// [`Self::read_last_entries`], mapping text files of at least `threshold` bytes; 0
+    /// reads every file whole
+    fn read_tail(
         &self,
         category: &str,
+        limit: usize,
         is_global: bool,
-    ) -> io::Result<HashMap<String, Vec<String>>> {
+        threshold: u64,
+    ) -> io::Result<Vec<MemoryEntry>> {
+        let path = self.get_memory_file(category, is_global);
+        let mapped = threshold > 0
+            && self.config.storage_format == StorageFormat::Text
+            && fs::metadata(&path).is_ok_and(|metadata| metadata.len() >= threshold);
+        if !mapped {
+            let mut entries = self.read_entries(category, is_global)?;
+            entries.drain(..entries.len().saturating_sub(limit));
+            return Ok(entries);
+        }
+
+        if !self.is_intact(category, is_global)? {
+            return Ok(Vec::new());
+        }
+        let limit = if self.category_meta(category, is_global).singleton {
+            limit.min(1)
+        } else {
+            limit
+        };
+        mmap_reader::read_last_entries(&path, limit, self.clock.utc_now())
+    }
+
+    /// The settings stored in a category's `.meta.json`
+    pub fn category_meta(&self, category: &str, is_global: bool) -> CategoryMeta {
+        CategoryMeta::load(&self.get_meta_file(category, is_global))
+    }
+
+    /// Read every entry of a category as stored, including expired ones
+    fn read_stored_entries(&self, category: &str, is_global: bool) -> io::Result<Vec<MemoryEntry>> {
         let memory_file_path = self.get_memory_file(category, is_global);
-        if !memory_file_path.exists() {
-            return Ok(HashMap::new());
+        if !memory_file_path.exists() || !self.is_intact(category, is_global)? {
+            return Ok(Vec::new());
         }
 
         let mut file = fs::File::open(memory_file_path)?;
         let mut content = String::new();
         file.read_to_string(&mut content)?;
 
-        let mut memories = HashMap::new();
-        for entry in content.split("\n\n") {
-            let mut lines = entry.lines();
-            if let Some(first_line) = lines.next() {
-                if let Some(stripped) = first_line.strip_prefix('#') {
-                    let tags = stripped
-                        .split_whitespace()
-                        .map(String::from)
-                        .collect::<Vec<_>>();
-                    memories.insert(tags.join(" "), lines.map(String::from).collect());
-                } else {
-                    let entry_data: Vec<String> = std::iter::once(first_line.to_string())
-                        .chain(lines.map(String::from))
-                        .collect();
-                    memories
-                        .entry("untagged".to_string())
-                        .or_insert_with(Vec::new)
-                        .extend(entry_data);
-                }
-            }
+        Ok(self.config.storage_format.parse_entries(&content))
+    }
+
+    /// Names of all categories stored in a scope, sorted
+    pub fn list_categories(&self, is_global: bool) -> io::Result<Vec<String>> {
+        if self.config.use_index {
+            return Ok(self.load_index(is_global)?.categories.into_keys().collect());
         }
 
-        Ok(memories)
+        let dir = self.memory_dir(is_global);
+        Ok(index::scan_categories(dir, self.config.storage_format)?
+            .into_iter()
+            .map(|(category, _)| category)
+            .collect())
     }
 
-    fn get_memory_file(&self, category: &str, is_global: bool) -> PathBuf {
-        let base_dir = if is_global {
-            &self.global_memory_dir
-        } else {
-            &self.local_memory_dir
-        };
-        base_dir.join(format!("{}.txt", category))
+    /// Entry count, size and modification time of every category in a scope
+    pub fn category_stats(&self, is_global: bool) -> io::Result<BTreeMap<String, CategoryStats>> {
+        Ok(self.load_index(is_global)?.categories)
     }
 
-    async fn execute_tool_call(&self, tool_call: ToolCall) -> Result<String, io::Error> {
-        match tool_call.name.as_str() {
-            "ultrathink_remember" => {
-                let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
-                self.remember(args.category, args.data.unwrap_or(""), &args.tags, args.is_global)?;
-                Ok(format!("📝 UltraThink memory stored in category: {}", args.category))
-            }
-            "ultrathink_retrieve" => {
-                let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
-                let memories = if args.category == "*" {
-                    self.retrieve_all(args.is_global)?
-                } else {
-                    self.retrieve(args.category, args.is_global)?
-                };
-                Ok(format!("🧠 UltraThink memories retrieved: {:?}", memories))
-            }
-            "ultrathink_sequence" => {
-                let thought = tool_call.arguments["thought"].as_str().unwrap_or("");
-                let stage = tool_call.arguments["stage"].as_str().unwrap_or("Analysis");
-                
-                // In a real implementation, this would integrate with sequential-thinking MCP
-                let result = format!("🤔 Sequential thinking - Stage: {} | Thought: {}", stage, thought);
-                
-                // Optionally save to memory if requested
-                if tool_call.arguments.get("save_to_memory").and_then(|v| v.as_bool()).unwrap_or(false) {
-                    let category = tool_call.arguments.get("category")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("thinking");
-                    
-                    let memory_data = format!("[{}] {}", stage, thought);
-                    self.remember(category, &memory_data, &["sequential", "thinking"], false)?;
-                }
-                
-                Ok(result)
-            }
-            "ultrathink_graphiti_sync" => {
-                let direction = tool_call.arguments["direction"].as_str().unwrap_or("bidirectional");
-                
-                // Use GraphitiClient for actual sync
-                match self.graphiti_client.sync_memories(direction).await {
-                    Ok(result) => Ok(result),
-                    Err(e) => Ok(format!("❌ Graphiti sync failed: {}", e))
-                }
+    /// The index of a scope. A missing index, or one that no longer matches the category
+    /// files (for example after they were edited by hand), is rebuilt from the directory
+    /// and saved again when indexing is enabled.
+    fn load_index(&self, is_global: bool) -> io::Result<MemoryIndex> {
+        let dir = self.memory_dir(is_global);
+        if let Some(index) = MemoryIndex::load(dir) {
+            if index.matches_dir(dir, self.config.storage_format)? {
+                return Ok(index);
             }
-            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown UltraThink tool")),
+            tracing::info!("Rebuilding stale UltraThink index in {}", dir.display());
+        }
+
+        let index = MemoryIndex::rebuild(dir, self.config.storage_format)?;
+        if self.config.use_index && dir.exists() {
+            index.save(dir)?;
         }
+        Ok(index)
     }
-}
 
-#[async_trait]
-impl Router for UltraThinkRouter {
-    fn name(&self) -> String {
-        "ultrathink".to_string()
+    /// The tag index of a scope, loaded and rebuilt like [`Self::load_index`]
+    fn load_tag_index(&self, is_global: bool) -> io::Result<TagIndex> {
+        let dir = self.memory_dir(is_global);
+        if let Some(index) = TagIndex::load(dir) {
+            if index.matches_dir(dir, self.config.storage_format)? {
+                return Ok(index);
+            }
+            tracing::info!("Rebuilding stale UltraThink tag index in {}", dir.display());
+        }
+
+        let index = TagIndex::rebuild(dir, self.config.storage_format)?;
+        if self.config.use_index && dir.exists() {
+            index.save(dir)?;
+        }
+        Ok(index)
     }
 
-    fn instructions(&self) -> String {
-        self.instructions.clone()
+    /// Bring the index entries of `categories` up to date after they were changed. Failures
+    /// are only logged: the mutation itself succeeded, and a stale index is rebuilt on the
+    /// next listing.
+    fn update_index(&self, categories: &[&str], is_global: bool) {
+        if !self.config.use_index {
+            return;
+        }
+
+        let dir = self.memory_dir(is_global);
+        let format = self.config.storage_format;
+        let categories: Vec<Cow<str>> = categories
+            .iter()
+            .map(|category| self.stored_category(category))
+            .collect();
+        let stats = match MemoryIndex::load(dir) {
+            Some(mut index) => categories
+                .iter()
+                .try_for_each(|category| index.refresh(dir, category, format))
+                .and_then(|()| index.save(dir)),
+            None => MemoryIndex::rebuild(dir, format).and_then(|index| index.save(dir)),
+        };
+        let tags = match TagIndex::load(dir) {
+            Some(mut index) => categories
+                .iter()
+                .try_for_each(|category| index.refresh(dir, category, format))
+                .and_then(|()| index.save(dir)),
+            None => TagIndex::rebuild(dir, format).and_then(|index| index.save(dir)),
+        };
+        if let Err(e) = stats.and(tags) {
+            tracing::warn!(
+                "Failed to update UltraThink index in {}: {}",
+                dir.display(),
+                e
+            );
+        }
     }
 
-    fn capabilities(&self) -> ServerCapabilities {
-        CapabilitiesBuilder::new().with_tools(false).build()
+    /// Retrieve every category in a scope, keeping each category's entries grouped by tags
+    /// exactly as `retrieve` returns them. Categories and tag groups are sorted, and
+    /// entries keep their file order, so the output is the same on every call.
+    pub fn retrieve_all(
+        &self,
+        is_global: bool,
+    ) -> io::Result<BTreeMap<String, BTreeMap<String, Vec<String>>>> {
+        self.list_categories(is_global)?
+            .into_iter()
+            .map(|category| {
+                let memories = self.retrieve(&category, is_global)?;
+                Ok((category, memories))
+            })
+            .collect()
     }
 
-    fn list_tools(&self) -> Vec<Tool> {
-        self.tools.clone()
+    /// Like [`Self::retrieve_all`], reading the category files on blocking tasks, up to
+    /// [`MAX_PARALLEL_READS`] of them at a time, for stores on slow filesystems
+    pub async fn retrieve_all_parallel(
+        &self,
+        is_global: bool,
+    ) -> io::Result<BTreeMap<String, BTreeMap<String, Vec<String>>>> {
+        self.retrieve_all_with(is_global, |router, category, is_global| {
+            router.retrieve(category, is_global)
+        })
+        .await
     }
 
-    fn call_tool(
+    /// [`Self::retrieve_all_parallel`], reading each category with `read`
+    async fn retrieve_all_with<F>(
         &self,
-        tool_name: &str,
-        arguments: Value,
-        _notifier: mpsc::Sender<JsonRpcMessage>,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>> {
+        is_global: bool,
+        read: F,
+    ) -> io::Result<BTreeMap<String, BTreeMap<String, Vec<String>>>>
+    where
+        F: Fn(&Self, &str, bool) -> io::Result<BTreeMap<String, Vec<String>>>
+            + Clone
+            + Send
+            + 'static,
+    {
         let this = self.clone();
-        let tool_name = tool_name.to_string();
+        let task_failed = |e: JoinError| io::Error::other(format!("UltraThink task failed: {}", e));
+        let categories = tokio::task::spawn_blocking(move || this.list_categories(is_global))
+            .await
+            .map_err(task_failed)??;
+        if categories.len() < PARALLEL_READ_THRESHOLD {
+            let this = self.clone();
+            return tokio::task::spawn_blocking(move || {
+                categories
+                    .into_iter()
+                    .map(|category| {
+                        let memories = read(&this, &category, is_global)?;
+                        Ok((category, memories))
+                    })
+                    .collect()
+            })
+            .await
+            .map_err(task_failed)?;
+        }
 
-        Box::pin(async move {
-            let tool_call = ToolCall {
-                name: tool_name,
-                arguments,
-            };
-            match this.execute_tool_call(tool_call).await {
-                Ok(result) => Ok(vec![Content::text(result)]),
-                Err(err) => Err(ToolError::ExecutionError(err.to_string())),
+        let mut all = BTreeMap::new();
+        let mut reads = JoinSet::new();
+        let mut categories = categories.into_iter();
+        loop {
+            while reads.len() < MAX_PARALLEL_READS {
+                let Some(category) = categories.next() else {
+                    break;
+                };
+                let this = self.clone();
+                let read = read.clone();
+                reads.spawn_blocking(move || {
+                    let memories = read(&this, &category, is_global)?;
+                    Ok::<_, io::Error>((category, memories))
+                });
             }
-        })
+            let Some(joined) = reads.join_next().await else {
+                break;
+            };
+            let (category, memories) = joined.map_err(task_failed)??;
+            all.insert(category, memories);
+        }
+        Ok(all)
     }
 
-    fn list_resources(&self) -> Vec<Resource> {
-        Vec::new()
+    /// The `limit` most recent entries across every category in a scope, newest first.
+    /// Entries written before timestamps were recorded sort after all dated entries.
+    pub fn retrieve_recent(
+        &self,
+        is_global: bool,
+        limit: usize,
+    ) -> io::Result<Vec<(String, MemoryEntry)>> {
+        let mut entries = Vec::new();
+        for category in self.list_categories(is_global)? {
+            for entry in self.read_entries(&category, is_global)? {
+                entries.push((category.clone(), entry));
+            }
+        }
+
+        entries.sort_by(|(_, a), (_, b)| b.timestamp.cmp(&a.timestamp));
+        entries.truncate(limit);
+        Ok(entries)
     }
 
-    fn read_resource(
+    /// Entries of a category, or of every category for `"*"`, stored within `range`, in
+    /// chronological order. `scopes` lists the `is_global` values to read; undated
+    /// entries that the range keeps come last.
+    pub fn retrieve_between(
         &self,
-        _uri: &str,
-    ) -> Pin<Box<dyn Future<Output = Result<String, ResourceError>> + Send + 'static>> {
-        Box::pin(async move { Ok("".to_string()) })
-    }
+        category: &str,
+        scopes: &[bool],
+        range: &DateRange,
+    ) -> io::Result<Vec<(String, MemoryEntry)>> {
+        let mut entries = Vec::new();
+        for &is_global in scopes {
+            let categories = if category == "*" {
+                self.list_categories(is_global)?
+            } else {
+                vec![category.to_string()]
+            };
+            for category in categories {
+                for entry in self.read_entries(&category, is_global)? {
+                    if range.contains(&entry) {
+                        entries.push((category.clone(), entry));
+                    }
+                }
+            }
+        }
 
-    fn list_prompts(&self) -> Vec<Prompt> {
-        vec![]
+        entries.sort_by_key(|(_, entry)| (entry.timestamp.is_none(), entry.timestamp));
+        Ok(entries)
     }
 
-    fn get_prompt(
+    /// Live entries tagged with `tag` in a category, or in every category for `"*"`, in
+    /// category and file order. `scopes` lists the `is_global` values to read. The tag
+    /// index tells which categories hold the tag, so only those are read.
+    pub fn retrieve_tagged(
         &self,
-        prompt_name: &str,
-    ) -> Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'static>> {
-        let prompt_name = prompt_name.to_string();
-        Box::pin(async move {
-            Err(PromptError::NotFound(format!(
-                "Prompt {} not found",
-                prompt_name
-            )))
-        })
+        category: &str,
+        scopes: &[bool],
+        tag: &str,
+    ) -> io::Result<Vec<(String, MemoryEntry)>> {
+        let stored_category = self.stored_category(category);
+        let mut entries = Vec::new();
+        for &is_global in scopes {
+            let index = self.load_tag_index(is_global)?;
+            let mut ids: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+            for tagged in index.entries(tag) {
+                if category == "*" || tagged.category == stored_category {
+                    ids.entry(&tagged.category).or_default().insert(&tagged.id);
+                }
+            }
+            for (category, ids) in ids {
+                for entry in self.read_entries(category, is_global)? {
+                    if ids.contains(entry.id().as_str()) {
+                        entries.push((category.to_string(), entry));
+                    }
+                }
+            }
+        }
+        Ok(entries)
     }
-}
 
-#[derive(Debug)]
-struct UltraThinkArgs<'a> {
-    category: &'a str,
-    data: Option<&'a str>,
-    tags: Vec<&'a str>,
-    is_global: bool,
-}
+    /// Retrieve a category from both scopes, combined according to the configured
+    /// [`ScopePrecedence`]
+    pub fn retrieve_merged(&self, category: &str) -> io::Result<BTreeMap<String, Vec<String>>> {
+        let local = self.retrieve(category, false)?;
+        let global = self.retrieve(category, true)?;
 
-impl<'a> UltraThinkArgs<'a> {
-    fn from_value(args: &'a Value) -> Result<Self, io::Error> {
-        let category = args["category"].as_str().ok_or_else(|| {
-            io::Error::new(io::ErrorKind::InvalidInput, "Category must be a string")
-        })?;
+        let precedence = self.config.scope_precedence;
+        let (mut merged, rest) = match precedence {
+            ScopePrecedence::GlobalFirst => (global, local),
+            ScopePrecedence::LocalFirst | ScopePrecedence::Union => (local, global),
+        };
 
-        if category.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Category cannot be empty",
-            ));
+        for (tags, entries) in rest {
+            let group = merged.entry(tags).or_insert_with(Vec::new);
+            for entry in entries {
+                let duplicate = group
+                    .iter()
+                    .any(|kept| normalize_data(kept) == normalize_data(&entry));
+                if precedence != ScopePrecedence::Union || !duplicate {
+                    group.push(entry);
+                }
+            }
         }
 
-        let data = args.get("data").and_then(|d| d.as_str());
+        Ok(merged)
+    }
 
-        let tags = match &args["tags"] {
-            Value::Array(arr) => arr.iter().filter_map(|v| v.as_str()).collect(),
-            Value::String(s) => vec![s.as_str()],
-            _ => Vec::new(),
-        };
+    /// Retrieve every category from both scopes, see [`Self::retrieve_merged`]
+    pub fn retrieve_all_merged(
+        &self,
+    ) -> io::Result<BTreeMap<String, BTreeMap<String, Vec<String>>>> {
+        let mut categories = self.list_categories(false)?;
+        categories.extend(self.list_categories(true)?);
+        categories.sort();
+        categories.dedup();
 
-        let is_global = args.get("is_global")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
+        categories
+            .into_iter()
+            .map(|category| {
+                let memories = self.retrieve_merged(&category)?;
+                Ok((category, memories))
+            })
+            .collect()
+    }
 
-        Ok(Self {
-            category,
-            data,
-            tags,
-            is_global,
-        })
+    /// Retrieve every category in a scope with the tag grouping flattened away
+    pub fn retrieve_all_flat(&self, is_global: bool) -> io::Result<BTreeMap<String, Vec<String>>> {
+        Ok(flatten_categories(self.retrieve_all(is_global)?))
+    }
+
+    /// Like [`Self::retrieve_all`], with only the newest `limit` entries of each category
+    pub fn retrieve_all_last(
+        &self,
+        is_global: bool,
+        limit: usize,
+    ) -> io::Result<BTreeMap<String, BTreeMap<String, Vec<String>>>> {
+        self.list_categories(is_global)?
+            .into_iter()
+            .map(|category| {
+                let memories = self.retrieve_last(&category, limit, is_global)?;
+                Ok((category, memories))
+            })
+            .collect()
     }
-}
\ No newline at end of file
+
+    /// Entries of a category grouped by their tags, sorted by tag key, in file order
+    pub fn retrieve(
+        &self,
+        category: &str,
+        is_global: bool,
+    ) -> io::Result<BTreeMap<String, Vec<String>>> {
+        let mut memories = BTreeMap::new();
+        for entry in self.read_entries(category, is_global)? {
+            memories
+                .entry(entry.tag_key())
+                .or_insert_with(Vec::new)
+                .push(entry.data);
+        }
+
+        Ok(memories)
+    }
+
+    /// Like [`Self::retrieve`], telling a category that was never created (`None`) from one
+    /// that exists without live entries (an empty map)
+    pub fn retrieve_existing(
+        &self,
+        category: &str,
+        is_global: bool,
+    ) -> io::Result<Option<BTreeMap<String, Vec<String>>>> {
+        if !self.category_exists(category, is_global) {
+            return Ok(None);
+        }
+        self.retrieve(category, is_global).map(Some)
+    }
+
+    /// Whether a category file exists in a scope, even if it holds no live entries
+    pub fn category_exists(&self, category: &str, is_global: bool) -> bool {
+        self.get_memory_file(category, is_global).exists()
+    }
+
+    /// Like [`Self::retrieve`], with only the newest `limit` entries of the category
+    pub fn retrieve_last(
+        &self,
+        category: &str,
+        limit: usize,
+        is_global: bool,
+    ) -> io::Result<BTreeMap<String, Vec<String>>> {
+        let mut memories = BTreeMap::new();
+        for entry in self.read_last_entries(category, limit, is_global)? {
+            memories
+                .entry(entry.tag_key())
+                .or_insert_with(Vec::new)
+                .push(entry.data);
+        }
+
+        Ok(memories)
+    }
+
+    /// The Graphiti client entries of a scope are synced through
+    fn graphiti_for(&self, is_global: bool) -> &GraphitiClient {
+        if is_global {
+            &self.global_graphiti_client
+        } else {
+            &self.local_graphiti_client
+        }
+    }
+
+    /// One client per distinct graph, for calls that span both scopes
+    fn graphiti_clients(&self) -> Vec<&GraphitiClient> {
+        let mut clients = vec![&self.local_graphiti_client];
+        if self.global_graphiti_client.endpoint() != self.local_graphiti_client.endpoint() {
+            clients.push(&self.global_graphiti_client);
+        }
+        clients
+    }
+
+    fn memory_dir(&self, is_global: bool) -> &PathBuf {
+        if is_global {
+            &self.global_memory_dir
+        } else {
+            &self.local_memory_dir
+        }
+    }
+
+    /// The name a category is stored under, see [`UltraThinkConfig::normalize_category_case`]
+    fn stored_category<'a>(&self, category: &'a str) -> Cow<'a, str> {
+        self.config.normalize_category_case.apply(category)
+    }
+
+    fn get_memory_file(&self, category: &str, is_global: bool) -> PathBuf {
+        self.memory_dir(is_global).join(format!(
+            "{}.{}",
+            encode_category(&self.stored_category(category)),
+            self.config.storage_format.extension()
+        ))
+    }
+
+    fn get_meta_file(&self, category: &str, is_global: bool) -> PathBuf {
+        self.memory_dir(is_global).join(format!(
+            "{}.meta.json",
+            encode_category(&self.stored_category(category))
+        ))
+    }
+
+    async fn execute_tool_call(&self, tool_call: ToolCall) -> Result<ToolOutput, io::Error> {
+        match tool_call.name.as_str() {
+            "ultrathink_graphiti_sync" => {
+                let direction =
+                    optional_str(&tool_call.arguments, "direction")?.unwrap_or("bidirectional");
+                // "*" or no category syncs everything
+                let category = optional_str(&tool_call.arguments, "category")?
+                    .filter(|category| *category != "*");
+                if let Some(category) = category {
+                    validate_category(category)?;
+                }
+                let available = self
+                    .graphiti_clients()
+                    .iter()
+                    .any(|client| client.unavailable().is_none());
+
+                let result = match direction {
+                    "to_graphiti" | "bidirectional" if available => {
+                        self.sync_to_graphiti_report(direction, category).await
+                    }
+                    "from_graphiti" if available => self.sync_from_graphiti(category).await,
+                    // Reports an invalid direction, or why Graphiti cannot be reached
+                    _ => {
+                        self.local_graphiti_client
+                            .sync_memories(direction, category)
+                            .await
+                    }
+                };
+                match result {
+                    Ok(report) => Ok(ToolOutput::from(self.formatter.synced(&report))
+                        .with_meta("report", json!(report))),
+                    Err(e) => Ok(self
+                        .formatter
+                        .error(&format!("Graphiti sync failed: {}", e))
+                        .into()),
+                }
+            }
+            "ultrathink_graphiti_diff" => {
+                let category = optional_str(&tool_call.arguments, "category")?
+                    .filter(|category| *category != "*");
+                if let Some(category) = category {
+                    validate_category(category)?;
+                }
+                let is_global = optional_bool(&tool_call.arguments, "is_global")?.unwrap_or(false);
+                if let Some(message) = self.graphiti_for(is_global).unavailable() {
+                    return Ok(format!("{}, nothing to compare", message).into());
+                }
+
+                let diff = self.graphiti_diff(category, is_global).await?;
+                if diff.is_empty() {
+                    return Ok("🔀 UltraThink memories match Graphiti".to_string().into());
+                }
+                let times = self.config.timestamp_display;
+                let report = json!({
+                    "local_only": diff
+                        .local_only
+                        .into_iter()
+                        .map(|(category, entry)| entry_json(&category, entry, times))
+                        .collect::<Vec<_>>(),
+                    "remote_only": diff
+                        .remote_only
+                        .into_iter()
+                        .map(|(category, node)| json!({"category": category, "entity": node}))
+                        .collect::<Vec<_>>(),
+                    "conflicting": diff
+                        .conflicting
+                        .into_iter()
+                        .map(|(category, entry, node)| {
+                            json!({"local": entry_json(&category, entry, times), "remote": node})
+                        })
+                        .collect::<Vec<_>>(),
+                });
+                let text = format!("🔀 UltraThink Graphiti diff: {}", report);
+                Ok(ToolOutput::from(text).with_meta("diff", report))
+            }
+            "ultrathink_graphiti_query" => {
+                let query = tool_call.arguments["query"].as_str().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "query must be a string")
+                })?;
+                let limit = optional_usize(&tool_call.arguments, "limit")?;
+                let fresh = optional_bool(&tool_call.arguments, "fresh")?.unwrap_or(false);
+
+                let mut results = Vec::new();
+                let mut cached = true;
+                for client in self.graphiti_clients() {
+                    let (client_results, hit) =
+                        client.search("", Some(query), limit, fresh).await?;
+                    results.extend(client_results);
+                    cached &= hit;
+                }
+                Ok(ToolOutput::from(self.formatter.retrieved(&json!(results)))
+                    .with_meta("cached", json!(cached)))
+            }
+            "ultrathink_remember"
+                if self.config.sync_on_write != SyncOnWrite::Off
+                    && self.priority_of(&tool_call.arguments) == Some("high") =>
+            {
+                self.remember_high_priority(tool_call)
+                    .await
+                    .map(ToolOutput::from)
+            }
+            "ultrathink_remember" if self.config.sync_on_write == SyncOnWrite::All => self
+                .remember_in_background(tool_call)
+                .await
+                .map(ToolOutput::from),
+            "ultrathink_retrieve"
+                if optional_bool(&tool_call.arguments, "expand_graph")?.unwrap_or(false) =>
+            {
+                self.retrieve_expanded(tool_call).await
+            }
+            "ultrathink_link" => self.link_tool(tool_call).await.map(ToolOutput::from),
+            "ultrathink_trace" => {
+                let mut records = self.trace();
+                if let Some(limit) = tool_call.arguments.get("limit").and_then(|v| v.as_u64()) {
+                    records.drain(..records.len().saturating_sub(limit as usize));
+                }
+                Ok(format!("🔍 UltraThink tool trace: {}", json!(records)).into())
+            }
+            _ => {
+                // Every other tool is file IO, which must not stall the async worker threads
+                let this = self.clone();
+                tokio::task::spawn_blocking(move || this.execute_file_tool_call(&tool_call))
+                    .await
+                    .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))?
+            }
+        }
+    }
+
+    /// The priority a remember call stores, falling back to
+    /// [`UltraThinkConfig::default_priority`]
+    fn priority_of<'a>(&'a self, arguments: &'a Value) -> Option<&'a str> {
+        arguments
+            .get("priority")
+            .and_then(|v| v.as_str())
+            .or(self.config.default_priority.as_deref())
+    }
+
+    /// Store a link locally, then mirror it to Graphiti as a relationship between the
+    /// entry ids when Graphiti is available. A failed mirror keeps the local link.
+    async fn link_tool(&self, tool_call: ToolCall) -> Result<String, io::Error> {
+        let this = self.clone();
+        let (from, to, relation, is_global, added) = tokio::task::spawn_blocking(move || {
+            let arg = |key: &str| {
+                tool_call.arguments[key]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("{} must be a string", key),
+                        )
+                    })
+            };
+            let (from, to) = (arg("from")?, arg("to")?);
+            let relation = arg("relation")?.trim().to_string();
+            let is_global = optional_bool(&tool_call.arguments, "is_global")?.unwrap_or(false);
+            let added = this.link(&from, &to, &relation, is_global)?;
+            Ok::<_, io::Error>((from, to, relation, is_global, added))
+        })
+        .await
+        .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))??;
+
+        if !added {
+            return Ok(format!(
+                "🔗 UltraThink link already exists: {} -[{}]-> {}",
+                from, relation, to
+            ));
+        }
+        let mut message = format!("🔗 UltraThink linked {} -[{}]-> {}", from, relation, to);
+        let client = self.graphiti_for(is_global);
+        if client.unavailable().is_none() {
+            match client.create_relationship(&from, &to, &relation).await {
+                Ok(_) => message.push_str(" (mirrored to Graphiti)"),
+                Err(e) => {
+                    tracing::warn!("Graphiti mirror of a memory link failed: {}", e);
+                    message.push_str(&format!(" (Graphiti mirror failed: {})", e));
+                }
+            }
+        }
+        Ok(message)
+    }
+
+    /// A retrieve result followed by the Graphiti neighbors of the retrieved entries that
+    /// have links, one hop away and bounded by [`MAX_EXPANDED_ENTRIES`] and
+    /// [`MAX_GRAPH_NEIGHBORS`]. Scopes whose Graphiti client is unavailable are not expanded.
+    async fn retrieve_expanded(&self, tool_call: ToolCall) -> Result<ToolOutput, io::Error> {
+        let this = self.clone();
+        let (mut output, linked) = tokio::task::spawn_blocking(move || {
+            let output = this.execute_file_tool_call(&tool_call)?;
+            let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+            let scopes: &[bool] = if optional_str(&tool_call.arguments, "scope")? == Some("both") {
+                &[false, true]
+            } else {
+                &[args.is_global]
+            };
+            let linked = this.linked_for_expansion(args.category, scopes)?;
+            Ok::<_, io::Error>((output, linked))
+        })
+        .await
+        .map_err(|e| io::Error::other(format!("UltraThink task failed: {}", e)))??;
+
+        let mut related = Vec::new();
+        for (is_global, category, entry) in linked {
+            let client = self.graphiti_for(is_global);
+            if client.unavailable().is_some() {
+                continue;
+            }
+            let id = entry.id();
+            match client.neighbors(&id, MAX_GRAPH_NEIGHBORS).await {
+                Ok(neighbors) if neighbors.is_empty() => {}
+                Ok(neighbors) => related.push(json!({
+                    "entry": entry_json(&category, entry, self.config.timestamp_display),
+                    "related": neighbors,
+                })),
+                Err(e) => {
+                    tracing::warn!("Graphiti expansion of entry {} failed: {}", id, e);
+                    output
+                        .text
+                        .push_str(&format!("\n⚠️ Graphiti expansion of {} failed: {}", id, e));
+                }
+            }
+        }
+        if related.is_empty() {
+            return Ok(output);
+        }
+        output.text.push_str(&format!(
+            "\n🕸️ Related in Graphiti: {}",
+            Value::Array(related.clone())
+        ));
+        Ok(output.with_meta("graph_related", Value::Array(related)))
+    }
+
+    /// The live entries of `category` (every category for `*`) that are linked to another
+    /// entry, and so may have related entities in Graphiti, at most
+    /// [`MAX_EXPANDED_ENTRIES`] of them
+    fn linked_for_expansion(
+        &self,
+        category: &str,
+        scopes: &[bool],
+    ) -> io::Result<Vec<(bool, String, MemoryEntry)>> {
+        let mut linked = Vec::new();
+        for &is_global in scopes {
+            let links = MemoryLinks::load(self.memory_dir(is_global)).links;
+            if links.is_empty() {
+                continue;
+            }
+            let categories = if category == "*" {
+                self.list_categories(is_global)?
+            } else {
+                vec![category.to_string()]
+            };
+            for name in categories {
+                for entry in self.read_entries(&name, is_global)? {
+                    let id = entry.id();
+                    if links.iter().any(|link| link.other_end(&id).is_some()) {
+                        linked.push((is_global, name.clone(), entry));
+                    }
+                }
+            }
+        }
+        linked.truncate(MAX_EXPANDED_ENTRIES);
+        Ok(linked)
+    }
+
+    /// A retrieve result, with the scope searched and how many of the returned entries
+    /// were cut to the size limit when stored as metadata
+    fn retrieved(
+        &self,
+        memories: &Value,
+        both_scopes: bool,
+        is_global: bool,
+        highlighter: Option<&Highlighter>,
+    ) -> ToolOutput {
+        let scope = match (both_scopes, MemoryScope::from_global(is_global)) {
+            (true, _) => "both",
+            (false, MemoryScope::Global) => "global",
+            (false, MemoryScope::Local) => "local",
+        };
+        let text = match highlighter {
+            Some(highlighter) => {
+                let mut highlighted = memories.clone();
+                highlighter.highlight_value(&mut highlighted);
+                self.formatter.retrieved(&highlighted)
+            }
+            None => self.formatter.retrieved(memories),
+        };
+        ToolOutput::from(text)
+            .with_meta("scope", json!(scope))
+            .with_meta("truncated", json!(count_truncated(memories)))
+    }
+
+    /// What a remember or retrieve that asks for `debug` resolves to, one target per scope
+    /// it reads or writes. Taken before the call runs, to tell whether it created the
+    /// memory dir.
+    fn debug_targets(&self, tool_call: &ToolCall) -> io::Result<Vec<DebugTarget>> {
+        let tool = tool_call.name.as_str();
+        if !matches!(tool, "ultrathink_remember" | "ultrathink_retrieve")
+            || !optional_bool(&tool_call.arguments, "debug")?.unwrap_or(false)
+        {
+            return Ok(Vec::new());
+        }
+        let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+
+        let both_scopes = tool == "ultrathink_retrieve"
+            && optional_str(&tool_call.arguments, "scope")? == Some("both");
+        let scopes: &[bool] = if both_scopes {
+            &[false, true]
+        } else {
+            &[args.is_global]
+        };
+        let whole_scope =
+            args.category == "*" || optional_bool(&tool_call.arguments, "recent")?.unwrap_or(false);
+        Ok(scopes
+            .iter()
+            .map(|&is_global| {
+                let dir = self.memory_dir(is_global).clone();
+                let path = if whole_scope {
+                    dir.clone()
+                } else {
+                    self.get_memory_file(args.category, is_global)
+                };
+                DebugTarget {
+                    is_global,
+                    path,
+                    dir_existed: dir.exists(),
+                    dir,
+                }
+            })
+            .collect())
+    }
+
+    /// The highlighter of a retrieve that asks for `highlight` with a non-empty `query`
+    fn highlighter(&self, arguments: &Value) -> io::Result<Option<Highlighter>> {
+        let query = optional_str(arguments, "query")?;
+        if !optional_bool(arguments, "highlight")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        Ok(query.and_then(|query| Highlighter::new(query, &self.config.highlight_marker)))
+    }
+
+    /// Build the entry of an `ultrathink_remember` call and store it, applying its
+    /// `singleton` argument to the category first
+    fn store_remembered(
+        &self,
+        args: &UltraThinkArgs,
+        arguments: &Value,
+    ) -> io::Result<MemoryEntry> {
+        let entry = self.remember_entry(args, arguments)?;
+        if let Some(singleton) = optional_bool(arguments, "singleton")? {
+            self.set_singleton(args.category, singleton, args.is_global)?;
+        }
+        self.append_entry(args.category, &entry, args.is_global)?;
+        Ok(entry)
+    }
+
+    /// The entry an `ultrathink_remember` call stores, with its pin and expiry applied
+    fn remember_entry(&self, args: &UltraThinkArgs, arguments: &Value) -> io::Result<MemoryEntry> {
+        let attachment = match optional_str(arguments, "attachment_path")? {
+            Some(path) => Some(Attachment::resolve(
+                Path::new(path),
+                &self.attachment_dirs(),
+            )?),
+            None => None,
+        };
+        let mut entry = match &attachment {
+            Some(attachment) => {
+                let description = args.data.filter(|data| !data.trim().is_empty());
+                self.new_entry(&attachment.entry_data(description), &args.tags)
+            }
+            None => self.new_entry(args.data.unwrap_or(""), &args.tags),
+        };
+        if let Some(attachment) = &attachment {
+            attachment.store_in(&mut entry);
+        }
+        entry.pinned = optional_bool(arguments, "pinned")?.unwrap_or(false);
+        for (argument, field) in [("priority", PRIORITY_FIELD), ("context", CONTEXT_FIELD)] {
+            if let Some(value) = optional_str(arguments, argument)? {
+                entry.fields.insert(field.to_string(), value.to_string());
+            }
+        }
+        if let Some(priority) = &self.config.default_priority {
+            entry
+                .fields
+                .entry(PRIORITY_FIELD.to_string())
+                .or_insert_with(|| priority.clone());
+        }
+        if let Some(ttl) = arguments.get("ttl_secs") {
+            let ttl = ttl.as_i64().filter(|ttl| *ttl >= 0).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "ttl_secs must be a non-negative integer",
+                )
+            })?;
+            entry.expires = entry
+                .timestamp
+                .map(|ts| ts + chrono::Duration::seconds(ttl));
+        }
+        Ok(entry)
+    }
+
+    /// Tools that only touch the local memory files; run on the blocking thread pool
+    fn execute_file_tool_call(&self, tool_call: &ToolCall) -> Result<ToolOutput, io::Error> {
+        match tool_call.name.as_str() {
+            "ultrathink_remember" => {
+                let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+                let entry = self.store_remembered(&args, &tool_call.arguments)?;
+                Ok(self.formatter.stored(args.category, &entry.id()).into())
+            }
+            "ultrathink_retrieve" => {
+                let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+                let arguments = &tool_call.arguments;
+                let both_scopes = optional_str(arguments, "scope")? == Some("both");
+                let highlighter = self.highlighter(arguments)?;
+                let highlighter = highlighter.as_ref();
+                let limit = optional_usize(arguments, "limit")?;
+                let times = self.config.timestamp_display;
+                let recent = optional_bool(arguments, "recent")?.unwrap_or(false);
+                if recent {
+                    let limit = limit.unwrap_or(DEFAULT_RECENT_LIMIT);
+                    let entries: Vec<Value> = self
+                        .retrieve_recent(args.is_global, limit)?
+                        .into_iter()
+                        .map(|(category, entry)| entry_json(&category, entry, times))
+                        .collect();
+                    return Ok(self.retrieved(
+                        &Value::Array(entries),
+                        false,
+                        args.is_global,
+                        highlighter,
+                    ));
+                }
+
+                let after = optional_str(arguments, "after")?;
+                let before = optional_str(arguments, "before")?;
+                if after.is_some() || before.is_some() {
+                    let include_undated =
+                        optional_bool(arguments, "include_undated")?.unwrap_or(false);
+                    let range = DateRange::parse(after, before, include_undated)?;
+                    let scopes: &[bool] = if both_scopes {
+                        &[false, true]
+                    } else {
+                        &[args.is_global]
+                    };
+                    let entries: Vec<Value> = self
+                        .retrieve_between(args.category, scopes, &range)?
+                        .into_iter()
+                        .map(|(category, entry)| entry_json(&category, entry, times))
+                        .collect();
+                    return Ok(self.retrieved(
+                        &Value::Array(entries),
+                        both_scopes,
+                        args.is_global,
+                        highlighter,
+                    ));
+                }
+
+                if let Some(tag) = optional_str(arguments, "tag")? {
+                    let scopes: &[bool] = if both_scopes {
+                        &[false, true]
+                    } else {
+                        &[args.is_global]
+                    };
+                    let entries: Vec<Value> = self
+                        .retrieve_tagged(args.category, scopes, tag)?
+                        .into_iter()
+                        .map(|(category, entry)| entry_json(&category, entry, times))
+                        .collect();
+                    return Ok(self.retrieved(
+                        &Value::Array(entries),
+                        both_scopes,
+                        args.is_global,
+                        highlighter,
+                    ));
+                }
+
+                if let Some(separator) = optional_str(arguments, "join")? {
+                    let categories = match (both_scopes, args.category == "*") {
+                        (true, true) => self.retrieve_all_merged()?,
+                        (true, false) => BTreeMap::from([(
+                            args.category.to_string(),
+                            self.retrieve_merged(args.category)?,
+                        )]),
+                        (false, true) => self.retrieve_all(args.is_global)?,
+                        (false, false) => BTreeMap::from([(
+                            args.category.to_string(),
+                            self.retrieve(args.category, args.is_global)?,
+                        )]),
+                    };
+                    // Joining leaves only the last marker of each category, so count first
+                    let truncated = count_truncated(&json!(categories));
+                    let joined: BTreeMap<String, String> = categories
+                        .into_iter()
+                        .map(|(category, groups)| {
+                            (category, flatten_groups(groups).join(separator))
+                        })
+                        .collect();
+                    return Ok(self
+                        .retrieved(&json!(joined), both_scopes, args.is_global, highlighter)
+                        .with_meta("truncated", json!(truncated)));
+                }
+
+                let flatten = optional_bool(arguments, "flatten")?.unwrap_or(false);
+                let include_links = optional_bool(arguments, "include_links")?.unwrap_or(false);
+                let memories = if both_scopes {
+                    if args.category == "*" {
+                        json!(self.retrieve_all_merged()?)
+                    } else {
+                        json!(self.retrieve_merged(args.category)?)
+                    }
+                } else {
+                    let single = args.category != "*";
+                    if single && !self.category_exists(args.category, args.is_global) {
+                        return Ok(self.formatter.missing_category(args.category).into());
+                    }
+                    let retrieve_single = || match limit {
+                        Some(limit) => self.retrieve_last(args.category, limit, args.is_global),
+                        None => self.retrieve(args.category, args.is_global),
+                    };
+                    let retrieve_each = || match limit {
+                        Some(limit) => self.retrieve_all_last(args.is_global, limit),
+                        None => self.retrieve_all(args.is_global),
+                    };
+                    let memories = match (single, flatten) {
+                        (false, false) => json!(retrieve_each()?),
+                        (false, true) => json!(flatten_categories(retrieve_each()?)),
+                        (true, false) => json!(retrieve_single()?),
+                        (true, true) => json!(flatten_groups(retrieve_single()?)),
+                    };
+                    let empty = match &memories {
+                        Value::Object(groups) => groups.is_empty(),
+                        Value::Array(entries) => entries.is_empty(),
+                        _ => false,
+                    };
+                    if single && empty {
+                        return Ok(self.formatter.empty_category(args.category).into());
+                    }
+                    memories
+                };
+                let memories = if include_links {
+                    let scopes: &[bool] = if both_scopes {
+                        &[false, true]
+                    } else {
+                        &[args.is_global]
+                    };
+                    let links = self.linked_entries(args.category, scopes)?;
+                    json!({"memories": memories, "links": links})
+                } else {
+                    memories
+                };
+                Ok(self.retrieved(&memories, both_scopes, args.is_global, highlighter))
+            }
+            "ultrathink_sequence" => {
+                let thought = required_str(&tool_call.arguments, "thought")?;
+                let stage = required_str(&tool_call.arguments, "stage")?;
+                let strict = optional_bool(&tool_call.arguments, "strict_order")?.unwrap_or(false);
+                let save_to_memory =
+                    optional_bool(&tool_call.arguments, "save_to_memory")?.unwrap_or(false);
+                let category = optional_str(&tool_call.arguments, "category")?;
+                match ThinkingStage::parse(stage) {
+                    Some(parsed) => self.stages.lock().unwrap().advance(parsed, strict)?,
+                    None if strict => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Unknown stage '{}'", stage),
+                        ))
+                    }
+                    None => {}
+                }
+                
+                // In a real implementation, this would integrate with sequential-thinking MCP
+                let result = format!("🤔 Sequential thinking - Stage: {} | Thought: {}", stage, thought);
+                
+                // Optionally save to memory if requested
+                if save_to_memory {
+                    let category = category.unwrap_or("thinking");
+                    
+                    let memory_data = format!("[{}] {}", stage, thought);
+                    self.remember(category, &memory_data, &["sequential", "thinking"], false)?;
+                }
+                
+                Ok(result.into())
+            }
+            "ultrathink_rename_category" => {
+                let from = tool_call.arguments["from"].as_str().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "from must be a string")
+                })?;
+                let to = tool_call.arguments["to"].as_str().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "to must be a string")
+                })?;
+                let is_global = optional_bool(&tool_call.arguments, "is_global")?.unwrap_or(false);
+                let merge = optional_bool(&tool_call.arguments, "merge")?.unwrap_or(false);
+
+                self.rename_category(from, to, is_global, merge)?;
+                Ok(format!("🏷️ UltraThink category renamed: {} → {}", from, to).into())
+            }
+            "ultrathink_merge_categories" => {
+                let arguments = &tool_call.arguments;
+                let sources = optional_strings(arguments, "sources")?.unwrap_or_default();
+                let destination = required_str(arguments, "destination")?;
+                let is_global = optional_bool(arguments, "is_global")?.unwrap_or(false);
+                let delete_sources = optional_bool(arguments, "delete_sources")?.unwrap_or(false);
+
+                let (merged, duplicates) =
+                    self.merge_categories(&sources, destination, is_global, delete_sources)?;
+                Ok(format!(
+                    "🏷️ UltraThink categories merged into {}: {} entries added, {} duplicates skipped",
+                    destination, merged, duplicates
+                )
+                .into())
+            }
+            "ultrathink_get_entry" => {
+                let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+                let id = tool_call.arguments["entry_id"].as_str().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "entry_id must be a string")
+                })?;
+
+                let (text, found) = match self.get_entry(args.category, id, args.is_global)? {
+                    Some(entry) => {
+                        let times = self.config.timestamp_display;
+                        let mut details = entry_details_json(args.category, entry, times);
+                        if let Some(attachment) = details.get_mut("attachment") {
+                            attachment["uri"] =
+                                json!(attachment_uri(args.category, id, args.is_global));
+                        }
+                        (format!("🔎 UltraThink entry: {}", details), true)
+                    }
+                    None => (
+                        format!(
+                            "❔ UltraThink entry {} not found in category: {}",
+                            id, args.category
+                        ),
+                        false,
+                    ),
+                };
+                Ok(ToolOutput::from(text).with_meta("found", json!(found)))
+            }
+            "ultrathink_pin" | "ultrathink_unpin" => {
+                let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+                let id = tool_call.arguments["id"].as_str().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "id must be a string")
+                })?;
+                let pinned = tool_call.name == "ultrathink_pin";
+
+                self.set_pinned(args.category, id, pinned, args.is_global)?;
+                Ok(format!(
+                    "📌 UltraThink entry {} {} in category: {}",
+                    id,
+                    if pinned { "pinned" } else { "unpinned" },
+                    args.category
+                )
+                .into())
+            }
+            "ultrathink_cleanup" => {
+                let is_global = optional_bool(&tool_call.arguments, "is_global")?.unwrap_or(false);
+
+                let removed = self.cleanup_expired(is_global)?;
+                Ok(format!("🧹 UltraThink removed {} expired entries", removed).into())
+            }
+            "ultrathink_clear" => {
+                let is_global = optional_bool(&tool_call.arguments, "is_global")?.unwrap_or(false);
+                let backup = optional_bool(&tool_call.arguments, "backup")?.unwrap_or(false);
+                if optional_bool(&tool_call.arguments, "confirm")? != Some(true) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "ultrathink_clear deletes every memory in the scope; pass confirm: true to proceed",
+                    ));
+                }
+
+                let backup_dir = if backup {
+                    Some(self.backup(is_global)?)
+                } else {
+                    None
+                };
+                let (categories, entries) = self.clear(is_global)?;
+                let mut message = format!(
+                    "🗑️ UltraThink cleared {} categories ({} entries)",
+                    categories, entries
+                );
+                if let Some(backup_dir) = backup_dir {
+                    message.push_str(&format!(", backup saved to {}", backup_dir.display()));
+                }
+                Ok(message.into())
+            }
+            "ultrathink_snapshot" => {
+                let is_global = optional_bool(&tool_call.arguments, "is_global")?.unwrap_or(false);
+                let id = self.snapshot(is_global)?;
+                Ok(format!("📸 UltraThink snapshot created: {}", id).into())
+            }
+            "ultrathink_restore" => {
+                let id = required_str(&tool_call.arguments, "snapshot")?;
+                let is_global = optional_bool(&tool_call.arguments, "is_global")?.unwrap_or(false);
+                let backup = optional_bool(&tool_call.arguments, "backup")?.unwrap_or(false);
+
+                let backup_id = self.restore(id, backup, is_global)?;
+                let mut message = format!("⏪ UltraThink snapshot restored: {}", id);
+                if let Some(backup_id) = backup_id {
+                    message.push_str(&format!(", previous state saved as snapshot {}", backup_id));
+                }
+                Ok(message.into())
+            }
+            "ultrathink_verify" => {
+                let is_global = optional_bool(&tool_call.arguments, "is_global")?.unwrap_or(false);
+                let report = self.verify(is_global)?;
+                let mut message = format!(
+                    "🛡️ UltraThink verified memory files: {}",
+                    serde_json::to_string(&report)?
+                );
+                if !report.corrupted.is_empty() {
+                    message.push_str(&format!(
+                        "\n⚠️ Memory file corrupted: {}",
+                        report.corrupted.join(", ")
+                    ));
+                }
+                Ok(message.into())
+            }
+            "ultrathink_forget" => {
+                let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+                let matching = optional_str(&tool_call.arguments, "match")?;
+
+                let removed = self.forget(args.category, matching, &args.tags, args.is_global)?;
+                Ok(format!(
+                    "🗑️ Removed {} entries from category: {}",
+                    removed, args.category
+                )
+                .into())
+            }
+            "ultrathink_compact" => {
+                let args = UltraThinkArgs::from_value(&tool_call.arguments)?;
+                let keep = tool_call.arguments["keep"].as_u64().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "keep must be a non-negative integer",
+                    )
+                })?;
+
+                let removed = self.compact(args.category, keep as usize, args.is_global)?;
+                Ok(format!(
+                    "🗜️ UltraThink compacted category {}: removed {} entries",
+                    args.category, removed
+                )
+                .into())
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown UltraThink tool")),
+        }
+    }
+}
+
+#[async_trait]
+impl Router for UltraThinkRouter {
+    fn name(&self) -> String {
+        "ultrathink".to_string()
+    }
+
+    fn instructions(&self) -> String {
+        self.instructions.clone()
+    }
+
+    fn capabilities(&self) -> ServerCapabilities {
+        CapabilitiesBuilder::new().with_tools(false).build()
+    }
+
+    fn list_tools(&self) -> Vec<Tool> {
+        self.tools.clone()
+    }
+
+    fn describe_tool(&self, name: &str) -> Option<Tool> {
+        self.tools.iter().find(|tool| tool.name == name).cloned()
+    }
+
+    fn call_tool(
+        &self,
+        tool_name: &str,
+        arguments: Value,
+        notifier: mpsc::Sender<JsonRpcMessage>,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>> {
+        let call = self.call_tool_with_meta(tool_name, arguments, notifier);
+        Box::pin(async move { call.await.map(|(content, _)| content) })
+    }
+
+    fn call_tool_with_meta(
+        &self,
+        tool_name: &str,
+        arguments: Value,
+        _notifier: mpsc::Sender<JsonRpcMessage>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Result<(Vec<Content>, Option<ToolResultMeta>), ToolError>>
+                + Send
+                + 'static,
+        >,
+    > {
+        let this = self.clone();
+        let tool_name = tool_name.to_string();
+
+        Box::pin(async move {
+            let tool_call = ToolCall {
+                name: tool_name,
+                arguments,
+            };
+            if !this.tools.iter().any(|tool| tool.name == tool_call.name) {
+                return Err(ToolError::NotFound(tool_call.name));
+            }
+
+            let tool = tool_call.name.clone();
+            let arguments = tool_call.arguments.clone();
+            let started_at = this.clock.utc_now();
+            let started = Instant::now();
+            // A mistyped `debug` fails the call before it runs, like any other argument
+            let (debug_targets, result) = match this.debug_targets(&tool_call) {
+                Ok(targets) => (targets, this.execute_tool_call(tool_call).await),
+                Err(e) => (Vec::new(), Err(e)),
+            };
+            // Reading the trace is left out of it so it only shows the calls under study
+            if tool != "ultrathink_trace" {
+                this.record_call(tool, &arguments, started_at, started.elapsed(), &result);
+            }
+
+            let output = result?.with_debug(&debug_targets);
+            Ok((vec![Content::text(output.text)], output.meta))
+        })
+    }
+
+    fn list_resources(&self) -> Vec<Resource> {
+        Vec::new()
+    }
+
+    fn read_resource(
+        &self,
+        uri: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ResourceError>> + Send + 'static>> {
+        let uri = uri.to_string();
+        let this = self.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || this.read_attachment(&uri))
+                .await
+                .map_err(|e| ResourceError::ExecutionError(e.to_string()))?
+                .map_err(|e| match e.kind() {
+                    io::ErrorKind::NotFound | io::ErrorKind::InvalidInput => {
+                        ResourceError::NotFound(e.to_string())
+                    }
+                    _ => ResourceError::ExecutionError(e.to_string()),
+                })
+        })
+    }
+
+    fn list_prompts(&self) -> Vec<Prompt> {
+        vec![]
+    }
+
+    fn get_prompt(
+        &self,
+        prompt_name: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'static>> {
+        let prompt_name = prompt_name.to_string();
+        Box::pin(async move {
+            Err(PromptError::NotFound(format!(
+                "Prompt {} not found",
+                prompt_name
+            )))
+        })
+    }
+}
+
+#[derive(Debug)]
+struct UltraThinkArgs<'a> {
+    category: &'a str,
+    data: Option<&'a str>,
+    tags: Vec<&'a str>,
+    is_global: bool,
+}
+
+impl<'a> UltraThinkArgs<'a> {
+    fn from_value(args: &'a Value) -> Result<Self, io::Error> {
+        let category = required_str(args, "category")?;
+
+        validate_category(category)?;
+
+        let data = optional_str(args, "data")?;
+
+        let tags = optional_strings(args, "tags")?.unwrap_or_default();
+
+        let is_global = optional_bool(args, "is_global")?.unwrap_or(false);
+
+        Ok(Self {
+            category,
+            data,
+            tags,
+            is_global,
+        })
+    }
+}
+
+/// What a tool call produced: the text for the model, and optional structured metadata
+/// for the client
+#[derive(Debug)]
+struct ToolOutput {
+    text: String,
+    meta: Option<ToolResultMeta>,
+}
+
+impl ToolOutput {
+    fn with_meta(mut self, key: &str, value: Value) -> Self {
+        self.meta
+            .get_or_insert_with(ToolResultMeta::new)
+            .insert(key.to_string(), value);
+        self
+    }
+
+    /// Append a line per debug target, and the same details under the `debug` meta key
+    fn with_debug(mut self, targets: &[DebugTarget]) -> Self {
+        if targets.is_empty() {
+            return self;
+        }
+
+        let mut details = Vec::new();
+        for target in targets {
+            let scope = if target.is_global { "global" } else { "local" };
+            let dir_created = !target.dir_existed && target.dir.exists();
+            self.text.push_str(&format!(
+                "\n🐞 Debug: scope: {}; path: {}; dir created: {}",
+                scope,
+                target.path.display(),
+                dir_created
+            ));
+            details.push(json!({
+                "scope": scope,
+                "path": target.path,
+                "dir_created": dir_created,
+            }));
+        }
+        self.with_meta("debug", json!(details))
+    }
+}
+
+/// A memory file, or memory dir for calls across categories, that a `debug` call resolved
+/// to
+struct DebugTarget {
+    is_global: bool,
+    path: PathBuf,
+    dir: PathBuf,
+    dir_existed: bool,
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        Self { text, meta: None }
+    }
+}
+
+/// Resolve the shared settings from the config file next to the global memory dir and
+/// the environment
+fn resolve_settings(global_memory_dir: &Path) -> GooseConfig {
+    GooseConfig::new(SETTINGS)
+        .with_file(&config::config_file_path(global_memory_dir))
+        .with_env()
+}
+
+/// Entries in a retrieve result whose data was truncated: listed entries carry a
+/// `truncated` flag, while grouped ones are plain strings ending in the marker
+fn count_truncated(memories: &Value) -> usize {
+    match memories {
+        Value::Object(map) if map.contains_key("truncated") && map.contains_key("data") => {
+            usize::from(map["truncated"].as_bool().unwrap_or(false))
+        }
+        Value::Object(map) => map.values().map(count_truncated).sum(),
+        Value::Array(values) => values.iter().map(count_truncated).sum(),
+        Value::String(data) => usize::from(data.ends_with(TRUNCATION_MARKER)),
+        _ => 0,
+    }
+}
+
+/// How a single entry is listed by the `recent` and date-filtered retrieve modes, with
+/// its timestamp shown as `times` renders it
+fn entry_json(category: &str, entry: MemoryEntry, times: TimestampDisplay) -> Value {
+    let attachment = Attachment::from_entry(&entry);
+    let mut json = json!({
+        "id": entry.id(),
+        "category": category,
+        "timestamp": entry.timestamp.map(|ts| times.render(ts)),
+        "pinned": entry.pinned,
+        "truncated": entry.truncated,
+        "data": entry.data,
+    });
+    if let Some(attachment) = attachment {
+        json["attachment"] = json!(attachment);
+    }
+    json
+}
+
+/// A single entry with all of its metadata, as returned by `ultrathink_get_entry`
+fn entry_details_json(category: &str, entry: MemoryEntry, times: TimestampDisplay) -> Value {
+    let tags = entry.tags.clone();
+    let expires = entry.expires.map(|ts| times.render(ts));
+    let fields = entry.fields.clone();
+    let mut details = entry_json(category, entry, times);
+    details["tags"] = json!(tags);
+    details["expires"] = json!(expires);
+    details["fields"] = json!(fields);
+    details
+}
+
+/// The longest prefix of `text` made of whole lines that fits in `max` bytes
+fn trim_to_lines(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut cut = max;
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    text[..cut].rfind('\n').map_or("", |end| &text[..=end])
+}
+
+/// Collapse tag-grouped entries into a single list
+fn flatten_groups(groups: BTreeMap<String, Vec<String>>) -> Vec<String> {
+    groups.into_values().flatten().collect()
+}
+
+/// [`flatten_groups`] for each category
+fn flatten_categories(
+    categories: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+) -> BTreeMap<String, Vec<String>> {
+    categories
+        .into_iter()
+        .map(|(category, groups)| (category, flatten_groups(groups)))
+        .collect()
+}
+
+/// Reject category names that would escape the memory directory or clash with hidden files
+fn validate_category(category: &str) -> io::Result<()> {
+    if category.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Category cannot be empty",
+        ));
+    }
+
+    if category.starts_with('.') || category.contains(|c| matches!(c, '/' | '\\' | '\0')) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid category '{}': categories cannot contain path separators or start with '.'",
+                category
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graphiti_backend::{GraphitiBackend, GraphitiError};
+    use serial_test::serial;
+    use tempfile::tempdir;
+
+    pub(super) fn test_router(base: &std::path::Path) -> UltraThinkRouter {
+        test_router_with_config(base, UltraThinkConfig::default())
+    }
+
+    pub(super) fn test_router_with_config(
+        base: &std::path::Path,
+        config: UltraThinkConfig,
+    ) -> UltraThinkRouter {
+        UltraThinkRouter::with_config(config, base.join("global"), base.join("local"))
+    }
+
+    #[test]
+    #[serial]
+    fn test_settings_read_from_config_file() {
+        // Any of these would take precedence over the file
+        let vars = [
+            "GRAPHITI_ENDPOINT",
+            "GRAPHITI_MCP_ENDPOINT",
+            "GRAPHITI_GLOBAL_MCP_ENDPOINT",
+            "GRAPHITI_LOCAL_MCP_ENDPOINT",
+        ];
+        let saved: Vec<_> = vars
+            .iter()
+            .map(|var| (var, std::env::var_os(var)))
+            .collect();
+        for var in vars {
+            std::env::remove_var(var);
+        }
+
+        let temp_dir = tempdir().unwrap();
+        fs::write(
+            temp_dir.path().join(config::CONFIG_FILE),
+            "graphiti_endpoint = \"http://localhost:8000\"\n",
+        )
+        .unwrap();
+        let router = test_router(temp_dir.path());
+        for (var, value) in saved {
+            if let Some(value) = value {
+                std::env::set_var(var, value);
+            }
+        }
+
+        assert_eq!(
+            router.graphiti_endpoint.as_deref(),
+            Some("http://localhost:8000")
+        );
+        assert!(router
+            .effective_config()
+            .contains("graphiti_endpoint = \"http://localhost:8000\" (file)"));
+        assert_eq!(
+            router.local_graphiti_client.endpoint(),
+            Some("http://localhost:8000")
+        );
+        assert_eq!(
+            router.global_graphiti_client.endpoint(),
+            Some("http://localhost:8000")
+        );
+    }
+
+    fn merged_for(precedence: ScopePrecedence) -> BTreeMap<String, Vec<String>> {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            scope_precedence: precedence,
+            ..Default::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+
+        router
+            .remember("shared", "local only", &["notes"], false)
+            .unwrap();
+        router
+            .remember("shared", "in both", &["notes"], false)
+            .unwrap();
+        router
+            .remember("shared", "in both", &["notes"], true)
+            .unwrap();
+        router
+            .remember("shared", "global only", &["notes"], true)
+            .unwrap();
+
+        router.retrieve_merged("shared").unwrap()
+    }
+
+    #[test]
+    fn test_scope_precedence_union() {
+        let merged = merged_for(ScopePrecedence::Union);
+        assert_eq!(
+            merged["notes"],
+            vec!["local only", "in both", "global only"]
+        );
+    }
+
+    #[test]
+    fn test_scope_precedence_local_first() {
+        let merged = merged_for(ScopePrecedence::LocalFirst);
+        assert_eq!(
+            merged["notes"],
+            vec!["local only", "in both", "in both", "global only"]
+        );
+    }
+
+    #[test]
+    fn test_scope_precedence_global_first() {
+        let merged = merged_for(ScopePrecedence::GlobalFirst);
+        assert_eq!(
+            merged["notes"],
+            vec!["in both", "global only", "local only", "in both"]
+        );
+    }
+
+    #[test]
+    fn test_retrieve_all_merged_covers_both_scopes() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        router.remember("local_cat", "local", &[], false).unwrap();
+        router.remember("global_cat", "global", &[], true).unwrap();
+
+        let merged = router.retrieve_all_merged().unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged["local_cat"]["untagged"], vec!["local"]);
+        assert_eq!(merged["global_cat"]["untagged"], vec!["global"]);
+    }
+
+    #[test]
+    fn test_permuted_and_duplicated_tags_group_together() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        router
+            .remember("dev", "first", &["auth", "api", "api"], false)
+            .unwrap();
+        router
+            .remember("dev", "second", &["api", "auth"], false)
+            .unwrap();
+
+        let grouped = router.retrieve("dev", false).unwrap();
+        assert_eq!(grouped.len(), 1);
+        assert_eq!(grouped["api auth"], vec!["first", "second"]);
+        let content = fs::read_to_string(temp_dir.path().join("local").join("dev.txt")).unwrap();
+        assert_eq!(content.matches("# tags: api, auth;").count(), 2);
+    }
+
+    #[test]
+    fn test_retrieve_all_preserves_tag_grouping() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        router
+            .remember("dev", "first api note", &["api"], false)
+            .unwrap();
+        router
+            .remember("dev", "second api note", &["api"], false)
+            .unwrap();
+        router
+            .remember("dev", "auth note", &["auth"], false)
+            .unwrap();
+        router.remember("dev", "untagged note", &[], false).unwrap();
+
+        let grouped = router.retrieve_all(false).unwrap();
+        let dev = &grouped["dev"];
+        assert_eq!(dev, &router.retrieve("dev", false).unwrap());
+        assert_eq!(dev["api"], vec!["first api note", "second api note"]);
+        assert_eq!(dev["auth"], vec!["auth note"]);
+        assert_eq!(dev["untagged"], vec!["untagged note"]);
+
+        let flat = router.retrieve_all_flat(false).unwrap();
+        let mut dev_flat = flat["dev"].clone();
+        dev_flat.sort();
+        assert_eq!(
+            dev_flat,
+            vec![
+                "auth note",
+                "first api note",
+                "second api note",
+                "untagged note"
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_large_retrieve_does_not_block_runtime() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        let content: String = (0..50_000)
+            .map(|i| format!("# tags: bulk\nentry number {}\n\n", i))
+            .collect();
+        fs::create_dir_all(&router.local_memory_dir).unwrap();
+        fs::write(router.get_memory_file("bulk", false), content).unwrap();
+
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = {
+            let ticks = ticks.clone();
+            tokio::spawn(async move {
+                loop {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        let (tx, _rx) = mpsc::channel(1);
+        let result = router
+            .call_tool(
+                "ultrathink_retrieve",
+                json!({"category": "bulk", "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap();
+        ticker.abort();
+
+        assert!(!result.is_empty());
+        assert!(
+            ticks.load(Ordering::SeqCst) > 0,
+            "concurrent task never ran while the retrieve was in progress"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tool_error_classification() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        let (tx, _rx) = mpsc::channel(1);
+        let missing_category = router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"data": "no category", "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(missing_category, ToolError::InvalidParameters(_)));
+
+        let missing_source = router
+            .call_tool(
+                "ultrathink_rename_category",
+                json!({"from": "absent", "to": "other", "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(missing_source, ToolError::ExecutionError(_)));
+
+        let unknown = router
+            .call_tool("ultrathink_unknown", json!({}), tx)
+            .await
+            .unwrap_err();
+        assert!(matches!(unknown, ToolError::NotFound(_)));
+    }
+
+    pub(super) fn clock_at(timestamp: &str) -> Arc<MockClock> {
+        let now = DateTime::parse_from_rfc3339(timestamp).unwrap();
+        Arc::new(MockClock::at(now.with_timezone(&Utc)))
+    }
+
+    #[test]
+    fn test_retrieve_recent_across_categories() {
+        let temp_dir = tempdir().unwrap();
+        let clock = clock_at("2025-01-06T09:00:00Z");
+        let router = test_router(temp_dir.path()).with_clock(clock.clone());
+
+        for (category, data) in [
+            ("alpha", "oldest"),
+            ("beta", "middle"),
+            ("alpha", "newer"),
+            ("gamma", "newest"),
+        ] {
+            router.remember(category, data, &[], false).unwrap();
+            clock.advance(std::time::Duration::from_millis(1));
+        }
+
+        let recent = router.retrieve_recent(false, 3).unwrap();
+        let summary: Vec<(&str, &str)> = recent
+            .iter()
+            .map(|(category, entry)| (category.as_str(), entry.data.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("gamma", "newest"), ("alpha", "newer"), ("beta", "middle")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_formatter_responses() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path()).with_formatter(Arc::new(JsonFormatter));
+
+        let (tx, _rx) = mpsc::channel(1);
+        router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "formatted", "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        let result = router
+            .call_tool(
+                "ultrathink_retrieve",
+                json!({"category": "dev", "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap();
+
+        let text = result[0].as_text().unwrap().text.clone();
+        let response: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(response["status"], "retrieved");
+        assert_eq!(response["memories"]["untagged"][0], "formatted");
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_highlights_query_terms() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router
+            .remember("dev", "Rotate the API key", &[], false)
+            .unwrap();
+
+        let retrieve = |args: Value| {
+            let router = router.clone();
+            async move {
+                let (tx, _rx) = mpsc::channel(1);
+                let result = router
+                    .call_tool("ultrathink_retrieve", args, tx)
+                    .await
+                    .unwrap();
+                let text = result[0].as_text().unwrap().text.clone();
+                let json = text
+                    .strip_prefix("🧠 UltraThink memories retrieved: ")
+                    .unwrap();
+                serde_json::from_str::<Value>(json).unwrap()
+            }
+        };
+
+        let retrieved = retrieve(
+            json!({"category": "dev", "is_global": false, "query": "api", "highlight": true}),
+        )
+        .await;
+        assert_eq!(retrieved["untagged"][0], "Rotate the **API** key");
+
+        let retrieved =
+            retrieve(json!({"category": "dev", "is_global": false, "query": "api"})).await;
+        assert_eq!(retrieved["untagged"][0], "Rotate the API key");
+
+        let retrieved = retrieve(json!({
+            "category": "dev",
+            "is_global": false,
+            "recent": true,
+            "query": "key",
+            "highlight": true
+        }))
+        .await;
+        assert_eq!(retrieved[0]["data"], "Rotate the API **key**");
+        assert_eq!(retrieved[0]["category"], "dev");
+    }
+
+    #[test]
+    fn test_leading_bom_is_skipped() {
+        let temp_dir = tempdir().unwrap();
+        let local_dir = temp_dir.path().join("local");
+        fs::create_dir_all(&local_dir).unwrap();
+        fs::write(
+            local_dir.join("dev.txt"),
+            "\u{feff}# api auth\nfirst\n\nsecond\n\n",
+        )
+        .unwrap();
+
+        let router = test_router(temp_dir.path());
+        let entries = router.read_entries("dev", false).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].tags, vec!["api", "auth"]);
+        assert_eq!(entries[0].data, "first");
+
+        let config = UltraThinkConfig {
+            mmap_threshold_bytes: 1,
+            ..Default::default()
+        };
+        let mapped = test_router_with_config(temp_dir.path(), config)
+            .read_last_entries("dev", 2, false)
+            .unwrap();
+        assert_eq!(mapped[0].tags, vec!["api", "auth"]);
+        assert_eq!(mapped[0].data, "first");
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_limit_applies_per_category() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path()).with_formatter(Arc::new(JsonFormatter));
+        for i in 0..10 {
+            router
+                .remember("dev", &format!("note {}", i), &[], false)
+                .unwrap();
+        }
+        for i in 0..5 {
+            router
+                .remember("ops", &format!("step {}", i), &[], false)
+                .unwrap();
+        }
+        let (tx, _rx) = mpsc::channel(1);
+        let retrieve = |args: Value| {
+            let router = router.clone();
+            let tx = tx.clone();
+            async move {
+                let result = router
+                    .call_tool("ultrathink_retrieve", args, tx)
+                    .await
+                    .unwrap();
+                let text = result[0].as_text().unwrap().text.clone();
+                serde_json::from_str::<Value>(&text).unwrap()["memories"].clone()
+            }
+        };
+
+        let memories =
+            retrieve(json!({"category": "dev", "is_global": false, "flatten": true, "limit": 3}))
+                .await;
+        assert_eq!(memories, json!(["note 7", "note 8", "note 9"]));
+
+        let memories =
+            retrieve(json!({"category": "*", "is_global": false, "flatten": true, "limit": 3}))
+                .await;
+        assert_eq!(memories["dev"].as_array().unwrap().len(), 3);
+        assert_eq!(memories["ops"], json!(["step 2", "step 3", "step 4"]));
+        let memories = retrieve(json!({"category": "*", "is_global": false, "limit": 3})).await;
+        assert_eq!(memories["dev"]["untagged"].as_array().unwrap().len(), 3);
+
+        // Without a limit every entry is returned
+        let memories =
+            retrieve(json!({"category": "*", "is_global": false, "flatten": true})).await;
+        assert_eq!(memories["dev"].as_array().unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_limited_retrieve_of_large_file_is_mapped() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            mmap_threshold_bytes: 1024,
+            ..Default::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        let local_dir = temp_dir.path().join("local");
+        fs::create_dir_all(&local_dir).unwrap();
+        let content: String = (0..50_000)
+            .map(|i| format!("# tags: log\nentry {}\n\n", i))
+            .collect();
+        fs::write(local_dir.join("dev.txt"), content).unwrap();
+
+        let (tx, _rx) = mpsc::channel(1);
+        let result = router
+            .call_tool(
+                "ultrathink_retrieve",
+                json!({"category": "dev", "is_global": false, "limit": 3}),
+                tx,
+            )
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        let json = text
+            .strip_prefix("🧠 UltraThink memories retrieved: ")
+            .unwrap();
+        let retrieved: Value = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            retrieved["log"],
+            json!(["entry 49997", "entry 49998", "entry 49999"])
+        );
+
+        // Below the threshold the file is read whole, with the same result
+        let whole = test_router(temp_dir.path());
+        assert_eq!(
+            whole.retrieve_last("dev", 3, false).unwrap(),
+            router.retrieve_last("dev", 3, false).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tampered_memory_file_is_detected() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            use_checksums: true,
+            ..Default::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        router.remember("dev", "first", &[], false).unwrap();
+        router.remember("notes", "kept", &[], false).unwrap();
+        assert_eq!(router.verify(false).unwrap().intact, vec!["dev", "notes"]);
+
+        let path = router.get_memory_file("dev", false);
+        let mut content = fs::read_to_string(&path).unwrap();
+        content.push_str("half-writ");
+        fs::write(&path, content).unwrap();
+
+        // The corrupted file is skipped rather than served
+        assert!(router.read_entries("dev", false).unwrap().is_empty());
+        assert_eq!(router.read_entries("notes", false).unwrap().len(), 1);
+
+        let (tx, _rx) = mpsc::channel(1);
+        let result = router
+            .call_tool("ultrathink_verify", json!({"is_global": false}), tx)
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.contains(r#""corrupted":["dev"]"#));
+        assert!(text.contains("Memory file corrupted: dev"));
+
+        // The next write moves the corrupted file aside and starts the category afresh
+        router.remember("dev", "second", &[], false).unwrap();
+        let entries = router.read_entries("dev", false).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].data, "second");
+        let report = router.verify(false).unwrap();
+        assert!(report.corrupted.is_empty());
+        let quarantined = fs::read_dir(temp_dir.path().join("local"))
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".corrupt"))
+            .count();
+        assert_eq!(quarantined, 1);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_global_policy() {
+        let temp_dir = tempdir().unwrap();
+        test_router(temp_dir.path())
+            .remember("team", "curated", &[], true)
+            .unwrap();
+        let config = UltraThinkConfig {
+            read_only_global: true,
+            ..Default::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+
+        // Reads of the global scope still work and merge with local memories
+        router.remember("team", "mine", &[], false).unwrap();
+        assert_eq!(
+            router.retrieve("team", true).unwrap()["untagged"],
+            vec!["curated"]
+        );
+        assert_eq!(
+            router.retrieve_merged("team").unwrap()["untagged"],
+            vec!["mine", "curated"]
+        );
+
+        let err = router.remember("team", "edit", &[], true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        let id = MemoryEntry::new("curated", &[]).id();
+        assert!(router.set_pinned("team", &id, true, true).is_err());
+        assert!(router
+            .rename_category("team", "other", true, false)
+            .is_err());
+        assert!(router.clear(true).is_err());
+
+        let (tx, _rx) = mpsc::channel(1);
+        let result = router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "team", "data": "edit", "is_global": true}),
+                tx,
+            )
+            .await;
+        match result {
+            Err(ToolError::InvalidParameters(message)) => assert!(message.contains("read-only")),
+            other => panic!("expected a rejected write, got {:?}", other),
+        }
+        assert_eq!(
+            router.retrieve("team", true).unwrap()["untagged"],
+            vec!["curated"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trace_records_calls_in_order() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let (tx, _rx) = mpsc::channel(1);
+
+        let calls = [
+            (
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "note", "is_global": false, "token": "abc"}),
+            ),
+            (
+                "ultrathink_retrieve",
+                json!({"category": "dev", "is_global": false}),
+            ),
+            ("ultrathink_clear", json!({"is_global": false})),
+        ];
+        for (tool, args) in calls {
+            let _ = router.call_tool(tool, args, tx.clone()).await;
+        }
+
+        let result = router
+            .call_tool("ultrathink_trace", json!({"limit": 10}), tx)
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        let json = text.strip_prefix("🔍 UltraThink tool trace: ").unwrap();
+        let reported: Vec<Value> = serde_json::from_str(json).unwrap();
+        assert_eq!(reported.len(), 3);
+
+        let trace = router.trace();
+        let tools: Vec<&str> = trace.iter().map(|record| record.tool.as_str()).collect();
+        assert_eq!(
+            tools,
+            vec![
+                "ultrathink_remember",
+                "ultrathink_retrieve",
+                "ultrathink_clear"
+            ]
+        );
+        assert_eq!(trace[0].arguments["token"], "[redacted]");
+        assert_eq!(trace[0].arguments["data"], "note");
+        assert!(trace[0].success && trace[1].success);
+        assert!(trace[1].result_bytes > 0);
+        assert!(!trace[2].success);
+        assert!(trace[2].error.as_deref().unwrap().contains("confirm: true"));
+        for (record, next) in trace.iter().zip(trace.iter().skip(1)) {
+            assert!(record.finished_at >= record.started_at);
+            assert!(next.started_at >= record.finished_at);
+            assert!(
+                (record.finished_at - record.started_at).num_milliseconds()
+                    >= record.duration_ms as i64 - 1
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsafe_category_names_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let category = "team: backend?";
+
+        router.remember(category, "note", &[], false).unwrap();
+        router.set_singleton(category, true, false).unwrap();
+        let local_dir = temp_dir.path().join("local");
+        assert!(local_dir.join("team%3A backend%3F.txt").exists());
+        assert!(local_dir.join("team%3A backend%3F.meta.json").exists());
+        assert!(router.category_meta(category, false).singleton);
+
+        assert_eq!(router.list_categories(false).unwrap(), vec![category]);
+        assert_eq!(
+            router.retrieve_all(false).unwrap()[category]["untagged"],
+            vec!["note"]
+        );
+
+        let indexed = indexed_router(temp_dir.path());
+        assert_eq!(indexed.list_categories(false).unwrap(), vec![category]);
+        assert_eq!(indexed.category_stats(false).unwrap()[category].count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_entry_expires_between_store_and_retrieve() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let (tx, _rx) = mpsc::channel(1);
+
+        for (data, ttl) in [("sprint goal", json!(0)), ("long lived", json!(3600))] {
+            router
+                .call_tool(
+                    "ultrathink_remember",
+                    json!({"category": "sprint", "data": data, "is_global": false, "ttl_secs": ttl}),
+                    tx.clone(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Hidden from retrieval before any cleanup has run
+        let memories = router.retrieve("sprint", false).unwrap();
+        assert_eq!(memories["untagged"], vec!["long lived"]);
+        assert_eq!(
+            router.read_stored_entries("sprint", false).unwrap().len(),
+            2
+        );
+
+        router
+            .call_tool("ultrathink_cleanup", json!({"is_global": false}), tx)
+            .await
+            .unwrap();
+        let stored = router.read_stored_entries("sprint", false).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].data, "long lived");
+    }
+
+    #[tokio::test]
+    async fn test_ttl_expires_on_the_clock() {
+        let temp_dir = tempdir().unwrap();
+        let clock = clock_at("2025-01-06T09:00:00Z");
+        let router = test_router(temp_dir.path()).with_clock(clock.clone());
+        let (tx, _rx) = mpsc::channel(1);
+        router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "sprint", "data": "standup notes", "is_global": false, "ttl_secs": 60}),
+                tx,
+            )
+            .await
+            .unwrap();
+        let entry = &router.read_stored_entries("sprint", false).unwrap()[0];
+        assert_eq!(
+            entry.expires.unwrap().to_rfc3339(),
+            "2025-01-06T09:01:00+00:00"
+        );
+
+        clock.advance(std::time::Duration::from_secs(59));
+        assert_eq!(router.read_entries("sprint", false).unwrap().len(), 1);
+        assert_eq!(router.cleanup_expired(false).unwrap(), 0);
+
+        // Expired from the second the TTL runs out
+        clock.advance(std::time::Duration::from_secs(1));
+        assert!(router.read_entries("sprint", false).unwrap().is_empty());
+        assert_eq!(router.cleanup_expired(false).unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_tells_missing_from_empty_categories() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("dev", "uses tokio", &[], false).unwrap();
+        fs::write(router.get_memory_file("empty", false), "").unwrap();
+        let mut expired = MemoryEntry::new("gone", &[]);
+        expired.expires = Some(Utc::now() - chrono::Duration::seconds(1));
+        router.append_entry("expired", &expired, false).unwrap();
+
+        assert_eq!(router.retrieve_existing("missing", false).unwrap(), None);
+        for category in ["empty", "expired"] {
+            let memories = router.retrieve_existing(category, false).unwrap();
+            assert_eq!(memories, Some(BTreeMap::new()));
+        }
+        let dev = router.retrieve_existing("dev", false).unwrap().unwrap();
+        assert_eq!(dev["untagged"], vec!["uses tokio"]);
+
+        let (tx, _rx) = mpsc::channel(1);
+        for (category, expected) in [
+            ("missing", "No such UltraThink category: missing"),
+            ("empty", "UltraThink category is empty: empty"),
+            ("expired", "UltraThink category is empty: expired"),
+            ("dev", "uses tokio"),
+        ] {
+            let result = router
+                .call_tool(
+                    "ultrathink_retrieve",
+                    json!({"category": category, "is_global": false, "flatten": true}),
+                    tx.clone(),
+                )
+                .await
+                .unwrap();
+            let text = &result[0].as_text().unwrap().text;
+            assert!(text.contains(expected), "{}: {}", category, text);
+        }
+    }
+
+    fn indexed_router(base: &std::path::Path) -> UltraThinkRouter {
+        let config = UltraThinkConfig {
+            use_index: true,
+            ..Default::default()
+        };
+        test_router_with_config(base, config)
+    }
+
+    #[test]
+    fn test_index_tracks_mutations() {
+        let temp_dir = tempdir().unwrap();
+        let router = indexed_router(temp_dir.path());
+        let local_dir = temp_dir.path().join("local");
+
+        router.remember("dev", "first", &[], false).unwrap();
+        router.remember("dev", "second", &[], false).unwrap();
+        router.remember("ops", "deploy", &[], false).unwrap();
+        router
+            .rename_category("ops", "release", false, false)
+            .unwrap();
+
+        // Written by the mutations themselves, without any listing
+        let index = MemoryIndex::load(&local_dir).unwrap();
+        assert_eq!(
+            index.categories.keys().collect::<Vec<_>>(),
+            vec!["dev", "release"]
+        );
+        assert_eq!(index.categories["dev"].count, 2);
+        assert_eq!(
+            index.categories["dev"].byte_size,
+            fs::metadata(local_dir.join("dev.txt")).unwrap().len()
+        );
+        assert!(index.matches_dir(&local_dir, StorageFormat::Text).unwrap());
+
+        router.compact("dev", 1, false).unwrap();
+        assert_eq!(router.category_stats(false).unwrap()["dev"].count, 1);
+        assert_eq!(
+            router.list_categories(false).unwrap(),
+            vec!["dev", "release"]
+        );
+    }
+
+    #[test]
+    fn test_index_rebuilt_when_directory_changes() {
+        let temp_dir = tempdir().unwrap();
+        let router = indexed_router(temp_dir.path());
+        let local_dir = temp_dir.path().join("local");
+
+        router.remember("dev", "first", &[], false).unwrap();
+        router.remember("ops", "deploy", &[], false).unwrap();
+
+        // Categories added and removed behind the router's back
+        fs::write(local_dir.join("manual.txt"), "one\n\ntwo\n\n").unwrap();
+        fs::remove_file(local_dir.join("ops.txt")).unwrap();
+        assert!(!MemoryIndex::load(&local_dir)
+            .unwrap()
+            .matches_dir(&local_dir, StorageFormat::Text)
+            .unwrap());
+
+        assert_eq!(
+            router.list_categories(false).unwrap(),
+            vec!["dev", "manual"]
+        );
+        let index = MemoryIndex::load(&local_dir).unwrap();
+        assert!(index.matches_dir(&local_dir, StorageFormat::Text).unwrap());
+        assert_eq!(index.categories["manual"].count, 2);
+
+        // A corrupt index is replaced as well
+        fs::write(local_dir.join(index::INDEX_FILE), "{").unwrap();
+        assert_eq!(
+            router.list_categories(false).unwrap(),
+            vec!["dev", "manual"]
+        );
+        assert!(MemoryIndex::load(&local_dir).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_tag_lookup_uses_tag_index() {
+        let temp_dir = tempdir().unwrap();
+        let router = indexed_router(temp_dir.path());
+        let local_dir = temp_dir.path().join("local");
+        router
+            .remember("dev", "use tokio", &["api", "rust"], false)
+            .unwrap();
+        router.remember("dev", "untagged", &[], false).unwrap();
+        router
+            .remember("ops", "deploy docs", &["api"], false)
+            .unwrap();
+
+        let tagged = |router: &UltraThinkRouter| -> Vec<(String, String)> {
+            router
+                .retrieve_tagged("*", &[false], "api")
+                .unwrap()
+                .into_iter()
+                .map(|(category, entry)| (category, entry.data))
+                .collect()
+        };
+        assert_eq!(
+            tagged(&router),
+            vec![
+                ("dev".to_string(), "use tokio".to_string()),
+                ("ops".to_string(), "deploy docs".to_string())
+            ]
+        );
+        assert_eq!(
+            router
+                .retrieve_tagged("dev", &[false], "api")
+                .unwrap()
+                .len(),
+            1
+        );
+        let index = TagIndex::load(&local_dir).unwrap();
+        assert_eq!(index.entries("rust").len(), 1);
+
+        let id = MemoryEntry::new("use tokio", &["api", "rust"]).id();
+        assert_eq!(router.forget_entry("dev", &id, false).unwrap(), 1);
+        assert_eq!(router.forget_entry("dev", &id, false).unwrap(), 0);
+        assert_eq!(
+            tagged(&router),
+            vec![("ops".to_string(), "deploy docs".to_string())]
+        );
+        let index = TagIndex::load(&local_dir).unwrap();
+        assert!(index.entries("rust").is_empty());
+        assert!(index.matches_dir(&local_dir, StorageFormat::Text).unwrap());
+
+        // A corrupt tag index is rebuilt from the category files
+        fs::write(local_dir.join(tag_index::TAG_INDEX_FILE), "{").unwrap();
+        assert_eq!(tagged(&router).len(), 1);
+        assert!(TagIndex::load(&local_dir).is_some());
+
+        let (tx, _rx) = mpsc::channel(1);
+        let content = router
+            .call_tool(
+                "ultrathink_retrieve",
+                json!({"category": "*", "tag": "api", "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap();
+        let text = &content[0].as_text().unwrap().text;
+        assert!(text.contains("deploy docs"));
+        assert!(!text.contains("use tokio"));
+    }
+
+    #[test]
+    fn test_index_not_written_when_disabled() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        router.remember("dev", "first", &[], false).unwrap();
+        assert_eq!(router.list_categories(false).unwrap(), vec!["dev"]);
+        assert_eq!(router.category_stats(false).unwrap()["dev"].count, 1);
+        assert!(!temp_dir
+            .path()
+            .join("local")
+            .join(index::INDEX_FILE)
+            .exists());
+    }
+
+    #[test]
+    fn test_instruction_memories_are_capped() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        for i in 0..5 {
+            let mut entry = MemoryEntry::new(&format!("note {}", i), &[]);
+            entry.timestamp = Some(Utc::now() + chrono::Duration::seconds(i));
+            router.append_entry("dev", &entry, false).unwrap();
+        }
+        let mut pinned = MemoryEntry::new("old but pinned", &[]);
+        pinned.timestamp = Some(Utc::now() - chrono::Duration::days(30));
+        pinned.pinned = true;
+        router.append_entry("ops", &pinned, true).unwrap();
+
+        let config = UltraThinkConfig {
+            max_instruction_memories: 3,
+            ..Default::default()
+        };
+        let instructions = test_router_with_config(temp_dir.path(), config).instructions();
+
+        assert!(instructions.contains("- old but pinned"));
+        assert!(instructions.contains("- note 4"));
+        assert!(instructions.contains("- note 3"));
+        assert!(!instructions.contains("- note 2"));
+        assert!(instructions.contains("…and 3 more, use ultrathink_retrieve"));
+
+        let instructions = test_router(temp_dir.path()).instructions();
+        assert!(instructions.contains("- note 0"));
+        assert!(!instructions.contains("more, use ultrathink_retrieve"));
+    }
+
+    #[test]
+    fn test_multiline_memories_keep_the_list_readable() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router
+            .remember("dev", "first line\nsecond line", &[], false)
+            .unwrap();
+        router.remember("dev", "single", &[], false).unwrap();
+
+        let instructions = test_router(temp_dir.path()).instructions();
+        assert!(instructions.contains("- first line\n  second line\n- single\n"));
+
+        for (style, expected) in [
+            (
+                MultilineStyle::Block,
+                "-\n  ```\n  first line\n  second line\n  ```\n- single\n",
+            ),
+            (
+                MultilineStyle::Join,
+                "- first line / second line\n- single\n",
+            ),
+        ] {
+            let config = UltraThinkConfig {
+                multiline_style: style,
+                ..Default::default()
+            };
+            let instructions = test_router_with_config(temp_dir.path(), config).instructions();
+            assert!(instructions.contains(expected), "{:?}", style);
+        }
+    }
+
+    pub(super) fn syncing_router(base: &std::path::Path) -> UltraThinkRouter {
+        let mut router = test_router(base);
+        let client = GraphitiClient::with_endpoint(Some("http://localhost:8000".to_string()));
+        router.global_graphiti_client = client.clone();
+        router.local_graphiti_client = client;
+        router.config.sync_on_write = SyncOnWrite::HighPriorityOnly;
+        router
+    }
+
+    #[tokio::test]
+    async fn test_router_serves_local_tools_when_graphiti_fails_to_build() {
+        let temp_dir = tempdir().unwrap();
+        let mut router = test_router(temp_dir.path());
+        let failed = || {
+            GraphitiClient::try_with_endpoint(Some("bad host".to_string()))
+                .unwrap_or_else(GraphitiClient::failed)
+        };
+        router.global_graphiti_client = failed();
+        router.local_graphiti_client = failed();
+        let (tx, _rx) = mpsc::channel(1);
+
+        router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "still works", "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        let result = router
+            .call_tool(
+                "ultrathink_retrieve",
+                json!({"category": "dev", "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        assert!(result[0].as_text().unwrap().text.contains("still works"));
+
+        let result = router
+            .call_tool("ultrathink_graphiti_query", json!({"query": "dev"}), tx)
+            .await
+            .unwrap();
+        assert!(result[0]
+            .as_text()
+            .unwrap()
+            .text
+            .contains("Graphiti unavailable: Invalid Graphiti endpoint 'bad host'"));
+    }
+
+    /// A syncing router whose calls, for both scopes, go through `backend`
+    pub(super) fn backed_router(
+        base: &std::path::Path,
+        backend: Arc<dyn GraphitiBackend>,
+    ) -> UltraThinkRouter {
+        let mut router = syncing_router(base);
+        router.local_graphiti_client = router.local_graphiti_client.clone().with_backend(backend);
+        router.global_graphiti_client = router.local_graphiti_client.clone();
+        router
+    }
+
+    #[test]
+    fn test_retrieve_all_order_is_stable() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let tag_sets: [&[&str]; 4] = [&["web"], &[], &["api", "auth"], &["db"]];
+        for category in ["zeta", "alpha", "mid", "beta"] {
+            for tags in tag_sets {
+                router
+                    .remember(category, &format!("{} {:?}", category, tags), tags, false)
+                    .unwrap();
+            }
+        }
+
+        let first = serde_json::to_string(&router.retrieve_all(false).unwrap()).unwrap();
+        let second = serde_json::to_string(&router.retrieve_all(false).unwrap()).unwrap();
+        assert_eq!(first, second);
+
+        let all = router.retrieve_all(false).unwrap();
+        assert_eq!(
+            all.keys().collect::<Vec<_>>(),
+            vec!["alpha", "beta", "mid", "zeta"]
+        );
+        assert_eq!(
+            all["alpha"].keys().collect::<Vec<_>>(),
+            vec!["api auth", "db", "untagged", "web"]
+        );
+
+        assert_eq!(
+            test_router(temp_dir.path()).instructions(),
+            test_router(temp_dir.path()).instructions()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_remember_is_truncated() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            max_entry_bytes: 8,
+            ..Default::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+
+        router.remember("dev", "12345678", &[], false).unwrap();
+        router.remember("dev", "123456789", &[], false).unwrap();
+        let entries = router.read_entries("dev", false).unwrap();
+        assert_eq!(entries[0].data, "12345678");
+        assert!(!entries[0].truncated);
+        assert_eq!(
+            entries[1].data,
+            format!("12345678{}", entry::TRUNCATION_MARKER)
+        );
+        assert!(entries[1].truncated);
+
+        let (tx, _rx) = mpsc::channel(1);
+        let result = router
+            .call_tool(
+                "ultrathink_retrieve",
+                json!({"category": "*", "is_global": false, "recent": true}),
+                tx,
+            )
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.contains("\"truncated\":true"));
+    }
+
+    #[tokio::test]
+    async fn test_singleton_category_keeps_one_entry() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let (tx, _rx) = mpsc::channel(1);
+
+        for task in ["write the parser", "review the parser", "ship it"] {
+            router
+                .call_tool(
+                    "ultrathink_remember",
+                    json!({
+                        "category": "current_task", "data": task, "is_global": false,
+                        "singleton": true
+                    }),
+                    tx.clone(),
+                )
+                .await
+                .unwrap();
+        }
+        // Later stores need not repeat the flag
+        router
+            .remember("current_task", "celebrate", &[], false)
+            .unwrap();
+
+        assert!(router.category_meta("current_task", false).singleton);
+        let entries = router.read_stored_entries("current_task", false).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].data, "celebrate");
+        let memories = router.retrieve("current_task", false).unwrap();
+        assert_eq!(flatten_groups(memories), vec!["celebrate"]);
+
+        // Other categories still accumulate
+        router.remember("dev", "one", &[], false).unwrap();
+        router.remember("dev", "two", &[], false).unwrap();
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_retrieve_metadata() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            max_entry_bytes: 8,
+            ..Default::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        router.remember("dev", "short", &[], false).unwrap();
+        router.remember("dev", "123456789", &[], false).unwrap();
+        router.remember("ops", "abcdefghi", &[], false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        for (args, truncated) in [
+            (json!({"category": "dev"}), 1),
+            (json!({"category": "*"}), 2),
+            (json!({"category": "*", "recent": true}), 2),
+            (json!({"category": "*", "join": "\n"}), 2),
+        ] {
+            let (_, meta) = router
+                .call_tool_with_meta("ultrathink_retrieve", args, tx.clone())
+                .await
+                .unwrap();
+            let meta = meta.unwrap();
+            assert_eq!(meta["truncated"], json!(truncated));
+            assert_eq!(meta["scope"], json!("local"));
+        }
+
+        let (_, meta) = router
+            .call_tool_with_meta(
+                "ultrathink_retrieve",
+                json!({"category": "dev", "scope": "both"}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(meta.unwrap()["scope"], json!("both"));
+
+        // Tools without anything to report attach no metadata
+        let (_, meta) = router
+            .call_tool_with_meta(
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "more", "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap();
+        assert!(meta.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_entry_by_id() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let (tx, _rx) = mpsc::channel(1);
+        router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "use tokio", "tags": ["rust", "async"], "context": "sprint 12", "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        router.remember("dev", "other", &[], false).unwrap();
+        let id = MemoryEntry::new("use tokio", &["async", "rust"]).id();
+
+        let (content, meta) = router
+            .call_tool_with_meta(
+                "ultrathink_get_entry",
+                json!({"category": "dev", "entry_id": id, "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(meta.unwrap()["found"], json!(true));
+        let text = content[0].as_text().unwrap().text.clone();
+        let json = text.strip_prefix("🔎 UltraThink entry: ").unwrap();
+        let entry: Value = serde_json::from_str(json).unwrap();
+        assert_eq!(entry["id"], json!(id));
+        assert_eq!(entry["data"], "use tokio");
+        assert_eq!(entry["tags"], json!(["async", "rust"]));
+        assert_eq!(entry["fields"][CONTEXT_FIELD], "sprint 12");
+        assert!(entry["timestamp"].is_string());
+
+        let (content, meta) = router
+            .call_tool_with_meta(
+                "ultrathink_get_entry",
+                json!({"category": "dev", "entry_id": "000000000000", "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap();
+        assert_eq!(meta.unwrap()["found"], json!(false));
+        assert!(content[0].as_text().unwrap().text.contains("not found"));
+        assert!(router.get_entry("absent", &id, false).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_priority_and_context_round_trip_through_text_files() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let (tx, _rx) = mpsc::channel(1);
+        router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "split the parser", "tags": ["rust"], "priority": "high", "context": "refactor; \"phase 2\"", "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap();
+
+        let content = fs::read_to_string(temp_dir.path().join("local").join("dev.txt")).unwrap();
+        assert!(content.starts_with("# "));
+        assert!(content.contains("priority: high"));
+
+        // A fresh router parses the fields back from the header line
+        let entries = test_router(temp_dir.path())
+            .read_entries("dev", false)
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].data, "split the parser");
+        assert_eq!(entries[0].tags, vec!["rust"]);
+        assert_eq!(entries[0].fields[PRIORITY_FIELD], "high");
+        assert_eq!(entries[0].fields[CONTEXT_FIELD], "refactor; \"phase 2\"");
+    }
+
+    #[tokio::test]
+    async fn test_default_priority_applies_without_one() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            default_priority: Some("low".to_string()),
+            ..Default::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        let (tx, _rx) = mpsc::channel(1);
+        for args in [
+            json!({"category": "dev", "data": "plain", "is_global": false}),
+            json!({"category": "dev", "data": "urgent", "priority": "high", "is_global": false}),
+        ] {
+            router
+                .call_tool("ultrathink_remember", args, tx.clone())
+                .await
+                .unwrap();
+        }
+
+        let entries = router.read_entries("dev", false).unwrap();
+        assert_eq!(entries[0].fields[PRIORITY_FIELD], "low");
+        assert_eq!(entries[1].fields[PRIORITY_FIELD], "high");
+    }
+
+    #[tokio::test]
+    async fn test_remember_returns_entry_id() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path()).with_formatter(Arc::new(JsonFormatter));
+        let (tx, _rx) = mpsc::channel(1);
+        let remember = |args: Value| {
+            let router = router.clone();
+            let tx = tx.clone();
+            async move {
+                let result = router
+                    .call_tool("ultrathink_remember", args, tx)
+                    .await
+                    .unwrap();
+                let text = result[0].as_text().unwrap().text.clone();
+                serde_json::from_str::<Value>(&text).unwrap()["id"].clone()
+            }
+        };
+
+        let id = remember(
+            json!({"category": "dev", "data": "use tokio", "tags": ["rust"], "is_global": false}),
+        )
+        .await;
+        let entry = router
+            .get_entry("dev", id.as_str().unwrap(), false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.data, "use tokio");
+
+        // Storing the same content again, even with other metadata, names the same entry
+        let pinned = json!({"category": "dev", "data": "use tokio", "tags": ["rust"], "pinned": true, "is_global": false});
+        assert_eq!(remember(pinned).await, id);
+        let other =
+            remember(json!({"category": "dev", "data": "use tokio", "is_global": false})).await;
+        assert_ne!(other, id);
+    }
+
+    #[tokio::test]
+    async fn test_remember_attachment_reference() {
+        let temp_dir = tempdir().unwrap();
+        let files = temp_dir.path().join("files");
+        fs::create_dir_all(&files).unwrap();
+        let shot = files.join("login.png");
+        fs::write(&shot, [0x89, b'P', b'N', b'G']).unwrap();
+        let config = UltraThinkConfig {
+            attachment_dirs: vec![files.clone()],
+            ..Default::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        let (tx, _rx) = mpsc::channel(1);
+
+        router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "ui", "data": "login screen", "attachment_path": shot, "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        let shot = fs::canonicalize(&shot).unwrap();
+        let stored = router.read_entries("ui", false).unwrap().remove(0);
+        assert_eq!(
+            stored.data,
+            format!(
+                "login screen\n[attachment: {} (image/png, 4 bytes)]",
+                shot.display()
+            )
+        );
+        assert_eq!(
+            router.retrieve("ui", false).unwrap()["untagged"],
+            vec![stored.data.clone()]
+        );
+
+        let id = stored.id();
+        let content = router
+            .call_tool(
+                "ultrathink_get_entry",
+                json!({"category": "ui", "entry_id": id, "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        let text = content[0].as_text().unwrap().text.clone();
+        let entry: Value =
+            serde_json::from_str(text.strip_prefix("🔎 UltraThink entry: ").unwrap()).unwrap();
+        assert_eq!(entry["attachment"]["path"], json!(shot));
+        assert_eq!(entry["attachment"]["mime"], "image/png");
+        assert_eq!(entry["attachment"]["size"], 4);
+        let uri = entry["attachment"]["uri"].as_str().unwrap();
+        assert_eq!(uri, format!("memory-attachment://local/ui/{}", id));
+        assert_eq!(router.read_resource(uri).await.unwrap(), "iVBORw==");
+
+        let missing = files.join("missing.png");
+        let err = router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "ui", "data": "gone", "attachment_path": missing, "is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ToolError::InvalidParameters(ref msg) if msg.contains("not found")));
+        let outside = temp_dir.path().join("secret.txt");
+        fs::write(&outside, "secret").unwrap();
+        let err = router
+            .call_tool(
+                "ultrathink_remember",
+                json!({"category": "ui", "data": "secret", "attachment_path": outside, "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ToolError::InvalidParameters(_)));
+        assert_eq!(router.read_entries("ui", false).unwrap().len(), 1);
+        assert!(matches!(
+            router
+                .read_resource("memory-attachment://local/ui/000000000000")
+                .await,
+            Err(ResourceError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_debug_responses_show_resolved_path() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let file = temp_dir.path().join("local").join("dev.txt");
+        let (tx, _rx) = mpsc::channel(1);
+        let remember =
+            json!({"category": "dev", "data": "use tokio", "is_global": false, "debug": true});
+
+        let (content, meta) = router
+            .call_tool_with_meta("ultrathink_remember", remember.clone(), tx.clone())
+            .await
+            .unwrap();
+        let text = &content[0].as_text().unwrap().text;
+        assert!(text.ends_with(&format!(
+            "\n🐞 Debug: scope: local; path: {}; dir created: true",
+            file.display()
+        )));
+        assert_eq!(
+            meta.unwrap()["debug"],
+            json!([{"scope": "local", "path": file, "dir_created": true}])
+        );
+
+        let content = router
+            .call_tool("ultrathink_remember", remember, tx.clone())
+            .await
+            .unwrap();
+        assert!(content[0]
+            .as_text()
+            .unwrap()
+            .text
+            .contains("dir created: false"));
+
+        let content = router
+            .call_tool(
+                "ultrathink_retrieve",
+                json!({"category": "dev", "scope": "both", "debug": true}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        let text = &content[0].as_text().unwrap().text;
+        assert!(text.contains(&format!("scope: local; path: {};", file.display())));
+        let global_file = temp_dir.path().join("global").join("dev.txt");
+        assert!(text.contains(&format!("scope: global; path: {};", global_file.display())));
+
+        let content = router
+            .call_tool(
+                "ultrathink_retrieve",
+                json!({"category": "dev", "is_global": false}),
+                tx,
+            )
+            .await
+            .unwrap();
+        assert!(!content[0].as_text().unwrap().text.contains("Debug"));
+    }
+
+    #[tokio::test]
+    async fn test_strict_sequence_rejects_forward_skips() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let (tx, _rx) = mpsc::channel(1);
+        let think = |stage: &str| {
+            router.call_tool(
+                "ultrathink_sequence",
+                json!({"thought": "step", "stage": stage, "strict_order": true}),
+                tx.clone(),
+            )
+        };
+
+        // A valid progression, including repeating a stage
+        for stage in ["Problem Definition", "Research", "Research", "Analysis"] {
+            think(stage).await.unwrap();
+        }
+        // Backtracking is allowed, and does not lose the progress made
+        think("Problem Definition").await.unwrap();
+        think("Synthesis").await.unwrap();
+        think("Conclusion").await.unwrap();
+
+        // Starting over after the Conclusion begins a new sequence
+        think("Problem Definition").await.unwrap();
+        let err = think("Analysis").await.unwrap_err();
+        assert!(matches!(
+            err,
+            ToolError::InvalidParameters(ref msg)
+                if msg.contains("'Analysis' skips ahead; expected 'Research' or an earlier stage")
+        ));
+        // The rejected stage was not recorded
+        think("Research").await.unwrap();
+        assert!(think("Brainstorm").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mistyped_arguments_are_rejected() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let (tx, _rx) = mpsc::channel(1);
+        let cases = [
+            (
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "x", "is_global": "yes"}),
+                "is_global must be a boolean, got \"yes\"",
+            ),
+            (
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "x", "tags": "api", "pinned": 1}),
+                "pinned must be a boolean, got 1",
+            ),
+            (
+                "ultrathink_retrieve",
+                json!({"category": "dev", "limit": "ten"}),
+                "limit must be a non-negative integer, got \"ten\"",
+            ),
+            (
+                "ultrathink_retrieve",
+                json!({"category": "dev", "recent": "true"}),
+                "recent must be a boolean",
+            ),
+            (
+                "ultrathink_retrieve",
+                json!({"category": "dev", "debug": true, "recent": "true"}),
+                "recent must be a boolean",
+            ),
+            (
+                "ultrathink_retrieve",
+                json!({"category": "dev", "debug": "yes"}),
+                "debug must be a boolean",
+            ),
+            (
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "x", "debug": 1}),
+                "debug must be a boolean, got 1",
+            ),
+            (
+                "ultrathink_retrieve",
+                json!({"category": "dev", "expand_graph": "yes"}),
+                "expand_graph must be a boolean",
+            ),
+            (
+                "ultrathink_retrieve",
+                json!({"category": "dev", "highlight": true, "query": 3}),
+                "query must be a string, got 3",
+            ),
+            (
+                "ultrathink_retrieve",
+                json!({"category": 7}),
+                "category must be a string, got 7",
+            ),
+            (
+                "ultrathink_sequence",
+                json!({"thought": ["step"], "stage": "Research"}),
+                "thought must be a string",
+            ),
+            (
+                "ultrathink_sequence",
+                json!({"thought": "step"}),
+                "stage is required and must be a string",
+            ),
+            (
+                "ultrathink_sequence",
+                json!({"thought": "step", "stage": "Research", "save_to_memory": "no"}),
+                "save_to_memory must be a boolean",
+            ),
+            (
+                "ultrathink_graphiti_sync",
+                json!({"direction": 1}),
+                "direction must be a string, got 1",
+            ),
+            (
+                "ultrathink_graphiti_sync",
+                json!({"category": false}),
+                "category must be a string, got false",
+            ),
+            (
+                "ultrathink_graphiti_query",
+                json!({"query": "dev", "fresh": "yes"}),
+                "fresh must be a boolean",
+            ),
+            (
+                "ultrathink_graphiti_query",
+                json!({"query": "dev", "limit": -1}),
+                "limit must be a non-negative integer",
+            ),
+            (
+                "ultrathink_verify",
+                json!({"is_global": "yes"}),
+                "is_global must be a boolean",
+            ),
+            (
+                "ultrathink_cleanup",
+                json!({"is_global": 1}),
+                "is_global must be a boolean, got 1",
+            ),
+            (
+                "ultrathink_link",
+                json!({"from": "a", "to": "b", "relation": "uses", "is_global": "yes"}),
+                "is_global must be a boolean",
+            ),
+            (
+                "ultrathink_rename_category",
+                json!({"from": "dev", "to": "ops", "is_global": "yes"}),
+                "is_global must be a boolean",
+            ),
+            (
+                "ultrathink_rename_category",
+                json!({"from": "dev", "to": "ops", "merge": "true"}),
+                "merge must be a boolean",
+            ),
+        ];
+
+        for (tool, arguments, message) in cases {
+            let err = router
+                .call_tool(tool, arguments.clone(), tx.clone())
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(err, ToolError::InvalidParameters(ref msg) if msg.contains(message)),
+                "{} {}: {:?}",
+                tool,
+                arguments,
+                err
+            );
+        }
+        assert!(!temp_dir.path().join("local").join("dev.txt").exists());
+    }
+
+    #[test]
+    fn test_blank_tags_are_dropped() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router
+            .remember("dev", "no tags", &["", "  "], false)
+            .unwrap();
+        router
+            .remember("dev", "one tag", &[" api ", "", "api"], false)
+            .unwrap();
+
+        let content = fs::read_to_string(temp_dir.path().join("local").join("dev.txt")).unwrap();
+        let headers: Vec<&str> = content
+            .lines()
+            .filter(|line| line.starts_with('#'))
+            .collect();
+        assert!(!headers[0].contains("tags"));
+        assert!(headers[1].starts_with("# tags: api;"));
+
+        let memories = router.retrieve("dev", false).unwrap();
+        assert_eq!(memories.keys().collect::<Vec<_>>(), vec!["api", "untagged"]);
+        assert_eq!(memories["untagged"], vec!["no tags"]);
+    }
+
+    #[test]
+    fn test_category_case_normalization() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            normalize_category_case: CategoryCase::Lower,
+            use_index: true,
+            ..Default::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        router
+            .remember("Development", "first", &["api"], false)
+            .unwrap();
+        router
+            .remember("development", "second", &[], false)
+            .unwrap();
+
+        // Listing the dir shows the file name whatever the file system's case handling
+        assert_eq!(router.list_categories(false).unwrap(), vec!["development"]);
+        for category in ["development", "DEVELOPMENT"] {
+            assert_eq!(router.read_entries(category, false).unwrap().len(), 2);
+        }
+        assert_eq!(
+            router
+                .retrieve_tagged("Development", &[false], "api")
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            router.category_stats(false).unwrap()["development"].count,
+            2
+        );
+    }
+
+    #[test]
+    fn test_describe_single_tool() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        let tool = router.describe_tool("ultrathink_remember").unwrap();
+        assert_eq!(tool.name, "ultrathink_remember");
+        assert_eq!(
+            tool.input_schema["required"],
+            json!(["category", "data", "is_global"])
+        );
+        assert!(tool.annotations.is_some());
+        assert!(router.describe_tool("ultrathink_unknown").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_parallel_retrieve_all_matches_sequential() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        // Too few categories to read in parallel
+        router
+            .remember("dev", "uses tokio", &["api"], false)
+            .unwrap();
+        assert_eq!(
+            router.retrieve_all_parallel(false).await.unwrap(),
+            router.retrieve_all(false).unwrap()
+        );
+
+        for n in 0..PARALLEL_READ_THRESHOLD * 2 {
+            let category = format!("category_{:02}", n);
+            router.remember(&category, "first", &[], false).unwrap();
+            router.remember(&category, "tagged", &["t"], false).unwrap();
+        }
+        let parallel = router.retrieve_all_parallel(false).await.unwrap();
+        assert_eq!(parallel.len(), PARALLEL_READ_THRESHOLD * 2 + 1);
+        assert_eq!(parallel, router.retrieve_all(false).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_parallel_retrieve_all_overlaps_slow_reads() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let categories = PARALLEL_READ_THRESHOLD * 2;
+        for n in 0..categories {
+            router
+                .remember(&format!("category_{:02}", n), "slow", &[], false)
+                .unwrap();
+        }
+
+        let delay = Duration::from_millis(25);
+        let started = Instant::now();
+        let all = router
+            .retrieve_all_with(false, move |router, category, is_global| {
+                std::thread::sleep(delay);
+                router.retrieve(category, is_global)
+            })
+            .await
+            .unwrap();
+        assert_eq!(all.len(), categories);
+        // Read one at a time, the delays alone would add up to the full total
+        assert!(started.elapsed() < delay * categories as u32 / 2);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_by_date_range() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        for (category, data, day, is_global) in [
+            ("dev", "monday", "2025-01-06", false),
+            ("dev", "wednesday", "2025-01-08", false),
+            ("ops", "thursday", "2025-01-09", false),
+            ("dev", "global tuesday", "2025-01-07", true),
+        ] {
+            let mut entry = MemoryEntry::new(data, &[]);
+            entry.timestamp = Some(format!("{}T09:00:00Z", day).parse().unwrap());
+            router.append_entry(category, &entry, is_global).unwrap();
+        }
+        router
+            .append_entry("dev", &MemoryEntry::new("undated", &[]), false)
+            .unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let retrieve = |args: Value| {
+            let router = router.clone();
+            let tx = tx.clone();
+            async move {
+                let result = router
+                    .call_tool("ultrathink_retrieve", args, tx)
+                    .await
+                    .unwrap();
+                let text = result[0].as_text().unwrap().text.clone();
+                let json = &text[text.find('[').unwrap()..];
+                serde_json::from_str::<Vec<Value>>(json)
+                    .unwrap()
+                    .iter()
+                    .map(|entry| entry["data"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let data = retrieve(json!({
+            "category": "*", "is_global": false,
+            "after": "2025-01-06", "before": "2025-01-08"
+        }))
+        .await;
+        assert_eq!(data, vec!["monday", "wednesday"]);
+
+        let data = retrieve(json!({
+            "category": "dev", "is_global": false, "scope": "both",
+            "after": "2025-01-07", "include_undated": true
+        }))
+        .await;
+        assert_eq!(data, vec!["global tuesday", "wednesday", "undated"]);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_joined() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path()).with_formatter(Arc::new(JsonFormatter));
+        router.remember("dev", "first", &[], false).unwrap();
+        router.remember("dev", "second", &[], false).unwrap();
+        router.remember("ops", "deploy", &[], false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let retrieve = |args: Value| {
+            let router = router.clone();
+            let tx = tx.clone();
+            async move {
+                let result = router
+                    .call_tool("ultrathink_retrieve", args, tx)
+                    .await
+                    .unwrap();
+                let text = result[0].as_text().unwrap().text.clone();
+                serde_json::from_str::<Value>(&text).unwrap()["memories"].clone()
+            }
+        };
+
+        let memories = retrieve(json!({"category": "dev", "is_global": false, "join": "\n"})).await;
+        assert_eq!(memories, json!({"dev": "first\nsecond"}));
+
+        let memories = retrieve(json!({"category": "*", "is_global": false, "join": " | "})).await;
+        assert_eq!(memories, json!({"dev": "first | second", "ops": "deploy"}));
+
+        // Without join the entries stay grouped
+        let memories = retrieve(json!({"category": "dev", "is_global": false})).await;
+        assert_eq!(memories, json!({"untagged": ["first", "second"]}));
+    }
+
+    #[test]
+    fn test_memories_not_preloaded_when_disabled() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            preload_memories: false,
+            ..Default::default()
+        };
+        let empty_len = test_router_with_config(temp_dir.path(), config.clone()).instructions_len();
+
+        let router = test_router(temp_dir.path());
+        for i in 0..20 {
+            router
+                .remember("dev", &format!("note {}", i), &[], i % 2 == 0)
+                .unwrap();
+        }
+        assert!(test_router(temp_dir.path()).instructions_len() > empty_len);
+
+        let lazy = test_router_with_config(temp_dir.path(), config);
+        assert_eq!(lazy.instructions_len(), empty_len);
+        assert!(!lazy.instructions().contains("Current UltraThink Memories"));
+        assert_eq!(lazy.read_entries("dev", false).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_large_categories_are_read_from_their_end_at_startup() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("small", "kept whole", &[], false).unwrap();
+        // A head that cannot even be read as text fails any read of the whole file
+        let mut content = vec![0xFF; INSTRUCTION_TAIL_BYTES as usize];
+        for i in 0..30 {
+            content.extend_from_slice(format!("\n\nnote {:02}", i).as_bytes());
+        }
+        fs::write(router.get_memory_file("big", false), content).unwrap();
+
+        let instructions = test_router(temp_dir.path()).instructions();
+        assert!(instructions.contains("- kept whole"));
+        assert!(instructions.contains("- note 29"));
+        assert!(instructions.contains("- note 10"));
+        assert!(!instructions.contains("- note 09"));
+        assert!(instructions.contains("…older memories of big were not loaded"));
+
+        let config = UltraThinkConfig {
+            instruction_entries_per_category: 0,
+            ..UltraThinkConfig::default()
+        };
+        let instructions = test_router_with_config(temp_dir.path(), config).instructions();
+        assert!(instructions.contains("- kept whole"));
+        assert!(!instructions.contains("- note 29"));
+        assert!(!instructions.contains("older memories"));
+    }
+
+    #[test]
+    fn test_instruction_appendix_trimmed_to_limit() {
+        let temp_dir = tempdir().unwrap();
+        let base_len = test_router(temp_dir.path()).instructions_len();
+        for i in 0..20 {
+            test_router(temp_dir.path())
+                .remember("dev", &format!("note {}", i), &[], false)
+                .unwrap();
+        }
+
+        let max = base_len + APPENDIX_TRIMMED_NOTE.len() + 60;
+        let config = UltraThinkConfig {
+            max_instruction_bytes: max,
+            ..UltraThinkConfig::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        let instructions = router.instructions();
+        assert!(router.instructions_len() <= max);
+        assert!(instructions.ends_with(APPENDIX_TRIMMED_NOTE));
+        assert!(instructions.contains("- note 0\n"));
+        assert!(!instructions.contains("- note 19"));
+
+        // The core instructions survive even a limit they exceed on their own
+        let config = UltraThinkConfig {
+            max_instruction_bytes: 1,
+            ..UltraThinkConfig::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        assert_eq!(
+            router.instructions_len(),
+            base_len + APPENDIX_TRIMMED_NOTE.len()
+        );
+        assert!(!router.instructions().contains("- note 0"));
+    }
+
+    #[tokio::test]
+    async fn test_markdown_storage() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            storage_format: StorageFormat::Markdown,
+            ..UltraThinkConfig::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        let (tx, _rx) = mpsc::channel(1);
+        router
+            .call_tool(
+                "ultrathink_remember",
+                json!({
+                    "category": "dev", "data": "Use **cargo nextest**\n\nfor the slow suites",
+                    "tags": ["testing"], "is_global": false, "priority": "low",
+                    "context": "CI setup"
+                }),
+                tx,
+            )
+            .await
+            .unwrap();
+        router.remember("dev", "second", &[], false).unwrap();
+
+        let local_dir = temp_dir.path().join("local");
+        assert!(!local_dir.join("dev.txt").exists());
+        let content = fs::read_to_string(local_dir.join("dev.md")).unwrap();
+        assert!(content.starts_with("---\ntags:\n- testing\n"));
+        assert!(content.contains("\npriority: low\n"));
+
+        let entries = router.read_entries("dev", false).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].data,
+            "Use **cargo nextest**\n\nfor the slow suites"
+        );
+        assert_eq!(entries[0].fields[CONTEXT_FIELD], "CI setup");
+        assert!(entries[0].timestamp.is_some());
+        assert_eq!(router.list_categories(false).unwrap(), vec!["dev"]);
+    }
+
+    #[tokio::test]
+    async fn test_link_and_retrieve_with_links() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router
+            .remember("decisions", "Use Postgres", &[], false)
+            .unwrap();
+        router
+            .remember("rationale", "Needs JSONB", &[], false)
+            .unwrap();
+        router.remember("ops", "unrelated", &[], false).unwrap();
+        let decision = router.read_entries("decisions", false).unwrap()[0].id();
+        let reason = router.read_entries("rationale", false).unwrap()[0].id();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let args =
+            json!({"from": decision, "to": reason, "relation": "rationale", "is_global": false});
+        let result = router
+            .call_tool("ultrathink_link", args.clone(), tx.clone())
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.contains("linked"));
+        assert!(!text.contains("Graphiti"));
+        let result = router
+            .call_tool("ultrathink_link", args, tx.clone())
+            .await
+            .unwrap();
+        assert!(result[0].as_text().unwrap().text.contains("already exists"));
+
+        let retrieve = |category: &str, include_links: bool| {
+            let router = router.clone();
+            let tx = tx.clone();
+            let args =
+                json!({"category": category, "is_global": false, "include_links": include_links});
+            async move {
+                let result = router
+                    .call_tool("ultrathink_retrieve", args, tx)
+                    .await
+                    .unwrap();
+                let text = result[0].as_text().unwrap().text.clone();
+                let json = text
+                    .strip_prefix("🧠 UltraThink memories retrieved: ")
+                    .unwrap();
+                serde_json::from_str::<Value>(json).unwrap()
+            }
+        };
+
+        let retrieved = retrieve("decisions", true).await;
+        assert_eq!(retrieved["memories"]["untagged"], json!(["Use Postgres"]));
+        let links = retrieved["links"].as_array().unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0]["relation"], "rationale");
+        assert_eq!(links[0]["from"]["data"], "Use Postgres");
+        assert_eq!(links[0]["to"]["category"], "rationale");
+        assert_eq!(links[0]["to"]["data"], "Needs JSONB");
+
+        assert_eq!(
+            retrieve("*", true).await["links"].as_array().unwrap().len(),
+            1
+        );
+        assert!(retrieve("ops", true).await["links"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+        assert!(retrieve("decisions", false).await.get("links").is_none());
+    }
+
+    /// Answers every call with a fixed graph
+    struct GraphBackend(Value);
+
+    #[async_trait]
+    impl GraphitiBackend for GraphBackend {
+        async fn call(
+            &self,
+            _server: &str,
+            _method: &str,
+            _params: Value,
+        ) -> Result<Value, GraphitiError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_expands_graph_neighbors() {
+        let temp_dir = tempdir().unwrap();
+        let unconfigured = test_router(temp_dir.path());
+        unconfigured
+            .remember("decisions", "Use Postgres", &[], false)
+            .unwrap();
+        unconfigured
+            .remember("decisions", "Deploy weekly", &[], false)
+            .unwrap();
+        unconfigured
+            .remember("rationale", "Needs JSONB", &[], false)
+            .unwrap();
+        let decision = MemoryEntry::new("Use Postgres", &[]).id();
+        let reason = MemoryEntry::new("Needs JSONB", &[]).id();
+        unconfigured
+            .link(&decision, &reason, "rationale", false)
+            .unwrap();
+
+        let mut relations =
+            vec![json!({"from": "other", "to": "policy_1", "relationType": "cites"})];
+        for n in 0..MAX_GRAPH_NEIGHBORS + 2 {
+            relations.push(
+                json!({"from": decision, "to": format!("policy_{}", n), "relationType": "follows"}),
+            );
+        }
+        let graph = json!({
+            "entities": [{"name": "policy_1", "entityType": "POLICY", "observations": ["Prefer managed databases"]}],
+            "relations": relations,
+        });
+        let router = backed_router(temp_dir.path(), Arc::new(GraphBackend(graph)));
+        let (tx, _rx) = mpsc::channel(1);
+        let args = json!({"category": "decisions", "is_global": false, "expand_graph": true});
+
+        let (content, meta) = router
+            .call_tool_with_meta("ultrathink_retrieve", args.clone(), tx.clone())
+            .await
+            .unwrap();
+        let text = &content[0].as_text().unwrap().text;
+        assert!(text.contains("Deploy weekly"));
+        assert!(text.contains("Related in Graphiti"));
+        let meta = meta.unwrap();
+        let related = &meta["graph_related"];
+        // Only the linked entry is expanded, with a bounded number of neighbors
+        assert_eq!(related.as_array().unwrap().len(), 1);
+        assert_eq!(related[0]["entry"]["id"], json!(decision));
+        let listed = related[0]["related"].as_array().unwrap();
+        assert_eq!(listed.len(), MAX_GRAPH_NEIGHBORS);
+        assert_eq!(listed[1]["name"], "policy_1");
+        assert_eq!(listed[1]["relation"], "follows");
+        assert_eq!(
+            listed[1]["observations"],
+            json!(["Prefer managed databases"])
+        );
+        assert_eq!(listed[0]["observations"], json!([]));
+
+        // Without Graphiti the retrieve is left as it is
+        let result = unconfigured
+            .call_tool("ultrathink_retrieve", args, tx)
+            .await
+            .unwrap();
+        let text = &result[0].as_text().unwrap().text;
+        assert!(text.contains("Use Postgres"));
+        assert!(!text.contains("Related in Graphiti"));
+    }
+}
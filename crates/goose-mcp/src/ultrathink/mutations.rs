@@ -0,0 +1,1449 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::category_meta::CategoryMeta;
+use super::entry::MemoryEntry;
+use super::links::{MemoryLink, MemoryLinks, LINKS_LOCK};
+use super::lock::CategoryLock;
+use super::{index, integrity, suggest, validate_category, MemoryEventKind, UltraThinkRouter};
+
+/// Directory of a memory dir holding the backups taken by `ultrathink_clear`
+const BACKUP_DIR: &str = "backups";
+
+/// Directory of a memory dir holding the snapshots taken by `ultrathink_snapshot`
+const SNAPSHOT_DIR: &str = "snapshots";
+
+impl UltraThinkRouter {
+    pub fn remember(
+        &self,
+        category: &str,
+        data: &str,
+        tags: &[&str],
+        is_global: bool,
+    ) -> io::Result<()> {
+        self.append_entry(category, &self.new_entry(data, tags), is_global)
+    }
+
+    pub(super) fn append_entry(
+        &self,
+        category: &str,
+        entry: &MemoryEntry,
+        is_global: bool,
+    ) -> io::Result<()> {
+        self.ensure_writable(is_global)?;
+        let memory_file_path = self.get_memory_file(category, is_global);
+        let _lock = self.lock_category(category, is_global)?;
+        if !memory_file_path.exists() {
+            self.ensure_category_room(category, is_global)?;
+        }
+
+        if self.category_meta(category, is_global).singleton {
+            let replaced = self.read_stored_entries(category, is_global)?;
+            self.write_entries(category, std::slice::from_ref(entry), is_global)?;
+            for old in &replaced {
+                self.emit(
+                    MemoryEventKind::Deleted,
+                    category,
+                    Some(old.id()),
+                    is_global,
+                );
+            }
+        } else {
+            self.quarantine_corrupted(category, is_global)?;
+            let mut file = fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&memory_file_path)?;
+            writeln!(file, "{}\n", self.config.storage_format.render_entry(entry))?;
+            self.update_checksum(category, is_global)?;
+            self.update_index(&[category], is_global);
+        }
+
+        self.emit(
+            MemoryEventKind::Created,
+            category,
+            Some(entry.id()),
+            is_global,
+        );
+        Ok(())
+    }
+
+    /// Fail with `PermissionDenied` when `is_global` names the global scope and
+    /// [`UltraThinkConfig::read_only_global`](super::UltraThinkConfig::read_only_global) is set. Every operation that writes to a
+    /// memory dir checks this before touching it.
+    fn ensure_writable(&self, is_global: bool) -> io::Result<()> {
+        if is_global && self.config.read_only_global {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Global memories are read-only in this deployment; store the memory locally with is_global: false",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fail with `InvalidInput` when a scope already holds
+    /// [`UltraThinkConfig::max_categories`](super::UltraThinkConfig::max_categories) categories, suggesting the existing ones
+    /// closest to the `category` that would have been created
+    fn ensure_category_room(&self, category: &str, is_global: bool) -> io::Result<()> {
+        let max = self.config.max_categories;
+        if max == 0 {
+            return Ok(());
+        }
+        let categories = self.list_categories(is_global)?;
+        if categories.len() < max {
+            return Ok(());
+        }
+        let scope = if is_global { "global" } else { "local" };
+        let suggestions = suggest::closest(category, &categories, suggest::MAX_SUGGESTIONS);
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The {} scope already holds the maximum of {} categories; store '{}' in an existing category instead, such as: {}",
+                scope,
+                max,
+                category,
+                suggestions.join(", ")
+            ),
+        ))
+    }
+
+    /// Hold the cross-process lock of a category for a read-modify-write. Locks are not
+    /// reentrant, so take each one once at the top of a public operation.
+    pub(super) fn lock_category(
+        &self,
+        category: &str,
+        is_global: bool,
+    ) -> io::Result<CategoryLock> {
+        CategoryLock::acquire(
+            self.memory_dir(is_global),
+            &self.stored_category(category),
+            self.config.lock_timeout,
+        )
+    }
+
+    /// Replace the contents of a category; the file is swapped in atomically so a crash
+    /// never leaves a half-written category behind. Callers hold the category lock.
+    fn write_entries(
+        &self,
+        category: &str,
+        entries: &[MemoryEntry],
+        is_global: bool,
+    ) -> io::Result<()> {
+        self.ensure_writable(is_global)?;
+        let format = self.config.storage_format;
+        let memory_file_path = self.get_memory_file(category, is_global);
+        let content: String = entries
+            .iter()
+            .map(|entry| format!("{}\n\n", format.render_entry(entry)))
+            .collect();
+
+        self.quarantine_corrupted(category, is_global)?;
+        let tmp_path = memory_file_path.with_extension(format!("{}.tmp", format.extension()));
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, &memory_file_path)?;
+        self.update_checksum(category, is_global)?;
+
+        self.update_index(&[category], is_global);
+        Ok(())
+    }
+
+    /// Move a corrupted category file aside before a write, so its content is neither
+    /// overwritten nor checksummed as valid. Callers hold the category lock.
+    fn quarantine_corrupted(&self, category: &str, is_global: bool) -> io::Result<()> {
+        if !self.is_intact(category, is_global)? {
+            let moved = integrity::quarantine(
+                &self.get_memory_file(category, is_global),
+                self.clock.utc_now(),
+            )?;
+            tracing::warn!(
+                "Moved corrupted UltraThink memory file to {}",
+                moved.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Record the checksum of a category file after it changed, or drop it when the file
+    /// was removed. Callers hold the category lock.
+    fn update_checksum(&self, category: &str, is_global: bool) -> io::Result<()> {
+        if self.config.use_checksums {
+            integrity::update(&self.get_memory_file(category, is_global))?;
+        }
+        Ok(())
+    }
+
+    /// Pin or unpin every entry of a category with the given id, returning how many changed.
+    /// Pinned entries are never removed by compaction or expiry.
+    pub fn set_pinned(
+        &self,
+        category: &str,
+        id: &str,
+        pinned: bool,
+        is_global: bool,
+    ) -> io::Result<usize> {
+        let _lock = self.lock_category(category, is_global)?;
+        let mut entries = self.read_stored_entries(category, is_global)?;
+        let mut matched = 0;
+        let mut changed = 0;
+        for entry in entries.iter_mut().filter(|entry| entry.id() == id) {
+            matched += 1;
+            if entry.pinned != pinned {
+                entry.pinned = pinned;
+                changed += 1;
+            }
+        }
+
+        if matched == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No entry with id '{}' in category '{}'", id, category),
+            ));
+        }
+        if changed > 0 {
+            self.write_entries(category, &entries, is_global)?;
+            self.emit(
+                MemoryEventKind::Updated,
+                category,
+                Some(id.to_string()),
+                is_global,
+            );
+        }
+        Ok(changed)
+    }
+
+    /// Delete every entry of a category with the given id, returning how many were removed
+    pub fn forget_entry(&self, category: &str, id: &str, is_global: bool) -> io::Result<usize> {
+        self.ensure_writable(is_global)?;
+        let _lock = self.lock_category(category, is_global)?;
+        let (removed, kept): (Vec<MemoryEntry>, Vec<MemoryEntry>) = self
+            .read_stored_entries(category, is_global)?
+            .into_iter()
+            .partition(|entry| entry.id() == id);
+        if removed.is_empty() {
+            return Ok(0);
+        }
+
+        self.write_entries(category, &kept, is_global)?;
+        for entry in &removed {
+            self.emit(
+                MemoryEventKind::Deleted,
+                category,
+                Some(entry.id()),
+                is_global,
+            );
+        }
+        Ok(removed.len())
+    }
+
+    /// Delete the entries of a category whose data contains `matching`, ignoring case, and
+    /// that carry every one of `tags`, returning how many were removed. Without either
+    /// filter the whole category is deleted, its metadata included. A category that does
+    /// not exist is a `NotFound` error.
+    pub fn forget(
+        &self,
+        category: &str,
+        matching: Option<&str>,
+        tags: &[&str],
+        is_global: bool,
+    ) -> io::Result<usize> {
+        self.ensure_writable(is_global)?;
+        let _lock = self.lock_category(category, is_global)?;
+        let memory_file = self.get_memory_file(category, is_global);
+        if !memory_file.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Category '{}' does not exist", category),
+            ));
+        }
+
+        let entries = self.read_stored_entries(category, is_global)?;
+        let removed = if matching.is_none() && tags.is_empty() {
+            fs::remove_file(&memory_file)?;
+            self.update_checksum(category, is_global)?;
+            let meta = self.get_meta_file(category, is_global);
+            if meta.exists() {
+                fs::remove_file(&meta)?;
+            }
+            self.update_index(&[category], is_global);
+            entries
+        } else {
+            let matching = matching.map(str::to_lowercase);
+            let (removed, kept): (Vec<MemoryEntry>, Vec<MemoryEntry>) =
+                entries.into_iter().partition(|entry| {
+                    matching
+                        .as_ref()
+                        .is_none_or(|matching| entry.data.to_lowercase().contains(matching))
+                        && tags
+                            .iter()
+                            .all(|tag| entry.tags.iter().any(|tagged| tagged == tag.trim()))
+                });
+            if !removed.is_empty() {
+                self.write_entries(category, &kept, is_global)?;
+            }
+            removed
+        };
+
+        for entry in &removed {
+            self.emit(
+                MemoryEventKind::Deleted,
+                category,
+                Some(entry.id()),
+                is_global,
+            );
+        }
+        Ok(removed.len())
+    }
+
+    /// Link two live entries of a scope with a relation label, returning false if the
+    /// same link already exists. Links live in the scope's `links.json`.
+    pub fn link(&self, from: &str, to: &str, relation: &str, is_global: bool) -> io::Result<bool> {
+        self.ensure_writable(is_global)?;
+        let relation = relation.trim();
+        if relation.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "relation must not be empty",
+            ));
+        }
+        if from == to {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "An entry cannot be linked to itself",
+            ));
+        }
+        for id in [from, to] {
+            if self.find_entry(id, is_global)?.is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No entry with id '{}'", id),
+                ));
+            }
+        }
+
+        let dir = self.memory_dir(is_global);
+        let _lock = CategoryLock::acquire(dir, LINKS_LOCK, self.config.lock_timeout)?;
+        let mut links = MemoryLinks::load(dir);
+        let added = links.add(MemoryLink {
+            from: from.to_string(),
+            to: to.to_string(),
+            relation: relation.to_string(),
+            created_at: self.clock.utc_now(),
+        });
+        if added {
+            links.save(dir)?;
+        }
+        Ok(added)
+    }
+
+    /// Keep the newest `keep` unpinned entries of a category, plus every pinned entry,
+    /// and return how many entries were removed
+    pub fn compact(&self, category: &str, keep: usize, is_global: bool) -> io::Result<usize> {
+        let _lock = self.lock_category(category, is_global)?;
+        let entries = self.read_stored_entries(category, is_global)?;
+        let unpinned = entries.iter().filter(|entry| !entry.pinned).count();
+        let mut to_remove = unpinned.saturating_sub(keep);
+        if to_remove == 0 {
+            return Ok(0);
+        }
+
+        // Entries are appended, so the oldest come first in the file
+        let (kept, removed): (Vec<MemoryEntry>, Vec<MemoryEntry>) =
+            entries.into_iter().partition(|entry| {
+                if entry.pinned || to_remove == 0 {
+                    true
+                } else {
+                    to_remove -= 1;
+                    false
+                }
+            });
+        self.write_entries(category, &kept, is_global)?;
+        for entry in &removed {
+            self.emit(
+                MemoryEventKind::Deleted,
+                category,
+                Some(entry.id()),
+                is_global,
+            );
+        }
+        Ok(removed.len())
+    }
+
+    /// Delete expired entries from every category in a scope, returning how many were removed
+    pub fn cleanup_expired(&self, is_global: bool) -> io::Result<usize> {
+        let now = self.clock.utc_now();
+        let mut removed = 0;
+        for category in self.list_categories(is_global)? {
+            let _lock = self.lock_category(&category, is_global)?;
+            let (expired, live): (Vec<MemoryEntry>, Vec<MemoryEntry>) = self
+                .read_stored_entries(&category, is_global)?
+                .into_iter()
+                .partition(|entry| entry.is_expired(now));
+            if !expired.is_empty() {
+                removed += expired.len();
+                self.write_entries(&category, &live, is_global)?;
+                for entry in &expired {
+                    self.emit(
+                        MemoryEventKind::Deleted,
+                        &category,
+                        Some(entry.id()),
+                        is_global,
+                    );
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Copy the category files of a scope, metadata included, into a new timestamped
+    /// directory under `backups/` and return that directory
+    pub fn backup(&self, is_global: bool) -> io::Result<PathBuf> {
+        self.ensure_writable(is_global)?;
+        let stamp = self.clock.utc_now().format("%Y%m%dT%H%M%S%.3fZ");
+        let backup_dir = self
+            .memory_dir(is_global)
+            .join(BACKUP_DIR)
+            .join(stamp.to_string());
+        fs::create_dir_all(&backup_dir)?;
+        for category in self.list_categories(is_global)? {
+            let _lock = self.lock_category(&category, is_global)?;
+            let memory_file = self.get_memory_file(&category, is_global);
+            if let Some(file_name) = memory_file.file_name() {
+                fs::copy(&memory_file, backup_dir.join(file_name))?;
+            }
+            let meta = self.get_meta_file(&category, is_global);
+            let checksum = integrity::checksum_path(&memory_file);
+            for path in [meta, checksum] {
+                if path.exists() {
+                    if let Some(file_name) = path.file_name() {
+                        fs::copy(&path, backup_dir.join(file_name))?;
+                    }
+                }
+            }
+        }
+        Ok(backup_dir)
+    }
+
+    /// Delete every category of a scope, returning how many categories and entries were
+    /// removed. Backups are kept.
+    pub fn clear(&self, is_global: bool) -> io::Result<(usize, usize)> {
+        self.ensure_writable(is_global)?;
+        let categories = self.list_categories(is_global)?;
+        let mut removed_entries = 0;
+        for category in &categories {
+            let _lock = self.lock_category(category, is_global)?;
+            let removed = self.read_stored_entries(category, is_global)?;
+            fs::remove_file(self.get_memory_file(category, is_global))?;
+            self.update_checksum(category, is_global)?;
+            let meta = self.get_meta_file(category, is_global);
+            if meta.exists() {
+                fs::remove_file(&meta)?;
+            }
+
+            removed_entries += removed.len();
+            for entry in &removed {
+                self.emit(
+                    MemoryEventKind::Deleted,
+                    category,
+                    Some(entry.id()),
+                    is_global,
+                );
+            }
+        }
+
+        let names: Vec<&str> = categories.iter().map(String::as_str).collect();
+        self.update_index(&names, is_global);
+        Ok((categories.len(), removed_entries))
+    }
+
+    /// Copy every file of a scope's memory dir, from category files and their metadata to
+    /// links and sync state, into a new timestamped directory under `snapshots/`, and
+    /// return the id of the snapshot
+    pub fn snapshot(&self, is_global: bool) -> io::Result<String> {
+        self.ensure_writable(is_global)?;
+        let (_, _locks) = self.lock_scope(&[], is_global)?;
+        self.snapshot_locked(is_global)
+    }
+
+    /// Take a snapshot of a scope whose locks the caller already holds
+    fn snapshot_locked(&self, is_global: bool) -> io::Result<String> {
+        let dir = self.memory_dir(is_global);
+        let snapshots = dir.join(SNAPSHOT_DIR);
+        fs::create_dir_all(&snapshots)?;
+        let stamp = self
+            .clock
+            .utc_now()
+            .format("%Y%m%dT%H%M%S%.3fZ")
+            .to_string();
+
+        // Snapshots taken within the same millisecond get a numbered suffix
+        let mut id = stamp.clone();
+        let mut suffix = 1;
+        let snapshot_dir = loop {
+            let snapshot_dir = snapshots.join(&id);
+            match fs::create_dir(&snapshot_dir) {
+                Ok(()) => break snapshot_dir,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    id = format!("{}-{}", stamp, suffix);
+                    suffix += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        for path in scope_files(dir)? {
+            if let Some(file_name) = path.file_name() {
+                fs::copy(&path, snapshot_dir.join(file_name))?;
+            }
+        }
+        Ok(id)
+    }
+
+    /// Replace the memory dir of a scope with the snapshot `id`. With `backup` the current
+    /// state is snapshotted first and the id of that snapshot is returned.
+    pub fn restore(&self, id: &str, backup: bool, is_global: bool) -> io::Result<Option<String>> {
+        self.ensure_writable(is_global)?;
+        if id.is_empty() || id.starts_with('.') || id.contains(|c| matches!(c, '/' | '\\')) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid snapshot id '{}'", id),
+            ));
+        }
+        let dir = self.memory_dir(is_global);
+        let snapshot_dir = dir.join(SNAPSHOT_DIR).join(id);
+        if !snapshot_dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No snapshot '{}'", id),
+            ));
+        }
+
+        let restored: Vec<String> =
+            index::scan_categories(&snapshot_dir, self.config.storage_format)?
+                .into_iter()
+                .map(|(category, _)| category)
+                .collect();
+        let (categories, _locks) = self.lock_scope(&restored, is_global)?;
+        let backup_id = if backup {
+            Some(self.snapshot_locked(is_global)?)
+        } else {
+            None
+        };
+        for path in scope_files(dir)? {
+            fs::remove_file(path)?;
+        }
+        for path in scope_files(&snapshot_dir)? {
+            if let Some(file_name) = path.file_name() {
+                fs::copy(&path, dir.join(file_name))?;
+            }
+        }
+
+        let names: Vec<&str> = categories.iter().map(String::as_str).collect();
+        self.update_index(&names, is_global);
+        Ok(backup_id)
+    }
+
+    /// Lock the links, then every category of the scope along with `extra`, so nothing in
+    /// the scope changes while its files are copied as a whole. The categories are listed
+    /// only once the links lock is held, and the locked ones are returned in order.
+    fn lock_scope(
+        &self,
+        extra: &[String],
+        is_global: bool,
+    ) -> io::Result<(Vec<String>, Vec<CategoryLock>)> {
+        let dir = self.memory_dir(is_global);
+        let mut locks = vec![CategoryLock::acquire(
+            dir,
+            LINKS_LOCK,
+            self.config.lock_timeout,
+        )?];
+        let mut categories = self.list_categories(is_global)?;
+        for category in extra {
+            if !categories.contains(category) {
+                categories.push(category.clone());
+            }
+        }
+        categories.sort();
+        for category in &categories {
+            locks.push(self.lock_category(category, is_global)?);
+        }
+        Ok((categories, locks))
+    }
+
+    /// Mark a category as holding a single value that each store replaces, or go back to
+    /// accumulating entries. Existing entries are kept until the next store.
+    pub fn set_singleton(
+        &self,
+        category: &str,
+        singleton: bool,
+        is_global: bool,
+    ) -> io::Result<()> {
+        self.ensure_writable(is_global)?;
+        let _lock = self.lock_category(category, is_global)?;
+        let path = self.get_meta_file(category, is_global);
+        let mut meta = CategoryMeta::load(&path);
+        if meta.singleton != singleton {
+            meta.singleton = singleton;
+            fs::create_dir_all(self.memory_dir(is_global))?;
+            meta.save(&path)?;
+        }
+        Ok(())
+    }
+
+    pub fn rename_category(
+        &self,
+        from: &str,
+        to: &str,
+        is_global: bool,
+        merge: bool,
+    ) -> io::Result<()> {
+        validate_category(from)?;
+        validate_category(to)?;
+        self.ensure_writable(is_global)?;
+        // Names that differ only in a normalized case are stored in the same file
+        let (stored_from, stored_to) = (self.stored_category(from), self.stored_category(to));
+        if stored_from == stored_to {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Source and destination categories are the same",
+            ));
+        }
+
+        // Always lock in stored name order so two opposite renames cannot deadlock
+        let (first, second) = if stored_from < stored_to {
+            (stored_from, stored_to)
+        } else {
+            (stored_to, stored_from)
+        };
+        let _locks = (
+            self.lock_category(&first, is_global)?,
+            self.lock_category(&second, is_global)?,
+        );
+        self.quarantine_corrupted(from, is_global)?;
+        self.quarantine_corrupted(to, is_global)?;
+
+        let source = self.get_memory_file(from, is_global);
+        if !source.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Category '{}' does not exist", from),
+            ));
+        }
+
+        let destination = self.get_memory_file(to, is_global);
+        let source_meta = self.get_meta_file(from, is_global);
+        let destination_meta = self.get_meta_file(to, is_global);
+
+        if destination.exists() {
+            if !merge {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "Category '{}' already exists; pass merge: true to combine them",
+                        to
+                    ),
+                ));
+            }
+
+            // Entries are separated by a blank line, so the appended entries must start on a fresh block
+            let mut merged = fs::read_to_string(&destination)?;
+            if !merged.is_empty() && !merged.ends_with("\n\n") {
+                merged.push_str(if merged.ends_with('\n') { "\n" } else { "\n\n" });
+            }
+            merged.push_str(&fs::read_to_string(&source)?);
+            fs::write(&destination, merged)?;
+            fs::remove_file(&source)?;
+
+            // The destination keeps its own metadata when both categories have some
+            if source_meta.exists() {
+                if destination_meta.exists() {
+                    fs::remove_file(&source_meta)?;
+                } else {
+                    fs::rename(&source_meta, &destination_meta)?;
+                }
+            }
+        } else {
+            fs::rename(&source, &destination)?;
+            if source_meta.exists() {
+                fs::rename(&source_meta, &destination_meta)?;
+            }
+        }
+
+        self.update_checksum(from, is_global)?;
+        self.update_checksum(to, is_global)?;
+        self.update_index(&[from, to], is_global);
+        self.emit(
+            MemoryEventKind::CategoryRenamed {
+                from: from.to_string(),
+            },
+            to,
+            None,
+            is_global,
+        );
+        Ok(())
+    }
+
+    /// Append the entries of the `sources` categories to `destination`, skipping entries
+    /// equal to one already there, and with `delete_sources` remove the
+    /// sources afterwards. Entries keep their tags and header fields. Returns how many
+    /// entries were appended and how many duplicates were skipped.
+    pub fn merge_categories(
+        &self,
+        sources: &[&str],
+        destination: &str,
+        is_global: bool,
+        delete_sources: bool,
+    ) -> io::Result<(usize, usize)> {
+        validate_category(destination)?;
+        for source in sources {
+            validate_category(source)?;
+        }
+        self.ensure_writable(is_global)?;
+        // Compare the stored names, which may fold several spellings into one category
+        let stored_sources: BTreeSet<Cow<str>> = sources
+            .iter()
+            .map(|source| self.stored_category(source))
+            .collect();
+        let sources: Vec<&str> = stored_sources
+            .iter()
+            .map(|source| source.as_ref())
+            .collect();
+        if sources.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sources must name at least one category",
+            ));
+        }
+        let stored_destination = self.stored_category(destination);
+        if sources.contains(&stored_destination.as_ref()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Category '{}' cannot be merged into itself", destination),
+            ));
+        }
+
+        // Lock in name order, as rename_category does, so overlapping merges cannot deadlock
+        let names: BTreeSet<&str> = sources
+            .iter()
+            .copied()
+            .chain([stored_destination.as_ref()])
+            .collect();
+        let _locks = names
+            .iter()
+            .map(|name| self.lock_category(name, is_global))
+            .collect::<io::Result<Vec<_>>>()?;
+        for name in &names {
+            self.quarantine_corrupted(name, is_global)?;
+        }
+        for source in &sources {
+            if !self.get_memory_file(source, is_global).exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Category '{}' does not exist", source),
+                ));
+            }
+        }
+
+        let mut entries = self.read_stored_entries(destination, is_global)?;
+        let mut hashes: BTreeSet<String> = entries.iter().map(MemoryEntry::content_hash).collect();
+        let (mut merged, mut duplicates) = (0, 0);
+        for source in &sources {
+            for entry in self.read_stored_entries(source, is_global)? {
+                if hashes.insert(entry.content_hash()) {
+                    entries.push(entry);
+                    merged += 1;
+                } else {
+                    duplicates += 1;
+                }
+            }
+        }
+        self.write_entries(destination, &entries, is_global)?;
+
+        if delete_sources {
+            // The destination keeps its own metadata, or else takes that of the first source
+            let destination_meta = self.get_meta_file(destination, is_global);
+            for source in &sources {
+                fs::remove_file(self.get_memory_file(source, is_global))?;
+                let source_meta = self.get_meta_file(source, is_global);
+                if source_meta.exists() {
+                    if destination_meta.exists() {
+                        fs::remove_file(&source_meta)?;
+                    } else {
+                        fs::rename(&source_meta, &destination_meta)?;
+                    }
+                }
+                self.update_checksum(source, is_global)?;
+            }
+            self.update_index(&sources, is_global);
+        }
+
+        for source in &sources {
+            self.emit(
+                MemoryEventKind::CategoryRenamed {
+                    from: source.to_string(),
+                },
+                destination,
+                None,
+                is_global,
+            );
+        }
+        Ok((merged, duplicates))
+    }
+}
+
+/// The files a snapshot covers: every file directly in `dir`, except lock files and
+/// temporary files of writes in progress
+fn scope_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if entry.file_type()?.is_file() && !matches!(extension, Some("lock" | "tmp")) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ultrathink::tests::{clock_at, test_router, test_router_with_config};
+    use crate::ultrathink::{
+        events, links, CategoryCase, MemoryEvent, MemoryScope, StorageFormat, UltraThinkConfig,
+    };
+    use chrono::Utc;
+    use mcp_core::handler::ToolError;
+    use mcp_server::Router;
+    use serde_json::{json, Value};
+    use tempfile::tempdir;
+    use tokio::sync::{broadcast, mpsc};
+
+    #[test]
+    fn test_rename_category() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        router
+            .remember("old", "some data", &["tag"], false)
+            .unwrap();
+        fs::write(router.get_meta_file("old", false), "{}").unwrap();
+
+        router.rename_category("old", "new", false, false).unwrap();
+
+        assert!(!router.get_memory_file("old", false).exists());
+        assert!(!router.get_meta_file("old", false).exists());
+        assert!(router.get_meta_file("new", false).exists());
+        let memories = router.retrieve("new", false).unwrap();
+        assert!(memories.values().flatten().any(|m| m.contains("some data")));
+    }
+
+    #[test]
+    fn test_rename_category_destination_exists() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        router.remember("bugs", "bug entry", &[], false).unwrap();
+        router
+            .remember("issues", "issue entry", &[], false)
+            .unwrap();
+
+        let err = router
+            .rename_category("bugs", "issues", false, false)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(router.get_memory_file("bugs", false).exists());
+
+        router
+            .rename_category("bugs", "issues", false, true)
+            .unwrap();
+        assert!(!router.get_memory_file("bugs", false).exists());
+        let memories: Vec<String> = router
+            .retrieve("issues", false)
+            .unwrap()
+            .into_values()
+            .flatten()
+            .collect();
+        assert!(memories.iter().any(|m| m.contains("bug entry")));
+        assert!(memories.iter().any(|m| m.contains("issue entry")));
+    }
+
+    #[test]
+    fn test_rename_category_rejects_path_traversal() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        router.remember("safe", "data", &[], false).unwrap();
+
+        for bad in ["../escape", "..", "nested/name", ".hidden"] {
+            let err = router
+                .rename_category("safe", bad, false, false)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(router.get_memory_file("safe", false).exists());
+    }
+
+    #[test]
+    fn test_rename_and_merge_compare_stored_names() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            normalize_category_case: CategoryCase::Lower,
+            ..UltraThinkConfig::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        router.remember("dev", "only entry", &[], false).unwrap();
+
+        // `Dev` and `dev` are one file, so these must fail fast instead of locking it twice
+        let err = router
+            .rename_category("Dev", "dev", false, true)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = router
+            .merge_categories(&["Dev"], "dev", false, false)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 1);
+
+        let (merged, duplicates) = router
+            .merge_categories(&["DEV", "dev"], "notes", false, true)
+            .unwrap();
+        assert_eq!((merged, duplicates), (1, 0));
+        assert_eq!(router.list_categories(false).unwrap(), vec!["notes"]);
+    }
+
+    #[test]
+    fn test_pinned_entry_does_not_expire() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+
+        let mut entry = MemoryEntry::new("keep me", &[]);
+        entry.expires = Some(Utc::now() - chrono::Duration::seconds(60));
+        entry.pinned = true;
+        router.append_entry("notes", &entry, false).unwrap();
+
+        assert_eq!(router.cleanup_expired(false).unwrap(), 0);
+        assert_eq!(
+            router.retrieve("notes", false).unwrap()["untagged"],
+            vec!["keep me"]
+        );
+    }
+
+    #[test]
+    fn test_mutations_are_broadcast() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let mut events = router.subscribe();
+
+        router.remember("dev", "first", &["api"], true).unwrap();
+        let id = MemoryEntry::new("first", &["api"]).id();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            MemoryEvent {
+                kind: MemoryEventKind::Created,
+                scope: MemoryScope::Global,
+                category: "dev".to_string(),
+                entry_id: Some(id.clone()),
+            }
+        );
+
+        router.compact("dev", 0, true).unwrap();
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.kind, MemoryEventKind::Deleted);
+        assert_eq!(event.entry_id, Some(id));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_lagging_subscriber_does_not_block_mutations() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        let mut events = router.subscribe();
+
+        for i in 0..events::EVENT_CAPACITY + 10 {
+            router
+                .remember("dev", &format!("note {}", i), &[], false)
+                .unwrap();
+        }
+
+        assert!(matches!(
+            events.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(10))
+        ));
+        assert_eq!(events.try_recv().unwrap().kind, MemoryEventKind::Created);
+    }
+
+    #[test]
+    fn test_singleton_marked_after_accumulating() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("status", "red", &[], false).unwrap();
+        router.remember("status", "green", &[], false).unwrap();
+
+        router.set_singleton("status", true, false).unwrap();
+        let entries = router.read_entries("status", false).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].data, "green");
+
+        router.set_singleton("status", false, false).unwrap();
+        assert_eq!(router.read_entries("status", false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_merge_categories_dedupes_entries() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("bugs", "shared", &["api"], false).unwrap();
+        router.remember("bugs", "bug only", &["ui"], false).unwrap();
+        router
+            .remember("issues", "shared", &["api"], false)
+            .unwrap();
+        router.remember("issues", "issue only", &[], false).unwrap();
+        let id = MemoryEntry::new("bug only", &["ui"]).id();
+        router.set_pinned("bugs", &id, true, false).unwrap();
+
+        let err = router
+            .merge_categories(&["bugs", "tasks"], "tasks", false, false)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = router
+            .merge_categories(&["absent"], "tasks", false, false)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let (merged, duplicates) = router
+            .merge_categories(&["bugs", "issues"], "tasks", false, true)
+            .unwrap();
+        assert_eq!((merged, duplicates), (3, 1));
+        assert!(!router.get_memory_file("bugs", false).exists());
+        assert!(!router.get_memory_file("issues", false).exists());
+
+        let entries = router.read_entries("tasks", false).unwrap();
+        let data: Vec<&str> = entries.iter().map(|entry| entry.data.as_str()).collect();
+        assert_eq!(data, vec!["shared", "bug only", "issue only"]);
+        assert_eq!(entries[0].tags, vec!["api"]);
+        assert!(entries[1].pinned);
+        assert_eq!(router.list_categories(false).unwrap(), vec!["tasks"]);
+    }
+
+    #[test]
+    fn test_new_categories_beyond_the_limit_are_rejected() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            max_categories: 2,
+            ..UltraThinkConfig::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config);
+        router
+            .remember("development", "uses tokio", &[], false)
+            .unwrap();
+        router
+            .remember("decisions", "use Postgres", &[], false)
+            .unwrap();
+
+        let err = router
+            .remember("devops", "deploys on fridays", &[], false)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("maximum of 2 categories"));
+        assert!(err.to_string().ends_with("such as: decisions, development"));
+        assert_eq!(router.list_categories(false).unwrap().len(), 2);
+
+        // Existing categories stay writable, and each scope has its own limit
+        router
+            .remember("development", "tests with tempdir", &[], false)
+            .unwrap();
+        assert_eq!(router.read_entries("development", false).unwrap().len(), 2);
+        router
+            .remember("devops", "deploys on fridays", &[], true)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_forget_removes_matching_entries() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router
+            .remember("development", "Use Tokio", &["rust"], false)
+            .unwrap();
+        router
+            .remember("development", "tokio-console helps", &[], false)
+            .unwrap();
+        router
+            .remember("development", "use serde", &["rust"], false)
+            .unwrap();
+        router
+            .remember("ops", "deploy on fridays", &[], false)
+            .unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+        let forget = |args: Value| router.call_tool("ultrathink_forget", args, tx.clone());
+
+        let result =
+            forget(json!({"category": "development", "match": "TOKIO", "is_global": false}))
+                .await
+                .unwrap();
+        assert_eq!(
+            result[0].as_text().unwrap().text,
+            "🗑️ Removed 2 entries from category: development"
+        );
+        assert_eq!(
+            router.retrieve("development", false).unwrap()["rust"],
+            vec!["use serde"]
+        );
+        let filters: [(Option<&str>, &[&str], usize); 3] = [
+            (Some("tokio"), &[], 0),
+            (None, &["go"], 0),
+            (None, &["rust"], 1),
+        ];
+        for (matching, tags, removed) in filters {
+            let forgotten = router.forget("development", matching, tags, false).unwrap();
+            assert_eq!(forgotten, removed);
+        }
+
+        // Without a filter the whole category goes
+        router.set_singleton("ops", true, false).unwrap();
+        forget(json!({"category": "ops", "is_global": false}))
+            .await
+            .unwrap();
+        assert!(!router.category_exists("ops", false));
+        assert!(!router.get_meta_file("ops", false).exists());
+        assert_eq!(router.list_categories(false).unwrap(), vec!["development"]);
+
+        let err = forget(json!({"category": "ops", "is_global": false}))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ToolError::ExecutionError(ref msg) if msg.contains("does not exist"))
+        );
+    }
+
+    #[test]
+    fn test_writes_wait_for_other_lock_holders() {
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            lock_timeout: std::time::Duration::from_millis(20),
+            ..UltraThinkConfig::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config.clone());
+        // A second router on the same dirs, as another process would construct
+        let other = test_router_with_config(temp_dir.path(), config);
+        router.remember("dev", "first", &[], false).unwrap();
+
+        let held = other.lock_category("dev", false).unwrap();
+        let err = router.remember("dev", "second", &[], false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        let err = router.compact("dev", 0, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        router.remember("ops", "deploy", &[], false).unwrap();
+
+        drop(held);
+        router.remember("dev", "second", &[], false).unwrap();
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_lock_waits_do_not_block_runtime() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let temp_dir = tempdir().unwrap();
+        let config = UltraThinkConfig {
+            lock_timeout: std::time::Duration::from_millis(100),
+            ..UltraThinkConfig::default()
+        };
+        let router = test_router_with_config(temp_dir.path(), config.clone());
+        let other = test_router_with_config(temp_dir.path(), config);
+        router.remember("dev", "first", &[], false).unwrap();
+        let _held = other.lock_category("dev", false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        for (tool, args) in [
+            (
+                "ultrathink_remember",
+                json!({"category": "dev", "data": "second", "is_global": false}),
+            ),
+            (
+                "ultrathink_forget",
+                json!({"category": "dev", "is_global": false}),
+            ),
+            (
+                "ultrathink_compact",
+                json!({"category": "dev", "keep": 0, "is_global": false}),
+            ),
+        ] {
+            let ticks = Arc::new(AtomicUsize::new(0));
+            let ticker = {
+                let ticks = ticks.clone();
+                tokio::spawn(async move {
+                    loop {
+                        ticks.fetch_add(1, Ordering::SeqCst);
+                        tokio::task::yield_now().await;
+                    }
+                })
+            };
+
+            // The other router holds the lock throughout, so every call times out
+            let result = router.call_tool(tool, args, tx.clone()).await;
+            ticker.abort();
+            assert!(result.is_err(), "{}", tool);
+            assert!(
+                ticks.load(Ordering::SeqCst) > 0,
+                "concurrent task never ran while {} waited for the lock",
+                tool
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clear_requires_confirmation() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("dev", "keep me", &[], false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        for args in [
+            json!({"is_global": false}),
+            json!({"is_global": false, "confirm": false}),
+            json!({"is_global": false, "confirm": "true"}),
+        ] {
+            let err = router
+                .call_tool("ultrathink_clear", args, tx.clone())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ToolError::InvalidParameters(_)));
+        }
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_clear_rejects_mistyped_scope_and_backup() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("dev", "keep me", &[], false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        for (args, message) in [
+            (
+                json!({"is_global": "yes", "backup": true, "confirm": true}),
+                "is_global must be a boolean",
+            ),
+            (
+                json!({"is_global": false, "backup": "true", "confirm": true}),
+                "backup must be a boolean",
+            ),
+        ] {
+            let err = router
+                .call_tool("ultrathink_clear", args, tx.clone())
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(err, ToolError::InvalidParameters(ref msg) if msg.contains(message)),
+                "{:?}",
+                err
+            );
+        }
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 1);
+        assert!(!temp_dir.path().join("local").join(BACKUP_DIR).exists());
+    }
+
+    #[tokio::test]
+    async fn test_clear_with_backup() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("dev", "first", &[], false).unwrap();
+        router.remember("dev", "second", &[], false).unwrap();
+        router.remember("ops", "deploy", &[], false).unwrap();
+        router.remember("ops", "global", &[], true).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let result = router
+            .call_tool(
+                "ultrathink_clear",
+                json!({"is_global": false, "confirm": true, "backup": true}),
+                tx,
+            )
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.contains("cleared 2 categories (3 entries)"));
+        assert!(router.list_categories(false).unwrap().is_empty());
+        assert_eq!(router.list_categories(true).unwrap(), vec!["ops"]);
+
+        let backups: Vec<_> = fs::read_dir(temp_dir.path().join("local").join(BACKUP_DIR))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert!(text.contains(&backups[0].display().to_string()));
+        let backed_up = fs::read_to_string(backups[0].join("dev.txt")).unwrap();
+        let entries = StorageFormat::Text.parse_entries(&backed_up);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].data, "second");
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_and_restore() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("dev", "first", &["api"], false).unwrap();
+        router.remember("ops", "deploy", &[], false).unwrap();
+        router.set_singleton("ops", true, false).unwrap();
+        let first = MemoryEntry::new("first", &["api"]).id();
+        let deploy = MemoryEntry::new("deploy", &[]).id();
+        router.link(&first, &deploy, "triggers", false).unwrap();
+        let before = router.retrieve_all(false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        let result = router
+            .call_tool(
+                "ultrathink_snapshot",
+                json!({"is_global": false}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        let id = text
+            .strip_prefix("📸 UltraThink snapshot created: ")
+            .unwrap()
+            .to_string();
+
+        // Mutate the scope in every way the snapshot should undo
+        router.remember("dev", "second", &[], false).unwrap();
+        router.clear(false).unwrap();
+        router
+            .remember("scratch", "agent notes", &[], false)
+            .unwrap();
+
+        let result = router
+            .call_tool(
+                "ultrathink_restore",
+                json!({"snapshot": id, "is_global": false, "backup": true}),
+                tx.clone(),
+            )
+            .await
+            .unwrap();
+        let text = result[0].as_text().unwrap().text.clone();
+        assert!(text.starts_with(&format!("⏪ UltraThink snapshot restored: {}", id)));
+
+        assert_eq!(router.retrieve_all(false).unwrap(), before);
+        assert!(router.category_meta("ops", false).singleton);
+        let links = MemoryLinks::load(&temp_dir.path().join("local"));
+        assert_eq!(links.links.len(), 1);
+        assert_eq!(links.links[0].relation, "triggers");
+
+        // The state replaced by the restore was snapshotted and can be restored in turn
+        let backup_id = text.rsplit(' ').next().unwrap();
+        router.restore(backup_id, false, false).unwrap();
+        assert_eq!(router.list_categories(false).unwrap(), vec!["scratch"]);
+    }
+
+    #[tokio::test]
+    async fn test_restore_rejects_unknown_snapshots() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("dev", "first", &[], false).unwrap();
+
+        let err = router
+            .restore("20240101T000000.000Z", false, false)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let (tx, _rx) = mpsc::channel(1);
+        let result = router
+            .call_tool(
+                "ultrathink_restore",
+                json!({"snapshot": "../local", "is_global": false}),
+                tx,
+            )
+            .await;
+        assert!(matches!(result, Err(ToolError::InvalidParameters(_))));
+        assert_eq!(router.list_categories(false).unwrap(), vec!["dev"]);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_tools_reject_mistyped_arguments() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("dev", "first", &[], false).unwrap();
+        let id = router.snapshot(false).unwrap();
+        router.remember("dev", "second", &[], false).unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+
+        for (tool, args, message) in [
+            (
+                "ultrathink_snapshot",
+                json!({"is_global": "yes"}),
+                "is_global must be a boolean",
+            ),
+            (
+                "ultrathink_restore",
+                json!({"snapshot": id, "is_global": "yes", "backup": true}),
+                "is_global must be a boolean",
+            ),
+            (
+                "ultrathink_restore",
+                json!({"snapshot": id, "is_global": false, "backup": "true"}),
+                "backup must be a boolean",
+            ),
+        ] {
+            let err = router.call_tool(tool, args, tx.clone()).await.unwrap_err();
+            assert!(
+                matches!(err, ToolError::InvalidParameters(ref msg) if msg.contains(message)),
+                "{} {:?}",
+                tool,
+                err
+            );
+        }
+
+        // Nothing was restored over the scope and no further snapshot was taken
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 2);
+        let snapshots = fs::read_dir(temp_dir.path().join("local").join(SNAPSHOT_DIR)).unwrap();
+        assert_eq!(snapshots.count(), 1);
+    }
+
+    #[test]
+    fn test_snapshots_in_the_same_millisecond_get_distinct_ids() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path()).with_clock(clock_at("2025-01-06T09:00:00Z"));
+        router.remember("dev", "first", &[], false).unwrap();
+
+        let first = router.snapshot(false).unwrap();
+        router.remember("dev", "second", &[], false).unwrap();
+        let second = router.snapshot(false).unwrap();
+        assert_eq!(first, "20250106T090000.000Z");
+        assert_eq!(second, "20250106T090000.000Z-1");
+
+        let backup = router.restore(&first, true, false).unwrap().unwrap();
+        assert_eq!(backup, "20250106T090000.000Z-2");
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 1);
+        router.restore(&second, false, false).unwrap();
+        assert_eq!(router.read_entries("dev", false).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_link_rejects_unknown_and_self_links() {
+        let temp_dir = tempdir().unwrap();
+        let router = test_router(temp_dir.path());
+        router.remember("dev", "only entry", &[], false).unwrap();
+        let id = router.read_entries("dev", false).unwrap()[0].id();
+
+        let err = router
+            .link(&id, "000000000000", "rationale", false)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = router.link(&id, &id, "rationale", false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = router.link(&id, "000000000000", "  ", false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(!temp_dir
+            .path()
+            .join("local")
+            .join(links::LINKS_FILE)
+            .exists());
+    }
+}
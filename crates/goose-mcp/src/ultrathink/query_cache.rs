@@ -0,0 +1,168 @@
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+/// Number of Graphiti queries cached when `GRAPHITI_CACHE_SIZE` is not set
+pub const DEFAULT_CACHE_SIZE: usize = 64;
+/// How long cached Graphiti results are served when `GRAPHITI_CACHE_TTL_SECS` is not set
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+struct CachedResult {
+    results: Vec<String>,
+    stored_at: Instant,
+}
+
+/// What a cached search asked for: its normalized query text, the category it was made
+/// for and how many entities it was limited to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryKey {
+    query: String,
+    category: String,
+    limit: Option<usize>,
+}
+
+impl QueryKey {
+    pub fn new(query: &str, category: &str, limit: Option<usize>) -> Self {
+        Self {
+            query: QueryCache::normalize(query),
+            category: category.to_string(),
+            limit,
+        }
+    }
+}
+
+/// LRU cache of Graphiti search results keyed on the query, see [`QueryKey`].
+/// A capacity of zero disables caching.
+pub struct QueryCache {
+    entries: Option<LruCache<QueryKey, CachedResult>>,
+    ttl: Duration,
+}
+
+impl QueryCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: NonZeroUsize::new(capacity).map(LruCache::new),
+            ttl,
+        }
+    }
+
+    /// Read the size and TTL from `GRAPHITI_CACHE_SIZE` and `GRAPHITI_CACHE_TTL_SECS`
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("GRAPHITI_CACHE_SIZE")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_CACHE_SIZE);
+        let ttl = std::env::var("GRAPHITI_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .map_or(DEFAULT_CACHE_TTL, Duration::from_secs);
+        Self::new(capacity, ttl)
+    }
+
+    /// Queries differing only in case or whitespace share a cache entry
+    pub fn normalize(query: &str) -> String {
+        query
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+
+    /// Results cached for `key` within the TTL; stale entries are dropped
+    pub fn get(&mut self, key: &QueryKey) -> Option<Vec<String>> {
+        let entries = self.entries.as_mut()?;
+        let cached = entries.get(key)?;
+        if cached.stored_at.elapsed() < self.ttl {
+            return Some(cached.results.clone());
+        }
+        entries.pop(key);
+        None
+    }
+
+    pub fn insert(&mut self, key: QueryKey, results: Vec<String>) {
+        if let Some(entries) = self.entries.as_mut() {
+            entries.put(
+                key,
+                CachedResult {
+                    results,
+                    stored_at: Instant::now(),
+                },
+            );
+        }
+    }
+
+    /// Drop every cached query that would match one of `texts`. The memory server
+    /// matches a query as a case-insensitive substring of entity names, types and
+    /// observations, so these are exactly the queries a new entity can change.
+    pub fn invalidate_matching(&mut self, texts: &[&str]) {
+        let Some(entries) = self.entries.as_mut() else {
+            return;
+        };
+        let texts: Vec<String> = texts.iter().map(|text| text.to_lowercase()).collect();
+        let stale: Vec<QueryKey> = entries
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| texts.iter().any(|text| text.contains(key.query.as_str())))
+            .cloned()
+            .collect();
+        for key in stale {
+            entries.pop(&key);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        if let Some(entries) = self.entries.as_mut() {
+            entries.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(QueryCache::normalize("  Auth   Flow\n"), "auth flow");
+    }
+
+    fn key(query: &str) -> QueryKey {
+        QueryKey::new(query, "dev", None)
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let mut cache = QueryCache::new(2, DEFAULT_CACHE_TTL);
+        cache.insert(key("a"), vec!["1".to_string()]);
+        cache.insert(key("b"), vec!["2".to_string()]);
+        cache.get(&key("a"));
+        cache.insert(key("c"), vec!["3".to_string()]);
+
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("c")).is_some());
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_cache() {
+        let mut cache = QueryCache::new(0, DEFAULT_CACHE_TTL);
+        cache.insert(key("a"), vec!["1".to_string()]);
+        assert!(cache.get(&key("a")).is_none());
+    }
+
+    #[test]
+    fn test_category_and_limit_are_part_of_the_key() {
+        let mut cache = QueryCache::new(8, DEFAULT_CACHE_TTL);
+        cache.insert(
+            QueryKey::new(" Auth ", "dev", Some(5)),
+            vec!["1".to_string()],
+        );
+
+        assert!(cache.get(&QueryKey::new("auth", "dev", Some(5))).is_some());
+        assert!(cache.get(&QueryKey::new("auth", "ops", Some(5))).is_none());
+        assert!(cache.get(&QueryKey::new("auth", "dev", None)).is_none());
+
+        cache.invalidate_matching(&["New AUTH flow"]);
+        assert!(cache.get(&QueryKey::new("auth", "dev", Some(5))).is_none());
+    }
+}
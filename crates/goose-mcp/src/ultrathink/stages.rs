@@ -0,0 +1,124 @@
+use std::io;
+
+/// The stages of `ultrathink_sequence`, in the order a sequence goes through them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThinkingStage {
+    ProblemDefinition,
+    Research,
+    Analysis,
+    Synthesis,
+    Conclusion,
+}
+
+impl ThinkingStage {
+    pub const ALL: [Self; 5] = [
+        Self::ProblemDefinition,
+        Self::Research,
+        Self::Analysis,
+        Self::Synthesis,
+        Self::Conclusion,
+    ];
+
+    /// Parse a stage name as listed in the tool schema, ignoring case
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|stage| stage.name().eq_ignore_ascii_case(value.trim()))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ProblemDefinition => "Problem Definition",
+            Self::Research => "Research",
+            Self::Analysis => "Analysis",
+            Self::Synthesis => "Synthesis",
+            Self::Conclusion => "Conclusion",
+        }
+    }
+
+    fn next(self) -> Option<Self> {
+        Self::ALL.get(self as usize + 1).copied()
+    }
+}
+
+/// How far the sequence of a router has progressed, to keep strict sequences from
+/// skipping stages
+#[derive(Debug, Default)]
+pub struct StageTracker {
+    /// The furthest stage reached; earlier stages can be revisited freely
+    reached: Option<ThinkingStage>,
+}
+
+impl StageTracker {
+    /// Record a move to `stage`, failing without recording it when `strict` and the move
+    /// skips ahead of the stage after the furthest one reached. A new sequence starts with
+    /// Problem Definition, and starting over at Problem Definition after the Conclusion
+    /// begins a new one.
+    pub fn advance(&mut self, stage: ThinkingStage, strict: bool) -> io::Result<()> {
+        if stage == ThinkingStage::ProblemDefinition
+            && self.reached == Some(ThinkingStage::Conclusion)
+        {
+            self.reached = None;
+        }
+
+        let expected = match self.reached {
+            Some(reached) => reached.next().unwrap_or(reached),
+            None => ThinkingStage::ProblemDefinition,
+        };
+        if stage <= expected {
+            self.reached = self.reached.max(Some(stage));
+            return Ok(());
+        }
+        if !strict {
+            self.reached = Some(stage);
+            return Ok(());
+        }
+
+        let revisit = match self.reached {
+            Some(_) => " or an earlier stage",
+            None => "",
+        };
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Stage '{}' skips ahead; expected '{}'{} next",
+                stage.name(),
+                expected.name(),
+                revisit
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stage_names() {
+        assert_eq!(
+            ThinkingStage::parse("problem definition"),
+            Some(ThinkingStage::ProblemDefinition)
+        );
+        assert_eq!(
+            ThinkingStage::parse("Conclusion"),
+            Some(ThinkingStage::Conclusion)
+        );
+        assert_eq!(ThinkingStage::parse("Brainstorm"), None);
+    }
+
+    #[test]
+    fn test_non_strict_moves_are_recorded() {
+        let mut tracker = StageTracker::default();
+        tracker.advance(ThinkingStage::Analysis, false).unwrap();
+        // Having reached Analysis, Synthesis is the next stage
+        tracker.advance(ThinkingStage::Synthesis, true).unwrap();
+        assert!(tracker.advance(ThinkingStage::Conclusion, true).is_ok());
+
+        tracker
+            .advance(ThinkingStage::ProblemDefinition, true)
+            .unwrap();
+        let err = tracker.advance(ThinkingStage::Analysis, true).unwrap_err();
+        assert!(err.to_string().contains("expected 'Research'"));
+    }
+}
@@ -0,0 +1,316 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_yaml::{Mapping, Value};
+
+use super::entry::{parse_entries, MemoryEntry, CONTEXT_FIELD};
+
+/// Line opening and closing the frontmatter of a Markdown entry
+const FRONTMATTER_DELIMITER: &str = "---";
+
+/// How the entries of a category are laid out on disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageFormat {
+    /// `<category>.txt`: blocks separated by a blank line, each with an optional `#` header
+    #[default]
+    Text,
+    /// `<category>.md`: each entry is a Markdown body below `---` YAML frontmatter holding
+    /// its tags and metadata, so the file reads as a set of notes
+    Markdown,
+}
+
+impl StorageFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "text" | "txt" => Some(Self::Text),
+            "markdown" | "md" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+
+    /// File extension of the category files
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Text => "txt",
+            Self::Markdown => "md",
+        }
+    }
+
+    /// Render one entry, without the trailing blank-line separator
+    pub fn render_entry(self, entry: &MemoryEntry) -> String {
+        match self {
+            Self::Text => entry.to_block(),
+            Self::Markdown => render_markdown(entry),
+        }
+    }
+
+    /// Parse the contents of a category file into its entries, in file order
+    pub fn parse_entries(self, content: &str) -> Vec<MemoryEntry> {
+        // Some Windows editors start files with a BOM, which would hide the first header
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+        match self {
+            Self::Text => parse_entries(content),
+            Self::Markdown => parse_markdown(content),
+        }
+    }
+}
+
+fn render_markdown(entry: &MemoryEntry) -> String {
+    let mut frontmatter = Mapping::new();
+    if !entry.tags.is_empty() {
+        let tags = entry.tags.iter().cloned().map(Value::String).collect();
+        frontmatter.insert("tags".into(), Value::Sequence(tags));
+    }
+    for (field, value) in &entry.fields {
+        let key = if field == CONTEXT_FIELD {
+            "context"
+        } else {
+            field
+        };
+        frontmatter.insert(key.into(), value.as_str().into());
+    }
+    for (key, time) in [("timestamp", entry.timestamp), ("expires", entry.expires)] {
+        if let Some(time) = time {
+            frontmatter.insert(
+                key.into(),
+                time.to_rfc3339_opts(SecondsFormat::Millis, true).into(),
+            );
+        }
+    }
+    for (key, set) in [("pinned", entry.pinned), ("truncated", entry.truncated)] {
+        if set {
+            frontmatter.insert(key.into(), true.into());
+        }
+    }
+
+    let yaml = if frontmatter.is_empty() {
+        String::new()
+    } else {
+        serde_yaml::to_string(&frontmatter).expect("a mapping of plain values serializes")
+    };
+    format!(
+        "{}\n{}{}\n{}",
+        FRONTMATTER_DELIMITER, yaml, FRONTMATTER_DELIMITER, entry.data
+    )
+}
+
+/// Entries start at a `---` line, at the top of the file or after a blank line, that is
+/// closed by another `---` line with a YAML mapping in between. Any other `---` line, such
+/// as a horizontal rule, belongs to the body. Text before the first frontmatter is read in
+/// the text format, so a `.txt` file can be turned into a `.md` one by hand.
+fn parse_markdown(content: &str) -> Vec<MemoryEntry> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut entries = Vec::new();
+    let mut current: Option<MemoryEntry> = None;
+    let mut body_start = 0;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let starts_block = i == 0 || lines[i - 1].trim().is_empty();
+        if let Some((entry, end)) = starts_block.then(|| frontmatter_at(&lines, i)).flatten() {
+            finish_entry(current.take(), &lines[body_start..i], &mut entries);
+            current = Some(entry);
+            i = end + 1;
+            body_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    finish_entry(current, &lines[body_start..], &mut entries);
+    entries
+}
+
+/// The entry described by frontmatter opening at `start`, and the line that closes it
+fn frontmatter_at(lines: &[&str], start: usize) -> Option<(MemoryEntry, usize)> {
+    if lines[start].trim_end() != FRONTMATTER_DELIMITER {
+        return None;
+    }
+    let end = start
+        + 1
+        + lines[start + 1..]
+            .iter()
+            .position(|line| line.trim_end() == FRONTMATTER_DELIMITER)?;
+
+    let frontmatter = match serde_yaml::from_str(&lines[start + 1..end].join("\n")).ok()? {
+        Value::Mapping(mapping) => mapping,
+        Value::Null => Mapping::new(),
+        _ => return None,
+    };
+    Some((entry_from_frontmatter(frontmatter), end))
+}
+
+fn finish_entry(entry: Option<MemoryEntry>, body: &[&str], entries: &mut Vec<MemoryEntry>) {
+    let body = body.join("\n");
+    let body = body.trim_end_matches('\n');
+    match entry {
+        Some(mut entry) => {
+            entry.data = body.to_string();
+            entries.push(entry);
+        }
+        None => entries.extend(parse_entries(body)),
+    }
+}
+
+/// Known keys fill the matching entry fields; missing ones keep their defaults and any
+/// other key is kept as a field so it survives a rewrite
+fn entry_from_frontmatter(frontmatter: Mapping) -> MemoryEntry {
+    let mut entry = MemoryEntry::default();
+    for (key, value) in frontmatter {
+        let Some(key) = scalar_to_string(&key) else {
+            continue;
+        };
+        match key.to_lowercase().as_str() {
+            "tags" => entry.tags = tags_from(&value),
+            "timestamp" => entry.timestamp = time_from(&value),
+            "expires" => entry.expires = time_from(&value),
+            "pinned" => entry.pinned = flag_from(&value),
+            "truncated" => entry.truncated = flag_from(&value),
+            key => {
+                let field = if key == "context" { CONTEXT_FIELD } else { key };
+                if let Some(value) = value_to_string(&value) {
+                    entry.fields.insert(field.to_string(), value);
+                }
+            }
+        }
+    }
+    entry
+}
+
+/// Tags are a YAML list, or a single comma separated string
+fn tags_from(value: &Value) -> Vec<String> {
+    let tags: Vec<String> = match value {
+        Value::Sequence(tags) => tags.iter().filter_map(scalar_to_string).collect(),
+        value => scalar_to_string(value)
+            .map(|tags| tags.split(',').map(String::from).collect())
+            .unwrap_or_default(),
+    };
+    tags.into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// An RFC 3339 time; unparseable values are dropped
+fn time_from(value: &Value) -> Option<DateTime<Utc>> {
+    scalar_to_string(value)
+        .and_then(|time| DateTime::parse_from_rfc3339(time.trim()).ok())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn flag_from(value: &Value) -> bool {
+    match value {
+        Value::Bool(set) => *set,
+        value => scalar_to_string(value).as_deref() == Some("true"),
+    }
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Scalars as their text; lists and maps as YAML so nothing is lost
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Sequence(_) | Value::Mapping(_) | Value::Tagged(_) => serde_yaml::to_string(value)
+            .ok()
+            .map(|yaml| yaml.trim_end().to_string()),
+        value => scalar_to_string(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_file(entries: &[MemoryEntry]) -> String {
+        entries
+            .iter()
+            .map(|entry| format!("{}\n\n", StorageFormat::Markdown.render_entry(entry)))
+            .collect()
+    }
+
+    #[test]
+    fn test_markdown_round_trip() {
+        let mut first = MemoryEntry::new(
+            "# Heading\n\nA paragraph\n\n---\n\nafter a rule",
+            &["a", "b"],
+        );
+        first.timestamp = Some(
+            DateTime::parse_from_rfc3339("2024-05-01T10:00:00.250Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        );
+        first
+            .fields
+            .insert("priority".to_string(), "high".to_string());
+        first.fields.insert(
+            CONTEXT_FIELD.to_string(),
+            "release: v2; see notes".to_string(),
+        );
+        first.pinned = true;
+        let second = MemoryEntry::new("bare", &[]);
+
+        let content = render_file(&[first.clone(), second]);
+        assert!(content.starts_with("---\ntags:\n- a\n- b\n"));
+        assert!(content.contains("\ncontext: "));
+
+        let parsed = StorageFormat::Markdown.parse_entries(&content);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].tags, first.tags);
+        assert_eq!(parsed[0].data, first.data);
+        assert_eq!(parsed[0].timestamp, first.timestamp);
+        assert!(parsed[0].pinned);
+        assert!(!parsed[0].truncated);
+        assert_eq!(parsed[0].fields, first.fields);
+        assert_eq!(parsed[1].data, "bare");
+        assert!(parsed[1].tags.is_empty());
+    }
+
+    #[test]
+    fn test_markdown_missing_and_extra_keys() {
+        let content = "---\ntags: api, auth\nowner: sam\nreviewed: 3\nlinks: [a, b]\nexpires: soon\n---\nbody\n\n---\n---\nno metadata\n";
+        let parsed = StorageFormat::Markdown.parse_entries(content);
+        assert_eq!(parsed.len(), 2);
+
+        assert_eq!(parsed[0].tags, vec!["api", "auth"]);
+        assert_eq!(parsed[0].data, "body");
+        assert!(parsed[0].timestamp.is_none());
+        assert!(parsed[0].expires.is_none());
+        assert_eq!(parsed[0].fields["owner"], "sam");
+        assert_eq!(parsed[0].fields["reviewed"], "3");
+        assert_eq!(parsed[0].fields["links"], "- a\n- b");
+
+        assert!(parsed[1].tags.is_empty());
+        assert!(parsed[1].fields.is_empty());
+        assert_eq!(parsed[1].data, "no metadata");
+
+        // Extra keys survive a rewrite
+        let reparsed = StorageFormat::Markdown.parse_entries(&render_file(&parsed));
+        assert_eq!(reparsed[0].fields, parsed[0].fields);
+    }
+
+    #[test]
+    fn test_markdown_rules_and_preamble() {
+        // A rule not followed by a YAML mapping stays in the body
+        let content = "legacy note\n\n---\ntags: [x]\n---\nbefore\n\n---\n\njust text\n---\n";
+        let parsed = StorageFormat::Markdown.parse_entries(content);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].data, "legacy note");
+        assert_eq!(parsed[1].tags, vec!["x"]);
+        assert_eq!(parsed[1].data, "before\n\n---\n\njust text\n---");
+    }
+
+    #[test]
+    fn test_parse_format_names() {
+        assert_eq!(
+            StorageFormat::parse("Markdown"),
+            Some(StorageFormat::Markdown)
+        );
+        assert_eq!(StorageFormat::parse("txt"), Some(StorageFormat::Text));
+        assert_eq!(StorageFormat::parse("yaml"), None);
+    }
+}
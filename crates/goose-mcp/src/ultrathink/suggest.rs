@@ -0,0 +1,60 @@
+/// Number of existing categories suggested in place of one that cannot be created
+pub const MAX_SUGGESTIONS: usize = 3;
+
+/// The `limit` names of `candidates` closest to `name`, by edit distance ignoring case and
+/// then alphabetically
+pub fn closest<'a>(name: &str, candidates: &'a [String], limit: usize) -> Vec<&'a str> {
+    let name = name.to_lowercase();
+    let mut ranked: Vec<(usize, &str)> = candidates
+        .iter()
+        .map(|candidate| {
+            (
+                edit_distance(&name, &candidate.to_lowercase()),
+                candidate.as_str(),
+            )
+        })
+        .collect();
+    ranked.sort();
+    ranked
+        .into_iter()
+        .take(limit)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_ranks_by_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+
+        let categories: Vec<String> = ["decisions", "development", "Dev", "ops"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            closest("developer", &categories, 2),
+            vec!["development", "Dev"]
+        );
+        assert_eq!(closest("decision", &categories, 1), vec!["decisions"]);
+        assert!(closest("dev", &[], MAX_SUGGESTIONS).is_empty());
+    }
+}
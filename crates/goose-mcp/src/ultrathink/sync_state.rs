@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::entry::MemoryEntry;
+
+/// Name of the file recording what has been uploaded from a memory dir
+pub const SYNC_STATE_FILE: &str = "graphiti_sync.json";
+
+/// Name the sync state is locked under; categories cannot start with a dot, so it never
+/// shares a lock with one
+pub const SYNC_STATE_LOCK: &str = ".graphiti_sync";
+
+/// When an entry was last uploaded and what its content was at the time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncedEntry {
+    pub hash: String,
+    pub synced_at: DateTime<Utc>,
+    /// The [`idempotency_key`] the entry was stored under; missing in older states
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+/// Entries of one memory dir that have been uploaded to Graphiti, keyed by [`Self::key`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    pub entries: BTreeMap<String, SyncedEntry>,
+}
+
+impl SyncState {
+    /// Load the sync state of `dir`. A missing or corrupt state counts as empty, so the
+    /// next sync uploads everything and writes a fresh state.
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(SYNC_STATE_FILE);
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            tracing::warn!(
+                "Ignoring corrupt Graphiti sync state {}: {}",
+                path.display(),
+                e
+            );
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let path = dir.join(SYNC_STATE_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)
+    }
+
+    /// Identifies an entry across syncs; the entry id only covers data and tags, so
+    /// metadata changes such as pinning show up as a changed hash under the same key
+    pub fn key(category: &str, entry: &MemoryEntry) -> String {
+        format!("{}/{}", category, entry.id())
+    }
+}
+
+/// SHA-256 of the stored form of an entry, header fields included, so that any change
+/// to a synced entry is noticed; see [`MemoryEntry::content_hash`] for what makes two
+/// entries the same
+pub fn stored_hash(entry: &MemoryEntry) -> String {
+    Sha256::digest(entry.to_block().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Deterministic key of the memory Graphiti stores for `data` in `category`. It is sent
+/// with the entity so a store that is retried after it timed out, but actually succeeded,
+/// is recognised as the same operation instead of creating a second entity.
+pub fn idempotency_key(category: &str, data: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(category.as_bytes());
+    hasher.update([0]);
+    hasher.update(data.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The outcome of a Graphiti sync, returned as JSON by `ultrathink_graphiti_sync`.
+///
+/// A sync carries on past entries that fail; they are listed in `errors` and retried
+/// by the next sync.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SyncReport {
+    pub direction: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    pub uploaded: usize,
+    pub downloaded: usize,
+    /// Entries not uploaded because they are unchanged since the last sync
+    pub skipped: usize,
+    pub errors: Vec<String>,
+    pub duration_ms: u64,
+}
+
+impl SyncReport {
+    pub fn new(direction: &str, category: Option<&str>) -> Self {
+        Self {
+            direction: direction.to_string(),
+            category: category.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// One human-readable line describing the report
+    pub fn summary(&self) -> String {
+        let icon = match self.direction.as_str() {
+            "to_graphiti" => "📤",
+            "from_graphiti" => "📥",
+            _ => "🔄",
+        };
+        let scope = self
+            .category
+            .as_ref()
+            .map(|category| format!(" (category: {})", category))
+            .unwrap_or_default();
+        let mut summary = format!(
+            "{} Graphiti sync {}{}: {} uploaded, {} unchanged skipped, {} downloaded",
+            icon, self.direction, scope, self.uploaded, self.skipped, self.downloaded
+        );
+        if !self.is_success() {
+            summary.push_str(&format!(", {} failed", self.errors.len()));
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_report_summary() {
+        let mut report = SyncReport::new("bidirectional", Some("dev"));
+        report.uploaded = 2;
+        report.downloaded = 3;
+        assert_eq!(
+            report.summary(),
+            "🔄 Graphiti sync bidirectional (category: dev): 2 uploaded, 0 unchanged skipped, 3 downloaded"
+        );
+
+        report
+            .errors
+            .push("dev/abc: connection refused".to_string());
+        assert!(!report.is_success());
+        assert!(report.summary().ends_with(", 1 failed"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["errors"][0], "dev/abc: connection refused");
+        assert!(SyncReport::new("to_graphiti", None)
+            .summary()
+            .starts_with("📤"));
+    }
+
+    #[test]
+    fn test_idempotency_key_is_deterministic() {
+        let key = idempotency_key("dev", "uses tokio");
+        assert_eq!(key, idempotency_key("dev", "uses tokio"));
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, idempotency_key("ops", "uses tokio"));
+        assert_ne!(key, idempotency_key("dev", "uses async-std"));
+        assert_ne!(idempotency_key("a", "bc"), idempotency_key("ab", "c"));
+    }
+}
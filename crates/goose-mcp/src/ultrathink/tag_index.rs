@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::file_name::encode_category;
+use super::index::{scan_categories, MemoryIndex};
+use super::storage::StorageFormat;
+
+/// Name of the tag index kept next to the category files of a memory dir
+pub const TAG_INDEX_FILE: &str = "tags.json";
+
+/// An entry listed under one of its tags
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaggedEntry {
+    pub category: String,
+    pub id: String,
+}
+
+/// The entries of every tag in one memory dir, stored in its `tags.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagIndex {
+    /// Entries of each tag, sorted by category and in file order within a category.
+    /// Expired entries that were not cleaned up yet are included.
+    pub tags: BTreeMap<String, Vec<TaggedEntry>>,
+    /// Stats of the category files the tags were read from, to tell when they changed
+    files: MemoryIndex,
+}
+
+impl TagIndex {
+    /// Load the tag index of `dir`; `None` if it is missing or unreadable
+    pub fn load(dir: &Path) -> Option<Self> {
+        let content = fs::read_to_string(dir.join(TAG_INDEX_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Build the tag index of `dir` from scratch by reading every category file
+    pub fn rebuild(dir: &Path, format: StorageFormat) -> io::Result<Self> {
+        let mut index = Self {
+            tags: BTreeMap::new(),
+            files: MemoryIndex::rebuild(dir, format)?,
+        };
+        for (category, path) in scan_categories(dir, format)? {
+            index.add_file(&category, &path, format)?;
+        }
+        Ok(index)
+    }
+
+    /// Save the tag index through a temporary file so readers never see a partial index
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let path = dir.join(TAG_INDEX_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)
+    }
+
+    /// Re-read the tagged entries of one category, dropping them if its file no longer
+    /// exists
+    pub fn refresh(&mut self, dir: &Path, category: &str, format: StorageFormat) -> io::Result<()> {
+        self.files.refresh(dir, category, format)?;
+        for entries in self.tags.values_mut() {
+            entries.retain(|entry| entry.category != category);
+        }
+        self.tags.retain(|_, entries| !entries.is_empty());
+
+        let path = dir.join(format!(
+            "{}.{}",
+            encode_category(category),
+            format.extension()
+        ));
+        if path.exists() {
+            self.add_file(category, &path, format)?;
+            // A stable sort keeps the file order within each category
+            for entries in self.tags.values_mut() {
+                entries.sort_by(|a, b| a.category.cmp(&b.category));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the tag index was read from exactly the category files in `dir`
+    pub fn matches_dir(&self, dir: &Path, format: StorageFormat) -> io::Result<bool> {
+        self.files.matches_dir(dir, format)
+    }
+
+    /// The entries tagged with `tag`
+    pub fn entries(&self, tag: &str) -> &[TaggedEntry] {
+        self.tags.get(tag).map_or(&[], Vec::as_slice)
+    }
+
+    fn add_file(&mut self, category: &str, path: &Path, format: StorageFormat) -> io::Result<()> {
+        for entry in format.parse_entries(&fs::read_to_string(path)?) {
+            let id = entry.id();
+            for tag in entry.tags {
+                self.tags.entry(tag).or_default().push(TaggedEntry {
+                    category: category.to_string(),
+                    id: id.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_refresh_replaces_entries_of_one_category() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("ops.txt"), "# api\ndeploy\n\n").unwrap();
+        fs::write(
+            dir.path().join("dev.txt"),
+            "# api db\nfirst\n\n# api\nsecond\n\n",
+        )
+        .unwrap();
+
+        let mut index = TagIndex::rebuild(dir.path(), StorageFormat::Text).unwrap();
+        let categories = |index: &TagIndex, tag: &str| -> Vec<String> {
+            index
+                .entries(tag)
+                .iter()
+                .map(|entry| entry.category.clone())
+                .collect()
+        };
+        assert_eq!(categories(&index, "api"), vec!["dev", "dev", "ops"]);
+        assert_eq!(categories(&index, "db"), vec!["dev"]);
+        assert!(index.matches_dir(dir.path(), StorageFormat::Text).unwrap());
+
+        fs::write(dir.path().join("dev.txt"), "# api\nsecond\n\n").unwrap();
+        index
+            .refresh(dir.path(), "dev", StorageFormat::Text)
+            .unwrap();
+        assert_eq!(categories(&index, "api"), vec!["dev", "ops"]);
+        assert!(index.entries("db").is_empty());
+        assert!(!index.tags.contains_key("db"));
+
+        fs::remove_file(dir.path().join("ops.txt")).unwrap();
+        index
+            .refresh(dir.path(), "ops", StorageFormat::Text)
+            .unwrap();
+        assert_eq!(categories(&index, "api"), vec!["dev"]);
+    }
+}
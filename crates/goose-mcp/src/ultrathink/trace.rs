@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+
+/// Number of tool calls kept when no trace size is configured
+pub const DEFAULT_TRACE_SIZE: usize = 50;
+
+/// Argument fields redacted from the trace when no list is configured
+pub const DEFAULT_REDACTED_FIELDS: &[&str] =
+    &["password", "secret", "token", "api_key", "authorization"];
+
+/// Replaces the value of a redacted argument
+const REDACTED: &str = "[redacted]";
+
+/// One call made to the router, as kept in its trace
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolCallRecord {
+    pub tool: String,
+    /// The arguments with the value of every redacted field replaced
+    pub arguments: Value,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// Size of the returned text, or of the error message for a failed call
+    pub result_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The most recent tool calls of a router, oldest first; older calls are dropped once
+/// `capacity` calls are kept
+#[derive(Debug)]
+pub struct ToolTrace {
+    records: VecDeque<ToolCallRecord>,
+    capacity: usize,
+    redacted_fields: Vec<String>,
+}
+
+impl ToolTrace {
+    pub fn new(capacity: usize, redacted_fields: &[String]) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+            redacted_fields: redacted_fields.iter().map(|f| f.to_lowercase()).collect(),
+        }
+    }
+
+    pub fn record(&mut self, record: ToolCallRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn records(&self) -> Vec<ToolCallRecord> {
+        self.records.iter().cloned().collect()
+    }
+
+    /// A copy of `arguments` with the values of redacted fields replaced, at any depth.
+    /// Field names are compared ignoring case.
+    pub fn redact(&self, arguments: &Value) -> Value {
+        match arguments {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, value)| {
+                        let value = if self.redacted_fields.contains(&key.to_lowercase()) {
+                            Value::String(REDACTED.to_string())
+                        } else {
+                            self.redact(value)
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect(),
+            ),
+            Value::Array(values) => Value::Array(values.iter().map(|v| self.redact(v)).collect()),
+            value => value.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(tool: &str) -> ToolCallRecord {
+        let now = Utc::now();
+        ToolCallRecord {
+            tool: tool.to_string(),
+            arguments: json!({}),
+            started_at: now,
+            finished_at: now,
+            duration_ms: 0,
+            success: true,
+            result_bytes: 0,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_oldest_records_are_dropped() {
+        let mut trace = ToolTrace::new(2, &[]);
+        for tool in ["a", "b", "c"] {
+            trace.record(record(tool));
+        }
+        let tools: Vec<String> = trace.records().into_iter().map(|r| r.tool).collect();
+        assert_eq!(tools, vec!["b", "c"]);
+
+        let mut disabled = ToolTrace::new(0, &[]);
+        disabled.record(record("a"));
+        assert!(disabled.records().is_empty());
+    }
+
+    #[test]
+    fn test_redacts_nested_fields_ignoring_case() {
+        let trace = ToolTrace::new(1, &["token".to_string(), "data".to_string()]);
+        let redacted = trace.redact(&json!({
+            "category": "dev",
+            "Data": "secret note",
+            "nested": [{"token": "abc", "kept": 1}]
+        }));
+        assert_eq!(
+            redacted,
+            json!({
+                "category": "dev",
+                "Data": REDACTED,
+                "nested": [{"token": REDACTED, "kept": 1}]
+            })
+        );
+    }
+}
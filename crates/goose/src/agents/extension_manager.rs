@@ -925,6 +925,7 @@ mod tests {
                 "tool" | "test__tool" => Ok(CallToolResult {
                     content: vec![],
                     is_error: None,
+                    meta: None,
                 }),
                 _ => Err(Error::NotInitialized),
             }
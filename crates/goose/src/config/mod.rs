@@ -8,6 +8,7 @@ pub use crate::agents::ExtensionConfig;
 pub use base::{Config, ConfigError, APP_STRATEGY};
 pub use experiments::ExperimentManager;
 pub use extensions::{ExtensionConfigManager, ExtensionEntry};
+pub use goose_mcp::{ConfigSource, GooseConfig};
 pub use permission::PermissionManager;
 pub use signup_openrouter::configure_openrouter;
 
@@ -18,6 +18,20 @@ pub enum ToolError {
 
 pub type ToolResult<T> = std::result::Result<T, ToolError>;
 
+/// Argument and validation problems, and operations the caller isn't allowed to perform,
+/// are the caller's fault and shouldn't be retried; everything else is reported as an
+/// execution failure
+impl From<std::io::Error> for ToolError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::InvalidInput | std::io::ErrorKind::PermissionDenied => {
+                ToolError::InvalidParameters(err.to_string())
+            }
+            _ => ToolError::ExecutionError(err.to_string()),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ResourceError {
     #[error("Execution failed: {0}")]
@@ -35,3 +49,35 @@ pub enum PromptError {
     #[error("Prompt not found: {0}")]
     NotFound(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn run(err: io::Error) -> ToolResult<()> {
+        Err(err)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_io_error_conversion() {
+        let invalid = run(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "missing category",
+        ));
+        assert_eq!(
+            invalid,
+            Err(ToolError::InvalidParameters("missing category".to_string()))
+        );
+
+        let denied = run(io::Error::new(io::ErrorKind::PermissionDenied, "read-only"));
+        assert!(matches!(denied, Err(ToolError::InvalidParameters(_))));
+
+        let missing = run(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert_eq!(
+            missing,
+            Err(ToolError::ExecutionError("no such file".to_string()))
+        );
+    }
+}
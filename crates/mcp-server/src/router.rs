@@ -5,6 +5,15 @@ use std::{
 };
 
 type PromptFuture = Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'static>>;
+/// Metadata attached to a tool result, sent to the client as `_meta`
+pub type ToolResultMeta = serde_json::Map<String, Value>;
+type ToolMetaFuture = Pin<
+    Box<
+        dyn Future<Output = Result<(Vec<Content>, Option<ToolResultMeta>), ToolError>>
+            + Send
+            + 'static,
+    >,
+>;
 use mcp_core::{
     handler::{PromptError, ResourceError, ToolError},
     protocol::{
@@ -87,12 +96,29 @@ pub trait Router: Send + Sync + 'static {
     fn instructions(&self) -> String;
     fn capabilities(&self) -> ServerCapabilities;
     fn list_tools(&self) -> Vec<rmcp::model::Tool>;
+    /// The tool called `name`, for clients that fetch the details of one tool lazily. The
+    /// default searches [`Self::list_tools`]; routers can override it to skip building the
+    /// whole list.
+    fn describe_tool(&self, name: &str) -> Option<rmcp::model::Tool> {
+        self.list_tools().into_iter().find(|tool| tool.name == name)
+    }
     fn call_tool(
         &self,
         tool_name: &str,
         arguments: Value,
         notifier: mpsc::Sender<JsonRpcMessage>,
     ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>>;
+    /// Call a tool and return structured metadata alongside its content. The default
+    /// attaches none, so only routers with something to report need to override it.
+    fn call_tool_with_meta(
+        &self,
+        tool_name: &str,
+        arguments: Value,
+        notifier: mpsc::Sender<JsonRpcMessage>,
+    ) -> ToolMetaFuture {
+        let call = self.call_tool(tool_name, arguments, notifier);
+        Box::pin(async move { Ok((call.await?, None)) })
+    }
     fn list_resources(&self) -> Vec<Resource>;
     fn read_resource(
         &self,
@@ -181,14 +207,16 @@ pub trait Router: Send + Sync + 'static {
 
             let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
 
-            let result = match self.call_tool(name, arguments, notifier).await {
-                Ok(result) => CallToolResult {
-                    content: result,
+            let result = match self.call_tool_with_meta(name, arguments, notifier).await {
+                Ok((content, meta)) => CallToolResult {
+                    content,
                     is_error: None,
+                    meta,
                 },
                 Err(err) => CallToolResult {
                     content: vec![Content::text(err.to_string())],
                     is_error: Some(true),
+                    meta: None,
                 },
             };
 